@@ -0,0 +1,21 @@
+//! `AtlasMap::find_path` corner to corner across an open 200x200 map and through a 200x200
+//! serpentine whose path runs the whole width of every other row.
+
+mod timing;
+
+use atlas_world::{math::ivec2, AtlasMap};
+
+fn main() {
+    let open = AtlasMap::empty(200, 200);
+    let mut serpentine = AtlasMap::empty(200, 200);
+    for y in (1..199).step_by(2) {
+        for x in 0..200 {
+            serpentine.wall.set(x, y, 1);
+        }
+        serpentine.wall.set(if y % 4 == 1 { 199 } else { 0 }, y, 0);
+    }
+    let (from, to) = (ivec2(0, 0), ivec2(199, 199));
+
+    timing::bench("find_path open 200x200", || open.find_path(from, to).unwrap());
+    timing::bench("find_path serpentine 200x200", || serpentine.find_path(from, to).unwrap());
+}
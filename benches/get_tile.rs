@@ -0,0 +1,41 @@
+//! `get_tile` over every cell of the mansion atlas's wall layer, through the position index
+//! and through the linear scan `Tiles::find` falls back to until the index is built.
+
+mod timing;
+
+use atlas_world::{Atlas, AtlasCollection, AtlasInfo, Collection, Orientation, Tiles};
+
+const ORIENTATIONS: [Option<Orientation>; 5] =
+    [None, Some(Orientation::Front), Some(Orientation::Back), Some(Orientation::Left), Some(Orientation::Right)];
+
+fn main() {
+    let info: AtlasInfo = serde_json::from_str(include_str!("../mansion.json")).unwrap();
+    let indexed = info.layers["wall-1"].clone();
+    // the same layer deserialized on its own, which leaves it unindexed until `build_index`
+    let scanned: Tiles = serde_json::from_value(serde_json::to_value(&indexed).unwrap()).unwrap();
+
+    // every position in the view cone of the default 9-deep, 22-wide render, plus misses
+    let queries: Vec<(i32, i32, Option<Orientation>)> = (-11..=11)
+        .flat_map(|x| (-10..=0).flat_map(move |z| ORIENTATIONS.map(|orientation| (x, z, orientation))))
+        .collect();
+    println!("{} tiles, {} lookups per iteration", indexed.tiles().len(), queries.len());
+
+    timing::bench("Tiles::find indexed", || queries.iter().filter(|(x, z, o)| indexed.find(*x, *z, *o).is_some()).count());
+    timing::bench("Tiles::find scanned", || queries.iter().filter(|(x, z, o)| scanned.find(*x, *z, *o).is_some()).count());
+
+    let mut collection = AtlasCollection::new();
+    collection.insert("mansion".to_owned(), Atlas {
+        atlas_info: info,
+        #[cfg(feature = "render")]
+        textures: Vec::new(),
+        texture_sizes: Vec::new(),
+        scale: 1,
+        data_path: None,
+        image_path: None,
+        findings: Vec::new(),
+        diagonal_art: false,
+    });
+    timing::bench("Collection::get_tile", || {
+        queries.iter().filter(|(x, z, o)| collection.get_tile("mansion", "wall-1", *x, *z, *o).is_some()).count()
+    });
+}
@@ -0,0 +1,44 @@
+//! How long the steps of each job take against a 2ms budget on a 301x301 maze, next to the
+//! blocking call the job stands in for. A step should only go over by one unit of work.
+
+mod timing;
+
+use std::time::{Duration, Instant};
+
+use atlas_world::{
+    jobs::generate_map_incremental,
+    math::ivec2,
+    pipeline::{generate_map, GenerateParams},
+    AutotileTable, Job, JobStatus, ValidationReport,
+};
+
+const BUDGET: Duration = Duration::from_millis(2);
+
+// prints the number of steps `job` took and the longest of them
+fn steps<J: Job>(name: &str, mut job: J) {
+    let (mut steps, mut longest) = (0, Duration::ZERO);
+    loop {
+        let start = Instant::now();
+        let status = job.step(BUDGET);
+        longest = longest.max(start.elapsed());
+        steps += 1;
+        if status == JobStatus::Done {
+            break;
+        }
+    }
+    println!("{name:<40} {steps:>6} steps, longest {:>8.3} ms", longest.as_secs_f64() * 1e3);
+}
+
+fn main() {
+    let params = GenerateParams { width: 301, height: 301, seed: 5, wall: 2 };
+    let map = generate_map(&params);
+    let table = AutotileTable::default();
+
+    steps("GenerationJob", generate_map_incremental(&params));
+    steps("ValidationJob", map.validate_incremental(ivec2(1, 1), &[]));
+    steps("BakeJob", map.bake_autotiles_incremental(&table));
+
+    timing::bench("generate_map", || generate_map(&params));
+    timing::bench("ValidationReport::for_map", || ValidationReport::for_map(&map, ivec2(1, 1), &[]));
+    timing::bench("bake_autotiles", || map.clone().bake_autotiles(&table));
+}
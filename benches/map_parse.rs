@@ -0,0 +1,23 @@
+//! Parse time of a large generated map as json and in the binary format.
+
+mod timing;
+
+use atlas_world::{
+    generator::{rooms_and_corridors, GeneratorConfig},
+    AtlasMap, Grid,
+};
+
+fn main() {
+    // an overworld-sized map with six layers
+    let mut map = rooms_and_corridors(512, 512, &GeneratorConfig::default(), 1).map;
+    map.elevation = Grid::new(512, 512);
+    map.layers.insert("doors".to_owned(), Grid::new(512, 512));
+    let json = serde_json::to_vec(&map).unwrap();
+    let binary = map.to_binary();
+    println!("512x512 map: {} bytes of json, {} bytes binary", json.len(), binary.len());
+
+    timing::bench("json", || serde_json::from_slice::<AtlasMap>(&json).unwrap());
+    timing::bench("from_reader_auto json", || AtlasMap::from_reader_auto(json.as_slice()).unwrap());
+    timing::bench("from_binary", || AtlasMap::from_binary(&binary).unwrap());
+    timing::bench("from_reader_auto binary", || AtlasMap::from_reader_auto(binary.as_slice()).unwrap());
+}
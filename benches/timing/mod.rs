@@ -0,0 +1,18 @@
+//! A minimal timing loop for the benches, which run with `cargo bench` on stable.
+
+use std::time::{Duration, Instant};
+
+/// Runs `f` until about half a second has passed and prints the mean time per call.
+pub fn bench<T>(name: &str, mut f: impl FnMut() -> T) {
+    for _ in 0..3 {
+        std::hint::black_box(f());
+    }
+    let mut iterations = 0u64;
+    let start = Instant::now();
+    while start.elapsed() < Duration::from_millis(500) {
+        std::hint::black_box(f());
+        iterations += 1;
+    }
+    let per_iteration = start.elapsed().as_secs_f64() / iterations as f64;
+    println!("{name:<40} {:>12.3} us/iter ({iterations} iterations)", per_iteration * 1e6);
+}
@@ -0,0 +1,62 @@
+//! Paths for a crowd of entities computed on a worker thread from a passability snapshot.
+//! Each turn the main thread hands over a fresh snapshot and applies whatever paths came back
+//! from the previous one, so the frame never waits on the search.
+
+use std::{fs::File, io::BufReader, sync::mpsc, thread};
+
+use atlas_world::*;
+use atlas_world::math::*;
+
+struct PathRequest {
+    grid: PassabilityGrid,
+    goals: Vec<(usize, IVec2, IVec2)>,
+}
+
+fn main() {
+    let map: AtlasMap = serde_json::from_reader(BufReader::new(File::open("map.json").unwrap())).unwrap();
+    let rules = CollisionRules::default();
+
+    let (request_tx, request_rx) = mpsc::channel::<PathRequest>();
+    let (result_tx, result_rx) = mpsc::channel::<Vec<(usize, Option<Vec<IVec2>>)>>();
+
+    let worker = thread::spawn(move || {
+        for request in request_rx {
+            let paths = request.goals.iter()
+                .map(|(entity, from, to)| (*entity, request.grid.find_path(*from, *to)))
+                .collect();
+            if result_tx.send(paths).is_err() {
+                break;
+            }
+        }
+    });
+
+    // every open cell gets an entity that wants to reach the player's corner
+    let player = ivec2(1, 1);
+    let mut entities: Vec<IVec2> = (0..map.height as i32)
+        .flat_map(|y| (0..map.width as i32).map(move |x| ivec2(x, y)))
+        .filter(|pos| map.is_passable(*pos) && *pos != player)
+        .collect();
+
+    for turn in 0..entities.len() {
+        // last turn's results, if the worker is done with them
+        for (entity, path) in result_rx.try_iter().flatten() {
+            if let Some(next) = path.and_then(|path| path.get(1).copied()) {
+                entities[entity] = next;
+            }
+        }
+
+        let goals = entities.iter().enumerate().map(|(entity, pos)| (entity, *pos, player)).collect();
+        request_tx.send(PathRequest { grid: map.passability_snapshot(&rules), goals }).unwrap();
+
+        // the main thread is free to draw here
+        let arrived = entities.iter().filter(|pos| **pos == player).count();
+        println!("turn {turn}: {arrived}/{} entities reached the player", entities.len());
+        if arrived == entities.len() {
+            break;
+        }
+        thread::sleep(std::time::Duration::from_millis(16));
+    }
+
+    drop(request_tx);
+    worker.join().unwrap();
+}
@@ -0,0 +1,118 @@
+//! Moves and turns that play out over time. The player's position and facing change as soon
+//! as one starts, so the logic never waits on the animation; only new inputs do, queued one
+//! deep until the current animation finishes.
+
+use crate::math::*;
+use crate::{
+    events::WorldEvent, AtlasWorld, Direction, MapSource, MoveResult, Movement, MovementView, Player, Turn,
+};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PlayerAnimation {
+    Move { from: IVec2, to: IVec2 },
+    Turn { from: Direction, to: Direction },
+}
+
+/// A move or turn waiting for the animation in flight to finish.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PlayerInput {
+    Move(Direction),
+    Turn(Turn),
+}
+
+impl Player {
+    /// Steps in an absolute direction and animates the step. While another animation is in
+    /// flight the step is queued instead, replacing anything queued before, and `None` is
+    /// returned.
+    pub fn start_move<M: MapSource + ?Sized>(&mut self, map: &M, direction: Direction) -> Option<MoveResult> {
+        if self.animation.is_some() {
+            self.queued_input = Some(PlayerInput::Move(direction));
+            return None;
+        }
+        let from = ivec2(self.x, self.y);
+        let result = self.step(map, direction);
+        if let MoveResult::Moved(to) = result {
+            self.animation = Some(PlayerAnimation::Move { from, to });
+            self.animation_progress = 0.0;
+        }
+        Some(result)
+    }
+
+    /// Turns and animates the turn, or queues it like `start_move`. Returns whether it started.
+    pub fn start_turn(&mut self, turn: Turn) -> bool {
+        if self.animation.is_some() {
+            self.queued_input = Some(PlayerInput::Turn(turn));
+            return false;
+        }
+        let from = self.direction;
+        self.turn(turn);
+        self.animation = Some(PlayerAnimation::Turn { from, to: self.direction });
+        self.animation_progress = 0.0;
+        true
+    }
+
+    /// Advances the animation by `dt` seconds. Once it finishes the queued input, if any, is
+    /// handed back for the caller to start.
+    pub fn update(&mut self, dt: f32) -> Option<PlayerInput> {
+        if self.animation.is_some() {
+            self.animation_progress += dt / self.animation_duration.max(f32::EPSILON);
+            if self.animation_progress < 1.0 {
+                return None;
+            }
+            self.animation = None;
+            self.animation_progress = 0.0;
+        }
+        self.queued_input.take()
+    }
+
+    pub fn animation(&self) -> Option<PlayerAnimation> {
+        self.animation
+    }
+
+    /// how far along the animation in flight is, 0..1, `None` when idle
+    pub fn animation_progress(&self) -> Option<f32> {
+        self.animation.map(|_| self.animation_progress)
+    }
+
+    pub fn is_animating(&self) -> bool {
+        self.animation.is_some()
+    }
+}
+
+impl<M: MapSource> AtlasWorld<M> {
+    /// `move_player` animated through `Player::start_move`, `None` when the move was queued
+    pub fn start_move(&mut self, movement: Movement) -> Option<MoveResult> {
+        let direction = self.player.movement_direction(movement);
+        self.start_step(direction)
+    }
+
+    pub(crate) fn update_animation(&mut self, dt: f32) {
+        match self.player.update(dt) {
+            Some(PlayerInput::Move(direction)) => {
+                self.start_step(direction);
+            }
+            Some(PlayerInput::Turn(turn)) => {
+                self.start_turn(turn);
+            }
+            None => {}
+        }
+    }
+
+    fn start_step(&mut self, direction: Direction) -> Option<MoveResult> {
+        let view = MovementView { map: &self.map, open: &self.open_doors, entities: &self.entities, rules: &self.movement_rules, walls: &self.wall_properties, objects: &self.object_defs };
+        let from = ivec2(self.player.x, self.player.y);
+        let result = self.player.start_move(&view, direction)?;
+        self.report_move(from, result);
+        Some(result)
+    }
+
+    /// `turn_player` animated through `Player::start_turn`
+    pub fn start_turn(&mut self, turn: Turn) -> bool {
+        let from = self.player.direction;
+        let started = self.player.start_turn(turn);
+        if started {
+            self.push_event(WorldEvent::Turned { from, to: self.player.direction, about_face: turn == Turn::Around });
+        }
+        started
+    }
+}
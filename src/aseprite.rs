@@ -0,0 +1,269 @@
+//! Reading the json Aseprite exports with `--data` as atlas info, so atlases drawn in Aseprite
+//! load without a conversion step. Every frame's name places it:
+//! `<layer>[#<orientation>]#x<x>z<z>[@<frame>]`, like `wall-3#front#x1z-2`. Frames that share
+//! everything before the `@` are one animated tile, played in `<frame>` order at the duration
+//! of the first. Their numbers can't skip any, and they all have to be the size of the first.
+//!
+//! Frames exported trimmed from a viewport-sized canvas keep where they were drawn as their
+//! screen rect; the rest are placed by the default `Projection`.
+
+use std::{fmt, io::Read};
+
+use ahash::AHashMap;
+use serde::Deserialize;
+
+use crate::{Animation, AtlasInfo, Coords, Orientation, Projection, Tile, Tiles, DEFAULT_FRAME_DURATION_MS};
+
+#[derive(Debug)]
+pub enum AsepriteError {
+    Json(serde_json::Error),
+    /// a frame name that doesn't follow `<layer>[#<orientation>]#x<x>z<z>[@<frame>]`
+    BadName { name: String, reason: String },
+    /// a frame Aseprite packed rotated, which atlases can't draw
+    Rotated { name: String },
+    /// an animated tile numbered with a gap, `frame` being the first number missing
+    MissingFrame { name: String, frame: u32 },
+    /// a frame of an animated tile that isn't the size of the tile's first frame
+    FrameSizeMismatch { name: String, expected: (i32, i32), found: (i32, i32) },
+}
+
+impl fmt::Display for AsepriteError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AsepriteError::Json(err) => write!(f, "not an Aseprite export at line {} column {}: {err}", err.line(), err.column()),
+            AsepriteError::BadName { name, reason } => {
+                write!(f, "frame '{name}' isn't named like 'wall-3#front#x1z-2': {reason}")
+            }
+            AsepriteError::Rotated { name } => write!(f, "frame '{name}' is packed rotated, export without rotation"),
+            AsepriteError::MissingFrame { name, frame } => write!(f, "the animation of '{name}' has no frame {frame}"),
+            AsepriteError::FrameSizeMismatch { name, expected: (w, h), found: (found_w, found_h) } => {
+                write!(f, "frame '{name}' is {found_w}x{found_h} but the first frame of its animation is {w}x{h}")
+            }
+        }
+    }
+}
+
+impl std::error::Error for AsepriteError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            AsepriteError::Json(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Deserialize)]
+struct Export {
+    frames: Frames,
+}
+
+// Aseprite writes frames as a hash keyed by name or, with `--format json-array`, an array
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum Frames {
+    Array(Vec<Frame>),
+    Hash(AHashMap<String, Frame>),
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Frame {
+    #[serde(default)]
+    filename: String,
+    frame: Coords,
+    #[serde(default)]
+    rotated: bool,
+    #[serde(default)]
+    trimmed: bool,
+    sprite_source_size: Option<Coords>,
+    duration: Option<u32>,
+}
+
+// where a frame name puts the frame
+#[derive(Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+struct Placement {
+    layer: String,
+    x: i32,
+    z: i32,
+    orientation: Option<Orientation>,
+}
+
+fn parse_name(name: &str) -> Result<(Placement, u32), AsepriteError> {
+    let bad = |reason: &str| AsepriteError::BadName { name: name.to_owned(), reason: reason.to_owned() };
+
+    let (rest, frame) = match name.rsplit_once('@') {
+        Some((rest, frame)) => (rest, frame.parse().map_err(|_| bad(&format!("'{frame}' after '@' is not a frame number")))?),
+        None => (name, 0),
+    };
+    let parts: Vec<&str> = rest.split('#').collect();
+    let (layer, orientation, position) = match parts[..] {
+        [layer, position] => (layer, None, position),
+        [layer, orientation, position] => {
+            let orientation = Orientation::from_name(orientation)
+                .ok_or_else(|| bad(&format!("unknown orientation '{orientation}', expected front, back, left or right")))?;
+            (layer, Some(orientation), position)
+        }
+        _ => return Err(bad("expected two or three '#'-separated parts")),
+    };
+    if layer.is_empty() {
+        return Err(bad("the layer is empty"));
+    }
+    let (x, z) = position.strip_prefix('x')
+        .and_then(|position| position.split_once('z'))
+        .and_then(|(x, z)| Some((x.parse().ok()?, z.parse().ok()?)))
+        .ok_or_else(|| bad(&format!("'{position}' is not a position like 'x1z-2'")))?;
+    Ok((Placement { layer: layer.to_owned(), x, z, orientation }, frame))
+}
+
+impl AtlasInfo {
+    /// Reads the json Aseprite exports with `--data`, in either its hash or array format, and
+    /// places each frame by its name as described in the `aseprite` module. Every tile is on
+    /// the first page.
+    pub fn from_aseprite_json<R: Read>(reader: R) -> Result<AtlasInfo, AsepriteError> {
+        let export: Export = serde_json::from_reader(reader).map_err(AsepriteError::Json)?;
+        let frames = match export.frames {
+            Frames::Array(frames) => frames,
+            Frames::Hash(frames) => frames.into_iter().map(|(filename, frame)| Frame { filename, ..frame }).collect(),
+        };
+
+        let mut placed: AHashMap<Placement, Vec<(u32, Frame)>> = AHashMap::new();
+        for frame in frames {
+            if frame.rotated {
+                return Err(AsepriteError::Rotated { name: frame.filename });
+            }
+            let (placement, number) = parse_name(&frame.filename)?;
+            placed.entry(placement).or_default().push((number, frame));
+        }
+
+        let projection = Projection::default();
+        let mut placed: Vec<_> = placed.into_iter().collect();
+        placed.sort_by(|(a, _), (b, _)| a.cmp(b));
+        let mut layers: AHashMap<String, Vec<Tile>> = AHashMap::new();
+        for (placement, mut frames) in placed {
+            frames.sort_by_key(|(number, _)| *number);
+            let (_, first) = &frames[0];
+            for pair in frames.windows(2) {
+                let ((previous, _), (number, frame)) = (&pair[0], &pair[1]);
+                if number == previous {
+                    return Err(AsepriteError::BadName { name: frame.filename.clone(), reason: format!("another frame is frame {number} of the same tile") });
+                }
+                if *number != previous + 1 {
+                    return Err(AsepriteError::MissingFrame { name: first.filename.clone(), frame: previous + 1 });
+                }
+            }
+            let size = |frame: &Frame| (frame.frame.w, frame.frame.h);
+            if let Some((_, other)) = frames.iter().find(|(_, frame)| size(frame) != size(first)) {
+                return Err(AsepriteError::FrameSizeMismatch { name: other.filename.clone(), expected: size(first), found: size(other) });
+            }
+            let screen_coords = match (first.trimmed, first.sprite_source_size) {
+                (true, Some(drawn)) => drawn,
+                _ => projection.tile_rect(Tiles::type_for_layer(&placement.layer), placement.x, placement.z, placement.orientation),
+            };
+            let mut tile = Tile::new(first.frame, screen_coords, placement.x, placement.z, placement.orientation);
+            if frames.len() > 1 {
+                tile.animation = Some(Animation {
+                    frames: frames.iter().map(|(_, frame)| frame.frame).collect(),
+                    frame_duration_ms: first.duration.unwrap_or(DEFAULT_FRAME_DURATION_MS),
+                });
+            }
+            layers.entry(placement.layer).or_default().push(tile);
+        }
+
+        let mut info = AtlasInfo::new();
+        for (layer_id, tiles) in layers {
+            // placements are unique, so there are no duplicates for `push_tile` to catch
+            info.add_layer(&layer_id, Tiles::from_tiles(0, Tiles::type_for_layer(&layer_id), tiles));
+        }
+        Ok(info)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::manifest_path;
+
+    fn import(name: &str) -> Result<AtlasInfo, AsepriteError> {
+        let file = std::fs::File::open(manifest_path(&format!("fixtures/aseprite/{name}.json"))).unwrap();
+        AtlasInfo::from_aseprite_json(file)
+    }
+
+    fn rejected(name: &str) -> AsepriteError {
+        import(name).err().unwrap_or_else(|| panic!("{name} was accepted"))
+    }
+
+    fn coords(x: i32, y: i32, w: i32, h: i32) -> Coords {
+        Coords { x, y, w, h }
+    }
+
+    #[test]
+    fn frame_names_place_their_tiles() {
+        let info = import("hash").unwrap();
+        let layers: Vec<&String> = info.layers_sorted().map(|(layer_id, _)| layer_id).collect();
+        assert_eq!(layers, ["floor-1", "torch", "wall-3"]);
+
+        let floor = &info.layers["floor-1"];
+        assert_eq!(floor.r#type, Tiles::FLOOR_TYPE);
+        let tile = floor.find(0, -1, None).unwrap();
+        assert_eq!(tile.atlas_coords, coords(0, 0, 32, 16));
+        assert_eq!(tile.screen_coords, Projection::default().tile_rect(Tiles::FLOOR_TYPE, 0, -1, None));
+
+        // trimmed frames keep where they were drawn
+        let tile = info.layers["wall-3"].find(1, -2, Some(Orientation::Front)).unwrap();
+        assert_eq!((tile.atlas_coords, tile.screen_coords), (coords(32, 0, 24, 40), coords(200, 120, 24, 40)));
+        assert!(info.layers["wall-3"].find(1, -2, Some(Orientation::Back)).is_none());
+
+        let torch = &info.layers["torch"];
+        assert_eq!(torch.tiles().len(), 1);
+        let tile = torch.find(-1, 0, Some(Orientation::Left)).unwrap();
+        let animation = tile.animation.as_ref().unwrap();
+        assert_eq!(animation.frames, [coords(56, 0, 8, 16), coords(64, 0, 8, 16), coords(72, 0, 8, 16)]);
+        assert_eq!(animation.frame_duration_ms, 150);
+        assert_eq!(tile.atlas_coords, animation.frames[0]);
+
+        // the array format, with the torch frames listed out of order, reads the same
+        let array = import("array").unwrap();
+        assert_eq!(serde_json::to_value(&array).unwrap(), serde_json::to_value(&info).unwrap());
+    }
+
+    #[test]
+    fn exports_that_dont_place_their_frames_are_rejected() {
+        let err = rejected("bad_name");
+        assert!(matches!(&err, AsepriteError::BadName { name, reason } if name == "torch#up#x-1z0" && reason.contains("'up'")), "{err}");
+        assert!(matches!(rejected("rotated"), AsepriteError::Rotated { name } if name == "torch#left#x-1z0"));
+        assert!(matches!(rejected("no_frames"), AsepriteError::Json(_)));
+
+        let err = rejected("missing_frame");
+        assert!(matches!(&err, AsepriteError::MissingFrame { name, frame: 1 } if name == "torch#left#x-1z0@0"), "{err}");
+        assert_eq!(err.to_string(), "the animation of 'torch#left#x-1z0@0' has no frame 1");
+
+        let err = rejected("mismatched_sizes");
+        assert!(matches!(&err, AsepriteError::FrameSizeMismatch { name, expected: (8, 16), found: (8, 20) } if name == "torch#left#x-1z0@1"), "{err}");
+    }
+
+    #[test]
+    fn malformed_names_say_what_is_wrong() {
+        for (name, reason) in [
+            ("wall-3", "two or three"),
+            ("wall-3#front#x1z-2#extra", "two or three"),
+            ("#x1z-2", "layer is empty"),
+            ("wall-3#x1y-2", "'x1y-2'"),
+            ("wall-3#xaz0", "'xaz0'"),
+            ("wall-3#x1z-2@next", "'next'"),
+        ] {
+            match parse_name(name) {
+                Err(AsepriteError::BadName { reason: found, .. }) => assert!(found.contains(reason), "{name}: {found}"),
+                _ => panic!("{name} was accepted"),
+            }
+        }
+        // a repeated frame number is a name clash
+        let json = r#"{"frames": [
+            {"filename": "torch#x0z0@0", "frame": {"x": 0, "y": 0, "w": 8, "h": 8}},
+            {"filename": "torch#x0z0", "frame": {"x": 8, "y": 0, "w": 8, "h": 8}}
+        ]}"#;
+        assert!(matches!(AtlasInfo::from_aseprite_json(json.as_bytes()), Err(AsepriteError::BadName { .. })));
+
+        let (placement, frame) = parse_name("wall-3#right#x-4z-10@7").ok().unwrap();
+        assert_eq!((placement.layer.as_str(), placement.x, placement.z, placement.orientation, frame), ("wall-3", -4, -10, Some(Orientation::Right), 7));
+    }
+}
@@ -0,0 +1,131 @@
+//! Putting an `AtlasInfo` together in code, for editors and generators that write atlases out
+//! with `AtlasInfo::save`.
+
+use std::fmt;
+
+use crate::{AtlasInfo, Coords, DuplicateTile, Orientation, Projection, Tile, Tiles};
+
+#[derive(Debug)]
+pub enum AtlasBuildError {
+    /// a tile added before any `layer` call
+    NoLayer,
+    /// two tiles at the same (x, z, orientation) in one layer
+    Duplicate { layer: String, source: DuplicateTile },
+}
+
+impl fmt::Display for AtlasBuildError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AtlasBuildError::NoLayer => f.write_str("a tile was added before any layer"),
+            AtlasBuildError::Duplicate { layer, source } => write!(f, "layer '{layer}': {source}"),
+        }
+    }
+}
+
+impl std::error::Error for AtlasBuildError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            AtlasBuildError::NoLayer => None,
+            AtlasBuildError::Duplicate { source, .. } => Some(source),
+        }
+    }
+}
+
+/// Builds an `AtlasInfo` a layer at a time. Tiles go to the layer named last; a new layer has
+/// mode 0, so its tiles are drawn at their own screen rects, and `Tiles::type_for_layer` as its
+/// type. The first mistake is kept and returned by `build`.
+///
+/// ```
+/// use atlas_world::*;
+///
+/// let rect = Coords { x: 0, y: 0, w: 32, h: 32 };
+/// let info = AtlasInfoBuilder::new()
+///     .layer("wall-1")
+///     .tile(rect, rect, 1, -2, Some(Orientation::Front))
+///     .tile(rect, rect, 1, -2, Some(Orientation::Left))
+///     .layer("floor-1")
+///     .tile(rect, rect, 0, -1, None)
+///     .build()
+///     .unwrap();
+/// assert_eq!(info.layers["floor-1"].r#type, Tiles::FLOOR_TYPE);
+///
+/// let duplicate = AtlasInfoBuilder::new()
+///     .layer("wall-1")
+///     .tile(rect, rect, 0, -1, None)
+///     .tile(rect, rect, 0, -1, None)
+///     .build();
+/// assert!(duplicate.is_err());
+/// ```
+#[derive(Default)]
+pub struct AtlasInfoBuilder {
+    info: AtlasInfo,
+    current: Option<String>,
+    error: Option<AtlasBuildError>,
+}
+
+impl AtlasInfoBuilder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// adds tiles to `layer_id` from here on, creating it when it's new
+    pub fn layer(mut self, layer_id: &str) -> Self {
+        if !self.info.layers.contains_key(layer_id) {
+            self.info.add_layer(layer_id, Tiles::new(0, Tiles::type_for_layer(layer_id)));
+        }
+        self.current = Some(layer_id.to_owned());
+        self
+    }
+
+    /// sets the current layer's `mode`
+    pub fn mode(mut self, mode: i32) -> Self {
+        if let Some(tiles) = self.current_layer() {
+            tiles.mode = mode;
+        }
+        self
+    }
+
+    /// sets the current layer's `type`
+    pub fn tile_type(mut self, tile_type: i32) -> Self {
+        if let Some(tiles) = self.current_layer() {
+            tiles.r#type = tile_type;
+        }
+        self
+    }
+
+    /// the projection computed layers are placed with
+    pub fn projection(mut self, projection: Projection) -> Self {
+        self.info.projection = Some(projection);
+        self
+    }
+
+    pub fn tile(self, atlas_coords: Coords, screen_coords: Coords, x: i32, z: i32, orientation: Option<Orientation>) -> Self {
+        self.push(Tile::new(atlas_coords, screen_coords, x, z, orientation))
+    }
+
+    /// adds a tile set up beforehand, for animations, pages and variants
+    pub fn push(mut self, tile: Tile) -> Self {
+        if self.error.is_some() {
+            return self;
+        }
+        let Some(layer) = self.current.clone() else {
+            self.error = Some(AtlasBuildError::NoLayer);
+            return self;
+        };
+        if let Some(Err(source)) = self.current_layer().map(|tiles| tiles.push_tile(tile)) {
+            self.error = Some(AtlasBuildError::Duplicate { layer, source });
+        }
+        self
+    }
+
+    pub fn build(self) -> Result<AtlasInfo, AtlasBuildError> {
+        match self.error {
+            Some(err) => Err(err),
+            None => Ok(self.info),
+        }
+    }
+
+    fn current_layer(&mut self) -> Option<&mut Tiles> {
+        self.info.layers.get_mut(self.current.as_deref()?)
+    }
+}
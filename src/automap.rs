@@ -0,0 +1,326 @@
+//! The map of explored cells shown in the map view. Open cells and walls are revealed
+//! separately, so a wall seen down a corridor shows as an edge before the floor beside it is.
+
+#[cfg(not(feature = "render"))]
+use crate::math::*;
+#[cfg(feature = "render")]
+use macroquad::prelude::*;
+use serde::{Deserialize, Serialize};
+
+#[cfg(feature = "render")]
+use crate::{AtlasWorld, Player};
+use crate::{window_range, Direction, IRect, MapSource};
+
+/// What `AtlasWorld::update` reveals as the player moves.
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub enum RevealMode {
+    /// only the cells the player stands on
+    #[default]
+    Visited,
+    /// every cell the player can see within `radius`, walls included
+    LineOfSight { radius: i32 },
+    /// the whole map is shown, without marking it revealed, so switching back shows what was
+    /// revealed before
+    Full,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct AutoMap {
+    pub width: usize,
+    pub height: usize,
+    explored: Vec<Vec<bool>>,
+    // wall cells seen, empty in automaps saved before walls were tracked
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    seen_walls: Vec<Vec<bool>>,
+    #[serde(default)]
+    pub reveal_mode: RevealMode,
+    /// Marks the cells of teleporters the player goes through explored. Off, only where they
+    /// come out is, so the map doesn't give away that they were moved.
+    #[serde(default)]
+    pub mark_teleporters: bool,
+}
+
+impl AutoMap {
+    pub fn new(width: usize, height: usize) -> Self {
+        Self {
+            width,
+            height,
+            explored: vec![vec![false; width]; height],
+            seen_walls: vec![vec![false; width]; height],
+            reveal_mode: RevealMode::default(),
+            mark_teleporters: false,
+        }
+    }
+
+    /// marks a cell explored, returning whether it wasn't before
+    pub fn visit(&mut self, pos: IVec2) -> bool {
+        mark(&mut self.explored, pos)
+    }
+
+    /// marks a wall cell seen, returning whether it wasn't before
+    pub fn see_wall(&mut self, pos: IVec2) -> bool {
+        if self.seen_walls.is_empty() {
+            self.seen_walls = vec![vec![false; self.width]; self.height];
+        }
+        mark(&mut self.seen_walls, pos)
+    }
+
+    /// Reveals `cells` of `map`: walls are marked seen and everything else explored. Returns
+    /// how many weren't revealed before.
+    pub fn reveal<M: MapSource + ?Sized>(&mut self, map: &M, cells: impl IntoIterator<Item = IVec2>) -> usize {
+        cells.into_iter()
+            .filter(|pos| match map.get_wall(*pos) {
+                None => false,
+                Some(0) => self.visit(*pos),
+                Some(_) => self.see_wall(*pos),
+            })
+            .count()
+    }
+
+    /// whether the open cell at `pos` is explored, always true in `RevealMode::Full`
+    pub fn is_explored(&self, pos: IVec2) -> bool {
+        self.reveal_mode == RevealMode::Full || is_marked(&self.explored, pos)
+    }
+
+    /// whether the wall at `pos` was seen, always true in `RevealMode::Full`
+    pub fn is_wall_seen(&self, pos: IVec2) -> bool {
+        self.reveal_mode == RevealMode::Full || is_marked(&self.seen_walls, pos)
+    }
+
+    /// explores every cell, for debugging
+    pub fn reveal_all(&mut self) {
+        self.seen_walls = vec![vec![true; self.width]; self.height];
+        self.explored.iter_mut().flatten().for_each(|explored| *explored = true);
+    }
+
+    /// Where the map cells go inside `rect` for a player at `player` facing `direction`: square
+    /// cells as large as fit the cells `options` asks for, centered in `rect`. A window that
+    /// would run past the map's edge is slid back inside it.
+    pub fn layout(&self, player: IVec2, direction: Direction, rect: Rect, options: &MinimapOptions) -> MinimapLayout {
+        let (width, height) = (self.width as i32, self.height as i32);
+        let quarter_turns = match options.rotate_with_player {
+            true => Direction::CARDINALS.iter().position(|cardinal| *cardinal == direction.nearest_cardinal()).unwrap_or(0) as u8,
+            false => 0,
+        };
+        let (columns, rows) = match options.cells_visible {
+            // columns and rows are across the screen, which is down the map once it's turned
+            MinimapExtent::Cells(columns, rows) if quarter_turns % 2 == 1 => (rows.min(i32::MAX as u32) as i32, columns.min(i32::MAX as u32) as i32),
+            MinimapExtent::Cells(columns, rows) => (columns.min(i32::MAX as u32) as i32, rows.min(i32::MAX as u32) as i32),
+            MinimapExtent::FitWholeMap => (width, height),
+        };
+        let center = if options.follow_player { player } else { ivec2(width / 2, height / 2) };
+        let x = window_range(center.x, columns, width);
+        let y = window_range(center.y, rows, height);
+        let region = IRect::new(x.start, y.start, x.len() as i32, y.len() as i32);
+
+        let (screen_columns, screen_rows) = if quarter_turns % 2 == 1 { (region.h, region.w) } else { (region.w, region.h) };
+        let cell_size = (rect.w / screen_columns.max(1) as f32).min(rect.h / screen_rows.max(1) as f32).max(0.0);
+        let size = vec2(cell_size * screen_columns as f32, cell_size * screen_rows as f32);
+        let dest = Rect::new(rect.x + (rect.w - size.x) / 2.0, rect.y + (rect.h - size.y) / 2.0, size.x, size.y);
+        MinimapLayout { region, dest, cell_size, quarter_turns }
+    }
+
+    /// `draw_with` with every wall edge `options.edge_color`
+    #[cfg(feature = "render")]
+    pub fn draw<M: MapSource + ?Sized>(&self, map: &M, player: &Player, rect: Rect, options: &MinimapOptions) -> MinimapLayout {
+        self.draw_with(map, player, rect, options, |_| options.edge_color)
+    }
+
+    /// Draws the explored cells around the player into `rect`: a square per cell, an edge
+    /// toward each neighboring wall colored by its value, a dot for objects, and the player as a
+    /// triangle pointing the way they face. Seen walls get their edges even where the cell beside
+    /// them isn't explored. Returns where the cells went, for drawing over them.
+    #[cfg(feature = "render")]
+    pub fn draw_with<M: MapSource + ?Sized>(
+        &self,
+        map: &M,
+        player: &Player,
+        rect: Rect,
+        options: &MinimapOptions,
+        wall_color: impl Fn(u8) -> Color,
+    ) -> MinimapLayout {
+        let player_pos = ivec2(player.x, player.y);
+        let layout = self.layout(player_pos, player.direction, rect, options);
+        let region = layout.region;
+        let half = layout.cell_size / 2.0;
+        let thickness = layout.cell_size / 5.0;
+
+        for y in region.y..region.y + region.h {
+            for x in region.x..region.x + region.w {
+                let pos = ivec2(x, y);
+                let cell = layout.cell_rect(pos);
+                let explored = self.is_explored(pos);
+                if explored {
+                    draw_rectangle(cell.x, cell.y, cell.w, cell.h, options.cell_color);
+                }
+
+                // walls are drawn as edges of the open cells beside them, and the map's own edge
+                // like a wall
+                if map.get_wall(pos) == Some(0) {
+                    for offset in [ivec2(-1, 0), ivec2(1, 0), ivec2(0, -1), ivec2(0, 1)] {
+                        let color = match map.get_wall(pos + offset) {
+                            None if explored => Some(options.edge_color),
+                            Some(value) if value != 0 && (explored || self.is_wall_seen(pos + offset)) => Some(wall_color(value)),
+                            _ => None,
+                        };
+                        if let Some(color) = color {
+                            let side = layout.screen_vector(offset.as_vec2());
+                            let middle = cell.center() + side * half;
+                            let along = vec2(-side.y, side.x) * half;
+                            draw_line(middle.x - along.x, middle.y - along.y, middle.x + along.x, middle.y + along.y, thickness, color);
+                        }
+                    }
+                }
+
+                if explored && map.get("object", pos).unwrap_or(0) != 0 {
+                    draw_circle(cell.center().x, cell.center().y, layout.cell_size / 4.0, options.object_color);
+                }
+            }
+        }
+
+        if layout.contains(player_pos) {
+            let center = layout.cell_rect(player_pos).center();
+            let forward = layout.screen_vector(player.direction.to_vec().as_vec2()).normalize_or_zero() * half * 0.8;
+            let side = vec2(-forward.y, forward.x) * 0.7;
+            draw_triangle(center + forward, center - forward * 0.7 + side, center - forward * 0.7 - side, options.player_color);
+        }
+
+        layout
+    }
+}
+
+/// How much of the map the map view shows.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum MinimapExtent {
+    /// a window this many (columns, rows) of cells across
+    Cells(u32, u32),
+    /// all of it, as large as fits
+    FitWholeMap,
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct MinimapOptions {
+    pub cells_visible: MinimapExtent,
+    /// keep the player in the middle of the window, otherwise the middle of the map is
+    pub follow_player: bool,
+    /// turn the map so the way the player faces is up, by the nearest quarter turn
+    pub rotate_with_player: bool,
+    pub cell_color: Color,
+    /// the map's own edge, and walls unless the caller colors them
+    pub edge_color: Color,
+    pub object_color: Color,
+    pub player_color: Color,
+}
+
+impl Default for MinimapOptions {
+    fn default() -> Self {
+        Self {
+            cells_visible: MinimapExtent::Cells(50, 40),
+            follow_player: true,
+            rotate_with_player: false,
+            cell_color: GRAY,
+            edge_color: WHITE,
+            object_color: WHITE,
+            player_color: GREEN,
+        }
+    }
+}
+
+/// Where `AutoMap::draw` put the map cells: `region` of the map, turned `quarter_turns`
+/// counterclockwise, in square cells of `cell_size` filling `dest`.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct MinimapLayout {
+    pub region: IRect,
+    pub dest: Rect,
+    pub cell_size: f32,
+    pub quarter_turns: u8,
+}
+
+impl MinimapLayout {
+    /// `region` drawn into `dest` unturned, the way `draw_map_region` lays it out
+    pub fn new(region: IRect, dest: Rect) -> Self {
+        let cell_size = (dest.w / region.w.max(1) as f32).min(dest.h / region.h.max(1) as f32);
+        Self { region, dest, cell_size, quarter_turns: 0 }
+    }
+
+    pub fn contains(&self, pos: IVec2) -> bool {
+        let region = self.region;
+        pos.x >= region.x && pos.y >= region.y && pos.x < region.x.saturating_add(region.w) && pos.y < region.y.saturating_add(region.h)
+    }
+
+    /// the screen rect of map cell `pos`, which may lie outside the region
+    pub fn cell_rect(&self, pos: IVec2) -> Rect {
+        let local = pos - ivec2(self.region.x, self.region.y);
+        let screen = turn(local, ivec2(self.region.w, self.region.h), self.quarter_turns);
+        Rect::new(
+            self.dest.x + screen.x as f32 * self.cell_size,
+            self.dest.y + screen.y as f32 * self.cell_size,
+            self.cell_size,
+            self.cell_size,
+        )
+    }
+
+    /// the map cell under screen point `point`, if it's one of the region's
+    pub fn cell_at(&self, point: Vec2) -> Option<IVec2> {
+        if !self.dest.contains(point) || self.cell_size <= 0.0 {
+            return None;
+        }
+        let screen = ((point - self.dest.point()) / self.cell_size).floor().as_ivec2();
+        let size = ivec2(self.region.w, self.region.h);
+        let turned_size = if self.quarter_turns % 2 == 1 { ivec2(size.y, size.x) } else { size };
+        let pos = turn(screen, turned_size, (4 - self.quarter_turns % 4) % 4) + ivec2(self.region.x, self.region.y);
+        self.contains(pos).then_some(pos)
+    }
+
+    /// a direction on the map as it points on screen
+    pub fn screen_vector(&self, map: Vec2) -> Vec2 {
+        (0..self.quarter_turns % 4).fold(map, |v, _| vec2(v.y, -v.x))
+    }
+}
+
+// cell `local` of a `size` grid after it's turned counterclockwise `turns` times
+fn turn(mut local: IVec2, mut size: IVec2, turns: u8) -> IVec2 {
+    for _ in 0..turns % 4 {
+        local = ivec2(local.y, size.x - 1 - local.x);
+        size = ivec2(size.y, size.x);
+    }
+    local
+}
+
+#[cfg(feature = "render")]
+impl<M: MapSource> AtlasWorld<M> {
+    /// Draws `automap` into `rect` with the wall materials' colors, then the secrets and exits found so far,
+    /// naming the level one leads to when the mouse is over it. Does nothing without an automap.
+    pub fn draw_automap(&self, rect: Rect, options: &MinimapOptions) {
+        let Some(automap) = &self.automap else {
+            return;
+        };
+        let layout = automap.draw_with(&self.map, &self.player, rect, options, |value| {
+            self.wall_materials.minimap_color(value).unwrap_or(options.edge_color)
+        });
+
+        let discovered = |pos: IVec2| automap.is_explored(pos);
+        self.draw_secrets(&layout, SKYBLUE, discovered);
+        self.draw_exits(&layout, YELLOW, discovered);
+
+        let mouse = Vec2::from(mouse_position());
+        if let Some(level) = layout.cell_at(mouse).and_then(|hovered| self.exit_destination(hovered, discovered)) {
+            let text = self.resolve(level);
+            draw_text(&text, mouse.x + 12.0, mouse.y, 20.0, WHITE);
+        }
+    }
+}
+
+fn mark(cells: &mut [Vec<bool>], pos: IVec2) -> bool {
+    if pos.x < 0 || pos.y < 0 {
+        return false;
+    }
+    match cells.get_mut(pos.y as usize).and_then(|row| row.get_mut(pos.x as usize)) {
+        Some(marked) => !std::mem::replace(marked, true),
+        None => false,
+    }
+}
+
+fn is_marked(cells: &[Vec<bool>], pos: IVec2) -> bool {
+    pos.x >= 0 && pos.y >= 0 && cells.get(pos.y as usize).and_then(|row| row.get(pos.x as usize)).copied().unwrap_or(false)
+}
@@ -0,0 +1,213 @@
+//! Picking wall variant layers from the walls around a cell. The 4-neighborhood is packed into
+//! a mask with one bit per side: north 1, east 2, south 4, west 8. A neighbor counts when it
+//! has any nonzero wall value; cells outside the map don't count.
+
+use std::borrow::Cow;
+
+use crate::math::*;
+use crate::{
+    coords::{GRID_EAST, GRID_NORTH, GRID_SOUTH, GRID_WEST},
+    AtlasMap, AtlasWorld, Grid, LayerId, MapSource,
+};
+
+const NEIGHBORS: [IVec2; 4] = [GRID_NORTH, GRID_EAST, GRID_SOUTH, GRID_WEST];
+
+pub fn wall_mask<M: MapSource + ?Sized>(map: &M, pos: IVec2) -> u8 {
+    NEIGHBORS.iter().enumerate()
+        .filter(|(_, v)| map.get_wall(crate::coords::offset(pos, **v, 1)).is_some_and(|value| value != 0))
+        .fold(0, |mask, (bit, _)| mask | (1 << bit))
+}
+
+/// Mask -> layer suffix. A wall value `n` with suffix `s` draws from `wall-{n}-{s}` when the
+/// atlas has that layer and from plain `wall-{n}` otherwise.
+#[derive(Clone, Debug, PartialEq)]
+pub struct AutotileTable {
+    pub suffixes: [Option<String>; 16],
+}
+
+impl Default for AutotileTable {
+    /// `pillar` alone, `end` with one neighbor, `straight` and `corner` with two, `tee` with
+    /// three and `cross` when surrounded
+    fn default() -> Self {
+        let suffixes = std::array::from_fn(|mask: usize| {
+            let suffix = match mask {
+                0 => "pillar",
+                1 | 2 | 4 | 8 => "end",
+                5 | 10 => "straight",
+                3 | 6 | 9 | 12 => "corner",
+                15 => "cross",
+                _ => "tee",
+            };
+            Some(suffix.to_owned())
+        });
+        Self { suffixes }
+    }
+}
+
+impl AutotileTable {
+    pub fn suffix(&self, mask: u8) -> Option<&str> {
+        self.suffixes.get(mask as usize)?.as_deref()
+    }
+}
+
+impl AtlasMap {
+    /// Writes every wall cell's mask + 1 into the `autotile` layer (0 for open cells and masks
+    /// without a suffix) so rendering doesn't look at neighbors each frame. Wall edits made
+    /// afterwards aren't reflected until this is called again.
+    pub fn bake_autotiles(&mut self, table: &AutotileTable) {
+        let rows = (0..self.height).map(|y| self.baked_autotile_row(table, y)).collect();
+        self.autotile = Grid::from_rows(rows).expect("baked rows are all as wide as the map");
+    }
+
+    pub(crate) fn baked_autotile_row(&self, table: &AutotileTable, y: usize) -> Vec<u8> {
+        (0..self.width)
+            .map(|x| {
+                let pos = ivec2(x as i32, y as i32);
+                if self.get_wall(pos).is_none_or(|value| value == 0) {
+                    return 0;
+                }
+                let mask = wall_mask(self, pos);
+                if table.suffix(mask).is_some() { mask + 1 } else { 0 }
+            })
+            .collect()
+    }
+}
+
+impl<M: MapSource> AtlasWorld<M> {
+    /// the wall layer a cell is drawn from, taking `render_config.autotile` into account
+    pub fn wall_layer_id(&self, pos: IVec2, wall_value: u8) -> Cow<'_, str> {
+        match self.wall_layer(pos, wall_value) {
+            Cow::Borrowed(layer_id) => Cow::Borrowed(layer_id.id()),
+            Cow::Owned(layer_id) => Cow::Owned(layer_id.id().to_owned()),
+        }
+    }
+
+    // `wall_layer_id` along with its `@diag` form, borrowed from the binding unless autotiled
+    pub(crate) fn wall_layer(&self, pos: IVec2, wall_value: u8) -> Cow<'_, LayerId> {
+        let binding = self.render_bindings.get("wall");
+        let plain = binding.map_or_else(|| Cow::Owned(LayerId::new(&format!("wall-{wall_value}"))), |binding| Cow::Borrowed(binding.layer(wall_value)));
+        let Some(table) = &self.render_config.autotile else {
+            return plain;
+        };
+
+        // a bake from before the last map edit is ignored in favor of the live neighbors
+        let baked = self.map.get("autotile", pos).filter(|_| self.autotile_revision == Some(self.map_revision));
+        let mask = match baked {
+            Some(0) => return plain,
+            Some(baked) => baked - 1,
+            None => wall_mask(&self.map, pos),
+        };
+
+        match table.suffix(mask) {
+            Some(suffix) => {
+                let variant = format!("{}-{suffix}", plain.id());
+                let has_variant = binding.and_then(|binding| self.collection.get(&binding.atlas_id))
+                    .is_some_and(|atlas| atlas.atlas_info.layers.contains_key(&variant));
+                if has_variant { Cow::Owned(LayerId::new(&variant)) } else { plain }
+            }
+            None => plain,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::atlas;
+    use crate::{AtlasCollection, Direction, Player};
+
+    // every mask with the suffix the default table gives it
+    const EXPECTED: [(u8, &str); 16] = [
+        (0, "pillar"),
+        (1, "end"),
+        (2, "end"),
+        (3, "corner"),
+        (4, "end"),
+        (5, "straight"),
+        (6, "corner"),
+        (7, "tee"),
+        (8, "end"),
+        (9, "corner"),
+        (10, "straight"),
+        (11, "tee"),
+        (12, "corner"),
+        (13, "tee"),
+        (14, "tee"),
+        (15, "cross"),
+    ];
+
+    // a 3x3 map with wall 1 in the center and around it wherever `mask` has a bit
+    fn map(mask: u8) -> AtlasMap {
+        let mut map = AtlasMap::empty(3, 3);
+        map.wall.set(1, 1, 1);
+        for (bit, v) in NEIGHBORS.iter().enumerate() {
+            if mask & (1 << bit) != 0 {
+                map.wall.set((1 + v.x) as usize, (1 + v.y) as usize, 1);
+            }
+        }
+        map
+    }
+
+    // the mansion atlas as "dungeon", with wall-1 copied to a variant for every suffix
+    fn world(map: AtlasMap) -> AtlasWorld {
+        let mut dungeon = atlas("mansion", "dungeon");
+        let wall = dungeon.atlas_info.layers["wall-1"].clone();
+        for (_, suffix) in EXPECTED {
+            dungeon.atlas_info.layers.insert(format!("wall-1-{suffix}"), wall.clone());
+        }
+        let collection: AtlasCollection = [("dungeon".to_owned(), dungeon)].into_iter().collect();
+        let mut world = AtlasWorld::new(Player::new(0, 0, Direction::North), map, collection);
+        world.render_config.autotile = Some(AutotileTable::default());
+        world
+    }
+
+    #[test]
+    fn every_mask_gets_its_suffix() {
+        let table = AutotileTable::default();
+        for (mask, suffix) in EXPECTED {
+            assert_eq!(wall_mask(&map(mask), ivec2(1, 1)), mask);
+            assert_eq!(table.suffix(mask), Some(suffix), "mask {mask:04b}");
+            assert_eq!(world(map(mask)).wall_layer_id(ivec2(1, 1), 1), format!("wall-1-{suffix}"), "mask {mask:04b}");
+        }
+    }
+
+    #[test]
+    fn baked_masks_match_the_live_ones() {
+        let table = AutotileTable::default();
+        for (mask, suffix) in EXPECTED {
+            let mut baked = map(mask);
+            baked.bake_autotiles(&table);
+            assert_eq!(baked.autotile.get(1, 1), Some(mask + 1));
+            assert_eq!(baked.autotile.get(0, 0), Some(0));
+            assert_eq!(world(baked).wall_layer_id(ivec2(1, 1), 1), format!("wall-1-{suffix}"), "mask {mask:04b}");
+        }
+    }
+
+    #[test]
+    fn cells_outside_the_map_are_not_walls() {
+        let mut map = AtlasMap::empty(1, 1);
+        map.wall.set(0, 0, 1);
+        assert_eq!(wall_mask(&map, ivec2(0, 0)), 0);
+    }
+
+    #[test]
+    fn edits_after_a_bake_use_the_live_neighbors() {
+        let mut world = world(map(0b0101));
+        world.bake_autotiles(&AutotileTable::default());
+        world.set_cell("wall", ivec2(2, 1), 1);
+        assert_eq!(world.wall_layer_id(ivec2(1, 1), 1), "wall-1-tee");
+    }
+
+    #[test]
+    fn missing_variants_and_suffixes_fall_back_to_the_plain_layer() {
+        let mut world = world(map(0b1111));
+        world.collection.get_mut("dungeon").unwrap().atlas_info.layers.remove("wall-1-cross");
+        assert_eq!(world.wall_layer_id(ivec2(1, 1), 1), "wall-1");
+
+        let mut world = self::world(map(0b0101));
+        world.render_config.autotile.as_mut().unwrap().suffixes[0b0101] = None;
+        assert_eq!(world.wall_layer_id(ivec2(1, 1), 1), "wall-1");
+        world.render_config.autotile = None;
+        assert_eq!(world.wall_layer_id(ivec2(1, 1), 1), "wall-1");
+    }
+}
@@ -0,0 +1,77 @@
+//! Drawing `render`'s quads as meshes. Quads are gathered while the texture stays the same and
+//! submitted as one mesh when it changes, so the back to front order is kept while most of a
+//! frame, being cut from one atlas, goes out in a handful of draw calls.
+
+#[cfg(feature = "render")]
+use macroquad::{models::Vertex, prelude::*};
+
+/// What the last `render` submitted.
+#[derive(Clone, Copy, Default, PartialEq, Eq, Debug)]
+pub struct RenderStats {
+    pub quads: usize,
+    pub draw_calls: usize,
+}
+
+// a mesh's indices are u16
+#[cfg(feature = "render")]
+const MAX_QUADS: usize = u16::MAX as usize / 4;
+
+#[cfg(feature = "render")]
+fn vertex(x: f32, y: f32, u: f32, v: f32, color: Color) -> Vertex {
+    Vertex { position: vec3(x, y, 0.0), uv: vec2(u, v), color }
+}
+
+#[cfg(feature = "render")]
+pub(crate) struct QuadBatch {
+    atlas_id: String,
+    page: usize,
+    texture: Option<Texture2D>,
+    vertices: Vec<Vertex>,
+    indices: Vec<u16>,
+}
+
+#[cfg(feature = "render")]
+impl QuadBatch {
+    pub(crate) fn new() -> Self {
+        Self { atlas_id: String::new(), page: 0, texture: None, vertices: Vec::new(), indices: Vec::new() }
+    }
+
+    /// Adds a quad cut from `texture`, first drawing what was gathered when that was cut from
+    /// another atlas or page, or the mesh is full. Returns whether a draw call was made.
+    pub(crate) fn push(&mut self, atlas_id: &str, page: usize, texture: &Texture2D, source: Rect, dest: Rect, color: Color) -> bool {
+        let flushed = (self.atlas_id != atlas_id || self.page != page || self.vertices.len() / 4 >= MAX_QUADS) && self.flush();
+        if self.texture.is_none() {
+            self.atlas_id = atlas_id.to_owned();
+            self.page = page;
+            self.texture = Some(texture.clone());
+        }
+
+        let size = texture.size();
+        let (u0, v0) = (source.x / size.x, source.y / size.y);
+        let (u1, v1) = ((source.x + source.w) / size.x, (source.y + source.h) / size.y);
+        let first = self.vertices.len() as u16;
+        self.vertices.extend([
+            vertex(dest.x, dest.y, u0, v0, color),
+            vertex(dest.x + dest.w, dest.y, u1, v0, color),
+            vertex(dest.x + dest.w, dest.y + dest.h, u1, v1, color),
+            vertex(dest.x, dest.y + dest.h, u0, v1, color),
+        ]);
+        self.indices.extend([0, 1, 2, 0, 2, 3].map(|i| first + i));
+        flushed
+    }
+
+    /// draws whatever was gathered, returning whether there was anything
+    pub(crate) fn flush(&mut self) -> bool {
+        let texture = self.texture.take();
+        if self.vertices.is_empty() {
+            return false;
+        }
+        let mesh = Mesh {
+            vertices: std::mem::take(&mut self.vertices),
+            indices: std::mem::take(&mut self.indices),
+            texture,
+        };
+        draw_mesh(&mesh);
+        true
+    }
+}
@@ -0,0 +1,227 @@
+//! What entities do on the world's turn. Randomness comes from the world's own seeded generator,
+//! so the same seed and the same player actions always play out the same way.
+
+use serde::{Deserialize, Serialize};
+
+use crate::math::*;
+use crate::{encounter::EntityId, events::WorldEvent, generator::Rng, AtlasWorld, Direction, MapSource, MovementView};
+
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Debug, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum Behavior {
+    #[default]
+    Idle,
+    /// steps to a random open neighboring cell with probability `chance` each turn
+    Wander { chance: f32 },
+    /// walks toward the player while they are within `sight_radius` cells and in sight
+    Chase { sight_radius: i32 },
+}
+
+impl<M: MapSource> AtlasWorld<M> {
+    /// Restarts the generator behaviors, spinners and random teleporter facings draw from, for
+    /// replays.
+    pub fn seed_entities(&mut self, seed: u64) {
+        self.entity_rng = Rng::new(seed);
+    }
+
+    /// Gives every entity that isn't defeated its turn, in the order they were added, moving
+    /// each at most one cell. Entities never step onto each other or the player. A chaser next to
+    /// the player after its turn emits `EntityAdjacent`. Nothing moves during an encounter.
+    /// `take_turn` calls this once per turn the player's action took.
+    pub fn update_entities(&mut self) {
+        if self.encounter.is_some() {
+            return;
+        }
+        let ids: Vec<EntityId> = self.entities.iter().map(|entity| entity.id).filter(|id| !self.is_defeated(*id)).collect();
+        for id in ids {
+            let Some(entity) = self.entity(id) else {
+                continue;
+            };
+            let (from, behavior) = (entity.pos, entity.behavior);
+            let step = match behavior {
+                Behavior::Idle => None,
+                Behavior::Wander { chance } => self.wander_step(from, chance),
+                Behavior::Chase { sight_radius } => self.chase_step(from, sight_radius),
+            };
+            let pos = match step {
+                Some(to) => {
+                    if let Some(entity) = self.entity_mut(id) {
+                        entity.pos = to;
+                        entity.facing = Direction::from_vec(to - from).unwrap_or(entity.facing);
+                    }
+                    self.push_event(WorldEvent::EntityMoved { id, from, to });
+                    to
+                }
+                None => from,
+            };
+            let player = ivec2(self.player.x, self.player.y);
+            if matches!(behavior, Behavior::Chase { .. }) && Direction::from_vec(player - pos).is_some() {
+                self.push_event(WorldEvent::EntityAdjacent { id });
+            }
+        }
+    }
+
+    fn wander_step(&mut self, from: IVec2, chance: f32) -> Option<IVec2> {
+        if !self.entity_rng.chance(chance) {
+            return None;
+        }
+        let open: Vec<IVec2> = Direction::CARDINALS.iter().map(|direction| from + direction.to_vec()).filter(|pos| self.entity_can_enter(*pos)).collect();
+        let index = self.entity_rng.below(open.len());
+        open.get(index).copied()
+    }
+
+    fn chase_step(&self, from: IVec2, sight_radius: i32) -> Option<IVec2> {
+        let player = ivec2(self.player.x, self.player.y);
+        let offset = player - from;
+        if offset.x.saturating_mul(offset.x).saturating_add(offset.y.saturating_mul(offset.y)) > sight_radius.saturating_mul(sight_radius) || !self.line_of_sight(from, player) {
+            return None;
+        }
+        let next = *self.find_path(from, player)?.get(1)?;
+        self.entity_can_enter(next).then_some(next)
+    }
+
+    // open to walk into by the movement rules, and not taken by the player or another entity
+    fn entity_can_enter(&self, pos: IVec2) -> bool {
+        let view = MovementView { map: &self.map, open: &self.open_doors, entities: &self.entities, rules: &self.movement_rules, walls: &self.wall_properties, objects: &self.object_defs };
+        view.blocker(pos).is_none()
+            && pos != ivec2(self.player.x, self.player.y)
+            && !self.entities_at(pos).any(|entity| !self.is_defeated(entity.id))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{encounter::{Encounter, EncounterOutcome}, AtlasCollection, AtlasMap, AtlasWorld, Entity, Player};
+
+    // a `width` x `height` room walled all around, the player at (1, 1)
+    fn room(width: usize, height: usize) -> AtlasWorld {
+        let mut map = AtlasMap::empty(width, height);
+        map.wall.fill(1);
+        for y in 1..height - 1 {
+            for x in 1..width - 1 {
+                map.wall.set(x, y, 0);
+            }
+        }
+        AtlasWorld::new(Player::new(1, 1, Direction::South), map, AtlasCollection::new())
+    }
+
+    fn spawn(world: &mut AtlasWorld, x: i32, y: i32, behavior: Behavior) -> EntityId {
+        world.add_entity(Entity { behavior, ..Entity::new(ivec2(x, y), Direction::North, "dungeon", "entity") })
+    }
+
+    fn position(world: &AtlasWorld, id: EntityId) -> IVec2 {
+        world.entity(id).unwrap().pos
+    }
+
+    // where two wanderers go over 30 turns in a 7x7 room from `seed`
+    fn wandered(seed: u64) -> Vec<(IVec2, IVec2)> {
+        let mut world = room(7, 7);
+        let a = spawn(&mut world, 3, 3, Behavior::Wander { chance: 0.7 });
+        let b = spawn(&mut world, 5, 5, Behavior::Wander { chance: 0.7 });
+        world.seed_entities(seed);
+        (0..30).map(|_| {
+            let before = (position(&world, a), position(&world, b));
+            world.update_entities();
+            let after = (position(&world, a), position(&world, b));
+            for (from, to) in [(before.0, after.0), (before.1, after.1)] {
+                assert!(from == to || Direction::from_vec(to - from).is_some(), "{from} to {to}");
+                assert_eq!(world.map.get_wall(to), Some(0), "{to}");
+            }
+            assert_ne!(after.0, after.1);
+            after
+        }).collect()
+    }
+
+    #[test]
+    fn wanderers_repeat_under_the_same_seed() {
+        let first = wandered(42);
+        assert_eq!(wandered(42), first);
+        assert_ne!(wandered(43), first);
+        // they did get around
+        assert!(first.iter().filter(|(a, _)| *a != ivec2(3, 3)).count() > 10);
+    }
+
+    #[test]
+    fn chasers_close_on_the_player() {
+        let mut world = room(7, 7);
+        let id = spawn(&mut world, 5, 5, Behavior::Chase { sight_radius: 10 });
+        let distance = |world: &AtlasWorld| {
+            let offset = position(world, id) - ivec2(world.player.x, world.player.y);
+            offset.x.abs() + offset.y.abs()
+        };
+        for expected in (1..8).rev() {
+            world.update_entities();
+            assert_eq!(distance(&world), expected);
+        }
+        // next to the player it stays put
+        world.update_entities();
+        assert_eq!(distance(&world), 1);
+    }
+
+    #[test]
+    fn chasers_wait_for_the_player_to_be_in_sight() {
+        let mut world = room(7, 7);
+        // a wall down the room between them, open only at the bottom
+        for y in 1..5 {
+            world.map.wall.set(3, y, 1);
+        }
+        world.touch_map();
+        let hidden = spawn(&mut world, 5, 1, Behavior::Chase { sight_radius: 10 });
+        let far = spawn(&mut world, 1, 5, Behavior::Chase { sight_radius: 3 });
+        world.update_entities();
+        assert_eq!((position(&world, hidden), position(&world, far)), (ivec2(5, 1), ivec2(1, 5)));
+        assert_eq!(world.poll_events().count(), 0);
+
+        // in sight and in range once the player comes down the room
+        world.player.y = 3;
+        world.update_entities();
+        assert_eq!(position(&world, far), ivec2(1, 4));
+        assert_eq!(position(&world, hidden), ivec2(5, 1));
+    }
+
+    #[test]
+    fn entities_never_stack() {
+        // a corridor: the player, a wanderer with nowhere to go, an idle entity, then two chasers
+        let mut world = room(9, 3);
+        let wanderer = spawn(&mut world, 2, 1, Behavior::Wander { chance: 1.0 });
+        spawn(&mut world, 3, 1, Behavior::Idle);
+        let first = spawn(&mut world, 5, 1, Behavior::Chase { sight_radius: 10 });
+        let second = spawn(&mut world, 6, 1, Behavior::Chase { sight_radius: 10 });
+        world.seed_entities(7);
+        for _ in 0..10 {
+            world.update_entities();
+        }
+        // the chasers only got as far as the idle entity, and the wanderer stayed boxed in
+        let placed = [wanderer, first, second].map(|id| position(&world, id));
+        assert_eq!(placed, [ivec2(2, 1), ivec2(4, 1), ivec2(5, 1)]);
+    }
+
+    #[test]
+    fn adjacent_chasers_call_for_a_fight() {
+        let mut world = room(7, 4);
+        let chaser = spawn(&mut world, 3, 1, Behavior::Chase { sight_radius: 10 });
+        // a wanderer next to the player doesn't count
+        spawn(&mut world, 1, 2, Behavior::Wander { chance: 0.0 });
+        world.update_entities();
+        let events: Vec<WorldEvent> = world.poll_events().collect();
+        assert_eq!(events, [WorldEvent::EntityMoved { id: chaser, from: ivec2(3, 1), to: ivec2(2, 1) }, WorldEvent::EntityAdjacent { id: chaser }]);
+        // and staying next to the player asks again each turn
+        world.update_entities();
+        let events: Vec<WorldEvent> = world.poll_events().collect();
+        assert_eq!(events, [WorldEvent::EntityAdjacent { id: chaser }]);
+
+        // the fight that answers it holds everyone still until it ends
+        assert!(world.start_encounter(Encounter { participants: vec![chaser], initiated_by: Some(chaser), zone: None }));
+        world.player.x = 4;
+        world.poll_events().count();
+        world.update_entities();
+        assert_eq!(world.poll_events().count(), 0);
+        assert_eq!(position(&world, chaser), ivec2(2, 1));
+        world.end_encounter(EncounterOutcome::default());
+        world.poll_events().count();
+        world.update_entities();
+        assert_eq!(position(&world, chaser), ivec2(3, 1));
+        assert!(world.poll_events().any(|event| event == WorldEvent::EntityAdjacent { id: chaser }));
+    }
+}
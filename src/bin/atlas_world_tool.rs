@@ -0,0 +1,31 @@
+//! `atlas_world_tool <command>` reads the command's JSON input from stdin and writes the JSON
+//! result to stdout. See the `pipeline` module for the commands and their shapes.
+
+use std::io::{self, Read};
+
+use atlas_world::pipeline;
+
+fn main() {
+    let Some(command) = std::env::args().nth(1) else {
+        eprintln!("usage: atlas_world_tool <validate_map|validate_all|preflight|find_path|generate_map> < input.json");
+        std::process::exit(2);
+    };
+
+    let mut input = String::new();
+    if let Err(err) = io::stdin().read_to_string(&mut input) {
+        eprintln!("could not read stdin: {err}");
+        std::process::exit(1);
+    }
+
+    let result = serde_json::from_str(&input)
+        .map_err(pipeline::PipelineError::BadInput)
+        .and_then(|input| pipeline::run(&command, input));
+
+    match result {
+        Ok(output) => println!("{output}"),
+        Err(err) => {
+            eprintln!("{err}");
+            std::process::exit(1);
+        }
+    }
+}
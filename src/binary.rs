@@ -0,0 +1,169 @@
+//! A binary map file for maps too large to parse from json quickly. A header of the magic bytes
+//! `AWMAP`, the format version and the map size is followed by every layer as its name and its
+//! rows of raw cell values. Integers are little endian. Behind the `binary` feature.
+
+use crate::{AtlasMap, Grid, MapError};
+
+pub const BINARY_MAGIC: &[u8; 5] = b"AWMAP";
+/// the layout `to_binary` writes; `from_binary` reads this and every earlier version
+pub const BINARY_VERSION: u16 = 1;
+
+impl AtlasMap {
+    pub fn to_binary(&self) -> Vec<u8> {
+        let cells: usize = self.layer_names().filter_map(|name| self.layer(name)).map(|grid| grid.data().len()).sum();
+        let mut bytes = Vec::with_capacity(32 + cells);
+        bytes.extend_from_slice(BINARY_MAGIC);
+        bytes.extend_from_slice(&BINARY_VERSION.to_le_bytes());
+        bytes.extend_from_slice(&(self.width as u32).to_le_bytes());
+        bytes.extend_from_slice(&(self.height as u32).to_le_bytes());
+
+        let layers: Vec<(&str, &Grid)> = self.layer_names().filter_map(|name| Some((name, self.layer(name)?))).collect();
+        bytes.extend_from_slice(&(layers.len() as u32).to_le_bytes());
+        for (name, grid) in layers {
+            bytes.extend_from_slice(&(name.len() as u16).to_le_bytes());
+            bytes.extend_from_slice(name.as_bytes());
+            bytes.extend_from_slice(&(grid.height() as u32).to_le_bytes());
+            for row in grid.iter_rows() {
+                bytes.extend_from_slice(&(row.len() as u32).to_le_bytes());
+                bytes.extend_from_slice(row);
+            }
+        }
+        bytes
+    }
+
+    /// Reads a map written by `to_binary`.
+    ///
+    /// # Errors
+    ///
+    /// When `bytes` don't start with `BINARY_MAGIC`, come from a newer version, or end early.
+    pub fn from_binary(bytes: &[u8]) -> Result<AtlasMap, MapError> {
+        let mut reader = ByteReader { bytes, offset: 0 };
+        if reader.take(BINARY_MAGIC.len())? != BINARY_MAGIC {
+            return Err(reader.error("not a binary map"));
+        }
+        let version = reader.u16()?;
+        if version > BINARY_VERSION {
+            return Err(MapError::BinaryVersion { found: version, supported: BINARY_VERSION });
+        }
+
+        let (width, height) = (reader.u32()? as usize, reader.u32()? as usize);
+        // every map has a wall layer of width * height cells, so a size the rest of the file can't
+        // hold is a broken header, caught here before allocating the layers
+        let fits = matches!(width.checked_mul(height), Some(cells) if cells <= reader.remaining());
+        if !fits {
+            return Err(reader.error(&format!("{width}x{height} map is larger than the file")));
+        }
+        let mut map = AtlasMap::empty(width, height);
+        for _ in 0..reader.u32()? {
+            let name_len = reader.u16()? as usize;
+            let name = std::str::from_utf8(reader.take(name_len)?).map_err(|_| reader.error("layer name is not utf-8"))?.to_owned();
+            let row_count = reader.u32()? as usize;
+            let mut rows = Vec::with_capacity(row_count.min(height));
+            for _ in 0..row_count {
+                let row_len = reader.u32()? as usize;
+                rows.push(reader.take(row_len)?.to_vec());
+            }
+            let rows = Grid::from_rows(rows).map_err(|err| reader.error(&format!("layer '{name}' {err}")))?;
+            match map.layer_mut(&name) {
+                Some(layer) => *layer = rows,
+                None => {
+                    map.layers.insert(name, rows);
+                }
+            }
+        }
+        map.check_shape()?;
+        Ok(map)
+    }
+}
+
+struct ByteReader<'a> {
+    bytes: &'a [u8],
+    offset: usize,
+}
+
+impl<'a> ByteReader<'a> {
+    fn error(&self, reason: &str) -> MapError {
+        MapError::Binary { offset: self.offset, reason: reason.to_owned() }
+    }
+
+    fn remaining(&self) -> usize {
+        self.bytes.len().saturating_sub(self.offset)
+    }
+
+    fn take(&mut self, len: usize) -> Result<&'a [u8], MapError> {
+        let bytes = self.bytes.get(self.offset..self.offset.saturating_add(len)).ok_or_else(|| self.error("unexpected end of file"))?;
+        self.offset += len;
+        Ok(bytes)
+    }
+
+    fn u16(&mut self) -> Result<u16, MapError> {
+        Ok(u16::from_le_bytes(self.take(2)?.try_into().unwrap()))
+    }
+
+    fn u32(&mut self) -> Result<u32, MapError> {
+        Ok(u32::from_le_bytes(self.take(4)?.try_into().unwrap()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::generator::{rooms_and_corridors, GeneratorConfig};
+
+    fn map() -> AtlasMap {
+        let mut map = rooms_and_corridors(40, 30, &GeneratorConfig::default(), 7).map;
+        map.elevation = Grid::new(40, 30);
+        map.elevation.set(3, 4, (-2i8) as u8);
+        let mut doors = Grid::new(40, 30);
+        doors.set(5, 6, 2);
+        map.layers.insert("doors".to_owned(), doors);
+        map
+    }
+
+    #[test]
+    fn round_trip() {
+        let map = map();
+        assert_eq!(AtlasMap::from_binary(&map.to_binary()).unwrap(), map);
+    }
+
+    #[test]
+    fn either_format_is_read() {
+        let map = map();
+        assert_eq!(AtlasMap::from_reader_auto(map.to_binary().as_slice()).unwrap(), map);
+        assert_eq!(AtlasMap::from_reader_auto(serde_json::to_vec(&map).unwrap().as_slice()).unwrap(), map);
+    }
+
+    #[test]
+    fn bad_files_are_errors() {
+        let bytes = map().to_binary();
+        assert!(matches!(AtlasMap::from_binary(b"AWMAX"), Err(MapError::Binary { offset: 5, .. })));
+        assert!(matches!(AtlasMap::from_binary(&bytes[..bytes.len() - 1]), Err(MapError::Binary { .. })));
+
+        let mut newer = bytes.clone();
+        newer[5..7].copy_from_slice(&(BINARY_VERSION + 1).to_le_bytes());
+        assert!(matches!(AtlasMap::from_binary(&newer), Err(MapError::BinaryVersion { found, .. }) if found == BINARY_VERSION + 1));
+
+        // a map 1 cell wider than its layers
+        let mut wider = bytes;
+        wider[7..11].copy_from_slice(&41u32.to_le_bytes());
+        assert!(matches!(AtlasMap::from_binary(&wider), Err(MapError::RaggedRow { .. })));
+    }
+
+    #[test]
+    fn huge_or_truncated_headers_are_errors() {
+        let header = |width: u32, height: u32| {
+            let mut bytes = BINARY_MAGIC.to_vec();
+            bytes.extend_from_slice(&BINARY_VERSION.to_le_bytes());
+            bytes.extend_from_slice(&width.to_le_bytes());
+            bytes.extend_from_slice(&height.to_le_bytes());
+            bytes
+        };
+        assert!(matches!(AtlasMap::from_binary(&header(u32::MAX, u32::MAX)), Err(MapError::Binary { offset: 15, .. })));
+        assert!(matches!(AtlasMap::from_binary(&header(4_000_000, 4_000_000)), Err(MapError::Binary { offset: 15, .. })));
+        assert!(matches!(AtlasMap::from_binary(&header(40, 30)[..12]), Err(MapError::Binary { .. })));
+
+        let mut truncated = map().to_binary();
+        truncated.truncate(100);
+        assert!(matches!(AtlasMap::from_binary(&truncated), Err(MapError::Binary { offset: 15, .. })));
+    }
+}
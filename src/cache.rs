@@ -0,0 +1,221 @@
+//! Keeping derived data in step with the map. Every edit made through the world bumps
+//! `map_revision`; caches remember the revision they were built from and rebuild when it moved.
+//! Editing `world.map` directly skips this, call `touch_map` afterwards.
+
+use std::cell::Ref;
+
+use crate::math::*;
+use crate::{autotile::AutotileTable, AtlasMap, AtlasWorld, MapSource, PassabilityGrid};
+
+impl<M: MapSource> AtlasWorld<M> {
+    pub fn map_revision(&self) -> u64 {
+        self.map_revision
+    }
+
+    /// marks the map as changed after editing it without going through the world
+    pub fn touch_map(&mut self) {
+        self.map_revision += 1;
+    }
+
+    /// writes one cell of a map layer, returning false when it couldn't be written
+    pub fn set_cell(&mut self, layer: &str, pos: IVec2, value: u8) -> bool {
+        let written = self.map.set(layer, pos, value);
+        if written {
+            self.touch_map();
+        }
+        written
+    }
+
+    /// The passability of the current map, rebuilt only after the map or the collision rules
+    /// changed. The rules come from the object defs, wall materials, wall properties, terrain
+    /// and movement rules, which are compared rather than tracked so they can stay plain fields.
+    pub fn cached_passability(&self) -> Ref<'_, PassabilityGrid> {
+        let rules = self.collision_rules();
+        let stale = self.passability_cache.borrow().as_ref()
+            .is_none_or(|(cached_rules, grid)| grid.revision != self.map_revision || *cached_rules != rules);
+        if stale {
+            let grid = self.passability_snapshot_with(&rules);
+            *self.passability_cache.borrow_mut() = Some((rules, grid));
+        }
+        Ref::map(self.passability_cache.borrow(), |cache| &cache.as_ref().unwrap().1)
+    }
+
+    /// Compares every cache against a fresh computation.
+    ///
+    /// # Panics
+    ///
+    /// When a cache claims to be current but differs, i.e. the map was edited behind the
+    /// world's back.
+    pub fn check_caches(&self) {
+        if let Some((rules, cached)) = self.passability_cache.borrow().as_ref() {
+            if cached.revision == self.map_revision && *rules == self.collision_rules() {
+                assert!(
+                    *cached == self.passability_snapshot(),
+                    "cached passability is stale at map revision {}, was the map edited without set_cell or touch_map?",
+                    self.map_revision
+                );
+            }
+        }
+    }
+}
+
+impl AtlasWorld<AtlasMap> {
+    /// `AtlasMap::bake_autotiles`, remembered as current until the next map edit
+    pub fn bake_autotiles(&mut self, table: &AutotileTable) {
+        self.map.bake_autotiles(table);
+        self.autotile_revision = Some(self.map_revision);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::atlas;
+    use crate::{AtlasCollection, Direction, Grid, ObjectDef, Player, Terrain, WallFlags, WallMaterial};
+
+    fn world() -> AtlasWorld {
+        let mut map = AtlasMap::empty(3, 3);
+        map.wall.set(1, 1, 5);
+        map.object.set(2, 2, 4);
+        AtlasWorld::new(Player::new(0, 0, Direction::East), map, AtlasCollection::new())
+    }
+
+    #[test]
+    fn the_cache_follows_the_collision_settings() {
+        let mut world = world();
+        assert!(!world.cached_passability().is_passable(ivec2(1, 1)));
+        assert!(world.cached_passability().is_passable(ivec2(2, 2)));
+
+        world.wall_properties.insert(5, WallFlags { blocks_movement: false, ..WallFlags::default() });
+        assert!(world.cached_passability().is_passable(ivec2(1, 1)));
+
+        world.object_defs.insert(4, ObjectDef { blocks_paths: true, ..ObjectDef::default() });
+        assert!(!world.cached_passability().is_passable(ivec2(2, 2)));
+
+        world.terrain.insert(0, Terrain { name: "mud".to_owned(), move_cost: 3 });
+        assert_eq!(world.cached_passability().move_cost(ivec2(0, 0)), 3);
+        world.check_caches();
+    }
+
+    #[test]
+    fn map_edits_through_the_world_rebuild_the_cache() {
+        let mut world = world();
+        assert!(world.cached_passability().is_passable(ivec2(0, 1)));
+        world.set_cell("wall", ivec2(0, 1), 5);
+        assert!(!world.cached_passability().is_passable(ivec2(0, 1)));
+        world.check_caches();
+    }
+
+    #[test]
+    #[should_panic(expected = "cached passability is stale")]
+    fn edits_behind_the_worlds_back_are_caught() {
+        let mut world = world();
+        world.cached_passability();
+        world.map.wall.set(0, 1, 5);
+        world.check_caches();
+    }
+
+    // A 5x3 room walled above and below with `middle` as the wall at (1, 1) and a light (9) at
+    // (0, 1). The walls are autotiled from a bake, so all three caches hang on that one cell.
+    fn lit_room(middle: u8) -> AtlasWorld {
+        let mut map = AtlasMap::empty(5, 3);
+        for x in 0..5 {
+            map.wall.set(x, 0, 1);
+            map.wall.set(x, 2, 1);
+        }
+        map.wall.set(1, 1, middle);
+        map.object.set(0, 1, 9);
+        map.add_layer("doors");
+
+        let mut dungeon = atlas("mansion", "dungeon");
+        let wall = dungeon.atlas_info.layers["wall-1"].clone();
+        for suffix in ["tee", "straight", "corner"] {
+            dungeon.atlas_info.layers.insert(format!("wall-1-{suffix}"), wall.clone());
+        }
+        let collection: AtlasCollection = [("dungeon".to_owned(), dungeon)].into_iter().collect();
+        let mut world = AtlasWorld::new(Player::new(3, 1, Direction::West), map, collection);
+        world.wall_materials.insert(3, WallMaterial { name: "wood".to_owned(), bashable: true, ..Default::default() });
+        world.set_light_emitter(9, 200);
+        world.render_config.autotile = Some(AutotileTable::default());
+        world.bake_autotiles(&AutotileTable::default());
+        world
+    }
+
+    // whether (1, 1) is passable, the light reaching (2, 1) past it and the layer the wall above
+    // it draws from, each read back through its cache
+    fn observed(world: &mut AtlasWorld) -> (bool, u8, String) {
+        world.compute_lighting();
+        world.check_caches();
+        let passable = world.cached_passability().is_passable(ivec2(1, 1));
+        (passable, world.map.light.get(2, 1).unwrap(), world.wall_layer_id(ivec2(1, 0), 1).into_owned())
+    }
+
+    const CLOSED: (bool, u8, &str) = (false, 0, "wall-1-tee");
+    const OPEN: (bool, u8, &str) = (true, 136, "wall-1-straight");
+
+    fn assert_observed(world: &mut AtlasWorld, expected: (bool, u8, &str)) {
+        let (passable, light, layer_id) = observed(world);
+        assert_eq!((passable, light, layer_id.as_str()), expected);
+    }
+
+    #[test]
+    fn bashed_walls_reach_every_cache() {
+        let mut world = lit_room(3);
+        assert_observed(&mut world, CLOSED);
+        assert_eq!(world.bash_wall(1, 1), Some(3));
+        assert_observed(&mut world, OPEN);
+    }
+
+    #[test]
+    fn revealed_secrets_reach_every_cache() {
+        let mut world = lit_room(1);
+        assert!(world.add_secret_wall(ivec2(1, 1)));
+        assert_observed(&mut world, CLOSED);
+        assert!(world.reveal_secret(ivec2(1, 1)));
+        assert_observed(&mut world, OPEN);
+    }
+
+    #[test]
+    fn toggled_doors_reach_every_cache() {
+        let mut world = lit_room(0);
+        world.map.set("doors", ivec2(1, 1), 1);
+        world.touch_map();
+        // light and autotiling pass doors by, only passability sees them close
+        let past_the_door = (false, OPEN.1, OPEN.2);
+        assert_observed(&mut world, past_the_door);
+
+        assert_eq!(world.toggle_door_at(ivec2(1, 1)), Some(true));
+        assert!(world.compute_lighting());
+        assert_observed(&mut world, OPEN);
+        assert_eq!(world.toggle_door_at(ivec2(1, 1)), Some(false));
+        assert!(world.compute_lighting());
+        assert_observed(&mut world, past_the_door);
+    }
+
+    #[test]
+    fn level_changes_reach_every_cache() {
+        let mut world = lit_room(3);
+        assert_observed(&mut world, CLOSED);
+        // the same room opened up and never baked, so the bake of the first one must not carry over
+        let mut open = world.map.clone();
+        open.wall.set(1, 1, 0);
+        open.autotile = Grid::default();
+        open.light = Grid::default();
+        world.add_level("open", open);
+
+        assert!(world.switch_level("open", ivec2(3, 1), Direction::West));
+        assert_observed(&mut world, OPEN);
+        assert!(world.switch_level("main", ivec2(3, 1), Direction::West));
+        assert_observed(&mut world, CLOSED);
+    }
+
+    #[test]
+    fn loaded_states_reach_every_cache() {
+        let mut world = lit_room(3);
+        let state = world.save_state();
+        world.bash_wall(1, 1);
+        assert_observed(&mut world, OPEN);
+        world.load_state(state).unwrap();
+        assert_observed(&mut world, CLOSED);
+    }
+}
@@ -0,0 +1,461 @@
+use std::{fs, io, path::PathBuf};
+
+use ahash::AHashMap;
+
+use crate::math::*;
+use crate::{AtlasMap, MapSource};
+
+/// Where the chunks of a `ChunkedMap` come from and where edited ones go back to.
+/// Chunks are addressed in chunk coordinates, `chunk * chunk_size` is their top left cell.
+pub trait ChunkProvider {
+    /// `Ok(None)` leaves the chunk unavailable, which movement treats as solid. An error does
+    /// too, and is passed on by `ChunkedMap::update_residency`.
+    fn load_chunk(&mut self, chunk: IVec2, chunk_size: usize) -> io::Result<Option<AtlasMap>>;
+
+    /// called with edited chunks when they are evicted or flushed
+    fn store_chunk(&mut self, chunk: IVec2, map: &AtlasMap) -> io::Result<()>;
+}
+
+/// One `AtlasMap` json file per chunk, named `{x}_{y}.json` after its chunk coordinates. A
+/// missing file is an unavailable chunk; one that doesn't parse or isn't `chunk_size` cells
+/// square is an `InvalidData` error.
+pub struct DirectoryProvider {
+    pub dir: PathBuf,
+}
+
+impl DirectoryProvider {
+    pub fn new<P: Into<PathBuf>>(dir: P) -> Self {
+        Self { dir: dir.into() }
+    }
+
+    pub fn chunk_path(&self, chunk: IVec2) -> PathBuf {
+        self.dir.join(format!("{}_{}.json", chunk.x, chunk.y))
+    }
+}
+
+impl ChunkProvider for DirectoryProvider {
+    fn load_chunk(&mut self, chunk: IVec2, chunk_size: usize) -> io::Result<Option<AtlasMap>> {
+        let path = self.chunk_path(chunk);
+        let json = match fs::read_to_string(&path) {
+            Ok(json) => json,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(None),
+            Err(err) => return Err(err),
+        };
+        let invalid = |message: String| io::Error::new(io::ErrorKind::InvalidData, format!("{}: {message}", path.display()));
+        let map: AtlasMap = serde_json::from_str(&json).map_err(|err| invalid(err.to_string()))?;
+        if (map.width, map.height) != (chunk_size, chunk_size) {
+            return Err(invalid(format!("chunk is {}x{}, expected {chunk_size}x{chunk_size}", map.width, map.height)));
+        }
+        map.check_shape().map_err(|err| invalid(err.to_string()))?;
+        Ok(Some(map))
+    }
+
+    fn store_chunk(&mut self, chunk: IVec2, map: &AtlasMap) -> io::Result<()> {
+        fs::create_dir_all(&self.dir)?;
+        fs::write(self.chunk_path(chunk), serde_json::to_string(map)?)
+    }
+}
+
+/// Chunks built on demand by a closure. Edited chunks are kept in memory and handed back
+/// instead of regenerating them.
+pub struct GeneratedProvider<F: FnMut(IVec2, usize) -> AtlasMap> {
+    generate: F,
+    edited: AHashMap<IVec2, AtlasMap>,
+}
+
+impl<F: FnMut(IVec2, usize) -> AtlasMap> GeneratedProvider<F> {
+    pub fn new(generate: F) -> Self {
+        Self {
+            generate,
+            edited: AHashMap::new(),
+        }
+    }
+}
+
+impl<F: FnMut(IVec2, usize) -> AtlasMap> ChunkProvider for GeneratedProvider<F> {
+    fn load_chunk(&mut self, chunk: IVec2, chunk_size: usize) -> io::Result<Option<AtlasMap>> {
+        match self.edited.get(&chunk) {
+            Some(map) => Ok(Some(map.clone())),
+            None => Ok(Some((self.generate)(chunk, chunk_size))),
+        }
+    }
+
+    fn store_chunk(&mut self, chunk: IVec2, map: &AtlasMap) -> io::Result<()> {
+        self.edited.insert(chunk, map.clone());
+        Ok(())
+    }
+}
+
+/// Which resident chunks get dropped when the player moves on.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Eviction {
+    /// drop every chunk outside the residency radius right away
+    OutsideRadius,
+    /// keep up to `capacity` chunks, dropping the least recently used ones outside the radius
+    LeastRecentlyUsed { capacity: usize },
+}
+
+struct ResidentChunk {
+    map: AtlasMap,
+    dirty: bool,
+    last_used: u64,
+}
+
+/// A map too big to hold at once, kept as `chunk_size` x `chunk_size` cell chunks of which only
+/// those within `residency_radius` chunks of the player are loaded.
+pub struct ChunkedMap {
+    pub width: usize,
+    pub height: usize,
+    pub chunk_size: usize,
+    pub residency_radius: i32,
+    pub eviction: Eviction,
+    provider: Box<dyn ChunkProvider>,
+    resident: AHashMap<IVec2, ResidentChunk>,
+    tick: u64,
+    /// bumped whenever a chunk is loaded or evicted
+    residency_changes: u64,
+}
+
+impl ChunkedMap {
+    pub fn new<P: ChunkProvider + 'static>(width: usize, height: usize, chunk_size: usize, provider: P) -> Self {
+        Self {
+            width,
+            height,
+            chunk_size: chunk_size.max(1),
+            residency_radius: 1,
+            eviction: Eviction::OutsideRadius,
+            provider: Box::new(provider),
+            resident: AHashMap::new(),
+            tick: 0,
+            residency_changes: 0,
+        }
+    }
+
+    /// chunk coordinates of the chunk holding cell `pos`
+    pub fn chunk_of(&self, pos: IVec2) -> IVec2 {
+        let size = self.chunk_size as i32;
+        ivec2(pos.x.div_euclid(size), pos.y.div_euclid(size))
+    }
+
+    pub fn is_resident(&self, chunk: IVec2) -> bool {
+        self.resident.contains_key(&chunk)
+    }
+
+    pub fn resident_chunks(&self) -> impl Iterator<Item = IVec2> + '_ {
+        self.resident.keys().copied()
+    }
+
+    /// Loads every chunk within the residency radius of cell `center` and evicts per `eviction`.
+    /// A chunk that fails to load stays unavailable, and one whose edits fail to store stays
+    /// resident so nothing is lost; the last such error is returned once the rest are done.
+    pub fn update_residency(&mut self, center: IVec2) -> io::Result<()> {
+        self.tick += 1;
+        let center_chunk = self.chunk_of(center);
+        let chunks_x = i32::try_from(self.width.div_ceil(self.chunk_size)).unwrap_or(i32::MAX);
+        let chunks_y = i32::try_from(self.height.div_ceil(self.chunk_size)).unwrap_or(i32::MAX);
+
+        let mut result = Ok(());
+        let radius = self.residency_radius.max(0);
+        let rows = center_chunk.y.saturating_sub(radius).max(0)..=center_chunk.y.saturating_add(radius).min(chunks_y - 1);
+        let columns = center_chunk.x.saturating_sub(radius).max(0)..=center_chunk.x.saturating_add(radius).min(chunks_x - 1);
+        for y in rows {
+            for x in columns.clone() {
+                let chunk = ivec2(x, y);
+                if let Some(resident) = self.resident.get_mut(&chunk) {
+                    resident.last_used = self.tick;
+                } else {
+                    match self.provider.load_chunk(chunk, self.chunk_size) {
+                        Ok(Some(map)) => {
+                            self.resident.insert(chunk, ResidentChunk { map, dirty: false, last_used: self.tick });
+                            self.residency_changes += 1;
+                        }
+                        Ok(None) => {}
+                        Err(err) => result = Err(err),
+                    }
+                }
+            }
+        }
+
+        let mut evictable: Vec<(IVec2, u64)> = self.resident.iter()
+            .filter(|(chunk, _)| {
+                chunk.x.abs_diff(center_chunk.x) > radius as u32 || chunk.y.abs_diff(center_chunk.y) > radius as u32
+            })
+            .map(|(chunk, resident)| (*chunk, resident.last_used))
+            .collect();
+
+        let evict_count = match self.eviction {
+            Eviction::OutsideRadius => evictable.len(),
+            Eviction::LeastRecentlyUsed { capacity } => self.resident.len().saturating_sub(capacity).min(evictable.len()),
+        };
+        evictable.sort_by_key(|(_, last_used)| *last_used);
+
+        for (chunk, _) in evictable.into_iter().take(evict_count) {
+            if let Err(err) = self.evict(chunk) {
+                result = Err(err);
+            }
+        }
+        result
+    }
+
+    /// hands every edited resident chunk to the provider
+    pub fn flush(&mut self) -> io::Result<()> {
+        for (chunk, resident) in self.resident.iter_mut() {
+            if resident.dirty {
+                self.provider.store_chunk(*chunk, &resident.map)?;
+                resident.dirty = false;
+            }
+        }
+        Ok(())
+    }
+
+    fn evict(&mut self, chunk: IVec2) -> io::Result<()> {
+        if let Some(resident) = self.resident.get(&chunk) {
+            if resident.dirty {
+                self.provider.store_chunk(chunk, &resident.map)?;
+            }
+        }
+        if self.resident.remove(&chunk).is_some() {
+            self.residency_changes += 1;
+        }
+        Ok(())
+    }
+
+    // the resident chunk holding `pos` and the cell's position inside it
+    fn locate(&self, pos: IVec2) -> Option<(IVec2, IVec2)> {
+        if !self.in_bounds(pos) {
+            return None;
+        }
+        let chunk = self.chunk_of(pos);
+        Some((chunk, pos - chunk * self.chunk_size as i32))
+    }
+}
+
+impl MapSource for ChunkedMap {
+    fn width(&self) -> usize {
+        self.width
+    }
+
+    fn height(&self) -> usize {
+        self.height
+    }
+
+    fn get(&self, layer: &str, pos: IVec2) -> Option<u8> {
+        let (chunk, local) = self.locate(pos)?;
+        self.resident.get(&chunk)?.map.get(layer, local)
+    }
+
+    fn set(&mut self, layer: &str, pos: IVec2, value: u8) -> bool {
+        let Some((chunk, local)) = self.locate(pos) else {
+            return false;
+        };
+        let Some(resident) = self.resident.get_mut(&chunk) else {
+            return false;
+        };
+        let written = resident.map.set(layer, local, value);
+        resident.dirty |= written;
+        written
+    }
+
+    fn focus(&mut self, pos: IVec2) -> bool {
+        let before = self.residency_changes;
+        // a failed load is retried on the next call and a failed store keeps its chunk
+        // resident and dirty for `flush` to report
+        let _ = self.update_residency(pos);
+        self.residency_changes != before
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{cell::RefCell, rc::Rc};
+
+    use super::*;
+    use crate::{AtlasCollection, AtlasWorld, Direction, Movement, Player, PlayerAction};
+
+    #[derive(Default)]
+    struct ProviderLog {
+        loads: Vec<IVec2>,
+        stores: Vec<IVec2>,
+        stored: AHashMap<IVec2, AtlasMap>,
+    }
+
+    // open chunks, or what was stored for them, with every load and store logged
+    #[derive(Default, Clone)]
+    struct CountingProvider(Rc<RefCell<ProviderLog>>);
+
+    impl ChunkProvider for CountingProvider {
+        fn load_chunk(&mut self, chunk: IVec2, chunk_size: usize) -> io::Result<Option<AtlasMap>> {
+            let mut log = self.0.borrow_mut();
+            log.loads.push(chunk);
+            Ok(Some(log.stored.get(&chunk).cloned().unwrap_or_else(|| AtlasMap::empty(chunk_size, chunk_size))))
+        }
+
+        fn store_chunk(&mut self, chunk: IVec2, map: &AtlasMap) -> io::Result<()> {
+            let mut log = self.0.borrow_mut();
+            log.stores.push(chunk);
+            log.stored.insert(chunk, map.clone());
+            Ok(())
+        }
+    }
+
+    // a 12x12 map of 4x4 chunks, so 3x3 chunks
+    fn counted(radius: i32, eviction: Eviction) -> (ChunkedMap, CountingProvider) {
+        let provider = CountingProvider::default();
+        let mut map = ChunkedMap::new(12, 12, 4, provider.clone());
+        map.residency_radius = radius;
+        map.eviction = eviction;
+        (map, provider)
+    }
+
+    fn resident(map: &ChunkedMap) -> Vec<IVec2> {
+        let mut chunks: Vec<IVec2> = map.resident_chunks().collect();
+        chunks.sort_by_key(|chunk| (chunk.y, chunk.x));
+        chunks
+    }
+
+    fn take(log: &mut Vec<IVec2>) -> Vec<IVec2> {
+        let mut chunks = std::mem::take(log);
+        chunks.sort_by_key(|chunk| (chunk.y, chunk.x));
+        chunks
+    }
+
+    #[test]
+    fn chunks_load_and_evict_as_the_player_crosses_boundaries() {
+        let (mut map, provider) = counted(0, Eviction::OutsideRadius);
+        map.update_residency(ivec2(1, 1)).unwrap();
+        assert_eq!(take(&mut provider.0.borrow_mut().loads), [ivec2(0, 0)]);
+
+        // staying inside the chunk loads nothing
+        map.update_residency(ivec2(3, 3)).unwrap();
+        assert!(provider.0.borrow().loads.is_empty());
+
+        map.update_residency(ivec2(4, 3)).unwrap();
+        assert_eq!(take(&mut provider.0.borrow_mut().loads), [ivec2(1, 0)]);
+        assert_eq!(resident(&map), [ivec2(1, 0)]);
+        map.update_residency(ivec2(4, 8)).unwrap();
+        assert_eq!(resident(&map), [ivec2(1, 2)]);
+        assert!(provider.0.borrow().stores.is_empty());
+    }
+
+    #[test]
+    fn the_residency_radius_is_clamped_to_the_map() {
+        let (mut map, provider) = counted(1, Eviction::OutsideRadius);
+        map.update_residency(ivec2(0, 0)).unwrap();
+        assert_eq!(resident(&map), [ivec2(0, 0), ivec2(1, 0), ivec2(0, 1), ivec2(1, 1)]);
+
+        map.residency_radius = i32::MAX;
+        map.update_residency(ivec2(11, 11)).unwrap();
+        assert_eq!(resident(&map).len(), 9);
+        assert_eq!(provider.0.borrow().loads.len(), 9);
+
+        // a negative radius counts as 0, keeping just the player's chunk
+        map.residency_radius = -5;
+        map.update_residency(ivec2(11, 11)).unwrap();
+        assert_eq!(resident(&map), [ivec2(2, 2)]);
+    }
+
+    #[test]
+    fn the_least_recently_used_chunks_go_first() {
+        let (mut map, provider) = counted(0, Eviction::LeastRecentlyUsed { capacity: 2 });
+        map.update_residency(ivec2(0, 0)).unwrap();
+        map.update_residency(ivec2(4, 0)).unwrap();
+        assert_eq!(resident(&map), [ivec2(0, 0), ivec2(1, 0)]);
+        map.update_residency(ivec2(8, 0)).unwrap();
+        assert_eq!(resident(&map), [ivec2(1, 0), ivec2(2, 0)]);
+
+        // coming back to (1, 0) makes (2, 0) the oldest
+        map.update_residency(ivec2(4, 0)).unwrap();
+        map.update_residency(ivec2(4, 4)).unwrap();
+        assert_eq!(resident(&map), [ivec2(1, 0), ivec2(1, 1)]);
+        assert_eq!(provider.0.borrow().loads.len(), 4);
+    }
+
+    #[test]
+    fn edited_chunks_are_stored_when_evicted_or_flushed() {
+        let (mut map, provider) = counted(0, Eviction::OutsideRadius);
+        map.update_residency(ivec2(0, 0)).unwrap();
+        assert!(map.set("wall", ivec2(2, 1), 7));
+        // cells of chunks that aren't resident can't be written
+        assert!(!map.set("wall", ivec2(6, 1), 7));
+
+        map.update_residency(ivec2(4, 0)).unwrap();
+        assert_eq!(take(&mut provider.0.borrow_mut().stores), [ivec2(0, 0)]);
+        // the clean chunk goes without a store
+        map.update_residency(ivec2(8, 0)).unwrap();
+        assert!(provider.0.borrow().stores.is_empty());
+
+        map.set("wall", ivec2(9, 2), 3);
+        map.flush().unwrap();
+        map.flush().unwrap();
+        assert_eq!(take(&mut provider.0.borrow_mut().stores), [ivec2(2, 0)]);
+
+        // edits come back with the chunk
+        map.update_residency(ivec2(0, 0)).unwrap();
+        assert_eq!(map.get_wall(ivec2(2, 1)), Some(7));
+        assert!(provider.0.borrow().stores.is_empty());
+    }
+
+    #[test]
+    fn worlds_load_chunks_as_the_player_walks() {
+        let (map, provider) = counted(1, Eviction::OutsideRadius);
+        let mut world = AtlasWorld::new(Player::new(1, 1, Direction::East), map, AtlasCollection::new());
+        assert_eq!(resident(&world.map), [ivec2(0, 0), ivec2(1, 0), ivec2(0, 1), ivec2(1, 1)]);
+
+        for _ in 0..8 {
+            world.take_turn(PlayerAction::Move(Movement::Forward));
+        }
+        assert_eq!((world.player.x, world.player.y), (9, 1));
+        // (2, 0) and (2, 1) came in when the player reached chunk (1, 0); (0, 0) and (0, 1)
+        // left when they reached chunk (2, 0)
+        assert_eq!(take(&mut provider.0.borrow_mut().loads), [
+            ivec2(0, 0), ivec2(1, 0), ivec2(2, 0), ivec2(0, 1), ivec2(1, 1), ivec2(2, 1),
+        ]);
+        assert_eq!(resident(&world.map), [ivec2(1, 0), ivec2(2, 0), ivec2(1, 1), ivec2(2, 1)]);
+    }
+
+    // a directory of 4x4 chunks: (0, 0) fine, (1, 0) the wrong size, (0, 1) not json and
+    // (1, 1) missing
+    fn provider(name: &str) -> DirectoryProvider {
+        let provider = DirectoryProvider::new(std::env::temp_dir().join(format!("atlas_world_chunks_{name}_{}", std::process::id())));
+        let _ = fs::remove_dir_all(&provider.dir);
+        fs::create_dir_all(&provider.dir).unwrap();
+        fs::write(provider.chunk_path(ivec2(0, 0)), serde_json::to_string(&AtlasMap::empty(4, 4)).unwrap()).unwrap();
+        fs::write(provider.chunk_path(ivec2(1, 0)), serde_json::to_string(&AtlasMap::empty(3, 4)).unwrap()).unwrap();
+        fs::write(provider.chunk_path(ivec2(0, 1)), "{ not json").unwrap();
+        provider
+    }
+
+    #[test]
+    fn directory_chunks_tell_missing_from_broken() {
+        let mut provider = provider("load");
+        assert!(provider.load_chunk(ivec2(0, 0), 4).unwrap().is_some());
+        assert!(provider.load_chunk(ivec2(1, 1), 4).unwrap().is_none());
+        for chunk in [ivec2(1, 0), ivec2(0, 1)] {
+            assert_eq!(provider.load_chunk(chunk, 4).unwrap_err().kind(), io::ErrorKind::InvalidData);
+        }
+        let _ = fs::remove_dir_all(&provider.dir);
+    }
+
+    #[test]
+    fn broken_chunks_are_reported_and_left_unavailable() {
+        let provider = provider("residency");
+        let dir = provider.dir.clone();
+        let mut map = ChunkedMap::new(8, 8, 4, provider);
+        assert!(map.update_residency(ivec2(0, 0)).is_err());
+        let mut resident: Vec<IVec2> = map.resident_chunks().collect();
+        resident.sort_by_key(|chunk| (chunk.y, chunk.x));
+        assert_eq!(resident, vec![ivec2(0, 0)]);
+        assert_eq!(map.get_wall(ivec2(5, 0)), None);
+        let _ = fs::remove_dir_all(dir);
+    }
+
+    #[test]
+    fn store_and_load_round_trip() {
+        let mut provider = provider("store");
+        let mut chunk = AtlasMap::empty(4, 4);
+        chunk.wall.set(1, 2, 9);
+        provider.store_chunk(ivec2(1, 1), &chunk).unwrap();
+        assert_eq!(provider.load_chunk(ivec2(1, 1), 4).unwrap(), Some(chunk));
+        let _ = fs::remove_dir_all(&provider.dir);
+    }
+}
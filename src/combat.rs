@@ -0,0 +1,103 @@
+//! Melee against the facing cell. Damage is the attacker's `attack` less the defender's
+//! `defense`, so fights play out the same every time; anything fancier is up to the game.
+
+use serde::{Deserialize, Serialize};
+
+use crate::math::*;
+use crate::{encounter::EntityId, events::WorldEvent, AtlasWorld, MapSource};
+
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Debug)]
+pub struct Stats {
+    /// dead at 0
+    pub hp: i32,
+    pub attack: i32,
+    pub defense: i32,
+}
+
+impl Default for Stats {
+    fn default() -> Self {
+        Self { hp: 10, attack: 2, defense: 0 }
+    }
+}
+
+impl Stats {
+    pub fn new(hp: i32, attack: i32, defense: i32) -> Self {
+        Self { hp, attack, defense }
+    }
+
+    /// what a hit from `attacker` takes off these stats, 0 when the defense soaks all of it
+    pub fn damage_from(&self, attacker: &Stats) -> i32 {
+        attacker.attack.saturating_sub(self.defense).max(0)
+    }
+
+    pub fn is_dead(&self) -> bool {
+        self.hp <= 0
+    }
+}
+
+/// How an attack went.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum AttackResult {
+    /// nothing to hit in the facing cell
+    NoTarget,
+    /// the target's defense soaked the whole hit
+    Miss,
+    Hit { damage: i32 },
+    /// the target entity died and was removed
+    Killed,
+    /// a `destructible_objects` object was knocked out of its cell
+    Destroyed { object: u8 },
+}
+
+impl<M: MapSource> AtlasWorld<M> {
+    /// Attacks the facing cell with the player's stats: the first entity there that isn't
+    /// defeated, otherwise a destructible object. An entity brought to 0 hp is removed, emitting
+    /// `EntityDied`; a destroyed object is cleared, emitting `ObjectDestroyed`.
+    pub fn attack(&mut self) -> AttackResult {
+        let pos = self.player.facing_cell();
+        let attacker = self.player.stats;
+        if let Some(id) = self.attack_target() {
+            return self.hit_entity(id, &attacker);
+        }
+
+        let object = self.map.get("object", pos).unwrap_or(0);
+        if object != 0 && self.destructible_objects.contains(&object) && self.set_cell("object", pos, 0) {
+            self.push_event(WorldEvent::ObjectDestroyed { id: object, pos });
+            return AttackResult::Destroyed { object };
+        }
+        AttackResult::NoTarget
+    }
+
+    fn hit_entity(&mut self, id: EntityId, attacker: &Stats) -> AttackResult {
+        let Some(entity) = self.entity_mut(id) else {
+            return AttackResult::NoTarget;
+        };
+        let damage = entity.stats.damage_from(attacker);
+        if damage == 0 {
+            return AttackResult::Miss;
+        }
+        entity.stats.hp = entity.stats.hp.saturating_sub(damage);
+        if !entity.stats.is_dead() {
+            return AttackResult::Hit { damage };
+        }
+        if let Some(entity) = self.remove_entity(id) {
+            self.push_event(WorldEvent::EntityDied { id, pos: entity.pos });
+        }
+        AttackResult::Killed
+    }
+
+    /// Entity `id` hits the player, for a game answering `EntityAdjacent` or `EntityContact`.
+    /// The player's hp can go to 0 or below; what that means is up to the game.
+    pub fn attack_player(&mut self, id: EntityId) -> AttackResult {
+        let Some(attacker) = self.entity(id).filter(|entity| !self.is_defeated(entity.id)).map(|entity| entity.stats) else {
+            return AttackResult::NoTarget;
+        };
+        let damage = self.player.stats.damage_from(&attacker);
+        if damage == 0 {
+            return AttackResult::Miss;
+        }
+        self.player.stats.hp = self.player.stats.hp.saturating_sub(damage);
+        self.push_event(WorldEvent::PlayerHit { entity: id, damage });
+        AttackResult::Hit { damage }
+    }
+}
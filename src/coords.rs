@@ -0,0 +1,392 @@
+//! World-space conventions.
+//!
+//! Map cells are addressed as `(x, y)` with `x` growing east and `y` growing *down* the rows of
+//! the map, so north is `-y`. [`Direction::to_vec`] is the one place that turns a facing into a
+//! grid step; everything else (movement, view offsets) is built on top of it.
+
+use serde::{Deserialize, Serialize};
+
+use crate::math::*;
+
+pub const GRID_NORTH: IVec2 = IVec2::new(0, -1);
+pub const GRID_EAST: IVec2 = IVec2::new(1, 0);
+pub const GRID_SOUTH: IVec2 = IVec2::new(0, 1);
+pub const GRID_WEST: IVec2 = IVec2::new(-1, 0);
+
+/// A facing on the grid. Turning right goes North -> East -> South -> West.
+///
+/// With the `diagonal` feature the four diagonals sit between them and every turn is 45°.
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub enum Direction {
+    North,
+    #[cfg(feature = "diagonal")]
+    NorthEast,
+    East,
+    #[cfg(feature = "diagonal")]
+    SouthEast,
+    South,
+    #[cfg(feature = "diagonal")]
+    SouthWest,
+    West,
+    #[cfg(feature = "diagonal")]
+    NorthWest,
+}
+
+impl Direction {
+    /// every facing, clockwise from north
+    #[cfg(not(feature = "diagonal"))]
+    pub const ALL: [Direction; 4] = [Direction::North, Direction::East, Direction::South, Direction::West];
+
+    /// every facing, clockwise from north
+    #[cfg(feature = "diagonal")]
+    pub const ALL: [Direction; 8] = [
+        Direction::North,
+        Direction::NorthEast,
+        Direction::East,
+        Direction::SouthEast,
+        Direction::South,
+        Direction::SouthWest,
+        Direction::West,
+        Direction::NorthWest,
+    ];
+
+    pub const CARDINALS: [Direction; 4] = [Direction::North, Direction::East, Direction::South, Direction::West];
+
+    // position in `ALL`, which is also the number of turn steps clockwise from north
+    fn ring_index(self) -> usize {
+        Self::ALL.iter().position(|d| *d == self).unwrap()
+    }
+
+    fn rotated(self, steps: isize) -> Self {
+        let len = Self::ALL.len() as isize;
+        Self::ALL[(self.ring_index() as isize + steps).rem_euclid(len) as usize]
+    }
+
+    /// The integer facing used by older saves and tools: 0 north, 1 east, 2 south, 3 west.
+    /// Diagonals continue clockwise from 4 (north east) to 7 (north west), so the cardinal
+    /// numbers are the same with or without the `diagonal` feature.
+    pub fn to_index(self) -> i32 {
+        match self {
+            Direction::North => 0,
+            Direction::East => 1,
+            Direction::South => 2,
+            Direction::West => 3,
+            #[cfg(feature = "diagonal")]
+            Direction::NorthEast => 4,
+            #[cfg(feature = "diagonal")]
+            Direction::SouthEast => 5,
+            #[cfg(feature = "diagonal")]
+            Direction::SouthWest => 6,
+            #[cfg(feature = "diagonal")]
+            Direction::NorthWest => 7,
+        }
+    }
+
+    /// how far round a full clockwise turn from north this facing is, in 0..1
+    pub fn heading(self) -> f32 {
+        self.ring_index() as f32 / Self::ALL.len() as f32
+    }
+
+    /// the facing `to_index` gives `index` for, `None` for anything else
+    pub fn from_index(index: i32) -> Option<Self> {
+        Self::ALL.into_iter().find(|direction| direction.to_index() == index)
+    }
+
+    /// the direction a one cell step of `v` goes in, `None` for anything else
+    pub fn from_vec(v: IVec2) -> Option<Self> {
+        Self::ALL.into_iter().find(|direction| direction.to_vec() == v)
+    }
+
+    /// one cell step in this direction, in grid coordinates
+    pub fn to_vec(self) -> IVec2 {
+        match self {
+            Direction::North => GRID_NORTH,
+            Direction::East => GRID_EAST,
+            Direction::South => GRID_SOUTH,
+            Direction::West => GRID_WEST,
+            #[cfg(feature = "diagonal")]
+            Direction::NorthEast => GRID_NORTH + GRID_EAST,
+            #[cfg(feature = "diagonal")]
+            Direction::SouthEast => GRID_SOUTH + GRID_EAST,
+            #[cfg(feature = "diagonal")]
+            Direction::SouthWest => GRID_SOUTH + GRID_WEST,
+            #[cfg(feature = "diagonal")]
+            Direction::NorthWest => GRID_NORTH + GRID_WEST,
+        }
+    }
+
+    pub fn is_diagonal(self) -> bool {
+        let v = self.to_vec();
+        v.x != 0 && v.y != 0
+    }
+
+    /// one turn step to the left (90°, or 45° with the `diagonal` feature)
+    pub fn turn_left(self) -> Self {
+        self.rotated(-1)
+    }
+
+    /// one turn step to the right (90°, or 45° with the `diagonal` feature)
+    pub fn turn_right(self) -> Self {
+        self.rotated(1)
+    }
+
+    /// 90° to the left regardless of how fine turning is, used for strafing
+    pub fn quarter_left(self) -> Self {
+        self.rotated(-(Self::ALL.len() as isize / 4))
+    }
+
+    /// 90° to the right regardless of how fine turning is, used for strafing
+    pub fn quarter_right(self) -> Self {
+        self.rotated(Self::ALL.len() as isize / 4)
+    }
+
+    pub fn opposite(self) -> Self {
+        self.rotated(Self::ALL.len() as isize / 2)
+    }
+
+    /// The closest of the four cardinal directions. A diagonal resolves to the cardinal
+    /// counter-clockwise of it, so NorthEast becomes North.
+    pub fn nearest_cardinal(self) -> Self {
+        Self::CARDINALS[self.ring_index() * 4 / Self::ALL.len()]
+    }
+
+    /// The map cell at view-space `(x, z)` for a viewer at `origin` facing this way:
+    /// `x` is cells to the viewer's right, `z` is cells toward the viewer (so ahead is negative).
+    ///
+    /// The view basis is `forward = to_vec()` and `right` is `forward` rotated a quarter turn
+    /// clockwise. For a diagonal facing both are diagonal steps, so the view covers every
+    /// other cell of the grid; atlases drawn for diagonal facings are laid out for that lattice.
+    pub fn view_to_grid(self, origin: IVec2, x: i32, z: i32) -> IVec2 {
+        let forward = self.to_vec();
+        let right = ivec2(-forward.y, forward.x);
+        offset(offset(origin, right, x), -forward, z)
+    }
+}
+
+/// Where the view is drawn from: a cell and the way it faces. The player's own is
+/// `Player::view_point`, but any cell can be rendered with `AtlasWorld::render_from`.
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub struct ViewPoint {
+    pub pos: IVec2,
+    pub direction: Direction,
+}
+
+impl ViewPoint {
+    pub fn new(pos: IVec2, direction: Direction) -> Self {
+        Self { pos, direction }
+    }
+
+    /// the map cell at view-space `(x, z)`, see `Direction::view_to_grid`
+    pub fn get_direction_vector_offsets(&self, x: i32, z: i32) -> IVec2 {
+        self.direction.view_to_grid(self.pos, x, z)
+    }
+}
+
+/// `origin + v * n`, saturating at the i32 limits instead of overflowing
+pub fn offset(origin: IVec2, v: IVec2, n: i32) -> IVec2 {
+    // widened so `-1 * i32::MIN` doesn't saturate before the add brings it back in range
+    let axis = |origin: i32, v: i32| {
+        let exact = origin as i64 + v as i64 * n as i64;
+        exact.clamp(i32::MIN as i64, i32::MAX as i64) as i32
+    };
+    ivec2(axis(origin.x, v.x), axis(origin.y, v.y))
+}
+
+/// Which way the y axis of some external coordinate system points.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum YAxis {
+    /// rows counted from the top, same as the grid (Tiled, LDtk, images)
+    Down,
+    /// rows counted from the bottom ("math" convention)
+    Up,
+}
+
+/// converts a cell position from an external convention into grid coordinates
+pub fn to_grid(pos: IVec2, map_height: i32, axis: YAxis) -> IVec2 {
+    match axis {
+        YAxis::Down => pos,
+        YAxis::Up => ivec2(pos.x, map_height.saturating_sub(1).saturating_sub(pos.y)),
+    }
+}
+
+/// converts a grid cell position into an external convention
+pub fn from_grid(pos: IVec2, map_height: i32, axis: YAxis) -> IVec2 {
+    // the flip is its own inverse
+    to_grid(pos, map_height, axis)
+}
+
+/// converts a direction vector (not a position) between the grid and an external convention
+pub fn vec_to_grid(v: IVec2, axis: YAxis) -> IVec2 {
+    match axis {
+        YAxis::Down => v,
+        YAxis::Up => ivec2(v.x, v.y.saturating_neg()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::generator::Rng;
+    use crate::test_support::adversarial;
+
+    fn exact(origin: IVec2, v: IVec2, n: i32) -> (i64, i64) {
+        (origin.x as i64 + v.x as i64 * n as i64, origin.y as i64 + v.y as i64 * n as i64)
+    }
+
+    #[test]
+    fn offset_saturates_instead_of_overflowing() {
+        let mut rng = Rng::new(217);
+        for _ in 0..10_000 {
+            let origin = ivec2(adversarial(&mut rng), adversarial(&mut rng));
+            let direction = Direction::ALL[rng.below(Direction::ALL.len())];
+            let n = adversarial(&mut rng);
+            let moved = offset(origin, direction.to_vec(), n);
+            let (x, y) = exact(origin, direction.to_vec(), n);
+            let clamp = |v: i64| v.clamp(i32::MIN as i64, i32::MAX as i64) as i32;
+            assert_eq!(moved, ivec2(clamp(x), clamp(y)), "{origin} + {direction:?} * {n}");
+        }
+    }
+
+    #[test]
+    fn view_offsets_match_the_grid_steps() {
+        let mut rng = Rng::new(2170);
+        for _ in 0..10_000 {
+            let view = ViewPoint::new(ivec2(adversarial(&mut rng), adversarial(&mut rng)), Direction::ALL[rng.below(Direction::ALL.len())]);
+            let (x, z) = (adversarial(&mut rng), adversarial(&mut rng));
+            let cell = view.get_direction_vector_offsets(x, z);
+            assert_eq!(view.get_direction_vector_offsets(0, 0), view.pos);
+
+            // `pos + right * x - forward * z`, clamped to the i32 range
+            let forward = view.direction.to_vec();
+            let right = ivec2(-forward.y, forward.x);
+            let (ex, ey) = exact(view.pos, right, x);
+            let exact = |along: i64, step: i32| (along - step as i64 * z as i64).clamp(i32::MIN as i64, i32::MAX as i64) as i32;
+            // one clamp per step, so only compare when the first one didn't
+            if i32::try_from(ex).is_ok() && i32::try_from(ey).is_ok() {
+                assert_eq!(cell, ivec2(exact(ex, forward.x), exact(ey, forward.y)), "{view:?} ({x}, {z})");
+            }
+        }
+    }
+
+    #[test]
+    fn y_axis_flips_round_trip_inside_the_map() {
+        let mut rng = Rng::new(2171);
+        for _ in 0..10_000 {
+            let height = rng.range(1, 4096) as i32;
+            let pos = ivec2(adversarial(&mut rng), rng.below(height as usize) as i32);
+            assert_eq!(from_grid(to_grid(pos, height, YAxis::Up), height, YAxis::Up), pos);
+            // outside the map only has to not panic
+            to_grid(ivec2(adversarial(&mut rng), adversarial(&mut rng)), adversarial(&mut rng), YAxis::Up);
+            vec_to_grid(ivec2(adversarial(&mut rng), adversarial(&mut rng)), YAxis::Up);
+        }
+    }
+
+    #[test]
+    fn the_grid_steps_are_named_by_direction() {
+        assert_eq!([GRID_NORTH, GRID_EAST, GRID_SOUTH, GRID_WEST], Direction::CARDINALS.map(Direction::to_vec));
+        assert_eq!(GRID_NORTH, ivec2(0, -1), "north is up the rows of the map");
+        let player = crate::Player::new(5, 5, Direction::North);
+        for direction in Direction::ALL {
+            assert_eq!(player.get_dest_pos(direction), ivec2(5, 5) + direction.to_vec());
+            // the cell ahead of a viewer is one row up the view
+            assert_eq!(ViewPoint::new(ivec2(5, 5), direction).get_direction_vector_offsets(0, -1), ivec2(5, 5) + direction.to_vec());
+        }
+    }
+
+    // a step there and back lands where it started, however the direction is written down
+    #[test]
+    fn steps_there_and_back_return_to_the_start() {
+        let mut rng = Rng::new(213);
+        for _ in 0..10_000 {
+            let height = rng.range(1, 512) as i32;
+            let start = ivec2(rng.range(0, 512) as i32, rng.below(height as usize) as i32);
+            let direction = Direction::ALL[rng.below(Direction::ALL.len())];
+            let back = direction.opposite();
+
+            assert_eq!(start + direction.to_vec() + back.to_vec(), start, "{direction:?}");
+            let by_index = Direction::from_index(direction.to_index()).unwrap();
+            let by_vec = Direction::from_vec(direction.to_vec()).unwrap();
+            assert_eq!((by_index, by_vec), (direction, direction));
+            assert_eq!(back.to_vec(), -direction.to_vec());
+            assert_eq!(direction.turn_right().turn_left(), direction);
+            assert_eq!(direction.quarter_right().quarter_right(), back);
+
+            // the same walk in a y-up tool's coordinates
+            let math = from_grid(start, height, YAxis::Up);
+            let math_step = vec_to_grid(direction.to_vec(), YAxis::Up);
+            assert_eq!(to_grid(math + math_step, height, YAxis::Up), start + direction.to_vec());
+            assert_eq!(to_grid(math + math_step - math_step, height, YAxis::Up), start);
+            assert_eq!(vec_to_grid(math_step, YAxis::Up), direction.to_vec());
+            assert_eq!(to_grid(start, height, YAxis::Down), start);
+        }
+    }
+
+    #[cfg(feature = "diagonal")]
+    #[test]
+    fn diagonals_turn_in_eighths() {
+        let mut direction = Direction::North;
+        let mut ring = Vec::new();
+        for _ in 0..8 {
+            ring.push(direction);
+            direction = direction.turn_right();
+        }
+        assert_eq!(ring, Direction::ALL);
+        assert_eq!(direction, Direction::North);
+        assert_eq!(Direction::North.turn_left(), Direction::NorthWest);
+
+        for direction in Direction::ALL {
+            assert_eq!(direction.quarter_right(), direction.turn_right().turn_right());
+            assert_eq!(direction.quarter_left(), direction.turn_left().turn_left());
+            assert_eq!(direction.opposite(), direction.quarter_right().quarter_right());
+            assert_eq!(direction.opposite().opposite(), direction);
+            assert_eq!(Direction::from_index(direction.to_index()), Some(direction));
+            assert!((direction.heading() * 8.0 - direction.ring_index() as f32).abs() < 1e-6);
+        }
+        assert_eq!(Direction::ALL.map(Direction::to_index), [0, 4, 1, 5, 2, 6, 3, 7]);
+        assert_eq!(Direction::from_index(8), None);
+    }
+
+    #[cfg(feature = "diagonal")]
+    #[test]
+    fn diagonal_steps_are_one_cell_on_each_axis() {
+        let steps = [
+            (Direction::NorthEast, ivec2(1, -1)),
+            (Direction::SouthEast, ivec2(1, 1)),
+            (Direction::SouthWest, ivec2(-1, 1)),
+            (Direction::NorthWest, ivec2(-1, -1)),
+        ];
+        for (direction, step) in steps {
+            assert_eq!(direction.to_vec(), step);
+            assert!(direction.is_diagonal());
+            assert_eq!(Direction::from_vec(step), Some(direction));
+            // a diagonal is the sum of the cardinals either side of it
+            assert_eq!(direction.turn_left().to_vec() + direction.turn_right().to_vec(), step);
+        }
+        assert!(Direction::CARDINALS.iter().all(|direction| !direction.is_diagonal()));
+        assert_eq!(Direction::from_vec(ivec2(2, -2)), None);
+    }
+
+    #[cfg(feature = "diagonal")]
+    #[test]
+    fn diagonal_views_are_rotated_by_an_eighth() {
+        let origin = ivec2(10, 10);
+        for direction in Direction::ALL {
+            let view = ViewPoint::new(origin, direction);
+            // right is a quarter turn clockwise from forward, on the diagonals too
+            assert_eq!(view.get_direction_vector_offsets(0, -1), origin + direction.to_vec());
+            assert_eq!(view.get_direction_vector_offsets(1, 0), origin + direction.quarter_right().to_vec());
+            assert_eq!(view.get_direction_vector_offsets(-1, 0), origin + direction.quarter_left().to_vec());
+            assert_eq!(view.get_direction_vector_offsets(0, 1), origin + direction.opposite().to_vec());
+        }
+        let north_east = ViewPoint::new(origin, Direction::NorthEast);
+        assert_eq!(north_east.get_direction_vector_offsets(1, -2), origin + ivec2(3, -1));
+    }
+
+    #[cfg(feature = "diagonal")]
+    #[test]
+    fn diagonals_resolve_to_the_cardinal_counter_clockwise() {
+        let nearest = Direction::ALL.map(Direction::nearest_cardinal);
+        use Direction::*;
+        assert_eq!(nearest, [North, North, East, East, South, South, West, West]);
+    }
+}
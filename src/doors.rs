@@ -0,0 +1,123 @@
+//! Doors: a "doors" map layer holding door types, 0 for none, and which of them stand open.
+//! Doors start out closed and only the world knows which are open, so a bare map treats
+//! every door as closed.
+
+use ahash::AHashSet;
+
+use crate::math::*;
+use crate::{events::WorldEvent, AtlasWorld, MapSource, Orientation};
+
+/// A map as seen through the world's door state, with open doors passable and see-through.
+pub struct DoorView<'a, M: MapSource + ?Sized> {
+    pub map: &'a M,
+    pub open: &'a AHashSet<IVec2>,
+}
+
+impl<M: MapSource + ?Sized> MapSource for DoorView<'_, M> {
+    fn width(&self) -> usize {
+        self.map.width()
+    }
+
+    fn height(&self) -> usize {
+        self.map.height()
+    }
+
+    fn get(&self, layer: &str, pos: IVec2) -> Option<u8> {
+        self.map.get(layer, pos)
+    }
+
+    /// the view is read only
+    fn set(&mut self, _layer: &str, _pos: IVec2, _value: u8) -> bool {
+        false
+    }
+
+    fn is_door_closed(&self, pos: IVec2) -> bool {
+        self.map.is_door_closed(pos) && !self.open.contains(&pos)
+    }
+}
+
+impl<M: MapSource> AtlasWorld<M> {
+    /// the map with the world's open doors passable, what the player moves through
+    pub fn door_view(&self) -> DoorView<'_, M> {
+        DoorView { map: &self.map, open: &self.open_doors }
+    }
+
+    /// the door type at `pos`, `None` where there is no door
+    pub fn door_at(&self, pos: IVec2) -> Option<u8> {
+        self.map.get("doors", pos).filter(|value| *value != 0)
+    }
+
+    pub fn is_door_open(&self, pos: IVec2) -> bool {
+        self.open_doors.contains(&pos)
+    }
+
+    pub fn is_door_closed(&self, pos: IVec2) -> bool {
+        self.door_at(pos).is_some() && !self.is_door_open(pos)
+    }
+
+    /// Opens or closes the door at `pos` and returns whether it is open now. `None` when there
+    /// is no door there, or it would close on the player.
+    pub fn toggle_door_at(&mut self, pos: IVec2) -> Option<bool> {
+        let value = self.door_at(pos)?;
+        let open = !self.open_doors.remove(&pos);
+        if !open && pos == ivec2(self.player.x, self.player.y) {
+            self.open_doors.insert(pos);
+            return None;
+        }
+        if open {
+            self.open_doors.insert(pos);
+        }
+        self.touch_map();
+        self.push_event(WorldEvent::DoorToggled { pos, value, open });
+        Some(open)
+    }
+
+    /// the open doors, sorted
+    pub fn open_doors(&self) -> Vec<IVec2> {
+        let mut open: Vec<IVec2> = self.open_doors.iter().copied().collect();
+        open.sort_by_key(|pos| (pos.y, pos.x));
+        open
+    }
+
+    /// Replaces which doors are open. Positions without a door are dropped.
+    pub fn set_open_doors(&mut self, open: impl IntoIterator<Item = IVec2>) {
+        self.open_doors = open.into_iter().filter(|pos| self.door_at(*pos).is_some()).collect();
+        self.touch_map();
+    }
+
+    /// Closes the doors that have stood open for `door_close_turns`, starting the count for
+    /// doors opened since the last call. A door the player stands in waits for them to leave.
+    pub(crate) fn close_expired_doors(&mut self) {
+        let Some(close_turns) = self.door_close_turns else {
+            self.door_timers.clear();
+            return;
+        };
+        let open_doors = &self.open_doors;
+        self.door_timers.retain(|pos, _| open_doors.contains(pos));
+        for pos in &self.open_doors {
+            self.door_timers.entry(*pos).or_insert(self.turn);
+        }
+
+        let mut expired: Vec<IVec2> = self.door_timers.iter()
+            .filter(|(_, opened)| self.turn - **opened >= close_turns as u64)
+            .map(|(pos, _)| *pos)
+            .collect();
+        expired.sort_by_key(|pos| (pos.y, pos.x));
+        for pos in expired {
+            if self.toggle_door_at(pos) == Some(false) {
+                self.door_timers.remove(&pos);
+            }
+        }
+    }
+
+    /// draws the closed door in view cell (x, z) like a front wall
+    pub fn draw_door(&self, x: i32, z: i32) {
+        let p = self.view_cell(x, z);
+        let (Some(value), Some(binding)) = (self.door_at(p), self.render_bindings.get("doors")) else {
+            return;
+        };
+        if !self.is_door_open(p) {
+            self.draw_layer(&binding.atlas_id, binding.layer(value), x, z, Some(Orientation::Front));
+        }
+    }
+}
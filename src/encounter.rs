@@ -0,0 +1,120 @@
+use ahash::AHashSet;
+use serde::{Deserialize, Serialize};
+
+use crate::{events::WorldEvent, AtlasWorld, MapSource};
+
+/// Identifies an entity for as long as the world exists, including after it is defeated.
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord, Debug)]
+pub struct EntityId(pub u32);
+
+/// The glue around a fight: who is in it and where it started. The rules of the fight itself
+/// are up to the game.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Encounter {
+    pub participants: Vec<EntityId>,
+    /// `None` when the encounter wasn't started by an entity (a trigger, a random roll)
+    pub initiated_by: Option<EntityId>,
+    pub zone: Option<String>,
+}
+
+impl Encounter {
+    /// the encounter an `EncounterTriggered` or `EntityContact` event asks for
+    pub fn from_event(event: &WorldEvent) -> Option<Self> {
+        match event {
+            WorldEvent::EncounterTriggered { participants, zone } => Some(Self {
+                participants: participants.clone(),
+                initiated_by: None,
+                zone: zone.clone(),
+            }),
+            WorldEvent::EntityContact { entity, .. } => Some(Self {
+                participants: vec![*entity],
+                initiated_by: Some(*entity),
+                zone: None,
+            }),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct EncounterOutcome {
+    /// participants that are gone for good
+    pub defeated: Vec<EntityId>,
+    pub player_fled: bool,
+}
+
+impl<M: MapSource> AtlasWorld<M> {
+    /// Starts an encounter unless one is already running. While it runs entity AI, triggers,
+    /// warps, traps and new encounters are paused and the automap stops revealing; rendering
+    /// goes on.
+    pub fn start_encounter(&mut self, encounter: Encounter) -> bool {
+        if self.encounter.is_some() {
+            return false;
+        }
+        self.push_event(WorldEvent::EncounterStarted { participants: encounter.participants.clone() });
+        self.encounter = Some(encounter);
+        true
+    }
+
+    pub fn in_encounter(&self) -> bool {
+        self.encounter.is_some()
+    }
+
+    pub fn encounter(&self) -> Option<&Encounter> {
+        self.encounter.as_ref()
+    }
+
+    /// Ends the running encounter, remembering its defeated participants so they stay gone.
+    pub fn end_encounter(&mut self, outcome: EncounterOutcome) -> Option<Encounter> {
+        let encounter = self.encounter.take()?;
+        self.defeated.extend(outcome.defeated.iter().copied());
+        self.push_event(WorldEvent::EncounterEnded { outcome });
+        Some(encounter)
+    }
+
+    pub fn is_defeated(&self, entity: EntityId) -> bool {
+        self.defeated.contains(&entity)
+    }
+
+    pub fn defeated(&self) -> &AHashSet<EntityId> {
+        &self.defeated
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{AtlasCollection, AtlasMap, Direction, Grid, Player, Warp};
+
+    // the player at (1, 1) facing a trigger and a warp at (2, 1)
+    fn world() -> AtlasWorld {
+        let mut map = AtlasMap::empty(5, 5);
+        let mut triggers = Grid::new(5, 5);
+        triggers.set(2, 1, 7);
+        map.layers.insert("trigger".to_owned(), triggers);
+        let mut world = AtlasWorld::new(Player::new(1, 1, Direction::East), map, AtlasCollection::new());
+        world.warps.push(Warp { x: 2, y: 1, dest_level: None, dest_x: 4, dest_y: 4, direction: None, kind: None });
+        world
+    }
+
+    fn entered_trigger(world: &mut AtlasWorld) -> bool {
+        world.poll_events().any(|event| matches!(event, WorldEvent::TriggerEntered { id: 7, .. }))
+    }
+
+    #[test]
+    fn triggers_and_warps_wait_during_an_encounter() {
+        let mut world = world();
+        assert!(world.start_encounter(Encounter { participants: Vec::new(), initiated_by: None, zone: None }));
+        world.try_move(Direction::East);
+        assert_eq!((world.player.x, world.player.y), (2, 1));
+        assert!(!entered_trigger(&mut world));
+    }
+
+    #[test]
+    fn triggers_and_warps_go_off_otherwise() {
+        let mut world = world();
+        world.try_move(Direction::East);
+        assert_eq!((world.player.x, world.player.y), (4, 4));
+        assert!(entered_trigger(&mut world));
+    }
+}
@@ -0,0 +1,127 @@
+//! Things that stand in cells without being part of the map: monsters, NPCs, dropped items.
+//! They are drawn in the object pass of their cell, after the cell's own object.
+
+use crate::math::*;
+use crate::{encounter::EntityId, AtlasWorld, Behavior, Direction, MapSource, Stats, OBJECT_ORIENTATIONS};
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Entity {
+    /// assigned by `AtlasWorld::add_entity`
+    pub id: EntityId,
+    pub pos: IVec2,
+    pub facing: Direction,
+    pub atlas_id: String,
+    /// drawn like an `object-{n}` layer, with the orientation picked from `facing`
+    pub layer_id: String,
+    /// keeps the player out of its cell while `MovementRules::entities_block` is on
+    pub blocks_movement: bool,
+    /// what it does on the world's turn, see `AtlasWorld::update_entities`
+    pub behavior: Behavior,
+    pub stats: Stats,
+}
+
+impl Entity {
+    pub fn new(pos: IVec2, facing: Direction, atlas_id: &str, layer_id: &str) -> Self {
+        Self {
+            id: EntityId(0),
+            pos,
+            facing,
+            atlas_id: atlas_id.to_owned(),
+            layer_id: layer_id.to_owned(),
+            blocks_movement: true,
+            behavior: Behavior::Idle,
+            stats: Stats::default(),
+        }
+    }
+}
+
+/// A map with the cells of blocking entities closed off.
+pub struct EntityView<'a, M: MapSource> {
+    pub map: M,
+    pub entities: &'a [Entity],
+}
+
+impl<M: MapSource> MapSource for EntityView<'_, M> {
+    fn width(&self) -> usize {
+        self.map.width()
+    }
+
+    fn height(&self) -> usize {
+        self.map.height()
+    }
+
+    fn get(&self, layer: &str, pos: IVec2) -> Option<u8> {
+        self.map.get(layer, pos)
+    }
+
+    /// the view is read only
+    fn set(&mut self, _layer: &str, _pos: IVec2, _value: u8) -> bool {
+        false
+    }
+
+    fn is_door_closed(&self, pos: IVec2) -> bool {
+        self.map.is_door_closed(pos)
+    }
+
+    fn is_passable(&self, pos: IVec2) -> bool {
+        self.map.is_passable(pos) && !self.entities.iter().any(|entity| entity.blocks_movement && entity.pos == pos)
+    }
+}
+
+impl<M: MapSource> AtlasWorld<M> {
+    /// Adds an entity under a new id, which is returned. Whatever id it had is replaced.
+    pub fn add_entity(&mut self, mut entity: Entity) -> EntityId {
+        let id = EntityId(self.next_entity_id);
+        self.next_entity_id += 1;
+        entity.id = id;
+        self.entities.push(entity);
+        id
+    }
+
+    pub fn remove_entity(&mut self, id: EntityId) -> Option<Entity> {
+        let index = self.entities.iter().position(|entity| entity.id == id)?;
+        Some(self.entities.remove(index))
+    }
+
+    /// Puts an entity in another cell, without any checks. Returns false for an unknown id.
+    pub fn move_entity(&mut self, id: EntityId, pos: IVec2) -> bool {
+        match self.entity_mut(id) {
+            Some(entity) => {
+                entity.pos = pos;
+                true
+            }
+            None => false,
+        }
+    }
+
+    pub fn entity(&self, id: EntityId) -> Option<&Entity> {
+        self.entities.iter().find(|entity| entity.id == id)
+    }
+
+    pub fn entity_mut(&mut self, id: EntityId) -> Option<&mut Entity> {
+        self.entities.iter_mut().find(|entity| entity.id == id)
+    }
+
+    /// every entity, in the order they were added
+    pub fn entities(&self) -> &[Entity] {
+        &self.entities
+    }
+
+    pub fn entities_at(&self, pos: IVec2) -> impl Iterator<Item = &Entity> {
+        self.entities.iter().filter(move |entity| entity.pos == pos)
+    }
+
+    /// Draws the entities in view cell (x, z) that aren't defeated. An entity facing the
+    /// player shows its `front` tiles, like an object seen while facing north.
+    pub fn draw_entities(&self, x: i32, z: i32) {
+        let p = self.view_cell(x, z);
+        let view = self.view_direction().nearest_cardinal().to_index();
+        for entity in self.entities_at(p).filter(|entity| !self.is_defeated(entity.id)) {
+            let relative = Direction::from_index((view - entity.facing.nearest_cardinal().to_index() + 2).rem_euclid(4));
+            let orientation = OBJECT_ORIENTATIONS.iter()
+                .find(|(direction, _)| Some(*direction) == relative)
+                .map(|(_, orientation)| *orientation);
+            self.draw_tile(&entity.atlas_id, &entity.layer_id, x, z, orientation);
+        }
+    }
+}
@@ -0,0 +1,137 @@
+//! What happened in the world, queued for the game to react to: to play sounds, count stats
+//! or start fights. Everything the player does through `AtlasWorld` and every change the world
+//! makes to itself pushes one.
+
+use crate::math::*;
+use crate::{
+    encounter::{EncounterOutcome, EntityId},
+    AtlasWorld, Blocker, Direction, Interaction, MapSource,
+};
+
+/// Events queued at most by default, see `AtlasWorld::max_queued_events`.
+pub const DEFAULT_MAX_QUEUED_EVENTS: usize = 4096;
+
+/// Things that happened in the world since the game last called `poll_events`.
+#[derive(Clone, Debug, PartialEq)]
+pub enum WorldEvent {
+    /// the player stepped into `pos`, whose "floor" layer value is `floor`, e.g. for footsteps
+    Moved { pos: IVec2, floor: u8 },
+    /// the player stepped down into a pit at `pos`, `depth` below level, with
+    /// `MovementRules::fall_into_pits` on
+    Fell { pos: IVec2, depth: u8 },
+    /// the player stepped into `pos`, whose "trigger" layer value is `id`
+    TriggerEntered { id: u8, pos: IVec2 },
+    /// a move was blocked by the wall at `pos`
+    WallBumped { pos: IVec2, value: u8, sound: String },
+    /// a move into `pos` was stopped by something other than a wall or an entity
+    MoveBlocked { pos: IVec2, blocker: Blocker },
+    WallBashed { pos: IVec2, value: u8 },
+    /// the door of type `value` at `pos` opened or closed
+    DoorToggled { pos: IVec2, value: u8, open: bool },
+    /// `AtlasWorld::reveal_secret` cleared the secret wall `value` at `pos`
+    SecretRevealed { pos: IVec2, value: u8 },
+    /// `AtlasWorld::take_object` cleared object `id` from `pos`
+    ObjectTaken { id: u8, pos: IVec2 },
+    /// `AtlasWorld::attack` knocked object `id` out of `pos`
+    ObjectDestroyed { id: u8, pos: IVec2 },
+    /// `AtlasWorld::respawn_object` put object `id` at `pos`
+    ObjectPlaced { id: u8, pos: IVec2 },
+    /// `AtlasWorld::interact` used the object or wall at `pos`
+    Interacted { pos: IVec2, interaction: Interaction },
+    /// the player was sent from map `from` to map `to`, see `AtlasWorld::switch_level`
+    LevelChanged { from: String, to: String },
+    /// the player was sent from `from` to `to` on the same map
+    Teleported { from: IVec2, to: IVec2 },
+    /// a spinner at `pos` turned the player from `from` to `to`
+    Spun { pos: IVec2, from: Direction, to: Direction },
+    /// `about_face` marks a 180° quick turn so it can be animated as one spin
+    Turned { from: Direction, to: Direction, about_face: bool },
+    /// `AtlasWorld::advance_turn` ended a turn, `turn` being the count after it
+    TurnEnded { turn: u64, ticks: u32 },
+    /// something asks for a fight, see `Encounter::from_event`
+    EncounterTriggered { participants: Vec<EntityId>, zone: Option<String> },
+    /// an entity and the player ran into each other
+    EntityContact { entity: EntityId, pos: IVec2 },
+    /// `AtlasWorld::update_entities` moved entity `id`
+    EntityMoved { id: EntityId, from: IVec2, to: IVec2 },
+    /// a chasing entity ended its turn next to the player
+    EntityAdjacent { id: EntityId },
+    /// `AtlasWorld::attack` killed entity `id` at `pos`, which is gone from the world
+    EntityDied { id: EntityId, pos: IVec2 },
+    /// `AtlasWorld::attack_player` took `damage` off the player
+    PlayerHit { entity: EntityId, damage: i32 },
+    EncounterStarted { participants: Vec<EntityId> },
+    EncounterEnded { outcome: EncounterOutcome },
+    /// a game defined event, see `AtlasWorld::push_custom_event`
+    Custom { name: String },
+}
+
+impl WorldEvent {
+    /// the name stats are counted under
+    pub fn name(&self) -> &str {
+        match self {
+            WorldEvent::Moved { .. } => "moved",
+            WorldEvent::Fell { .. } => "fell",
+            WorldEvent::TriggerEntered { .. } => "trigger_entered",
+            WorldEvent::WallBumped { .. } => "wall_bumped",
+            WorldEvent::MoveBlocked { .. } => "move_blocked",
+            WorldEvent::WallBashed { .. } => "wall_bashed",
+            WorldEvent::DoorToggled { .. } => "door_toggled",
+            WorldEvent::SecretRevealed { .. } => "secret_revealed",
+            WorldEvent::ObjectTaken { .. } => "object_taken",
+            WorldEvent::ObjectDestroyed { .. } => "object_destroyed",
+            WorldEvent::ObjectPlaced { .. } => "object_placed",
+            WorldEvent::Interacted { .. } => "interacted",
+            WorldEvent::LevelChanged { .. } => "level_changed",
+            WorldEvent::Teleported { .. } => "teleported",
+            WorldEvent::Spun { .. } => "spun",
+            WorldEvent::Turned { .. } => "turned",
+            WorldEvent::TurnEnded { .. } => "turn_ended",
+            WorldEvent::EncounterTriggered { .. } => "encounter_triggered",
+            WorldEvent::EntityContact { .. } => "entity_contact",
+            WorldEvent::EntityMoved { .. } => "entity_moved",
+            WorldEvent::EntityAdjacent { .. } => "entity_adjacent",
+            WorldEvent::EntityDied { .. } => "entity_died",
+            WorldEvent::PlayerHit { .. } => "player_hit",
+            WorldEvent::EncounterStarted { .. } => "encounter_started",
+            WorldEvent::EncounterEnded { .. } => "encounter_ended",
+            WorldEvent::Custom { name } => name,
+        }
+    }
+}
+
+impl<M: MapSource> AtlasWorld<M> {
+    /// Queues `event`. A queue already holding `max_queued_events` drops its oldest event to
+    /// make room, so a game that never polls doesn't grow it forever.
+    pub fn push_event(&mut self, event: WorldEvent) {
+        while self.events.len() >= self.max_queued_events.max(1) {
+            self.events.pop_front();
+            self.dropped_events += 1;
+        }
+        if let Some(stats) = &mut self.stats {
+            stats.record(&event);
+        }
+        self.events.push_back(event);
+        self.events_pushed += 1;
+    }
+
+    /// events dropped unpolled because the queue was full, a sign `poll_events` isn't called
+    pub fn dropped_events(&self) -> u64 {
+        self.dropped_events
+    }
+
+    // the events pushed since `events_pushed` was `since` that are still queued, oldest first
+    pub(crate) fn events_since(&self, since: u64) -> impl Iterator<Item = &WorldEvent> {
+        let pushed = (self.events_pushed - since).min(self.events.len() as u64) as usize;
+        self.events.range(self.events.len() - pushed..)
+    }
+
+    pub fn push_custom_event<S: Into<String>>(&mut self, name: S) {
+        self.push_event(WorldEvent::Custom { name: name.into() });
+    }
+
+    /// drains every event queued since the last call
+    pub fn poll_events(&mut self) -> impl Iterator<Item = WorldEvent> + '_ {
+        self.events.drain(..)
+    }
+}
@@ -0,0 +1,363 @@
+//! Maps generated at runtime. Every generator is deterministic: the same size, config and seed
+//! always give the same map, and every open cell can be walked to from the spawn it suggests.
+
+use serde::{Deserialize, Serialize};
+
+use crate::math::*;
+use crate::{
+    pipeline::{ConnectivityScan, GenerateParams, MazeCarver},
+    AtlasMap, IRect, MapSource,
+};
+
+/// Xorshift seeded from a `u64`, enough for level generation and cheap to step.
+#[derive(Clone, Debug)]
+pub(crate) struct Rng {
+    state: u64,
+}
+
+impl Rng {
+    pub(crate) fn new(seed: u64) -> Self {
+        Self { state: seed.wrapping_mul(0x9E37_79B9_7F4A_7C15) | 1 }
+    }
+
+    pub(crate) fn next_u64(&mut self) -> u64 {
+        self.state ^= self.state << 13;
+        self.state ^= self.state >> 7;
+        self.state ^= self.state << 17;
+        self.state
+    }
+
+    /// a number in `0..n`, 0 when `n` is 0
+    pub(crate) fn below(&mut self, n: usize) -> usize {
+        if n == 0 { 0 } else { (self.next_u64() % n as u64) as usize }
+    }
+
+    /// a number in `min..=max`
+    pub(crate) fn range(&mut self, min: usize, max: usize) -> usize {
+        min + self.below(max.saturating_sub(min) + 1)
+    }
+
+    pub(crate) fn chance(&mut self, p: f32) -> bool {
+        ((self.next_u64() >> 40) as f32 / (1u64 << 24) as f32) < p
+    }
+
+    /// one of `values` with odds by weight, `None` when empty or all weights are 0
+    pub(crate) fn weighted<T: Copy>(&mut self, values: &[(T, u32)]) -> Option<T> {
+        let total: u64 = values.iter().map(|(_, weight)| *weight as u64).sum();
+        if total == 0 {
+            return None;
+        }
+        let mut roll = self.next_u64() % total;
+        values.iter().find(|(_, weight)| {
+            let hit = roll < *weight as u64;
+            roll = roll.saturating_sub(*weight as u64);
+            hit
+        }).map(|(value, _)| *value)
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(default)]
+pub struct GeneratorConfig {
+    /// wall value of solid cells
+    pub wall: u8,
+    /// floor values, one picked per room
+    pub floors: Vec<u8>,
+    /// ceiling values, one picked per room
+    pub ceilings: Vec<u8>,
+    /// (object value, weight) to pick objects from
+    pub objects: Vec<(u8, u32)>,
+    /// chance of an open cell getting an object, the spawn never does
+    pub object_chance: f32,
+    /// smallest and largest room side, walls not included
+    pub room_size: [usize; 2],
+    /// rooms tried before giving up on fitting more
+    pub room_attempts: usize,
+}
+
+impl Default for GeneratorConfig {
+    fn default() -> Self {
+        Self {
+            wall: 1,
+            floors: vec![1],
+            ceilings: vec![1],
+            objects: Vec::new(),
+            object_chance: 0.0,
+            room_size: [3, 8],
+            room_attempts: 40,
+        }
+    }
+}
+
+// a value from `palette`, 1 when it's empty
+fn pick(rng: &mut Rng, palette: &[u8]) -> u8 {
+    palette.get(rng.below(palette.len())).copied().unwrap_or(1)
+}
+
+#[derive(Clone, Debug)]
+pub struct GeneratedMap {
+    pub map: AtlasMap,
+    /// an open cell everything open can be reached from
+    pub spawn: IVec2,
+}
+
+pub trait MapGenerator {
+    fn generate(&self, width: usize, height: usize, config: &GeneratorConfig, seed: u64) -> GeneratedMap;
+}
+
+/// Rectangular rooms placed at random without overlapping, each joined to the one placed
+/// before it by an L-shaped corridor.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct RoomsAndCorridors;
+
+/// A maze carved by a recursive backtracker, corridors on odd cells, like `pipeline::generate_map`.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Maze;
+
+pub fn rooms_and_corridors(width: usize, height: usize, config: &GeneratorConfig, seed: u64) -> GeneratedMap {
+    RoomsAndCorridors.generate(width, height, config, seed)
+}
+
+pub fn maze(width: usize, height: usize, config: &GeneratorConfig, seed: u64) -> GeneratedMap {
+    Maze.generate(width, height, config, seed)
+}
+
+impl MapGenerator for RoomsAndCorridors {
+    fn generate(&self, width: usize, height: usize, config: &GeneratorConfig, seed: u64) -> GeneratedMap {
+        let (width, height) = (width.max(3), height.max(3));
+        let mut rng = Rng::new(seed);
+        let mut map = solid_map(width, height, config, &mut rng);
+
+        let [min_side, max_side] = config.room_size;
+        let min_side = min_side.max(1);
+        let (max_w, max_h) = (max_side.max(min_side).min(width - 2), max_side.max(min_side).min(height - 2));
+        let mut rooms: Vec<IRect> = Vec::new();
+        for _ in 0..config.room_attempts {
+            let w = rng.range(min_side.min(max_w), max_w) as i32;
+            let h = rng.range(min_side.min(max_h), max_h) as i32;
+            let x = rng.range(1, width - 1 - w as usize) as i32;
+            let y = rng.range(1, height - 1 - h as usize) as i32;
+            let room = IRect::new(x, y, w, h);
+            // rooms keep a wall between them
+            if rooms.iter().any(|other| rects_touch(other, &room)) {
+                continue;
+            }
+
+            carve(&mut map, room, config, &mut rng);
+            if let Some(previous) = rooms.last() {
+                let (from, to) = (rect_center(previous), rect_center(&room));
+                let corner = if rng.chance(0.5) { ivec2(to.x, from.y) } else { ivec2(from.x, to.y) };
+                carve_line(&mut map, from, corner);
+                carve_line(&mut map, corner, to);
+            }
+            rooms.push(room);
+        }
+        if rooms.is_empty() {
+            let room = IRect::new(1, 1, width as i32 - 2, height as i32 - 2);
+            carve(&mut map, room, config, &mut rng);
+            rooms.push(room);
+        }
+
+        let spawn = rect_center(&rooms[0]);
+        finish(map, spawn, config, &mut rng)
+    }
+}
+
+impl MapGenerator for Maze {
+    fn generate(&self, width: usize, height: usize, config: &GeneratorConfig, seed: u64) -> GeneratedMap {
+        let mut rng = Rng::new(seed);
+        let mut carver = MazeCarver::new(&GenerateParams { width, height, seed, wall: config.wall });
+        while !carver.step() {}
+
+        let mut map = carver.map;
+        let (floor, ceiling) = (pick(&mut rng, &config.floors), pick(&mut rng, &config.ceilings));
+        for (layer, value) in [("floor", floor), ("ceiling", ceiling)] {
+            map.fill_rect(layer, IRect::new(0, 0, map.width as i32, map.height as i32), value);
+        }
+        finish(map, ivec2(1, 1), config, &mut rng)
+    }
+}
+
+fn solid_map(width: usize, height: usize, config: &GeneratorConfig, rng: &mut Rng) -> AtlasMap {
+    let mut map = AtlasMap::empty(width, height);
+    let all = IRect::new(0, 0, width as i32, height as i32);
+    map.fill_rect("wall", all, config.wall);
+    map.fill_rect("floor", all, pick(rng, &config.floors));
+    map.fill_rect("ceiling", all, pick(rng, &config.ceilings));
+    map
+}
+
+fn carve(map: &mut AtlasMap, room: IRect, config: &GeneratorConfig, rng: &mut Rng) {
+    map.fill_rect("wall", room, 0);
+    map.fill_rect("floor", room, pick(rng, &config.floors));
+    map.fill_rect("ceiling", room, pick(rng, &config.ceilings));
+}
+
+// opens the straight run of cells from `from` to `to`, which share a row or column
+fn carve_line(map: &mut AtlasMap, from: IVec2, to: IVec2) {
+    let (min, max) = (from.min(to), from.max(to));
+    map.fill_rect("wall", IRect::new(min.x, min.y, max.x - min.x + 1, max.y - min.y + 1), 0);
+}
+
+fn rect_center(rect: &IRect) -> IVec2 {
+    ivec2(rect.x + rect.w / 2, rect.y + rect.h / 2)
+}
+
+fn rects_touch(a: &IRect, b: &IRect) -> bool {
+    a.x <= b.x + b.w && b.x <= a.x + a.w && a.y <= b.y + b.h && b.y <= a.y + a.h
+}
+
+// walls off whatever can't be reached from the spawn, then places the objects
+fn finish(mut map: AtlasMap, spawn: IVec2, config: &GeneratorConfig, rng: &mut Rng) -> GeneratedMap {
+    let mut scan = ConnectivityScan::from_cell(&map, spawn);
+    while !scan.step(&map) {}
+    for [x, y] in scan.unreachable() {
+        map.wall.set(x as usize, y as usize, config.wall);
+    }
+
+    for y in 0..map.height {
+        for x in 0..map.width {
+            let pos = ivec2(x as i32, y as i32);
+            if pos == spawn || !map.is_passable(pos) || !rng.chance(config.object_chance) {
+                continue;
+            }
+            if let Some(object) = rng.weighted(&config.objects) {
+                map.object.set(x, y, object);
+            }
+        }
+    }
+    GeneratedMap { map, spawn }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::VecDeque;
+
+    use super::*;
+
+    const GENERATORS: [&dyn MapGenerator; 2] = [&RoomsAndCorridors, &Maze];
+
+    fn config() -> GeneratorConfig {
+        GeneratorConfig {
+            wall: 3,
+            floors: vec![4, 5],
+            ceilings: vec![6],
+            objects: vec![(7, 3), (8, 1), (9, 0)],
+            object_chance: 0.2,
+            ..GeneratorConfig::default()
+        }
+    }
+
+    // open cells a walk from `from` reaches, and every open cell
+    fn reached(map: &AtlasMap, from: IVec2) -> (Vec<IVec2>, Vec<IVec2>) {
+        let mut reached = vec![from];
+        let mut queue = VecDeque::from([from]);
+        while let Some(pos) = queue.pop_front() {
+            for direction in crate::Direction::CARDINALS {
+                let next = pos + direction.to_vec();
+                if map.get_wall(next) == Some(0) && !reached.contains(&next) {
+                    reached.push(next);
+                    queue.push_back(next);
+                }
+            }
+        }
+        let open = (0..map.height as i32)
+            .flat_map(|y| (0..map.width as i32).map(move |x| ivec2(x, y)))
+            .filter(|pos| map.get_wall(*pos) == Some(0))
+            .collect();
+        reached.sort_by_key(|pos| (pos.y, pos.x));
+        (reached, open)
+    }
+
+    #[test]
+    fn the_same_seed_makes_the_same_map() {
+        for generator in GENERATORS {
+            for seed in [0, 1, 285, u64::MAX] {
+                let (a, b) = (generator.generate(33, 21, &config(), seed), generator.generate(33, 21, &config(), seed));
+                assert_eq!((a.map, a.spawn), (b.map, b.spawn));
+            }
+            let maps: Vec<AtlasMap> = (0..8).map(|seed| generator.generate(33, 21, &config(), seed).map).collect();
+            assert!(maps.iter().skip(1).any(|map| map.wall != maps[0].wall));
+        }
+    }
+
+    #[test]
+    fn everything_open_is_reachable_from_the_spawn() {
+        let mut rng = Rng::new(285);
+        for generator in GENERATORS {
+            for seed in 0..60 {
+                let (width, height) = (rng.range(0, 60), rng.range(0, 60));
+                let generated = generator.generate(width, height, &config(), seed);
+                let map = &generated.map;
+                assert_eq!((map.width, map.height), (width.max(3), height.max(3)));
+                let (reached, open) = reached(map, generated.spawn);
+                assert_eq!(reached, open, "{width}x{height} seed {seed}");
+                assert!(map.get_wall(generated.spawn) == Some(0));
+                assert_eq!(map.object.get(generated.spawn.x as usize, generated.spawn.y as usize), Some(0));
+                // walled all round
+                for x in 0..map.width {
+                    assert_eq!((map.wall.get(x, 0), map.wall.get(x, map.height - 1)), (Some(3), Some(3)));
+                }
+                for y in 0..map.height {
+                    assert_eq!((map.wall.get(0, y), map.wall.get(map.width - 1, y)), (Some(3), Some(3)));
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn layers_come_from_the_palettes_and_the_object_table() {
+        let mut counts = [0usize; 10];
+        for generator in GENERATORS {
+            for seed in 0..20 {
+                let map = generator.generate(41, 41, &config(), seed).map;
+                for y in 0..map.height {
+                    for x in 0..map.width {
+                        assert!([4, 5].contains(&map.floor.get(x, y).unwrap()));
+                        assert_eq!(map.ceiling.get(x, y), Some(6));
+                        let object = map.object.get(x, y).unwrap();
+                        assert!(object == 0 || map.wall.get(x, y) == Some(0));
+                        counts[object as usize] += 1;
+                    }
+                }
+            }
+        }
+        // a weight of 0 is never picked, and 7 comes up about three times as often as 8
+        assert_eq!(counts[9], 0);
+        assert!(counts[8] > 100);
+        let ratio = counts[7] as f32 / counts[8] as f32;
+        assert!((2.5..3.5).contains(&ratio), "{ratio}");
+
+        let bare = GeneratorConfig { floors: Vec::new(), ceilings: Vec::new(), ..GeneratorConfig::default() };
+        let map = rooms_and_corridors(20, 20, &bare, 1).map;
+        assert!(map.floor.data().iter().chain(map.ceiling.data()).all(|value| *value == 1));
+        assert!(map.object.data().iter().all(|value| *value == 0));
+    }
+
+    #[test]
+    fn odd_room_sizes_still_make_a_room() {
+        for room_size in [[0, 0], [10, 2], [1, 1], [100, 200]] {
+            for (width, height) in [(3, 3), (4, 30), (30, 4), (12, 12)] {
+                let config = GeneratorConfig { room_size, ..GeneratorConfig::default() };
+                let generated = rooms_and_corridors(width, height, &config, 7);
+                let (reached, open) = reached(&generated.map, generated.spawn);
+                assert!(!open.is_empty() && reached == open, "{room_size:?} {width}x{height}");
+            }
+        }
+        // no attempts at all falls back to one room filling the map
+        let config = GeneratorConfig { room_attempts: 0, ..GeneratorConfig::default() };
+        let generated = rooms_and_corridors(6, 5, &config, 0);
+        assert_eq!(reached(&generated.map, generated.spawn).1.len(), 4 * 3);
+    }
+
+    #[test]
+    fn weighted_picks_skip_empty_tables() {
+        let mut rng = Rng::new(0);
+        assert_eq!(rng.weighted::<u8>(&[]), None);
+        assert_eq!(rng.weighted(&[(1u8, 0), (2, 0)]), None);
+        assert!((0..50).all(|_| rng.weighted(&[(1u8, 0), (2, 5)]) == Some(2)));
+        assert_eq!(rng.below(0), 0);
+        assert!((0..50).all(|_| (3..=5).contains(&rng.range(3, 5))));
+        assert!((0..50).all(|_| rng.range(4, 2) == 4));
+    }
+}
@@ -0,0 +1,142 @@
+//! One map layer: `width` x `height` cell values stored row after row in a single buffer, so
+//! every row is the same length by construction. Written to json as an array of rows.
+
+use serde::{de, Deserialize, Deserializer, Serialize, Serializer};
+
+#[derive(Clone, Default, PartialEq, Eq, Debug)]
+pub struct Grid {
+    width: usize,
+    height: usize,
+    data: Vec<u8>,
+}
+
+/// Rows handed to `Grid::from_rows` that aren't all the same length.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct RaggedRows {
+    pub row: usize,
+    pub len: usize,
+    pub expected: usize,
+}
+
+impl std::fmt::Display for RaggedRows {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "row {} has {} cells, expected {}", self.row, self.len, self.expected)
+    }
+}
+
+impl std::error::Error for RaggedRows {}
+
+impl Grid {
+    /// a zeroed `width` x `height` grid
+    pub fn new(width: usize, height: usize) -> Self {
+        Self::filled(width, height, 0)
+    }
+
+    pub fn filled(width: usize, height: usize, value: u8) -> Self {
+        Self { width, height, data: vec![value; width * height] }
+    }
+
+    /// A grid as wide as the first row.
+    ///
+    /// # Errors
+    ///
+    /// When a row is longer or shorter than the first.
+    pub fn from_rows(rows: Vec<Vec<u8>>) -> Result<Self, RaggedRows> {
+        let width = rows.first().map_or(0, Vec::len);
+        let mut data = Vec::with_capacity(width * rows.len());
+        for (row, cells) in rows.iter().enumerate() {
+            if cells.len() != width {
+                return Err(RaggedRows { row, len: cells.len(), expected: width });
+            }
+            data.extend_from_slice(cells);
+        }
+        Ok(Self { width, height: rows.len(), data })
+    }
+
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    pub fn height(&self) -> usize {
+        self.height
+    }
+
+    /// true for a grid without cells, like a layer that hasn't been baked yet
+    pub fn is_empty(&self) -> bool {
+        self.data.is_empty()
+    }
+
+    /// every cell, row after row
+    pub fn data(&self) -> &[u8] {
+        &self.data
+    }
+
+    pub fn data_mut(&mut self) -> &mut [u8] {
+        &mut self.data
+    }
+
+    fn index(&self, x: usize, y: usize) -> Option<usize> {
+        (x < self.width && y < self.height).then_some(y * self.width + x)
+    }
+
+    /// `None` outside the grid
+    pub fn get(&self, x: usize, y: usize) -> Option<u8> {
+        self.index(x, y).map(|i| self.data[i])
+    }
+
+    /// returns false, changing nothing, outside the grid
+    pub fn set(&mut self, x: usize, y: usize, value: u8) -> bool {
+        match self.index(x, y) {
+            Some(i) => {
+                self.data[i] = value;
+                true
+            }
+            None => false,
+        }
+    }
+
+    pub fn fill(&mut self, value: u8) {
+        self.data.fill(value);
+    }
+
+    pub fn row(&self, y: usize) -> Option<&[u8]> {
+        (y < self.height).then(|| &self.data[y * self.width..(y + 1) * self.width])
+    }
+
+    pub fn iter_rows(&self) -> impl Iterator<Item = &[u8]> {
+        (0..self.height).map(|y| &self.data[y * self.width..(y + 1) * self.width])
+    }
+
+    pub fn to_rows(&self) -> Vec<Vec<u8>> {
+        self.iter_rows().map(<[u8]>::to_vec).collect()
+    }
+}
+
+impl Serialize for Grid {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_seq(self.iter_rows())
+    }
+}
+
+impl<'de> Deserialize<'de> for Grid {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        Grid::from_rows(Vec::deserialize(deserializer)?).map_err(de::Error::custom)
+    }
+}
+
+/// (De)serializes a grid holding `i8` values as their bytes, written to json as signed numbers,
+/// for `#[serde(with = "grid::signed")]`.
+pub mod signed {
+    use serde::{de, Deserialize, Deserializer, Serializer};
+
+    use super::Grid;
+
+    pub fn serialize<S: Serializer>(grid: &Grid, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_seq(grid.iter_rows().map(|row| row.iter().map(|value| *value as i8).collect::<Vec<i8>>()))
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Grid, D::Error> {
+        let rows: Vec<Vec<i8>> = Vec::deserialize(deserializer)?;
+        Grid::from_rows(rows.into_iter().map(|row| row.into_iter().map(|value| value as u8).collect()).collect()).map_err(de::Error::custom)
+    }
+}
@@ -0,0 +1,85 @@
+//! Game code drawing inside the view. The cell hook runs right after each cell is drawn, so
+//! what it draws sits at that cell's depth: nearer cells are drawn over it and farther ones
+//! under it. The post-render hook runs once after every cell.
+
+use crate::math::*;
+use crate::{screen_rect, AtlasWorld, Collection, MapSource, RenderStats, ViewPoint};
+
+/// What the cell hook is told about the cell just drawn.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct CellDrawInfo {
+    /// map cell
+    pub pos: IVec2,
+    /// view-space cell, x to the right and z toward the viewer
+    pub view: IVec2,
+    /// where the cell's floor is on screen, from its floor tile or else the floor atlas' projection
+    pub floor_rect: Rect,
+    /// the tint the cell's tiles were drawn with, fog and light included
+    pub tint: Color,
+}
+
+pub type CellHook = Box<dyn FnMut(&CellDrawInfo)>;
+pub type PostRenderHook = Box<dyn FnMut(ViewPoint, RenderStats)>;
+
+impl<M: MapSource> AtlasWorld<M> {
+    /// Calls `hook` after each cell `render` draws, replacing any earlier one.
+    pub fn set_cell_hook(&mut self, hook: impl FnMut(&CellDrawInfo) + 'static) {
+        *self.cell_hook.get_mut() = Some(Box::new(hook));
+    }
+
+    pub fn clear_cell_hook(&mut self) {
+        *self.cell_hook.get_mut() = None;
+    }
+
+    /// Calls `hook` once at the end of each `render`, with the viewpoint drawn from and the
+    /// stats so far. Replaces any earlier one.
+    pub fn set_post_render_hook(&mut self, hook: impl FnMut(ViewPoint, RenderStats) + 'static) {
+        *self.post_render_hook.get_mut() = Some(Box::new(hook));
+    }
+
+    pub fn clear_post_render_hook(&mut self) {
+        *self.post_render_hook.get_mut() = None;
+    }
+
+    // hooks draw for real, so they are skipped while commands are only being captured
+    fn hooks_enabled(&self) -> bool {
+        self.capture.borrow().is_none()
+    }
+
+    pub(crate) fn run_cell_hook(&self, pos: IVec2, x: i32, z: i32) {
+        if !self.hooks_enabled() {
+            return;
+        }
+        let mut hook = self.cell_hook.borrow_mut();
+        let Some(hook) = hook.as_mut() else {
+            return;
+        };
+        // what the hook draws has to land on top of the quads batched so far
+        self.flush_batch();
+        hook(&CellDrawInfo { pos, view: ivec2(x, z), floor_rect: self.floor_rect(pos, x, z), tint: self.tile_color(x, z, None) });
+    }
+
+    pub(crate) fn run_post_render_hook(&self) {
+        if !self.hooks_enabled() {
+            return;
+        }
+        if let Some(hook) = self.post_render_hook.borrow_mut().as_mut() {
+            hook(self.view_point(), self.render_stats.get());
+        }
+    }
+
+    fn floor_rect(&self, pos: IVec2, x: i32, z: i32) -> Rect {
+        let binding = self.render_bindings.get("floor");
+        let value = self.map.get("floor", pos).unwrap_or(0);
+        let tile = binding.filter(|_| value != 0).and_then(|binding| self.collection.get_tile(&binding.atlas_id, binding.layer_id(value), x, z, None));
+        let rect = match tile {
+            Some(tile) => screen_rect(tile),
+            None => {
+                let atlas = binding.and_then(|binding| self.collection.get(&binding.atlas_id));
+                let floor = atlas.and_then(|atlas| atlas.atlas_info.projection).unwrap_or_default().floor_rect(x, z);
+                Rect::new(floor.x as f32, floor.y as f32, floor.w as f32, floor.h as f32)
+            }
+        };
+        rect.offset(vec2(self.peek_offset(z), 0.0))
+    }
+}
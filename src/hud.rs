@@ -0,0 +1,338 @@
+//! Data-driven HUD: a viewport on the left and panels of square cells stacked to its right.
+
+#[cfg(feature = "render")]
+use ahash::AHashMap;
+#[cfg(not(feature = "render"))]
+use crate::math::*;
+#[cfg(feature = "render")]
+use macroquad::prelude::*;
+#[cfg(feature = "render")]
+use macroquad::ui::{hash, root_ui, widgets};
+use serde::{Deserialize, Serialize};
+
+#[cfg(feature = "render")]
+use crate::ui::StatBar;
+use crate::{fit_viewport_integer, Movement, Turn};
+
+/// Something the player does to the world, as bound to buttons and keys.
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Debug)]
+pub enum PlayerAction {
+    Move(Movement),
+    Turn(Turn),
+    CornerLeft,
+    CornerRight,
+    /// use the facing cell, see `AtlasWorld::interact`
+    Interact,
+    /// strike whatever entity stands in the facing cell
+    Attack,
+    /// let the turn pass without doing anything
+    Wait,
+}
+
+/// What pressing a HUD button asks for. Custom actions are named and left to the game.
+#[derive(Serialize, Deserialize, Clone, PartialEq, Eq, Debug)]
+pub enum HudAction {
+    Player(PlayerAction),
+    Custom(String),
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Debug)]
+pub enum PanelAnchor {
+    /// stacked down from the top of the window in listed order
+    Top,
+    /// stacked up against the bottom of the window, never overlapping the top panels
+    Bottom,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub enum HudWidget {
+    Button {
+        /// [column, row] of the panel cell
+        cell: [f32; 2],
+        /// key into `HudContext::icons`
+        icon: String,
+        #[serde(default)]
+        action: Option<HudAction>,
+    },
+    /// a bar filled by `current / max` of a stat in `HudContext::stats`, labeled with both
+    StatBar {
+        stat: String,
+        cell: [f32; 2],
+        /// [width, height] in cells
+        size: [f32; 2],
+        /// rgba
+        color: [f32; 4],
+        background: [f32; 4],
+    },
+    /// room left for the game to draw a minimap in, see `ResolvedHud::minimaps`
+    Minimap {
+        cell: [f32; 2],
+        size: [f32; 2],
+    },
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct HudPanel {
+    pub anchor: PanelAnchor,
+    pub columns: u32,
+    pub rows: u32,
+    pub widgets: Vec<HudWidget>,
+}
+
+/// Where everything on the HUD goes, resolved against the window size every frame. The viewport
+/// takes up to `viewport_width` of the window; the panels share one cell size, as large as fits
+/// both the width left over and the height of all panels stacked.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct HudLayout {
+    /// gap in pixels around the viewport and between panels
+    pub margin: f32,
+    /// pixels a panel's frame adds around its cells, both sides together
+    pub frame: f32,
+    /// share of the window width the viewport may use, margins included
+    pub viewport_width: f32,
+    pub panels: Vec<HudPanel>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct ResolvedHud {
+    pub viewport: Rect,
+    pub cell_size: f32,
+    /// outer rect of each panel, frame included, in `HudLayout::panels` order
+    pub panels: Vec<Rect>,
+    /// screen rects of the minimap slots
+    pub minimaps: Vec<Rect>,
+}
+
+impl HudLayout {
+    /// Lays the HUD out in a `screen`-sized window. The viewport is a whole multiple of its
+    /// size at `asset_scale`, see `fit_viewport_integer`, so 2x atlases aren't scaled twice.
+    pub fn resolve(&self, screen: Vec2, asset_scale: u32) -> ResolvedHud {
+        let margin = self.margin;
+        let frame = self.frame;
+        let viewport = fit_viewport_integer(Rect::new(
+            margin,
+            margin,
+            (screen.x * self.viewport_width - margin * 2.0).max(0.0),
+            (screen.y - margin * 2.0).max(0.0),
+        ), asset_scale);
+        let column_x = viewport.right() + margin;
+
+        let columns = self.panels.iter().map(|panel| panel.columns).max().unwrap_or(1).max(1) as f32;
+        let rows: u32 = self.panels.iter().map(|panel| panel.rows).sum();
+        let count = self.panels.len() as f32;
+        let by_width = (screen.x - column_x - margin - frame) / columns;
+        let by_height = (screen.y - (count + 1.0) * margin - count * frame) / rows.max(1) as f32;
+        let cell_size = by_width.min(by_height).max(0.0);
+
+        let size = |panel: &HudPanel| vec2(panel.columns as f32 * cell_size + frame, panel.rows as f32 * cell_size + frame);
+
+        let mut panels = vec![Rect::default(); self.panels.len()];
+        let mut y = margin;
+        for (i, panel) in self.panels.iter().enumerate().filter(|(_, panel)| panel.anchor == PanelAnchor::Top) {
+            let size = size(panel);
+            panels[i] = Rect::new(column_x, y, size.x, size.y);
+            y += size.y + margin;
+        }
+        let top_end = y;
+
+        let bottom: Vec<usize> = (0..self.panels.len()).filter(|i| self.panels[*i].anchor == PanelAnchor::Bottom).collect();
+        let stack_height: f32 = bottom.iter().map(|i| size(&self.panels[*i]).y + margin).sum::<f32>() - margin;
+        let mut y = (screen.y - margin - stack_height).max(top_end);
+        for i in bottom {
+            let size = size(&self.panels[i]);
+            panels[i] = Rect::new(column_x, y, size.x, size.y);
+            y += size.y + margin;
+        }
+
+        let minimaps = self.panels.iter().zip(&panels)
+            .flat_map(|(panel, rect)| panel.widgets.iter().filter_map(move |widget| match widget {
+                HudWidget::Minimap { cell, size } => Some(Rect::new(
+                    rect.x + frame / 2.0 + cell[0] * cell_size,
+                    rect.y + frame / 2.0 + cell[1] * cell_size,
+                    size[0] * cell_size,
+                    size[1] * cell_size,
+                )),
+                _ => None,
+            }))
+            .collect();
+
+        ResolvedHud { viewport, cell_size, panels, minimaps }
+    }
+}
+
+/// What `draw_layout` draws with and collects the pressed buttons' actions into.
+#[cfg(feature = "render")]
+pub struct HudContext<'a> {
+    pub icons: &'a AHashMap<String, Texture2D>,
+    /// stat name -> (current, max)
+    pub stats: AHashMap<String, (f32, f32)>,
+    /// used to center stat bar labels, should match the skin's label font
+    pub font: Option<&'a Font>,
+    pub font_size: u16,
+    /// scale of the atlases drawn into the viewport, see `AtlasWorld::asset_scale`
+    pub asset_scale: u32,
+    pub actions: Vec<HudAction>,
+}
+
+#[cfg(feature = "render")]
+fn color(rgba: [f32; 4]) -> Color {
+    Color::new(rgba[0], rgba[1], rgba[2], rgba[3])
+}
+
+/// Draws the panels of `layout` with the root ui's current skin, pushing the actions of any
+/// buttons pressed onto `ctx.actions`. The viewport is left to the caller to draw into the
+/// returned rect.
+#[cfg(feature = "render")]
+pub fn draw_layout(layout: &HudLayout, ctx: &mut HudContext) -> ResolvedHud {
+    let resolved = layout.resolve(vec2(screen_width(), screen_height()), ctx.asset_scale);
+    let cell_size = resolved.cell_size;
+    let mut focus = None;
+
+    for (i, (panel, rect)) in layout.panels.iter().zip(&resolved.panels).enumerate() {
+        let window_hash = hash!("hud_panel", i);
+        if focus.is_none() && panel.widgets.iter().any(|widget| matches!(widget, HudWidget::Button { .. })) {
+            focus = Some(window_hash);
+        }
+
+        widgets::Window::new(window_hash, rect.point(), rect.size()).movable(false).close_button(false).ui(&mut root_ui(), |ui| {
+            for widget in &panel.widgets {
+                match widget {
+                    HudWidget::Button { cell, icon, action } => {
+                        let Some(texture) = ctx.icons.get(icon) else {
+                            continue;
+                        };
+                        let pressed = widgets::Button::new(texture.clone())
+                            .position(Vec2::from(*cell) * cell_size)
+                            .size(vec2(cell_size, cell_size))
+                            .ui(ui);
+                        if let (true, Some(action)) = (pressed, action) {
+                            ctx.actions.push(action.clone());
+                        }
+                    }
+                    HudWidget::StatBar { stat, cell, size, color: fill, background } => {
+                        let (current, max) = ctx.stats.get(stat).copied().unwrap_or((0.0, 0.0));
+                        let bar = Rect::new(cell[0] * cell_size, cell[1] * cell_size, size[0] * cell_size, size[1] * cell_size);
+                        let mut stat_bar = StatBar::new(current, max).color(color(*fill)).background(color(*background)).font_size(ctx.font_size);
+                        if let Some(font) = ctx.font {
+                            stat_bar = stat_bar.label_font(font);
+                        }
+                        stat_bar.draw(ui, bar);
+                    }
+                    HudWidget::Minimap { .. } => {}
+                }
+            }
+        });
+    }
+
+    if let Some(window_hash) = focus {
+        root_ui().focus_window(window_hash);
+    }
+
+    resolved
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::manifest_path;
+    use crate::Movement;
+
+    fn demo_layout() -> HudLayout {
+        serde_json::from_reader(std::fs::File::open(manifest_path("hud.json")).unwrap()).unwrap()
+    }
+
+    // the status bar and button panel as the demo placed them before hud.json, with the
+    // viewport fit the same way
+    fn hard_coded(screen: Vec2) -> (Rect, f32, Rect, Rect) {
+        let viewport = fit_viewport_integer(Rect::new(10.0, 10.0, screen.x * 0.7 - 20.0, screen.y - 20.0), 1);
+        let right_side = viewport.w + 20.0;
+        let mut button_size = ((screen.x - right_side) - 10.0 - 36.0) / 3.0;
+        if screen.y - 10.0 - 36.0 - button_size < button_size * 4.0 + 20.0 + 36.0 {
+            button_size = (screen.y - 30.0 - 72.0) / 5.0;
+        }
+        let buttons = Rect::new(
+            right_side,
+            (screen.y - button_size * 4.0 - 10.0 - 36.0).max(20.0 + 36.0 + button_size),
+            button_size * 3.0 + 36.0,
+            button_size * 4.0 + 36.0,
+        );
+        let status = Rect::new(right_side, 10.0, buttons.w, button_size + 36.0);
+        (viewport, button_size, status, buttons)
+    }
+
+    #[test]
+    fn the_demo_layout_matches_the_old_hud() {
+        let layout = demo_layout();
+        for screen in [vec2(1280.0, 720.0), vec2(1920.0, 1080.0), vec2(1024.0, 768.0), vec2(800.0, 1200.0), vec2(2560.0, 600.0)] {
+            let (viewport, cell_size, status, buttons) = hard_coded(screen);
+            let resolved = layout.resolve(screen, 1);
+            assert_eq!(resolved.viewport, viewport, "{screen}");
+            assert!((resolved.cell_size - cell_size).abs() < 1e-3, "{screen}");
+            for (resolved, expected) in resolved.panels.iter().zip([status, buttons]) {
+                let close = |a: f32, b: f32| (a - b).abs() < 1e-3;
+                assert!(close(resolved.x, expected.x) && close(resolved.y, expected.y), "{screen}: {resolved:?} {expected:?}");
+                assert!(close(resolved.w, expected.w) && close(resolved.h, expected.h), "{screen}: {resolved:?} {expected:?}");
+            }
+        }
+    }
+
+    #[test]
+    fn the_demo_buttons_are_bound() {
+        let layout = demo_layout();
+        let bound: Vec<(String, Option<HudAction>)> = layout.panels.iter()
+            .flat_map(|panel| &panel.widgets)
+            .filter_map(|widget| match widget {
+                HudWidget::Button { icon, action, .. } => Some((icon.clone(), action.clone())),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(bound.len(), 11);
+        let action = |icon: &str| bound.iter().find(|(name, _)| name == icon).and_then(|(_, action)| action.clone());
+        assert_eq!(action("forward"), Some(HudAction::Player(PlayerAction::Move(Movement::Forward))));
+        assert_eq!(action("turn_left"), Some(HudAction::Player(PlayerAction::Turn(Turn::Left))));
+        assert_eq!(action("map"), Some(HudAction::Custom("toggle_map".to_owned())));
+        assert_eq!(action("charge"), None);
+    }
+
+    #[test]
+    fn narrow_windows_keep_the_panels_apart_and_sizes_positive() {
+        let layout = demo_layout();
+        for screen in [vec2(320.0, 240.0), vec2(200.0, 900.0), vec2(120.0, 80.0), vec2(40.0, 40.0), Vec2::ZERO] {
+            let resolved = layout.resolve(screen, 1);
+            assert!(resolved.cell_size >= 0.0, "{screen}");
+            assert!(resolved.viewport.w >= 0.0 && resolved.viewport.h >= 0.0, "{screen}");
+            let (status, buttons) = (resolved.panels[0], resolved.panels[1]);
+            assert!(status.x >= resolved.viewport.right(), "{screen}");
+            // the bottom panel gets pushed off screen before it covers the top one
+            assert!(buttons.y >= status.bottom() + layout.margin, "{screen}");
+        }
+        // tall and thin, the width decides the cells and the buttons still sit at the bottom
+        let resolved = layout.resolve(vec2(200.0, 900.0), 1);
+        assert!((resolved.cell_size - (200.0 - resolved.viewport.right() - 10.0 - 10.0 - 36.0) / 3.0).abs() < 1e-3);
+        assert!((resolved.panels[1].bottom() - 890.0).abs() < 1e-3);
+    }
+
+    #[test]
+    fn panels_stack_from_their_anchors_and_place_minimaps() {
+        let panel = |anchor, rows, widgets| HudPanel { anchor, columns: 2, rows, widgets };
+        let layout = HudLayout {
+            margin: 4.0,
+            frame: 2.0,
+            viewport_width: 0.5,
+            panels: vec![
+                panel(PanelAnchor::Bottom, 1, Vec::new()),
+                panel(PanelAnchor::Top, 2, vec![HudWidget::Minimap { cell: [0.5, 0.0], size: [1.5, 2.0] }]),
+                panel(PanelAnchor::Bottom, 1, Vec::new()),
+            ],
+        };
+        let resolved = layout.resolve(vec2(656.0, 1000.0), 1);
+        assert_eq!(resolved.viewport, Rect::new(4.0, 4.0, 320.0, 256.0));
+        // 656 - 328 - 4 - 2 across two columns, the height would allow more
+        assert_eq!(resolved.cell_size, 161.0);
+        assert_eq!(resolved.panels[1], Rect::new(328.0, 4.0, 324.0, 324.0));
+        // the bottom panels keep their listed order, the last one against the bottom
+        assert_eq!(resolved.panels[2], Rect::new(328.0, 1000.0 - 4.0 - 163.0, 324.0, 163.0));
+        assert_eq!(resolved.panels[0], Rect::new(328.0, 1000.0 - 4.0 - 163.0 - 4.0 - 163.0, 324.0, 163.0));
+        assert_eq!(resolved.minimaps, [Rect::new(329.0 + 80.5, 5.0, 241.5, 322.0)]);
+    }
+}
@@ -0,0 +1,327 @@
+//! Key bindings: which keys ask for which `HudAction`, the same actions HUD buttons ask for, so
+//! app actions like toggling the map are `Custom` ones named like in hud.json. Keys are written
+//! by their `KeyCode` name, with modifiers in front, e.g. "W", "Up" or "Shift+Q".
+
+use std::{fmt, str::FromStr};
+
+use macroquad::prelude::*;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+use crate::{HudAction, Movement, PlayerAction, Turn};
+
+/// A key, and the modifiers that have to be held with it.
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub struct KeyChord {
+    pub key: KeyCode,
+    pub shift: bool,
+    pub ctrl: bool,
+    pub alt: bool,
+}
+
+impl KeyChord {
+    pub fn new(key: KeyCode) -> Self {
+        Self { key, shift: false, ctrl: false, alt: false }
+    }
+
+    pub fn shift(key: KeyCode) -> Self {
+        Self { shift: true, ..Self::new(key) }
+    }
+
+    fn modifiers(&self) -> (bool, bool, bool) {
+        (self.shift, self.ctrl, self.alt)
+    }
+}
+
+impl From<KeyCode> for KeyChord {
+    fn from(key: KeyCode) -> Self {
+        Self::new(key)
+    }
+}
+
+impl fmt::Display for KeyChord {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (held, name) in [(self.ctrl, "Ctrl"), (self.alt, "Alt"), (self.shift, "Shift")] {
+            if held {
+                write!(f, "{name}+")?;
+            }
+        }
+        write!(f, "{:?}", self.key)
+    }
+}
+
+impl FromStr for KeyChord {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut parts: Vec<&str> = s.split('+').map(str::trim).collect();
+        let name = parts.pop().unwrap_or_default();
+        let key = KEYS.iter().find(|key| format!("{key:?}") == name).ok_or_else(|| format!("unknown key \"{name}\""))?;
+        let mut chord = KeyChord::new(*key);
+        for modifier in parts {
+            match modifier {
+                "Shift" => chord.shift = true,
+                "Ctrl" => chord.ctrl = true,
+                "Alt" => chord.alt = true,
+                _ => return Err(format!("unknown modifier \"{modifier}\" in \"{s}\"")),
+            }
+        }
+        Ok(chord)
+    }
+}
+
+impl Serialize for KeyChord {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+impl<'de> Deserialize<'de> for KeyChord {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        String::deserialize(deserializer)?.parse().map_err(serde::de::Error::custom)
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, PartialEq, Debug)]
+pub struct Binding {
+    pub action: HudAction,
+    pub keys: Vec<KeyChord>,
+}
+
+/// Keys bound to actions, loaded from something like bindings.json. A key may be bound with
+/// and without modifiers to different actions: while the modifiers are held only the binding
+/// that asks for them fires.
+#[derive(Serialize, Deserialize, Clone, PartialEq, Debug)]
+pub struct InputMap {
+    pub bindings: Vec<Binding>,
+}
+
+/// The demo's keys: WASD to move, Q/E to turn, shift with them to cut the corner, X to turn
+/// around, space to interact, M for the map, F for fullscreen, Escape or P to pause and Z/C to
+/// lean.
+impl Default for InputMap {
+    fn default() -> Self {
+        let player = |action| HudAction::Player(action);
+        let custom = |name: &str| HudAction::Custom(name.to_owned());
+        let bindings = [
+            (player(PlayerAction::Move(Movement::Forward)), vec![KeyChord::new(KeyCode::W)]),
+            (player(PlayerAction::Move(Movement::Backward)), vec![KeyChord::new(KeyCode::S)]),
+            (player(PlayerAction::Move(Movement::StrafeLeft)), vec![KeyChord::new(KeyCode::A)]),
+            (player(PlayerAction::Move(Movement::StrafeRight)), vec![KeyChord::new(KeyCode::D)]),
+            (player(PlayerAction::Turn(Turn::Left)), vec![KeyChord::new(KeyCode::Q)]),
+            (player(PlayerAction::Turn(Turn::Right)), vec![KeyChord::new(KeyCode::E)]),
+            (player(PlayerAction::Turn(Turn::Around)), vec![KeyChord::new(KeyCode::X)]),
+            (player(PlayerAction::CornerLeft), vec![KeyChord::shift(KeyCode::Q)]),
+            (player(PlayerAction::CornerRight), vec![KeyChord::shift(KeyCode::E)]),
+            (player(PlayerAction::Interact), vec![KeyChord::new(KeyCode::Space)]),
+            (custom("toggle_map"), vec![KeyChord::new(KeyCode::M)]),
+            (custom("toggle_fullscreen"), vec![KeyChord::new(KeyCode::F)]),
+            (custom("pause"), vec![KeyChord::new(KeyCode::Escape), KeyChord::new(KeyCode::P)]),
+            (custom("lean_left"), vec![KeyChord::new(KeyCode::Z)]),
+            (custom("lean_right"), vec![KeyChord::new(KeyCode::C)]),
+        ];
+        Self { bindings: bindings.into_iter().map(|(action, keys)| Binding { action, keys }).collect() }
+    }
+}
+
+impl InputMap {
+    /// the keys bound to `action`, empty when it has none
+    pub fn keys(&self, action: &HudAction) -> &[KeyChord] {
+        self.bindings.iter().find(|binding| binding.action == *action).map_or(&[], |binding| &binding.keys)
+    }
+
+    /// the action `chord` is bound to
+    pub fn action(&self, chord: KeyChord) -> Option<&HudAction> {
+        self.bindings.iter().find(|binding| binding.keys.contains(&chord)).map(|binding| &binding.action)
+    }
+
+    /// Adds `chord` to the keys of `action`, taking it away from any other action first.
+    pub fn bind(&mut self, action: HudAction, chord: KeyChord) {
+        self.unbind_key(chord);
+        match self.bindings.iter_mut().find(|binding| binding.action == action) {
+            Some(binding) => binding.keys.push(chord),
+            None => self.bindings.push(Binding { action, keys: vec![chord] }),
+        }
+    }
+
+    /// Makes `chord` the only key of `action`, as a rebinding screen would.
+    pub fn rebind(&mut self, action: HudAction, chord: KeyChord) {
+        if let Some(binding) = self.bindings.iter_mut().find(|binding| binding.action == action) {
+            binding.keys.clear();
+        }
+        self.bind(action, chord);
+    }
+
+    /// removes `chord` from whatever it was bound to
+    pub fn unbind_key(&mut self, chord: KeyChord) {
+        for binding in &mut self.bindings {
+            binding.keys.retain(|key| *key != chord);
+        }
+    }
+
+    /// The actions whose keys were pressed this frame, in binding order.
+    pub fn poll_actions(&self) -> Vec<HudAction> {
+        let held = held_modifiers();
+        self.bindings.iter()
+            .filter(|binding| binding.keys.iter().any(|chord| is_key_pressed(chord.key) && self.fires(*chord, held)))
+            .map(|binding| binding.action.clone())
+            .collect()
+    }
+
+    /// `poll_actions` narrowed to the player's actions, ready for `AtlasWorld::take_turn`
+    pub fn poll(&self) -> Vec<PlayerAction> {
+        self.poll_actions().into_iter()
+            .filter_map(|action| match action {
+                HudAction::Player(action) => Some(action),
+                HudAction::Custom(_) => None,
+            })
+            .collect()
+    }
+
+    /// whether a key of `action` is held down, for things that last while held like leaning
+    pub fn is_down(&self, action: &HudAction) -> bool {
+        let held = held_modifiers();
+        self.keys(action).iter().any(|chord| is_key_down(chord.key) && self.fires(*chord, held))
+    }
+
+    // A chord fires when its modifiers are held, unless the key is also bound with exactly the
+    // held modifiers, which then wins.
+    fn fires(&self, chord: KeyChord, held: (bool, bool, bool)) -> bool {
+        let (shift, ctrl, alt) = chord.modifiers();
+        let satisfied = (!shift || held.0) && (!ctrl || held.1) && (!alt || held.2);
+        if !satisfied || chord.modifiers() == held {
+            return satisfied;
+        }
+        let exact = KeyChord { key: chord.key, shift: held.0, ctrl: held.1, alt: held.2 };
+        self.action(exact).is_none()
+    }
+}
+
+/// Turns held keys into actions that repeat: once on press, again after `initial_delay`
+/// seconds, then every `repeat_interval` seconds for as long as the key is held. Each action
+/// keeps its own timer, so holding a turn key while walking repeats both.
+#[derive(Clone, Debug)]
+pub struct RepeatingInput {
+    pub initial_delay: f32,
+    pub repeat_interval: f32,
+    /// the actions that repeat, anything else fires once per press
+    pub repeatable: Vec<HudAction>,
+    // seconds until each held action fires again
+    timers: Vec<(HudAction, f32)>,
+}
+
+impl Default for RepeatingInput {
+    fn default() -> Self {
+        Self::new(0.3, 0.15)
+    }
+}
+
+impl RepeatingInput {
+    /// repeats moving, turning and cutting corners
+    pub fn new(initial_delay: f32, repeat_interval: f32) -> Self {
+        let repeatable = [
+            PlayerAction::Move(Movement::Forward),
+            PlayerAction::Move(Movement::Backward),
+            PlayerAction::Move(Movement::StrafeLeft),
+            PlayerAction::Move(Movement::StrafeRight),
+            PlayerAction::Turn(Turn::Left),
+            PlayerAction::Turn(Turn::Right),
+            PlayerAction::CornerLeft,
+            PlayerAction::CornerRight,
+        ];
+        Self {
+            initial_delay,
+            repeat_interval,
+            repeatable: repeatable.into_iter().map(HudAction::Player).collect(),
+            timers: Vec::new(),
+        }
+    }
+
+    /// `InputMap::poll_actions` with held repeatable actions fired again when due, timed by
+    /// `get_frame_time`. Call it once a frame.
+    pub fn poll(&mut self, input: &InputMap) -> Vec<HudAction> {
+        self.poll_with(input, get_frame_time())
+    }
+
+    /// `poll` with the frame time given, for fixed time steps
+    pub fn poll_with(&mut self, input: &InputMap, dt: f32) -> Vec<HudAction> {
+        let pressed = input.poll_actions();
+        // a released key forgets its timer, so pressing it again starts over
+        self.timers.retain(|(action, _)| input.is_down(action));
+
+        let mut actions = Vec::new();
+        for action in pressed.iter().filter(|action| !self.repeatable.contains(action)) {
+            actions.push(action.clone());
+        }
+        for action in &self.repeatable {
+            let timer = self.timers.iter_mut().find(|(held, _)| held == action).map(|(_, timer)| timer);
+            let fire = match timer {
+                _ if pressed.contains(action) => {
+                    self.timers.retain(|(held, _)| held != action);
+                    self.timers.push((action.clone(), self.initial_delay));
+                    true
+                }
+                Some(timer) => {
+                    *timer -= dt;
+                    // a long frame fires once, not once per interval it spanned
+                    let due = *timer <= 0.0;
+                    if due {
+                        *timer = (*timer + self.repeat_interval).max(0.0);
+                    }
+                    due
+                }
+                None => false,
+            };
+            if fire {
+                actions.push(action.clone());
+            }
+        }
+        actions
+    }
+}
+
+/// The next key pressed this frame with the modifiers held, ignoring the modifier keys
+/// themselves. A rebinding screen calls this every frame until it returns a chord.
+pub fn capture_chord() -> Option<KeyChord> {
+    let key = get_last_key_pressed().filter(|key| !MODIFIER_KEYS.contains(key))?;
+    let (shift, ctrl, alt) = held_modifiers();
+    Some(KeyChord { key, shift, ctrl, alt })
+}
+
+fn held_modifiers() -> (bool, bool, bool) {
+    let down = |left, right| is_key_down(left) || is_key_down(right);
+    (
+        down(KeyCode::LeftShift, KeyCode::RightShift),
+        down(KeyCode::LeftControl, KeyCode::RightControl),
+        down(KeyCode::LeftAlt, KeyCode::RightAlt),
+    )
+}
+
+const MODIFIER_KEYS: [KeyCode; 8] = [
+    KeyCode::LeftShift,
+    KeyCode::RightShift,
+    KeyCode::LeftControl,
+    KeyCode::RightControl,
+    KeyCode::LeftAlt,
+    KeyCode::RightAlt,
+    KeyCode::LeftSuper,
+    KeyCode::RightSuper,
+];
+
+// every key a binding can name, looked up by its `Debug` name
+const KEYS: [KeyCode; 120] = {
+    use KeyCode::*;
+    [
+        Space, Apostrophe, Comma, Minus, Period, Slash,
+        Key0, Key1, Key2, Key3, Key4, Key5, Key6, Key7, Key8, Key9,
+        Semicolon, Equal,
+        A, B, C, D, E, F, G, H, I, J, K, L, M, N, O, P, Q, R, S, T, U, V, W, X, Y, Z,
+        LeftBracket, Backslash, RightBracket, GraveAccent, World1, World2,
+        Escape, Enter, Tab, Backspace, Insert, Delete, Right, Left, Down, Up,
+        PageUp, PageDown, Home, End, CapsLock, ScrollLock, NumLock, PrintScreen, Pause,
+        F1, F2, F3, F4, F5, F6, F7, F8, F9, F10, F11, F12, F13,
+        F14, F15, F16, F17, F18, F19, F20, F21, F22, F23, F24, F25,
+        Kp0, Kp1, Kp2, Kp3, Kp4, Kp5, Kp6, Kp7, Kp8, Kp9,
+        KpDecimal, KpDivide, KpMultiply, KpSubtract, KpAdd, KpEnter, KpEqual,
+        LeftShift, LeftControl, LeftAlt, LeftSuper, RightShift, RightControl, RightAlt, RightSuper, Menu,
+    ]
+};
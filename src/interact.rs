@@ -0,0 +1,238 @@
+//! What's in a cell, and what happens when the player uses the one in front of them.
+
+use crate::math::*;
+use crate::{events::WorldEvent, AtlasWorld, MapSource};
+
+/// Every layer value of one cell. The values are all 0 outside the map.
+#[derive(Clone, Copy, Default, PartialEq, Eq, Debug)]
+pub struct CellContents {
+    pub in_bounds: bool,
+    pub wall: u8,
+    pub floor: u8,
+    pub ceiling: u8,
+    pub object: u8,
+    pub door: u8,
+    pub trigger: u8,
+}
+
+/// What `AtlasWorld::interact` found in the facing cell.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Interaction {
+    /// a door, which was opened or closed by the interaction
+    Door { value: u8, open: bool },
+    Object(u8),
+    Wall(u8),
+    Nothing,
+    OutOfBounds,
+}
+
+impl<M: MapSource> AtlasWorld<M> {
+    pub fn cell_contents(&self, pos: IVec2) -> CellContents {
+        if !self.map.in_bounds(pos) {
+            return CellContents::default();
+        }
+        let get = |layer: &str| self.map.get(layer, pos).unwrap_or(0);
+        CellContents {
+            in_bounds: true,
+            wall: get("wall"),
+            floor: get("floor"),
+            ceiling: get("ceiling"),
+            object: get("object"),
+            door: get("doors"),
+            trigger: get("trigger"),
+        }
+    }
+
+    /// Uses the cell the player is facing. A door there is toggled, see `toggle_door_at`;
+    /// anything else is only reported, objects before walls, with an `Interacted` event for
+    /// an object or wall.
+    pub fn interact(&mut self) -> Interaction {
+        let pos = self.player.facing_cell();
+        let contents = self.cell_contents(pos);
+        if !contents.in_bounds {
+            return Interaction::OutOfBounds;
+        }
+        if contents.door != 0 {
+            if let Some(open) = self.toggle_door_at(pos) {
+                return Interaction::Door { value: contents.door, open };
+            }
+        }
+        let interaction = match contents {
+            CellContents { object, .. } if object != 0 => Interaction::Object(object),
+            CellContents { wall, .. } if wall != 0 => Interaction::Wall(wall),
+            _ => return Interaction::Nothing,
+        };
+        self.push_event(WorldEvent::Interacted { pos, interaction });
+        interaction
+    }
+
+    /// Clears the object at `pos` and returns its value, emitting `ObjectTaken`. `None`, changing
+    /// nothing, outside the map or where there is no object.
+    pub fn take_object(&mut self, pos: IVec2) -> Option<u8> {
+        let id = self.map.get("object", pos).filter(|id| *id != 0)?;
+        if !self.set_cell("object", pos, 0) {
+            return None;
+        }
+        self.push_event(WorldEvent::ObjectTaken { id, pos });
+        Some(id)
+    }
+
+    /// Puts object `id` at `pos`, emitting `ObjectPlaced`. Returns false, changing nothing,
+    /// outside the map or where an object already stands.
+    pub fn respawn_object(&mut self, pos: IVec2, id: u8) -> bool {
+        if id == 0 || self.map.get("object", pos) != Some(0) || !self.set_cell("object", pos, id) {
+            return false;
+        }
+        self.push_event(WorldEvent::ObjectPlaced { id, pos });
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{AtlasCollection, AtlasMap, CellPass, Direction, Grid, Player};
+
+    // a 3x3 map around (1, 1): wall 2 to the north, object 5 to the east, door 1 to the south
+    // and nothing to the west
+    fn world(x: i32, y: i32, direction: Direction) -> AtlasWorld {
+        let mut map = AtlasMap::empty(3, 3);
+        map.floor.fill(1);
+        map.ceiling.fill(4);
+        map.wall.set(1, 0, 2);
+        map.object.set(2, 1, 5);
+        let mut doors = Grid::new(3, 3);
+        doors.set(1, 2, 1);
+        map.layers.insert("doors".to_owned(), doors);
+        AtlasWorld::new(Player::new(x, y, direction), map, AtlasCollection::new())
+    }
+
+    fn interacted(world: &mut AtlasWorld) -> Vec<(IVec2, Interaction)> {
+        world.poll_events().filter_map(|event| match event {
+            WorldEvent::Interacted { pos, interaction } => Some((pos, interaction)),
+            _ => None,
+        }).collect()
+    }
+
+    #[test]
+    fn each_direction_uses_the_cell_it_faces() {
+        let cases = [
+            (Direction::North, ivec2(1, 0), Interaction::Wall(2)),
+            (Direction::East, ivec2(2, 1), Interaction::Object(5)),
+            (Direction::South, ivec2(1, 2), Interaction::Door { value: 1, open: true }),
+            (Direction::West, ivec2(0, 1), Interaction::Nothing),
+        ];
+        for (direction, facing, expected) in cases {
+            let mut world = world(1, 1, direction);
+            assert_eq!(world.player.facing_cell(), facing, "{direction:?}");
+            assert_eq!(world.interact(), expected, "{direction:?}");
+            let reported = matches!(expected, Interaction::Wall(_) | Interaction::Object(_));
+            assert_eq!(interacted(&mut world), if reported { vec![(facing, expected)] } else { vec![] }, "{direction:?}");
+        }
+    }
+
+    #[test]
+    fn doors_toggle_each_time() {
+        let mut world = world(1, 1, Direction::South);
+        assert_eq!(world.interact(), Interaction::Door { value: 1, open: true });
+        assert_eq!(world.interact(), Interaction::Door { value: 1, open: false });
+    }
+
+    #[test]
+    fn objects_come_before_walls() {
+        let mut world = world(1, 1, Direction::North);
+        world.map.object.set(1, 0, 7);
+        assert_eq!(world.interact(), Interaction::Object(7));
+    }
+
+    #[test]
+    fn map_edges_are_out_of_bounds() {
+        let corners = [
+            (0, 0, Direction::North),
+            (0, 0, Direction::West),
+            (2, 0, Direction::North),
+            (2, 0, Direction::East),
+            (2, 2, Direction::East),
+            (2, 2, Direction::South),
+            (0, 2, Direction::South),
+            (0, 2, Direction::West),
+        ];
+        for (x, y, direction) in corners {
+            let mut world = world(x, y, direction);
+            let facing = world.player.facing_cell();
+            assert_eq!(world.cell_contents(facing), CellContents::default(), "{facing}");
+            assert_eq!(world.interact(), Interaction::OutOfBounds, "({x}, {y}) {direction:?}");
+            assert!(interacted(&mut world).is_empty());
+        }
+    }
+
+    #[test]
+    fn contents_hold_every_layer() {
+        let world = world(1, 1, Direction::North);
+        let contents = world.cell_contents(ivec2(2, 1));
+        assert_eq!(contents, CellContents { in_bounds: true, wall: 0, floor: 1, ceiling: 4, object: 5, door: 0, trigger: 0 });
+        assert_eq!(world.cell_contents(ivec2(1, 2)).door, 1);
+        assert_eq!(world.cell_contents(ivec2(-1, i32::MAX)), CellContents::default());
+    }
+
+    fn object_events(world: &mut AtlasWorld) -> Vec<WorldEvent> {
+        world.poll_events().filter(|event| matches!(event, WorldEvent::ObjectTaken { .. } | WorldEvent::ObjectPlaced { .. })).collect()
+    }
+
+    #[test]
+    fn taking_and_dropping_objects_reports_them() {
+        let mut world = world(1, 1, Direction::East);
+        let revision = world.map_revision();
+        assert_eq!(world.take_object(ivec2(2, 1)), Some(5));
+        assert_eq!(object_events(&mut world), [WorldEvent::ObjectTaken { id: 5, pos: ivec2(2, 1) }]);
+        assert_eq!(world.map.object.get(2, 1), Some(0));
+        assert_eq!(world.interact(), Interaction::Nothing);
+        assert_ne!(world.map_revision(), revision);
+
+        // nothing left to take, nor anything past the edge or under the player
+        for pos in [ivec2(2, 1), ivec2(3, 1), ivec2(-1, i32::MIN), ivec2(1, 1)] {
+            assert_eq!(world.take_object(pos), None, "{pos}");
+        }
+        assert!(object_events(&mut world).is_empty());
+
+        assert!(world.respawn_object(ivec2(2, 1), 8));
+        assert_eq!(object_events(&mut world), [WorldEvent::ObjectPlaced { id: 8, pos: ivec2(2, 1) }]);
+        assert_eq!(world.interact(), Interaction::Object(8));
+        // not on top of another object, as nothing, or off the map
+        assert!(!world.respawn_object(ivec2(2, 1), 9));
+        assert!(!world.respawn_object(ivec2(0, 1), 0));
+        assert!(!world.respawn_object(ivec2(3, 1), 9));
+        assert!(object_events(&mut world).is_empty());
+        assert_eq!(world.map.object.get(2, 1), Some(8));
+    }
+
+    #[test]
+    fn taken_objects_stop_drawing_and_stay_taken_in_saves() {
+        let file = std::fs::File::open(crate::test_support::manifest_path("fixtures/scenes/object_room.json")).unwrap();
+        let map = AtlasMap::from_reader_auto(file).unwrap();
+        let mut world = crate::test_support::demo_world(Player::new(3, 5, Direction::North), map.clone());
+        let drawn_objects = |world: &AtlasWorld| {
+            let mut cells: Vec<IVec2> = world.capture_commands().iter()
+                .filter(|command| command.order.2 == CellPass::Object as u8)
+                .map(|command| {
+                    let view = world.render_config.draw_order.cell(command.order);
+                    world.view_cell(view.x, view.y)
+                })
+                .collect();
+            cells.sort_by_key(|pos| (pos.y, pos.x));
+            cells.dedup();
+            cells
+        };
+        assert_eq!(drawn_objects(&world), [ivec2(2, 2), ivec2(4, 2), ivec2(3, 3)]);
+
+        assert_eq!(world.take_object(ivec2(3, 3)), Some(1));
+        assert_eq!(drawn_objects(&world), [ivec2(2, 2), ivec2(4, 2)]);
+
+        let state = world.save_state();
+        let mut loaded = crate::test_support::demo_world(Player::new(3, 5, Direction::North), map);
+        loaded.load_state(state).unwrap();
+        assert_eq!(loaded.map.object.get(3, 3), Some(0));
+        assert_eq!(drawn_objects(&loaded), [ivec2(2, 2), ivec2(4, 2)]);
+    }
+}
+
@@ -0,0 +1,419 @@
+//! Long operations split into resumable jobs, so a game loop can spend a couple of
+//! milliseconds a frame on them instead of stalling. Every job gives the same result as the
+//! blocking call it stands in for.
+
+use std::time::Duration;
+
+use crate::math::*;
+use crate::{
+    pipeline::{shape_errors, ConnectivityScan, GenerateParams, MapReport, MazeCarver},
+    AtlasMap, AtlasWorld, AutotileTable, Grid, ValidationReport, Warp,
+};
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum JobStatus {
+    /// how far along the job is, 0..1
+    Running(f32),
+    Done,
+}
+
+pub trait Job {
+    type Output;
+
+    /// Works until `budget` is used up or the job is done. Each call makes some progress
+    /// however small the budget, and goes over it by at most one unit of work.
+    fn step(&mut self, budget: Duration) -> JobStatus;
+
+    /// how far along the job is, 0..1
+    fn progress(&self) -> f32;
+
+    /// the result, once `step` has returned `Done`
+    fn take_output(&mut self) -> Option<Self::Output>;
+
+    /// finishes the job in one go, the same as the blocking call
+    fn run_to_end(mut self) -> Self::Output
+    where
+        Self: Sized,
+    {
+        while self.step(Duration::MAX) != JobStatus::Done {}
+        self.take_output().expect("a done job has its output")
+    }
+}
+
+// seconds since the epoch, through miniquad's `date::now` when it's there since `Instant`
+// isn't available on wasm
+#[cfg(feature = "render")]
+fn now() -> f64 {
+    macroquad::miniquad::date::now()
+}
+
+#[cfg(not(feature = "render"))]
+fn now() -> f64 {
+    std::time::SystemTime::now().duration_since(std::time::UNIX_EPOCH).map_or(0.0, |since| since.as_secs_f64())
+}
+
+struct Deadline(f64);
+
+impl Deadline {
+    fn after(budget: Duration) -> Self {
+        Self(now() + budget.as_secs_f64())
+    }
+
+    fn passed(&self) -> bool {
+        now() >= self.0
+    }
+}
+
+enum ValidationStage {
+    Connectivity(ConnectivityScan),
+    Done(ValidationReport),
+}
+
+/// `ValidationReport::for_map`, one flood fill cell at a time. Works on its own copy of the
+/// map, so edits made while it runs aren't seen.
+pub struct ValidationJob {
+    map: AtlasMap,
+    spawn: IVec2,
+    warps: Vec<Warp>,
+    stage: Option<ValidationStage>,
+}
+
+impl ValidationJob {
+    pub fn new(map: AtlasMap, spawn: IVec2, warps: Vec<Warp>) -> Self {
+        let errors = shape_errors(&map);
+        let stage = if errors.is_empty() {
+            ValidationStage::Connectivity(ConnectivityScan::new(&map))
+        } else {
+            let map_report = MapReport { errors, ..Default::default() };
+            ValidationStage::Done(ValidationReport::for_map_with(&map, spawn, &warps, map_report))
+        };
+        Self { map, spawn, warps, stage: Some(stage) }
+    }
+}
+
+impl Job for ValidationJob {
+    type Output = ValidationReport;
+
+    fn step(&mut self, budget: Duration) -> JobStatus {
+        let deadline = Deadline::after(budget);
+        if let Some(ValidationStage::Connectivity(scan)) = &mut self.stage {
+            loop {
+                if scan.step(&self.map) {
+                    let map_report = MapReport { unreachable: scan.unreachable(), ..Default::default() };
+                    let report = ValidationReport::for_map_with(&self.map, self.spawn, &self.warps, map_report);
+                    self.stage = Some(ValidationStage::Done(report));
+                    break;
+                }
+                if deadline.passed() {
+                    return JobStatus::Running(self.progress());
+                }
+            }
+        }
+        JobStatus::Done
+    }
+
+    fn progress(&self) -> f32 {
+        match &self.stage {
+            Some(ValidationStage::Connectivity(scan)) => scan.progress().min(0.99),
+            _ => 1.0,
+        }
+    }
+
+    fn take_output(&mut self) -> Option<ValidationReport> {
+        match self.stage.take() {
+            Some(ValidationStage::Done(report)) => Some(report),
+            stage => {
+                self.stage = stage;
+                None
+            }
+        }
+    }
+}
+
+/// `pipeline::generate_map`, one corridor cell at a time.
+pub struct GenerationJob {
+    carver: MazeCarver,
+    done: bool,
+    taken: bool,
+}
+
+impl GenerationJob {
+    pub fn new(params: &GenerateParams) -> Self {
+        Self { carver: MazeCarver::new(params), done: false, taken: false }
+    }
+}
+
+impl Job for GenerationJob {
+    type Output = AtlasMap;
+
+    fn step(&mut self, budget: Duration) -> JobStatus {
+        let deadline = Deadline::after(budget);
+        while !self.done {
+            self.done = self.carver.step();
+            if !self.done && deadline.passed() {
+                return JobStatus::Running(self.progress());
+            }
+        }
+        JobStatus::Done
+    }
+
+    fn progress(&self) -> f32 {
+        if self.done { 1.0 } else { self.carver.progress() }
+    }
+
+    fn take_output(&mut self) -> Option<AtlasMap> {
+        if !self.done || self.taken {
+            return None;
+        }
+        self.taken = true;
+        Some(std::mem::replace(&mut self.carver.map, AtlasMap::empty(0, 0)))
+    }
+}
+
+/// `AtlasMap::bake_autotiles`, one row at a time, into a layer that `apply` writes into the
+/// map. Works on its own copy of the walls, so bake again after editing them.
+pub struct BakeJob {
+    map: AtlasMap,
+    table: AutotileTable,
+    rows: Vec<Vec<u8>>,
+    taken: bool,
+}
+
+impl BakeJob {
+    pub fn new(map: &AtlasMap, table: AutotileTable) -> Self {
+        let mut walls = AtlasMap::empty(map.width, map.height);
+        walls.wall = map.wall.clone();
+        Self { map: walls, table, rows: Vec::with_capacity(map.height), taken: false }
+    }
+
+    fn done(&self) -> bool {
+        self.rows.len() >= self.map.height
+    }
+}
+
+impl Job for BakeJob {
+    /// the `autotile` layer
+    type Output = Grid;
+
+    fn step(&mut self, budget: Duration) -> JobStatus {
+        let deadline = Deadline::after(budget);
+        while !self.done() {
+            let row = self.map.baked_autotile_row(&self.table, self.rows.len());
+            self.rows.push(row);
+            if !self.done() && deadline.passed() {
+                return JobStatus::Running(self.progress());
+            }
+        }
+        JobStatus::Done
+    }
+
+    fn progress(&self) -> f32 {
+        if self.done() { 1.0 } else { self.rows.len() as f32 / self.map.height as f32 }
+    }
+
+    fn take_output(&mut self) -> Option<Grid> {
+        if !self.done() || self.taken {
+            return None;
+        }
+        self.taken = true;
+        Some(Grid::from_rows(std::mem::take(&mut self.rows)).expect("baked rows are all as wide as the map"))
+    }
+}
+
+impl AtlasMap {
+    pub fn validate_incremental(&self, spawn: IVec2, warps: &[Warp]) -> ValidationJob {
+        ValidationJob::new(self.clone(), spawn, warps.to_vec())
+    }
+
+    pub fn bake_autotiles_incremental(&self, table: &AutotileTable) -> BakeJob {
+        BakeJob::new(self, table.clone())
+    }
+}
+
+impl AtlasWorld<AtlasMap> {
+    /// `ValidationReport::for_map` for the player's cell and the world's warps, as a job
+    pub fn validate_incremental(&self) -> ValidationJob {
+        self.map.validate_incremental(ivec2(self.player.x, self.player.y), &self.warps)
+    }
+
+    /// Writes the layer of a finished `BakeJob` like `bake_autotiles` would. Returns false, and
+    /// leaves the map alone, when the job isn't done or the map changed since it was started.
+    pub fn apply_bake(&mut self, job: &mut BakeJob) -> bool {
+        if job.map.wall != self.map.wall {
+            return false;
+        }
+        let Some(autotile) = job.take_output() else {
+            return false;
+        };
+        self.map.autotile = autotile;
+        self.autotile_revision = Some(self.map_revision());
+        true
+    }
+}
+
+/// `pipeline::generate_map` as a job
+pub fn generate_map_incremental(params: &GenerateParams) -> GenerationJob {
+    GenerationJob::new(params)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pipeline::{generate_map, validate_map, ValidateInput};
+    use crate::test_support::manifest_path;
+
+    fn params(width: usize, height: usize, seed: u64) -> GenerateParams {
+        GenerateParams { width, height, seed, wall: 2 }
+    }
+
+    // a maze with a walled off pocket in its bottom right corner, cutting off more of the maze
+    // wherever it ran through there
+    fn pocketed(size: usize, seed: u64) -> AtlasMap {
+        let mut map = generate_map(&params(size, size, seed));
+        for (x, y) in [(size - 4, size - 2), (size - 4, size - 3), (size - 3, size - 4), (size - 2, size - 4)] {
+            map.wall.set(x, y, 1);
+        }
+        for (x, y) in [(size - 3, size - 2), (size - 2, size - 2), (size - 2, size - 3)] {
+            map.wall.set(x, y, 0);
+        }
+        map
+    }
+
+    // steps `job` to the end a budget at a time, returning its output and how many steps it took
+    fn run_stepped<J: Job>(mut job: J, budget: Duration) -> (J::Output, usize) {
+        let mut steps = 1;
+        let mut last = job.progress();
+        while let JobStatus::Running(progress) = job.step(budget) {
+            assert!((0.0..1.0).contains(&progress) && progress >= last);
+            assert!(job.take_output().is_none());
+            last = progress;
+            steps += 1;
+        }
+        assert_eq!(job.progress(), 1.0);
+        (job.take_output().unwrap(), steps)
+    }
+
+    #[test]
+    fn generation_jobs_make_the_same_maps() {
+        for (width, height, seed) in [(3, 3, 0), (4, 9, 1), (31, 17, 7), (64, 64, 12345)] {
+            let params = params(width, height, seed);
+            let blocking = generate_map(&params);
+            assert_eq!(generate_map_incremental(&params).run_to_end().wall, blocking.wall);
+            let (stepped, _) = run_stepped(generate_map_incremental(&params), Duration::ZERO);
+            assert_eq!((stepped.wall, stepped.floor, stepped.ceiling), (blocking.wall, blocking.floor, blocking.ceiling));
+        }
+    }
+
+    #[test]
+    fn validation_jobs_make_the_same_reports() {
+        let mut inputs: Vec<ValidateInput> = ["clean", "row_length", "unreachable", "spawn_in_wall", "warp_target_in_wall"]
+            .iter()
+            .map(|name| serde_json::from_slice(&std::fs::read(manifest_path(&format!("fixtures/validation/{name}.json"))).unwrap()).unwrap())
+            .collect();
+        inputs.push(ValidateInput { map: pocketed(41, 3), spawn: [1, 1], warps: Vec::new(), atlases: Default::default() });
+        inputs.push(ValidateInput { map: AtlasMap::empty(0, 0), spawn: [0, 0], warps: Vec::new(), atlases: Default::default() });
+
+        for input in inputs {
+            let spawn = IVec2::from(input.spawn);
+            let blocking = ValidationReport::for_map(&input.map, spawn, &input.warps);
+            assert_eq!(input.map.validate_incremental(spawn, &input.warps).run_to_end(), blocking);
+            let (stepped, _) = run_stepped(input.map.validate_incremental(spawn, &input.warps), Duration::ZERO);
+            assert_eq!(stepped, blocking);
+        }
+        // the pocket and whatever it cut off show up, so the stepped fill was really compared
+        // on something
+        assert!(!validate_map(&pocketed(41, 3)).unreachable.is_empty());
+    }
+
+    #[test]
+    fn bake_jobs_make_the_same_layer() {
+        let table = AutotileTable::default();
+        for map in [pocketed(33, 9), generate_map(&params(5, 12, 2)), AtlasMap::empty(0, 0)] {
+            let mut blocking = map.clone();
+            blocking.bake_autotiles(&table);
+            let (stepped, _) = run_stepped(map.bake_autotiles_incremental(&table), Duration::ZERO);
+            assert_eq!(stepped, blocking.autotile);
+        }
+    }
+
+    #[test]
+    fn baking_applies_only_to_the_walls_it_started_from() {
+        let table = AutotileTable::default();
+        let map = generate_map(&params(21, 21, 4));
+        let mut world = AtlasWorld::new(crate::Player::new(1, 1, crate::Direction::North), map.clone(), crate::AtlasCollection::new());
+        let mut blocking = map.clone();
+        blocking.bake_autotiles(&table);
+
+        let mut job = world.map.bake_autotiles_incremental(&table);
+        // not done yet
+        assert!(!world.apply_bake(&mut job));
+        job.step(Duration::MAX);
+        world.map.wall.set(1, 1, 2);
+        assert!(!world.apply_bake(&mut job));
+        world.map.wall.set(1, 1, 0);
+        assert!(world.apply_bake(&mut job));
+        assert_eq!(world.map.autotile, blocking.autotile);
+        // the output is handed over once
+        assert!(!world.apply_bake(&mut job));
+    }
+
+    // With no budget every step does exactly one unit of work, which is how far past its budget
+    // a step may go.
+    #[test]
+    fn steps_without_a_budget_do_one_unit_of_work() {
+        let params = params(61, 61, 5);
+        let mut carver = MazeCarver::new(&params);
+        let mut carved = 1;
+        while !carver.step() {
+            carved += 1;
+        }
+        assert_eq!(run_stepped(generate_map_incremental(&params), Duration::ZERO).1, carved);
+
+        let map = pocketed(61, 5);
+        let mut scan = ConnectivityScan::new(&map);
+        let mut filled = 1;
+        while !scan.step(&map) {
+            filled += 1;
+        }
+        assert_eq!(run_stepped(map.validate_incremental(ivec2(1, 1), &[]), Duration::ZERO).1, filled);
+
+        let table = AutotileTable::default();
+        assert_eq!(run_stepped(map.bake_autotiles_incremental(&table), Duration::ZERO).1, map.height);
+
+        // and an unlimited budget finishes in one
+        assert_eq!(run_stepped(generate_map_incremental(&params), Duration::MAX).1, 1);
+        assert_eq!(run_stepped(map.validate_incremental(ivec2(1, 1), &[]), Duration::MAX).1, 1);
+        assert_eq!(run_stepped(map.bake_autotiles_incremental(&table), Duration::MAX).1, 1);
+    }
+
+    #[test]
+    fn steps_keep_near_their_budget() {
+        const BUDGET: Duration = Duration::from_millis(2);
+        // generous for a debug build on a busy machine, a blocking run takes far longer
+        let limit = BUDGET * 10;
+        let timed = |job: &mut dyn FnMut(Duration) -> JobStatus| {
+            let mut steps = 0;
+            loop {
+                let start = now();
+                let status = job(BUDGET);
+                let took = Duration::from_secs_f64((now() - start).max(0.0));
+                assert!(took <= limit, "a step took {took:?}");
+                steps += 1;
+                if status == JobStatus::Done {
+                    return steps;
+                }
+            }
+        };
+
+        let map = pocketed(301, 5);
+        let table = AutotileTable::default();
+        let mut generation = generate_map_incremental(&params(301, 301, 5));
+        let generation_steps = timed(&mut |budget| generation.step(budget));
+        let mut validation = map.validate_incremental(ivec2(1, 1), &[]);
+        let validation_steps = timed(&mut |budget| validation.step(budget));
+        let mut bake = map.bake_autotiles_incremental(&table);
+        let bake_steps = timed(&mut |budget| bake.step(budget));
+        // each was split up, so the limit was checked against steps that ran out of budget
+        assert!(generation_steps > 1 && validation_steps > 1 && bake_steps > 1, "{generation_steps} {validation_steps} {bake_steps}");
+    }
+}
@@ -0,0 +1,208 @@
+//! Several maps in one world, one of them current. The current map is `world.map`; the others
+//! are put away together with the doors, secrets, entities, warps, traps and automap that belong to them, and
+//! swapped back in when the player is sent to them.
+
+use ahash::{AHashMap, AHashSet};
+
+use crate::math::*;
+use crate::{events::WorldEvent, saves::{self, LevelState}, AtlasWorld, AutoMap, Direction, Entity, MapSource, Spinner, Teleporter, Warp};
+
+// a map that isn't current, with the state that goes with it
+pub(crate) struct Level<M> {
+    map: M,
+    open_doors: AHashSet<IVec2>,
+    // turn each open door was opened on, so doors left open still close after the player is back
+    door_timers: AHashMap<IVec2, u64>,
+    secret_walls: AHashSet<IVec2>,
+    revealed_secrets: AHashSet<IVec2>,
+    entities: Vec<Entity>,
+    warps: Vec<Warp>,
+    teleporters: Vec<Teleporter>,
+    spinners: Vec<Spinner>,
+    automap: Option<AutoMap>,
+}
+
+impl<M: MapSource> Level<M> {
+    pub(crate) fn state(&self) -> LevelState {
+        saves::level_state(&self.map, (&self.open_doors, &self.door_timers), (&self.secret_walls, &self.revealed_secrets), &self.entities, self.automap.as_ref())
+    }
+
+    // puts a saved state back, like `AtlasWorld::load_state` does for the current map; the
+    // state is expected to be this map's size
+    pub(crate) fn restore(&mut self, state: LevelState) {
+        saves::restore_layers(&mut self.map, &state);
+        let map = &self.map;
+        self.open_doors = saves::cells(&state.open_doors).filter(|pos| map.is_door_closed(*pos)).collect();
+        self.door_timers = saves::door_timers(&state, &self.open_doors);
+        if state.automap.is_some() {
+            self.automap = state.automap;
+        }
+        self.secret_walls = saves::cells(&state.secret_walls).collect();
+        self.revealed_secrets = saves::cells(&state.revealed_secrets).collect();
+        for pos in self.revealed_secrets.iter() {
+            self.map.set("wall", *pos, 0);
+        }
+        if let Some(entities) = state.entities {
+            self.entities = entities.into_iter().map(Entity::from).collect();
+        }
+    }
+
+    pub(crate) fn entities(&self) -> &[Entity] {
+        &self.entities
+    }
+}
+
+impl<M: MapSource> AtlasWorld<M> {
+    /// Adds a map the player can be sent to, with no open doors, entities, warps or traps. Returns
+    /// the map that was there under `map_id`, which can't be the current one.
+    pub fn add_level(&mut self, map_id: &str, map: M) -> Option<M> {
+        if map_id == self.current_map {
+            return Some(map);
+        }
+        let level = Level {
+            map,
+            open_doors: AHashSet::new(),
+            door_timers: AHashMap::new(),
+            secret_walls: AHashSet::new(),
+            revealed_secrets: AHashSet::new(),
+            entities: Vec::new(),
+            warps: Vec::new(),
+            teleporters: Vec::new(),
+            spinners: Vec::new(),
+            automap: None,
+        };
+        self.levels.insert(map_id.to_owned(), level).map(|level| level.map)
+    }
+
+    /// the map called `map_id`, whether or not it's current
+    pub fn level(&self, map_id: &str) -> Option<&M> {
+        if map_id == self.current_map {
+            return Some(&self.map);
+        }
+        self.levels.get(map_id).map(|level| &level.map)
+    }
+
+    /// the id of every map, the current one included, sorted
+    pub fn level_ids(&self) -> Vec<&str> {
+        let mut ids: Vec<&str> = self.levels.keys().map(String::as_str).chain([self.current_map.as_str()]).collect();
+        ids.sort_unstable();
+        ids
+    }
+
+    /// Makes `map_id` the current map and puts the player at `pos` facing `direction`. The
+    /// map left behind keeps its doors, entities, warps, traps and automap for when the player comes
+    /// back. Returns false, changing nothing, for an unknown map.
+    pub fn switch_level(&mut self, map_id: &str, pos: IVec2, direction: Direction) -> bool {
+        if map_id == self.current_map {
+            let from = ivec2(self.player.x, self.player.y);
+            self.place_player(pos, direction);
+            self.push_event(WorldEvent::Teleported { from, to: pos });
+            return true;
+        }
+        let Some(level) = self.levels.remove(map_id) else {
+            return false;
+        };
+
+        let automap = match (&self.automap, level.automap) {
+            (Some(current), None) => {
+                let mut automap = AutoMap::new(level.map.width(), level.map.height());
+                automap.reveal_mode = current.reveal_mode;
+                Some(automap)
+            }
+            (_, automap) => automap,
+        };
+        let left = Level {
+            map: std::mem::replace(&mut self.map, level.map),
+            open_doors: std::mem::replace(&mut self.open_doors, level.open_doors),
+            door_timers: std::mem::replace(&mut self.door_timers, level.door_timers),
+            secret_walls: std::mem::replace(&mut self.secret_walls, level.secret_walls),
+            revealed_secrets: std::mem::replace(&mut self.revealed_secrets, level.revealed_secrets),
+            entities: std::mem::replace(&mut self.entities, level.entities),
+            warps: std::mem::replace(&mut self.warps, level.warps),
+            teleporters: std::mem::replace(&mut self.teleporters, level.teleporters),
+            spinners: std::mem::replace(&mut self.spinners, level.spinners),
+            automap: std::mem::replace(&mut self.automap, automap),
+        };
+        let from = std::mem::replace(&mut self.current_map, map_id.to_owned());
+        self.levels.insert(from.clone(), left);
+
+        self.place_player(pos, direction);
+        self.touch_map();
+        // like `new`, a map that comes with baked autotiles is taken to be baked from what it holds
+        self.autotile_revision = self.map.get("autotile", IVec2::ZERO).map(|_| self.map_revision);
+        if let Some(stats) = &mut self.stats {
+            stats.enter_zone(map_id);
+        }
+        self.push_event(WorldEvent::LevelChanged { from, to: map_id.to_owned() });
+        true
+    }
+
+    pub(crate) fn place_player(&mut self, pos: IVec2, direction: Direction) {
+        self.player.x = pos.x;
+        self.player.y = pos.y;
+        self.player.direction = direction;
+        if self.map.focus(pos) {
+            self.touch_map();
+        }
+    }
+
+    /// Follows the warp at `pos` of the current map, if there is one, and otherwise sets off the
+    /// traps there, see `trigger_traps`. Returns whether the player was sent somewhere or
+    /// turned. Called after every move that lands.
+    pub fn step_on(&mut self, pos: IVec2) -> bool {
+        let Some(warp) = self.warps.iter().find(|warp| warp.pos() == pos).cloned() else {
+            return self.trigger_traps(pos);
+        };
+        let map_id = warp.dest_level.unwrap_or_else(|| self.current_map.clone());
+        let direction = warp.direction.unwrap_or(self.player.direction);
+        self.switch_level(&map_id, ivec2(warp.dest_x, warp.dest_y), direction)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{AtlasCollection, AtlasMap, Player};
+
+    fn world() -> AtlasWorld {
+        let mut world = AtlasWorld::new(Player::new(1, 1, Direction::North), AtlasMap::empty(5, 5), AtlasCollection::new());
+        world.add_level("cellar", AtlasMap::empty(3, 3));
+        world
+    }
+
+    fn warp(x: i32, y: i32, dest_level: Option<&str>, dest: (i32, i32), direction: Option<Direction>) -> Warp {
+        Warp { x, y, dest_level: dest_level.map(str::to_owned), dest_x: dest.0, dest_y: dest.1, direction, kind: None }
+    }
+
+    #[test]
+    fn stepping_on_an_exit_switches_level_and_back() {
+        let mut world = world();
+        world.warps.push(warp(2, 1, Some("cellar"), (0, 0), Some(Direction::South)));
+        assert!(world.step_on(ivec2(2, 1)));
+        assert_eq!(world.current_map, "cellar");
+        assert_eq!((world.player.x, world.player.y, world.player.direction), (0, 0, Direction::South));
+        // the warp went away with its level
+        assert!(world.warps.is_empty());
+        assert!(world.switch_level("main", ivec2(1, 1), Direction::North));
+        assert_eq!(world.warps.len(), 1);
+    }
+
+    #[test]
+    fn a_warp_on_the_same_level_keeps_the_facing() {
+        let mut world = world();
+        world.player.direction = Direction::West;
+        world.warps.push(warp(2, 1, None, (4, 4), None));
+        assert!(world.step_on(ivec2(2, 1)));
+        assert_eq!(world.current_map, "main");
+        assert_eq!((world.player.x, world.player.y, world.player.direction), (4, 4, Direction::West));
+        assert!(!world.step_on(ivec2(3, 3)));
+    }
+
+    #[test]
+    fn unknown_levels_change_nothing() {
+        let mut world = world();
+        world.warps.push(warp(2, 1, Some("attic"), (0, 0), None));
+        assert!(!world.step_on(ivec2(2, 1)));
+        assert_eq!((world.current_map.as_str(), world.player.x), ("main", 1));
+    }
+}
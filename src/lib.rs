@@ -1,13 +1,146 @@
-use std::{io::BufReader, path::Path};
+use std::{borrow::Cow, cell::{Cell, OnceCell, RefCell}, collections::{BTreeMap, VecDeque}, ops::Range, path::{Path, PathBuf}};
 
-use ahash::AHashMap;
+use ahash::{AHashMap, AHashSet};
+#[cfg(not(feature = "render"))]
+use crate::math::*;
+#[cfg(feature = "render")]
 use macroquad::prelude::*;
-use serde::{Deserialize, Serialize};
+use serde::{Deserialize, Serialize, Serializer};
+
+pub mod animation;
+#[cfg(feature = "aseprite")]
+pub mod aseprite;
+pub mod atlas_builder;
+pub mod automap;
+pub mod autotile;
+pub mod batch;
+pub mod behavior;
+#[cfg(feature = "binary")]
+pub mod binary;
+pub mod cache;
+pub mod chunked;
+pub mod combat;
+pub mod coords;
+pub mod doors;
+pub mod encounter;
+pub mod entities;
+pub mod events;
+pub mod generator;
+pub mod grid;
+pub mod hooks;
+pub mod hud;
+#[cfg(feature = "render")]
+pub mod input;
+pub mod interact;
+pub mod jobs;
+pub mod levels;
+pub mod lighting;
+pub mod localize;
+pub mod materials;
+pub mod math;
+pub mod movement;
+pub mod occlusion;
+pub mod passability;
+pub mod peek;
+pub mod pick;
+pub mod pipeline;
+pub mod preflight;
+pub mod projection;
+pub mod raster;
+pub mod render_order;
+pub mod replay;
+pub mod saves;
+pub mod secrets;
+pub mod sky;
+pub mod snapshot;
+pub mod stats;
+pub mod teleporters;
+pub mod terrain;
+#[cfg(test)]
+mod test_support;
+#[cfg(feature = "render")]
+pub mod textures;
+#[cfg(feature = "tiled")]
+pub mod tmx;
+pub mod turns;
+#[cfg(feature = "render")]
+pub mod ui;
+pub mod validation;
+pub mod viewport;
+pub mod wall_properties;
+pub mod warps;
+#[cfg(all(feature = "watch", not(target_arch = "wasm32")))]
+pub mod watch;
+
+pub use animation::{PlayerAnimation, PlayerInput};
+#[cfg(feature = "aseprite")]
+pub use aseprite::AsepriteError;
+pub use atlas_builder::{AtlasBuildError, AtlasInfoBuilder};
+pub use automap::{AutoMap, MinimapExtent, MinimapLayout, MinimapOptions, RevealMode};
+pub use autotile::AutotileTable;
+pub use batch::RenderStats;
+pub use behavior::Behavior;
+pub use chunked::{ChunkProvider, ChunkedMap, DirectoryProvider, Eviction, GeneratedProvider};
+pub use combat::{AttackResult, Stats};
+pub use coords::{Direction, ViewPoint};
+pub use doors::DoorView;
+pub use encounter::{Encounter, EncounterOutcome, EntityId};
+pub use entities::{Entity, EntityView};
+pub use events::WorldEvent;
+pub use generator::{GeneratedMap, GeneratorConfig, MapGenerator};
+pub use grid::Grid;
+pub use hooks::CellDrawInfo;
+pub use hud::{HudAction, HudLayout, PlayerAction};
+#[cfg(feature = "render")]
+pub use input::{InputMap, KeyChord, RepeatingInput};
+pub use interact::{CellContents, Interaction};
+pub use jobs::{BakeJob, GenerationJob, Job, JobStatus, ValidationJob};
+pub use localize::{wrap_text, IdentityLocalizer, Localizer};
+pub use materials::{WallMaterial, WallMaterials};
+pub use movement::{Blocker, MoveOutcome, MovementRules, MovementView};
+pub use passability::{CollisionRules, PassabilityGrid};
+pub use peek::PeekSide;
+pub use pick::{PickResult, PickSurface};
+pub use preflight::{PreflightReport, PreflightWarning};
+pub use projection::Projection;
+pub use raster::{LayerPalette, SoftwareViewport};
+pub use render_order::{render_order_key, CellPass, Depth, DrawOrder, RenderOrderKey, SKY_ORDER};
+pub use replay::{Recorder, ReplayError, ReplayLog, Replayer};
+pub use saves::{LevelState, SaveError, SaveSlotStore, SavedEntity, SlotInfo, WorldSave, WorldState};
+pub use sky::SkySettings;
+pub use snapshot::{RenderSnapshot, SnapshotDiff};
+pub use stats::WorldStats;
+pub use teleporters::{Spin, Spinner, TeleportFacing, Teleporter};
+pub use terrain::{Terrain, TerrainTable};
+#[cfg(feature = "render")]
+pub use textures::{image_flipped, image_rotated, texture_flipped, texture_from_png_bytes, texture_rotated, Flip, Rotation};
+#[cfg(feature = "tiled")]
+pub use tmx::TILED_LAYERS;
+pub use turns::TurnResult;
+pub use validation::{Finding, Severity, ValidationReport};
+pub use viewport::{PeekFill, Viewport};
+#[cfg(feature = "render")]
+pub use warps::draw_warp_glyph;
+pub use wall_properties::{WallFlags, WallProperties};
+pub use warps::{Warp, WarpKind};
+#[cfg(all(feature = "watch", not(target_arch = "wasm32")))]
+pub use watch::AtlasWatcher;
 
 pub const VIEWPORT_WIDTH: i32 = 320;
 pub const VIEWPORT_HEIGHT: i32 = 256;
 
-#[derive(Serialize, Deserialize, Clone, Copy)]
+/// largest rect with the viewport's aspect ratio that fits in `area`, anchored at its top left
+pub fn fit_viewport(area: Rect) -> Rect {
+    let aspect = VIEWPORT_WIDTH as f32 / VIEWPORT_HEIGHT as f32;
+    let size = if area.w / aspect > area.h {
+        vec2(area.h * aspect, area.h)
+    } else {
+        vec2(area.w, area.w / aspect)
+    };
+    Rect::new(area.x, area.y, size.x, size.y)
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Hash, Debug)]
 pub struct Coords {
     pub h: i32,
     pub w: i32,
@@ -15,307 +148,3787 @@ pub struct Coords {
     pub y: i32,
 }
 
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct IRect {
+    pub x: i32,
+    pub y: i32,
+    pub w: i32,
+    pub h: i32,
+}
+
+impl IRect {
+    pub fn new(x: i32, y: i32, w: i32, h: i32) -> Self {
+        Self { x, y, w, h }
+    }
+}
+
+/// Which face of a cell a tile shows. Written in lowercase in the atlas json.
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Debug)]
+#[serde(rename_all = "lowercase")]
+pub enum Orientation {
+    Front,
+    Back,
+    Left,
+    Right,
+}
+
+impl Orientation {
+    pub const ALL: [Orientation; 4] = [Orientation::Front, Orientation::Back, Orientation::Left, Orientation::Right];
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Orientation::Front => "front",
+            Orientation::Back => "back",
+            Orientation::Left => "left",
+            Orientation::Right => "right",
+        }
+    }
+
+    /// the orientation written as `name` in the json, which is case sensitive
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|orientation| orientation.as_str() == name)
+    }
+}
+
+impl std::fmt::Display for Orientation {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
 #[derive(Serialize, Deserialize, Clone)]
 pub struct Tile {
+    /// the rect drawn, and the one to show for animated tiles where only one fits
     pub atlas_coords: Coords,
     pub screen_coords: Coords,
     pub x: i32,
     pub z: i32,
-    pub orientation: Option<String>,
+    pub orientation: Option<Orientation>,
+    /// frames drawn in place of `atlas_coords` as world time passes
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub animation: Option<Animation>,
+    /// which of the atlas' textures the rects are cut from
+    #[serde(default, skip_serializing_if = "is_first_page")]
+    pub page: usize,
+    /// other rects drawn in place of `atlas_coords`, one picked per map cell, see `variant_coords`
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub variants: Vec<Coords>,
 }
 
-#[derive(Serialize, Deserialize)]
+fn is_first_page(page: &usize) -> bool {
+    *page == 0
+}
+
+impl Tile {
+    pub fn new(atlas_coords: Coords, screen_coords: Coords, x: i32, z: i32, orientation: Option<Orientation>) -> Self {
+        Self {
+            atlas_coords,
+            screen_coords,
+            x,
+            z,
+            orientation,
+            animation: None,
+            page: 0,
+            variants: Vec::new(),
+        }
+    }
+
+    /// the atlas rect to draw `time` seconds into the world clock
+    pub fn atlas_coords_at(&self, time: f32) -> Coords {
+        self.animation.as_ref().and_then(|animation| animation.frame_at(time)).unwrap_or(self.atlas_coords)
+    }
+
+    /// The rect to draw in map cell `cell`: `atlas_coords` or one of the `variants`, picked by
+    /// hashing the cell with `seed` so a cell shows the same one every frame.
+    pub fn variant_coords(&self, cell: IVec2, seed: u64) -> Coords {
+        if self.variants.is_empty() {
+            return self.atlas_coords;
+        }
+        let pick = cell_hash(cell, seed) % (self.variants.len() as u64 + 1);
+        pick.checked_sub(1).map_or(self.atlas_coords, |i| self.variants[i as usize])
+    }
+}
+
+// splitmix64 over the cell and seed, for choices that must stay put as the view moves
+fn cell_hash(cell: IVec2, seed: u64) -> u64 {
+    let mut h = seed ^ ((cell.x as u32 as u64) << 32 | cell.y as u32 as u64);
+    h = h.wrapping_add(0x9E37_79B9_7F4A_7C15);
+    h = (h ^ (h >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+    h = (h ^ (h >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+    h ^ (h >> 31)
+}
+
+pub const DEFAULT_FRAME_DURATION_MS: u32 = 100;
+
+/// Atlas rects a tile cycles through, each shown for `frame_duration_ms`. Written in the atlas
+/// json as a tile's `frames` and `frame_duration_ms`.
+#[derive(Serialize, Deserialize, Clone, PartialEq, Eq, Debug)]
+pub struct Animation {
+    pub frames: Vec<Coords>,
+    pub frame_duration_ms: u32,
+}
+
+impl Animation {
+    /// the frame shown `time` seconds in, looping; `None` without frames
+    pub fn frame_at(&self, time: f32) -> Option<Coords> {
+        let frame_duration = self.frame_duration_ms.max(1) as f64 / 1000.0;
+        let index = (time as f64 / frame_duration).floor() as i64;
+        let count = i64::try_from(self.frames.len()).ok().filter(|count| *count > 0)?;
+        Some(self.frames[index.rem_euclid(count) as usize])
+    }
+
+    /// Seconds to shift the animation of the tiles in `cell` by, a whole number of frames
+    /// picked from the coordinates so neighboring cells don't animate in lockstep.
+    pub fn phase_offset(&self, cell: IVec2) -> f32 {
+        let count = self.frames.len().max(1) as i32;
+        let frame = (cell.x.wrapping_mul(7) ^ cell.y.wrapping_mul(13)).rem_euclid(count);
+        frame as f32 * self.frame_duration_ms as f32 / 1000.0
+    }
+}
+
+#[derive(Debug)]
+pub struct DuplicateTile {
+    pub x: i32,
+    pub z: i32,
+    pub orientation: Option<Orientation>,
+}
+
+impl std::fmt::Display for DuplicateTile {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "layer already has a tile at x {} z {} orientation {:?}", self.x, self.z, self.orientation)
+    }
+}
+
+impl std::error::Error for DuplicateTile {}
+
+/// A layer's tiles. `tiles` is only reachable through `tiles_mut` and `push_tile` so that the
+/// lookup index `find` uses can't fall behind edits.
+#[derive(Serialize, Deserialize, Clone)]
 pub struct Tiles {
     pub mode: i32,
     pub name: String,
-    pub tiles: Vec<Tile>,
+    tiles: Vec<Tile>,
     pub r#type: i32,
+    #[serde(skip)]
+    index: TileIndex,
 }
 
-#[derive(Serialize, Deserialize)]
-pub struct AtlasInfo {
-    pub layers: AHashMap<String, Tiles>,
+// (x, z, orientation) -> index of the first such tile in `tiles`, built for `len` tiles; a
+// deserialized `Tiles` has an empty index until `build_index` runs
+#[derive(Clone, Default)]
+struct TileIndex {
+    by_key: AHashMap<(i32, i32, Option<Orientation>), usize>,
+    len: usize,
 }
 
-pub struct Atlas {
-    pub atlas_info: AtlasInfo,
-    pub texture: Texture2D,
+/// `Tiles::tiles_mut`'s view of the tile list; the index is rebuilt when it is dropped.
+pub struct TilesMut<'a> {
+    tiles: &'a mut Tiles,
 }
 
-pub type AtlasCollection = AHashMap<String, Atlas>;
-pub trait Collection {
-    fn load<P: AsRef<Path>>(&mut self, atlas_id: &str, image_data: &[u8], data_path: P);
-    fn get_tile(&self, atlas_id: &str, layer_id: &str, x: i32, z: i32, orientation: Option<String>) -> Option<Tile>;
+impl std::ops::Deref for TilesMut<'_> {
+    type Target = Vec<Tile>;
+
+    fn deref(&self) -> &Vec<Tile> {
+        &self.tiles.tiles
+    }
 }
 
-impl Collection for AtlasCollection {
-    fn load<P: AsRef<Path>>(&mut self, atlas_id: &str, image_data: &[u8], data_path: P) {
-        let f = std::fs::File::open(data_path).unwrap();
-        let buf = BufReader::new(f);
-        let atlas_info = serde_json::from_reader(buf).unwrap();
-        let texture = Texture2D::from_file_with_format(image_data, None);
-        texture.set_filter(FilterMode::Nearest);
-        let atlas = Atlas {
-            atlas_info,
-            texture,
-        };
-        self.insert(atlas_id.to_owned(), atlas);
+impl std::ops::DerefMut for TilesMut<'_> {
+    fn deref_mut(&mut self) -> &mut Vec<Tile> {
+        &mut self.tiles.tiles
     }
+}
 
-    fn get_tile(&self, atlas_id: &str, layer_id: &str, x: i32, z: i32, orientation: Option<String>) -> Option<Tile> {
-        let layer = if let Some(atlas_info) = self.get(atlas_id) {
-            if let Some(layer) = atlas_info.atlas_info.layers.get(layer_id) {
-                layer
-            } else {
-                return None
-            }
-        } else {
-            return None;
+impl Drop for TilesMut<'_> {
+    fn drop(&mut self) {
+        self.tiles.build_index();
+    }
+}
+
+impl Tiles {
+    /// `mode` of a layer whose tiles are placed by the atlas' `Projection`
+    pub const COMPUTED_MODE: i32 = 2;
+    /// `type` of floor and ceiling layers, which `Projection::tile_rect` places flat
+    pub const FLOOR_TYPE: i32 = 3;
+    pub const CEILING_TYPE: i32 = 4;
+
+    pub fn new(mode: i32, r#type: i32) -> Self {
+        Self::from_tiles(mode, r#type, Vec::new())
+    }
+
+    /// A layer holding `tiles` as they are, without `push_tile`'s duplicate check.
+    pub fn from_tiles(mode: i32, r#type: i32, tiles: Vec<Tile>) -> Self {
+        let mut tiles = Self {
+            mode,
+            name: String::new(),
+            tiles,
+            r#type,
+            index: TileIndex::default(),
         };
+        tiles.build_index();
+        tiles
+    }
 
-        for tile in &layer.tiles {
-            if tile.x == x && tile.z == z {
-                if tile.orientation.is_none() || tile.orientation == orientation {
-                    return Some(tile.clone());
-                }
-            }
+    /// The `type` a layer named `layer_id` is given when nothing says otherwise: `FLOOR_TYPE`
+    /// and `CEILING_TYPE` for ids starting with `floor` and `ceiling` like the map layers, 0
+    /// for anything drawn upright.
+    pub fn type_for_layer(layer_id: &str) -> i32 {
+        match layer_id.split('-').next() {
+            Some("floor") => Self::FLOOR_TYPE,
+            Some("ceiling") => Self::CEILING_TYPE,
+            _ => 0,
         }
+    }
 
-        None
+    pub fn tiles(&self) -> &[Tile] {
+        &self.tiles
     }
-}
 
-pub struct Player {
-    pub x: i32,
-    pub y: i32,
-    pub direction: i32,
-}
+    /// The tile list to edit in place. Pushing through it skips the duplicate check in
+    /// `push_tile`; the index is rebuilt once the returned guard is dropped.
+    pub fn tiles_mut(&mut self) -> TilesMut<'_> {
+        TilesMut { tiles: self }
+    }
 
-impl Player {
-    fn get_direction_vector_offsets(&self, x: i32, z: i32) -> IVec2 {
-        match self.direction {
-            0 => IVec2::new(self.x + x, self.y + z),
-            1 => IVec2::new(self.x - z, self.y + x),
-            2 => IVec2::new(self.x - x, self.y - z),
-            3 => IVec2::new(self.x + z, self.y - x),
-            _ => IVec2::NEG_ONE
+    /// Indexes `tiles` for `find`. Done on load, by `from_tiles` and after `tiles_mut`; only
+    /// needed by hand for a `Tiles` deserialized on its own.
+    pub fn build_index(&mut self) {
+        let mut by_key = AHashMap::with_capacity(self.tiles.len());
+        for (i, tile) in self.tiles.iter().enumerate() {
+            by_key.entry((tile.x, tile.z, tile.orientation)).or_insert(i);
         }
+        self.index = TileIndex { by_key, len: self.tiles.len() };
     }
 
-    pub fn can_move(&self, map: &AtlasMap, pos: IVec2) -> bool {
-        return (pos.x >= 0 && pos.y >= 0 && pos.x < map.width as i32 && pos.y < map.height as i32) && map.wall[pos.y as usize][pos.x as usize] == 0
+    /// The first tile at (x, z) whose orientation is `orientation` or unset. Uses the index
+    /// when it is built and falls back to a scan otherwise.
+    pub fn find(&self, x: i32, z: i32, orientation: Option<Orientation>) -> Option<&Tile> {
+        if self.index.len != self.tiles.len() {
+            return self.tiles.iter().find(|tile| {
+                tile.x == x && tile.z == z && (tile.orientation.is_none() || tile.orientation == orientation)
+            });
+        }
+        let first = |orientation| self.index.by_key.get(&(x, z, orientation)).copied();
+        let i = match orientation {
+            Some(_) => first(orientation).into_iter().chain(first(None)).min(),
+            None => first(None),
+        }?;
+        Some(&self.tiles[i])
     }
 
-    pub fn invert_direction(&self) -> i32 {
-        (self.direction + 2) % 4
+    pub fn push_tile(&mut self, tile: Tile) -> Result<(), DuplicateTile> {
+        let key = (tile.x, tile.z, tile.orientation);
+        let in_sync = self.index.len == self.tiles.len();
+        let duplicate = match in_sync {
+            true => self.index.by_key.contains_key(&key),
+            false => self.tiles.iter().any(|other| (other.x, other.z, other.orientation) == key),
+        };
+        if duplicate {
+            return Err(DuplicateTile {
+                x: tile.x,
+                z: tile.z,
+                orientation: tile.orientation,
+            });
+        }
+
+        self.tiles.push(tile);
+        if in_sync {
+            self.index.by_key.insert(key, self.tiles.len() - 1);
+            self.index.len = self.tiles.len();
+        }
+        Ok(())
     }
+}
 
-    pub fn get_dest_pos(&self, direction: i32) -> IVec2 {
+/// Atlas metadata, normally deserialized from the atlas json but can be built in code:
+///
+/// ```
+/// use atlas_world::*;
+///
+/// let coords = Coords { x: 0, y: 0, w: 32, h: 32 };
+///
+/// let mut floor = Tiles::new(0, 0);
+/// floor.push_tile(Tile::new(coords, coords, 0, 0, None)).unwrap();
+/// floor.push_tile(Tile::new(coords, coords, 0, -1, None)).unwrap();
+/// assert!(floor.push_tile(Tile::new(coords, coords, 0, 0, None)).is_err());
+///
+/// let mut wall = Tiles::new(0, 0);
+/// wall.push_tile(Tile::new(coords, coords, 0, -1, Some(Orientation::Front))).unwrap();
+///
+/// let mut info = AtlasInfo::new();
+/// info.add_layer("floor-1", floor);
+/// info.add_layer("wall-1", wall);
+///
+/// let json = serde_json::to_vec(&info).unwrap();
+/// let reloaded: AtlasInfo = serde_json::from_slice(&json).unwrap();
+/// assert_eq!(reloaded.layers["floor-1"].tiles().len(), 2);
+/// assert_eq!(reloaded.layers["wall-1"].name, "wall-1");
+/// ```
+#[derive(Serialize, Deserialize, Default, Clone)]
+#[serde(try_from = "RawAtlasInfo", into = "RawAtlasInfo")]
+pub struct AtlasInfo {
+    pub layers: AHashMap<String, Tiles>,
+    pub templates: Vec<ScreenTemplate>,
+    /// places the tiles of computed layers, `Projection::default` when unset
+    pub projection: Option<Projection>,
+}
 
-        let mut dest_vec = ivec2(
-            ((direction*90) as f32).to_radians().sin() as i32,
-            -(((direction*90) as f32).to_radians().cos() as i32)
-        );
+/// Shared screen rect for every tile at (x, z, orientation). Tiles in the json can leave out
+/// `screen_coords` and get the template's rect filled in when the atlas is loaded.
+#[derive(Serialize, Deserialize, Clone, PartialEq, Debug)]
+pub struct ScreenTemplate {
+    pub x: i32,
+    pub z: i32,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub orientation: Option<Orientation>,
+    pub screen_coords: Coords,
+}
+
+type TemplateKey = (i32, i32, Option<Orientation>);
 
-        dest_vec.x = dest_vec.x + self.x;
-        dest_vec.y = dest_vec.y + self.y;
+#[derive(Debug)]
+pub struct MissingScreenCoords {
+    pub layer: String,
+    pub x: i32,
+    pub z: i32,
+    pub orientation: Option<Orientation>,
+}
 
-        return dest_vec;
+impl std::fmt::Display for MissingScreenCoords {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "layer '{}': tile at x {} z {} orientation {:?} has no screen_coords and no matching template",
+            self.layer, self.x, self.z, self.orientation
+        )
     }
+}
 
-    pub fn move_forward(&mut self, map: &AtlasMap) {
+impl std::error::Error for MissingScreenCoords {}
 
-        let dest_pos = self.get_dest_pos(self.direction);
+/// Why an atlas json doesn't describe a usable atlas.
+#[derive(Debug)]
+pub enum AtlasInfoError {
+    MissingScreenCoords(MissingScreenCoords),
+    /// `index` is the position of the tile in the layer's `tiles` list
+    UnknownOrientation { layer: String, index: usize, orientation: String },
+    /// an atlas rect of tile `index` spanning `start..end` along `axis`, "width" or "height",
+    /// past the page's `limit` pixels
+    RectOutOfBounds { layer: String, index: usize, axis: &'static str, start: i64, end: i64, limit: u32 },
+}
 
-        if self.can_move(map, dest_pos) {
-            self.x = dest_pos.x;
-            self.y = dest_pos.y;
+impl std::fmt::Display for AtlasInfoError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            AtlasInfoError::MissingScreenCoords(err) => err.fmt(f),
+            AtlasInfoError::UnknownOrientation { layer, index, orientation } => write!(
+                f,
+                "layer '{layer}': tile {index} has unknown orientation '{orientation}', expected front, back, left or right"
+            ),
+            AtlasInfoError::RectOutOfBounds { layer, index, axis, start, end, limit } => {
+                write!(f, "layer '{layer}': tile {index} atlas rect ({start}..{end}) exceeds texture {axis} {limit}")
+            }
         }
     }
+}
 
-    pub fn move_backward(&mut self, map: &AtlasMap) {
+impl std::error::Error for AtlasInfoError {}
 
-        let dest_pos = self.get_dest_pos(self.invert_direction());
+// the on-disk shape of an atlas, where screen_coords may be left to a template
+#[derive(Serialize, Deserialize)]
+struct RawAtlasInfo {
+    #[serde(serialize_with = "serialize_sorted")]
+    layers: AHashMap<String, RawTiles>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    templates: Vec<ScreenTemplate>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    projection: Option<Projection>,
+}
 
-        if self.can_move(map, dest_pos) {
-            self.x = dest_pos.x;
-            self.y = dest_pos.y;
+#[derive(Serialize, Deserialize)]
+struct RawTiles {
+    mode: i32,
+    name: String,
+    tiles: Vec<RawTile>,
+    r#type: i32,
+}
+
+#[derive(Serialize, Deserialize)]
+struct RawTile {
+    atlas_coords: Coords,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    screen_coords: Option<Coords>,
+    // computed layers can list a tile per orientation without a position
+    #[serde(default)]
+    x: i32,
+    #[serde(default)]
+    z: i32,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    orientation: Option<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    frames: Vec<Coords>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    frame_duration_ms: Option<u32>,
+    #[serde(default, skip_serializing_if = "is_first_page")]
+    page: usize,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    variants: Vec<Coords>,
+}
+
+fn template_lookup(templates: &[ScreenTemplate]) -> AHashMap<TemplateKey, Coords> {
+    templates.iter()
+        .map(|template| ((template.x, template.z, template.orientation), template.screen_coords))
+        .collect()
+}
+
+impl TryFrom<RawAtlasInfo> for AtlasInfo {
+    type Error = AtlasInfoError;
+
+    fn try_from(raw: RawAtlasInfo) -> Result<Self, Self::Error> {
+        let lookup = template_lookup(&raw.templates);
+
+        let mut layers = AHashMap::with_capacity(raw.layers.len());
+        for (layer_id, raw_tiles) in raw.layers {
+            let mut tiles = Vec::with_capacity(raw_tiles.tiles.len());
+            for (index, raw_tile) in raw_tiles.tiles.into_iter().enumerate() {
+                let orientation = match raw_tile.orientation {
+                    Some(name) => match Orientation::from_name(&name) {
+                        Some(orientation) => Some(orientation),
+                        None => return Err(AtlasInfoError::UnknownOrientation { layer: layer_id, index, orientation: name }),
+                    },
+                    None => None,
+                };
+                let key = (raw_tile.x, raw_tile.z, orientation);
+                let computed = || {
+                    let projection = raw.projection.unwrap_or_default();
+                    (raw_tiles.mode == Tiles::COMPUTED_MODE).then(|| projection.tile_rect(raw_tiles.r#type, key.0, key.1, key.2))
+                };
+                let screen_coords = match raw_tile.screen_coords.or_else(|| lookup.get(&key).copied()).or_else(computed) {
+                    Some(screen_coords) => screen_coords,
+                    None => return Err(AtlasInfoError::MissingScreenCoords(MissingScreenCoords {
+                        layer: layer_id,
+                        x: key.0,
+                        z: key.1,
+                        orientation: key.2,
+                    })),
+                };
+                let mut tile = Tile::new(raw_tile.atlas_coords, screen_coords, key.0, key.1, key.2);
+                tile.page = raw_tile.page;
+                tile.variants = raw_tile.variants;
+                if !raw_tile.frames.is_empty() {
+                    tile.animation = Some(Animation {
+                        frames: raw_tile.frames,
+                        frame_duration_ms: raw_tile.frame_duration_ms.unwrap_or(DEFAULT_FRAME_DURATION_MS),
+                    });
+                }
+                tiles.push(tile);
+            }
+
+            let mut tiles = Tiles::from_tiles(raw_tiles.mode, raw_tiles.r#type, tiles);
+            tiles.name = raw_tiles.name;
+            layers.insert(layer_id, tiles);
         }
+
+        Ok(Self {
+            layers,
+            templates: raw.templates,
+            projection: raw.projection,
+        })
     }
+}
 
-    pub fn strafe_left(&mut self, map: &AtlasMap) {
+impl From<AtlasInfo> for RawAtlasInfo {
+    fn from(info: AtlasInfo) -> Self {
+        let lookup = template_lookup(&info.templates);
 
-        let mut direction = self.direction - 1;
-        if direction < 0 {
-            direction = 3;
-        }
+        let layers = info.layers.into_iter().map(|(layer_id, tiles)| {
+            let raw_tiles = tiles.tiles.into_iter().map(|tile| {
+                let key = (tile.x, tile.z, tile.orientation);
+                // tiles that match their template are written without a rect of their own
+                let screen_coords = match lookup.get(&key) {
+                    Some(template) if *template == tile.screen_coords => None,
+                    _ => Some(tile.screen_coords),
+                };
+                RawTile {
+                    atlas_coords: tile.atlas_coords,
+                    screen_coords,
+                    x: key.0,
+                    z: key.1,
+                    orientation: key.2.map(|orientation| orientation.as_str().to_owned()),
+                    frame_duration_ms: tile.animation.as_ref().map(|animation| animation.frame_duration_ms),
+                    frames: tile.animation.map_or_else(Vec::new, |animation| animation.frames),
+                    page: tile.page,
+                    variants: tile.variants,
+                }
+            }).collect();
 
-        let dest_pos = self.get_dest_pos(direction);
+            (layer_id, RawTiles {
+                mode: tiles.mode,
+                name: tiles.name,
+                tiles: raw_tiles,
+                r#type: tiles.r#type,
+            })
+        }).collect();
 
-        if self.can_move(map, dest_pos) {
-            self.x = dest_pos.x;
-            self.y = dest_pos.y;
+        Self {
+            layers,
+            templates: info.templates,
+            projection: info.projection,
         }
     }
+}
 
-    pub fn strafe_right(&mut self, map: &AtlasMap) {
+impl AtlasInfo {
+    pub fn new() -> Self {
+        Self::default()
+    }
 
-        let direction = (self.direction + 1) % 4;
+    /// adds `tiles` under `name`, returning the layer it replaced if there was one
+    pub fn add_layer(&mut self, name: &str, mut tiles: Tiles) -> Option<Tiles> {
+        tiles.name = name.to_owned();
+        self.layers.insert(name.to_owned(), tiles)
+    }
 
-        let dest_pos = self.get_dest_pos(direction);
+    /// layers in key order, for anything whose output should not change run to run
+    pub fn layers_sorted(&self) -> impl Iterator<Item = (&String, &Tiles)> {
+        sorted_by_key(&self.layers)
+    }
 
-        if self.can_move(map, dest_pos) {
-            self.x = dest_pos.x;
-            self.y = dest_pos.y;
+    /// Rebuilds `templates` from the most common screen rect at each (x, z, orientation)
+    /// so that saving writes each shared rect once. Returns how many tiles the templates cover.
+    pub fn compact_with_templates(&mut self) -> usize {
+        let mut counts: AHashMap<TemplateKey, AHashMap<Coords, usize>> = AHashMap::new();
+        for (_, tiles) in self.layers_sorted() {
+            for tile in tiles.tiles() {
+                let key = (tile.x, tile.z, tile.orientation);
+                *counts.entry(key).or_default().entry(tile.screen_coords).or_default() += 1;
+            }
         }
-    }
 
-    pub fn turn_left(&mut self) {
-        self.direction = self.direction - 1;
-        if self.direction < 0 {
-            self.direction = 3;
+        let mut covered = 0;
+        let mut templates = Vec::new();
+        for ((x, z, orientation), rects) in counts {
+            let (screen_coords, count) = rects.into_iter()
+                .max_by_key(|(coords, count)| (*count, std::cmp::Reverse((coords.x, coords.y, coords.w, coords.h))))
+                .unwrap();
+            // a template used by a single tile saves nothing
+            if count > 1 {
+                covered += count;
+                templates.push(ScreenTemplate { x, z, orientation, screen_coords });
+            }
         }
+        templates.sort_by_key(|template| (template.z, template.x, template.orientation));
+
+        self.templates = templates;
+        covered
     }
 
-    pub fn turn_right(&mut self) {
-        self.direction = self.direction + 1;
-        if self.direction > 3 {
-            self.direction = 0;
-        }
+    /// Writes the atlas as pretty-printed json in the format `Collection::load` reads, layers
+    /// in key order:
+    ///
+    /// ```
+    /// use atlas_world::*;
+    ///
+    /// let original: AtlasInfo = serde_json::from_slice(&std::fs::read("mansion.json").unwrap()).unwrap();
+    /// let path = std::env::temp_dir().join("atlas_world_save_doctest.json");
+    /// original.save(&path).unwrap();
+    /// let saved: AtlasInfo = serde_json::from_slice(&std::fs::read(&path).unwrap()).unwrap();
+    /// assert_eq!(serde_json::to_value(&saved).unwrap(), serde_json::to_value(&original).unwrap());
+    /// ```
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> std::io::Result<()> {
+        let f = std::fs::File::create(path)?;
+        serde_json::to_writer_pretty(std::io::BufWriter::new(f), self)?;
+        Ok(())
     }
 }
 
-#[derive(Deserialize)]
-pub struct AtlasMap {
-    pub width: usize,
-    pub height: usize,
-    pub wall: Vec<Vec<u8>>,
-    pub floor: Vec<Vec<u8>>,
-    pub ceiling: Vec<Vec<u8>>,
-    pub object: Vec<Vec<u8>>,
+fn sorted_by_key<V>(map: &AHashMap<String, V>) -> std::vec::IntoIter<(&String, &V)> {
+    let mut entries: Vec<_> = map.iter().collect();
+    entries.sort_unstable_by(|a, b| a.0.cmp(b.0));
+    entries.into_iter()
 }
 
-pub struct AtlasWorld {
-    pub player: Player,
-    pub map: AtlasMap,
-    pub collection: AtlasCollection,
-    pub render_depth: i32,
-    pub render_width: i32,
+// AHashMap iteration order is randomized per process, so maps are written out sorted
+fn serialize_sorted<S: Serializer, V: Serialize>(map: &AHashMap<String, V>, serializer: S) -> Result<S::Ok, S::Error> {
+    serializer.collect_map(map.iter().collect::<BTreeMap<_, _>>())
+}
+
+pub struct Atlas {
+    pub atlas_info: AtlasInfo,
+    /// one texture per page, tiles pick theirs by `Tile::page`
+    #[cfg(feature = "render")]
+    pub textures: Vec<Texture2D>,
+    /// width and height of each page's image in pixels
+    pub texture_sizes: Vec<(u32, u32)>,
+    /// resolution multiple the atlas was authored at, 1 for the 320x256 viewport
+    pub scale: u32,
+    /// the json the atlas was read from, `None` when it came as bytes
+    pub data_path: Option<PathBuf>,
+    /// the image file, only known for atlases loaded with `load_files`
+    pub image_path: Option<PathBuf>,
+    /// what `AtlasInfo::validate` found when the atlas was loaded, with the atlas id filled in
+    pub findings: Vec<Finding>,
+    /// whether any layer has an `@diag` variant, worked out with `findings` so the draw loop
+    /// doesn't scan the layers every tile
+    pub diagonal_art: bool,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum AssetScaleError {
+    /// `load_scaled` was given no variant at the selected scale
+    MissingVariant { atlas_id: String, selected: u32, available: Vec<u32> },
+    /// the loaded atlases were authored at different scales
+    Mixed { scales: Vec<(String, u32)> },
 }
 
-impl AtlasWorld {
-    pub fn render(&self) {
-        for z in -self.render_depth..1 {
-            for x in (-self.render_width / 2)..0 {
-                self.draw_map_square(x, z);
+impl std::fmt::Display for AssetScaleError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            AssetScaleError::MissingVariant { atlas_id, selected, available } => {
+                write!(f, "atlas '{atlas_id}' has no {selected}x variant (available: {available:?})")
             }
-            for x in (0..=(self.render_width / 2)).rev() {
-                self.draw_map_square(x, z);
+            AssetScaleError::Mixed { scales } => {
+                write!(f, "atlases are loaded at mixed scales:")?;
+                for (atlas_id, scale) in scales {
+                    write!(f, " '{atlas_id}' at {scale}x")?;
+                }
+                Ok(())
             }
         }
     }
+}
 
+impl std::error::Error for AssetScaleError {}
 
-    pub fn draw_map_square(&self, x: i32, z: i32) {
-        let p = self.player.get_direction_vector_offsets(x, z);
-
-        if p.x >= 0 && p.y >= 0 && p.x < self.map.width as i32 && p.y < self.map.height as i32 {
-            if self.map.floor[p.y as usize][p.x as usize] != 0 {
-                let map_value = self.map.floor[p.y as usize][p.x as usize];
-                self.draw_tile("dungeon", &format!("floor-{map_value}"), x, z, None);
-            }
+/// Why an atlas couldn't be loaded, naming the atlas and file involved.
+#[derive(Debug)]
+pub enum AtlasError {
+    Io { atlas_id: String, path: PathBuf, source: std::io::Error },
+    /// `path` is `None` for json handed over as bytes
+    Json { atlas_id: String, path: Option<PathBuf>, line: usize, column: usize, source: serde_json::Error },
+    /// a file macroquad couldn't fetch, see `load_async`
+    #[cfg(feature = "render")]
+    File { atlas_id: String, path: String, source: macroquad::Error },
+    Texture { atlas_id: String, source: ImageError },
+    /// tiles that don't fit the atlas' texture, see `Atlas::check_bounds`
+    Tiles { atlas_id: String, source: AtlasInfoError },
+    /// an Aseprite export that isn't one or whose frame names don't place them, see `load_aseprite`
+    #[cfg(feature = "aseprite")]
+    Aseprite { atlas_id: String, path: PathBuf, source: AsepriteError },
+    Scale(AssetScaleError),
+    /// an atlas to reload that was never loaded, or not from files
+    NotLoaded { atlas_id: String },
+}
 
-            if self.map.ceiling[p.y as usize][p.x as usize] != 0 {
-                let map_value = self.map.ceiling[p.y as usize][p.x as usize];
-                self.draw_tile("dungeon", &format!("ceiling-{map_value}"), x, z, None);
+impl std::fmt::Display for AtlasError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            AtlasError::Io { atlas_id, path, source } => {
+                write!(f, "atlas '{atlas_id}': can't read {}: {source}", path.display())
             }
-
-            if self.map.wall[p.y as usize][p.x as usize] != 0 {
-                self.draw_side_walls(x, z);
-                self.draw_front_walls(x, z);
+            AtlasError::Json { atlas_id, path, line, column, source } => {
+                let what = path.as_ref().map_or("the json".to_owned(), |path| path.display().to_string());
+                write!(f, "atlas '{atlas_id}': {what} is not a valid atlas at line {line} column {column}: {source}")
             }
+            #[cfg(feature = "render")]
+            AtlasError::File { atlas_id, path, source } => write!(f, "atlas '{atlas_id}': can't load {path}: {source}"),
+            AtlasError::Texture { atlas_id, source } => write!(f, "atlas '{atlas_id}': can't decode the image: {source}"),
+            AtlasError::Tiles { atlas_id, source } => write!(f, "atlas '{atlas_id}': {source}"),
+            #[cfg(feature = "aseprite")]
+            AtlasError::Aseprite { atlas_id, path, source } => write!(f, "atlas '{atlas_id}': {}: {source}", path.display()),
+            AtlasError::Scale(err) => err.fmt(f),
+            AtlasError::NotLoaded { atlas_id } => write!(f, "atlas '{atlas_id}' can't be reloaded, it wasn't loaded from a file"),
+        }
+    }
+}
 
-            if self.map.object[p.y as usize][p.x as usize] != 0 {
-                self.draw_objects(x, z);
-            }
+impl std::error::Error for AtlasError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            AtlasError::Io { source, .. } => Some(source),
+            AtlasError::Json { source, .. } => Some(source),
+            AtlasError::Tiles { source, .. } => Some(source),
+            #[cfg(feature = "aseprite")]
+            AtlasError::Aseprite { source, .. } => Some(source),
+            #[cfg(feature = "render")]
+            AtlasError::File { .. } => None,
+            AtlasError::Texture { .. } | AtlasError::NotLoaded { .. } => None,
+            AtlasError::Scale(err) => Some(err),
         }
     }
+}
 
-    pub fn draw_side_walls(&self, x: i32, z: i32) {
-        let p = self.player.get_direction_vector_offsets(x, z);
+/// Why an atlas image couldn't be read. With the `render` feature it's macroquad's decoding
+/// error; without it only the header of a png is read, for the page size.
+#[cfg(feature = "render")]
+pub type ImageError = macroquad::Error;
 
-        if p.x >= 0 && p.y >= 0 && p.x < self.map.width as i32 && p.y < self.map.height as i32 {
-            let wall_value = self.map.wall[p.y as usize][p.x as usize];
-            if wall_value != 0 {
-                self.draw_tile("dungeon", &format!("wall-{wall_value}"), x, z, Some("left".to_owned()));
-                self.draw_tile("dungeon", &format!("wall-{wall_value}"), x, z, Some("right".to_owned()));
-            }
-        }
+#[cfg(not(feature = "render"))]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ImageError;
+
+#[cfg(not(feature = "render"))]
+impl std::fmt::Display for ImageError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("not a png")
     }
+}
 
-    pub fn draw_front_walls(&self, x: i32, z: i32) {
-        let p = self.player.get_direction_vector_offsets(x, z);
+#[cfg(not(feature = "render"))]
+impl std::error::Error for ImageError {}
 
-        if p.x >= 0 && p.y >= 0 && p.x < self.map.width as i32 && p.y < self.map.height as i32 {
-            let wall_value = self.map.wall[p.y as usize][p.x as usize];
-            if wall_value != 0 {
-                self.draw_tile("dungeon", &format!("wall-{wall_value}"), x, z, Some("front".to_owned()));
+impl From<AssetScaleError> for AtlasError {
+    fn from(err: AssetScaleError) -> Self {
+        AtlasError::Scale(err)
+    }
+}
+
+#[derive(Debug)]
+pub enum MapError {
+    #[cfg(feature = "tiled")]
+    Tiled { path: PathBuf, source: tiled::Error },
+    /// a feature of the map file this crate has no equivalent for
+    Unsupported { path: PathBuf, feature: String },
+    /// a tile whose id doesn't fit in a cell
+    TileOutOfRange { path: PathBuf, layer: String, x: u32, y: u32, id: u32 },
+    Io { source: std::io::Error },
+    Json { line: usize, column: usize, source: serde_json::Error },
+    /// a binary map that is cut short or malformed at byte `offset`
+    #[cfg(feature = "binary")]
+    Binary { offset: usize, reason: String },
+    /// a binary map written by a newer version of the format
+    #[cfg(feature = "binary")]
+    BinaryVersion { found: u16, supported: u16 },
+    /// row `row` of a layer isn't as wide as the map
+    RaggedRow { layer: String, row: usize, len: usize, expected: usize },
+    /// a layer with more or fewer rows than the map is high
+    RowCount { layer: String, rows: usize, expected: usize },
+}
+
+impl std::fmt::Display for MapError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            #[cfg(feature = "tiled")]
+            MapError::Tiled { path, source } => write!(f, "can't read {}: {source}", path.display()),
+            MapError::Unsupported { path, feature } => write!(f, "{}: {feature} is not supported", path.display()),
+            MapError::TileOutOfRange { path, layer, x, y, id } => {
+                write!(f, "{}: layer '{layer}' has tile {id} at ({x}, {y}), only 255 fit in a cell", path.display())
+            }
+            MapError::Io { source } => write!(f, "can't read the map: {source}"),
+            MapError::Json { line, column, source } => write!(f, "not a valid map at line {line} column {column}: {source}"),
+            #[cfg(feature = "binary")]
+            MapError::Binary { offset, reason } => write!(f, "bad binary map at byte {offset}: {reason}"),
+            #[cfg(feature = "binary")]
+            MapError::BinaryVersion { found, supported } => {
+                write!(f, "binary map is format version {found}, only up to {supported} is supported")
             }
+            MapError::RaggedRow { layer, row, len, expected } => {
+                write!(f, "layer '{layer}': row {row} has {len} columns, expected {expected}")
+            }
+            MapError::RowCount { layer, rows, expected } => write!(f, "layer '{layer}' has {rows} rows, expected {expected}"),
         }
     }
+}
 
-    pub fn draw_objects(&self, x: i32, z: i32) {
+impl std::error::Error for MapError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            #[cfg(feature = "tiled")]
+            MapError::Tiled { source, .. } => Some(source),
+            MapError::Io { source } => Some(source),
+            MapError::Json { source, .. } => Some(source),
+            _ => None,
+        }
+    }
+}
 
-        let p = self.player.get_direction_vector_offsets(x, z);
+/// size in pixels of the viewport when drawn with atlases authored at `asset_scale`
+pub fn viewport_size(asset_scale: u32) -> (u32, u32) {
+    let scale = asset_scale.max(1);
+    (VIEWPORT_WIDTH as u32 * scale, VIEWPORT_HEIGHT as u32 * scale)
+}
 
-        if p.x >= 0 && p.y >= 0 && p.x < self.map.width as i32 && p.y < self.map.height as i32 {
-            let map_value = self.map.object[p.y as usize][p.x as usize];
-            if map_value != 0 {
-                let orientation = Some(match self.player.direction {
-                    0 => "front",
-                    1 => "right",
-                    2 => "back",
-                    3 => "left",
-                    _ => unreachable!()
-                }.to_owned());
-                self.draw_tile("common_objects", &format!("object-{map_value}"), x, z, orientation);
-            }
-        }
+/// Like `fit_viewport` but only at whole multiples of the viewport's pixel size at
+/// `asset_scale`, falling back to `fit_viewport` when not even 1x fits.
+pub fn fit_viewport_integer(area: Rect, asset_scale: u32) -> Rect {
+    let (width, height) = viewport_size(asset_scale);
+    let factor = (area.w / width as f32).min(area.h / height as f32).floor();
+    if factor < 1.0 {
+        return fit_viewport(area);
     }
+    Rect::new(area.x, area.y, width as f32 * factor, height as f32 * factor)
+}
 
-    pub fn draw_tile(
-        &self,
-        atlas_id: &str,
-        layer_id: &str,
-        x: i32,
-        z: i32,
-        orientation: Option<String>,
-    ) {
-        let tile = self.collection.get_tile(atlas_id, layer_id, x, z, orientation);
+pub type AtlasCollection = AHashMap<String, Atlas>;
+pub trait Collection {
+    /// Loads an atlas from its image and json, replacing any atlas with the same id. Nothing
+    /// is inserted when either fails to load. Problems with the tiles don't fail the load but
+    /// are kept in the atlas' `findings`.
+    fn load<P: AsRef<Path>>(&mut self, atlas_id: &str, image_data: &[u8], data_path: P) -> Result<(), AtlasError>;
+    /// `load` for an atlas split over several images, one per page in page order
+    fn load_paged<P: AsRef<Path>>(&mut self, atlas_id: &str, pages: &[&[u8]], data_path: P) -> Result<(), AtlasError>;
+    /// `load` with the json already in memory, for `include_bytes!` and the web
+    fn load_from_bytes(&mut self, atlas_id: &str, image_data: &[u8], json_data: &[u8]) -> Result<(), AtlasError>;
+    /// `load` with the json fetched through macroquad, which works on wasm where `std::fs` doesn't
+    #[cfg(feature = "render")]
+    #[allow(async_fn_in_trait)]
+    async fn load_async(&mut self, atlas_id: &str, image_data: &[u8], data_path: &str) -> Result<(), AtlasError>;
+    /// `load` with the image read from a file too, so `reload_all` can re-read both
+    fn load_files<P: AsRef<Path>>(&mut self, atlas_id: &str, image_path: P, data_path: P) -> Result<(), AtlasError>;
+    /// `load` with the json Aseprite exported alongside the image, see
+    /// `AtlasInfo::from_aseprite_json`. The atlas can't be reloaded from files.
+    #[cfg(feature = "aseprite")]
+    fn load_aseprite<P: AsRef<Path>>(&mut self, atlas_id: &str, image_data: &[u8], data_path: P) -> Result<(), AtlasError>;
+    /// Replaces the texture and info of a loaded atlas, keeping its scale. The loaded atlas is
+    /// left as it was when anything fails to load.
+    fn reload<P: AsRef<Path>>(&mut self, atlas_id: &str, image_data: &[u8], data_path: P) -> Result<(), AtlasError>;
+    /// Reloads an atlas from the json file it was loaded from, re-reading the image too when it
+    /// came from one and keeping the texture otherwise. Atlases loaded from bytes only can't
+    /// be, and are left as they were like any that fail.
+    fn reload_from_files(&mut self, atlas_id: &str) -> Result<(), AtlasError>;
+    /// `reload_from_files` for every atlas loaded from a json file, returning the failures
+    fn reload_all(&mut self) -> Vec<AtlasError>;
+    /// Loads the variant of an atlas authored at `selected` scale out of several (scale, image,
+    /// atlas json) variants.
+    fn load_scaled<P: AsRef<Path>>(&mut self, atlas_id: &str, variants: Vec<(u32, &[u8], P)>, selected: u32) -> Result<(), AtlasError>;
+    /// the scale every loaded atlas shares, 1 when nothing is loaded
+    fn asset_scale(&self) -> Result<u32, AssetScaleError>;
+    fn get_tile(&self, atlas_id: &str, layer_id: &str, x: i32, z: i32, orientation: Option<Orientation>) -> Option<&Tile>;
+    fn atlases_sorted(&self) -> impl Iterator<Item = (&String, &Atlas)>;
+}
 
-        let tex = if let Some(atlas_info) = self.collection.get(atlas_id) {
-            &atlas_info.texture
-        } else {
-            return;
-        };
+fn read_file(atlas_id: &str, path: &Path) -> Result<Vec<u8>, AtlasError> {
+    std::fs::read(path).map_err(|source| AtlasError::Io {
+        atlas_id: atlas_id.to_owned(),
+        path: path.to_owned(),
+        source,
+    })
+}
 
-        if let Some(tile) = tile {
-            draw_texture_ex(
-                tex,
-                tile.screen_coords.x as f32,
-                tile.screen_coords.y as f32,
-                WHITE,
-                DrawTextureParams {
-                    dest_size: Some(vec2(tile.screen_coords.w as f32, tile.screen_coords.h as f32)),
-                    source: Some(Rect::new(
-                            tile.atlas_coords.x as f32,
-                            tile.atlas_coords.y as f32,
-                            tile.atlas_coords.w as f32,
-                            tile.atlas_coords.h as f32,
-                    )),
-                    ..Default::default()
-                },
-            );
+fn parse_atlas_info(atlas_id: &str, json_data: &[u8], path: Option<&Path>) -> Result<AtlasInfo, AtlasError> {
+    serde_json::from_slice(json_data).map_err(|source| AtlasError::Json {
+        atlas_id: atlas_id.to_owned(),
+        path: path.map(Path::to_owned),
+        line: source.line(),
+        column: source.column(),
+        source,
+    })
+}
+
+#[cfg(feature = "render")]
+fn atlas_texture(image: &Image) -> Texture2D {
+    let texture = Texture2D::from_image(image);
+    texture.set_filter(FilterMode::Nearest);
+    texture
+}
+
+fn parse_atlas(atlas_id: &str, image_data: &[u8], json_data: &[u8], data_path: Option<&Path>) -> Result<Atlas, AtlasError> {
+    parse_paged_atlas(atlas_id, &[image_data], json_data, data_path)
+}
+
+fn parse_paged_atlas(atlas_id: &str, pages: &[&[u8]], json_data: &[u8], data_path: Option<&Path>) -> Result<Atlas, AtlasError> {
+    let atlas_info = parse_atlas_info(atlas_id, json_data, data_path)?;
+    atlas_from_pages(atlas_id, pages, atlas_info, data_path)
+}
+
+// the width and height in a png's header, which is all an atlas without textures needs
+#[cfg(not(feature = "render"))]
+fn png_size(image_data: &[u8]) -> Result<(u32, u32), ImageError> {
+    const SIGNATURE: &[u8] = b"\x89PNG\r\n\x1a\n";
+    if !image_data.starts_with(SIGNATURE) || image_data.get(12..16) != Some(b"IHDR") {
+        return Err(ImageError);
+    }
+    let size = |at: usize| image_data.get(at..at + 4).map(|bytes| u32::from_be_bytes(bytes.try_into().unwrap()));
+    size(16).zip(size(20)).ok_or(ImageError)
+}
+
+fn atlas_from_pages(atlas_id: &str, pages: &[&[u8]], atlas_info: AtlasInfo, data_path: Option<&Path>) -> Result<Atlas, AtlasError> {
+    let texture_error = |source| AtlasError::Texture { atlas_id: atlas_id.to_owned(), source };
+    #[cfg(feature = "render")]
+    let images = pages.iter()
+        .map(|image_data| Image::from_file_with_format(image_data, None))
+        .collect::<Result<Vec<Image>, _>>()
+        .map_err(texture_error)?;
+    #[cfg(feature = "render")]
+    let texture_sizes = images.iter().map(|image| (image.width as u32, image.height as u32)).collect();
+    #[cfg(not(feature = "render"))]
+    let texture_sizes = pages.iter().map(|image_data| png_size(image_data)).collect::<Result<_, _>>().map_err(texture_error)?;
+    let mut atlas = Atlas {
+        atlas_info,
+        #[cfg(feature = "render")]
+        textures: images.iter().map(atlas_texture).collect(),
+        texture_sizes,
+        scale: 1,
+        data_path: data_path.map(Path::to_owned),
+        image_path: None,
+        findings: Vec::new(),
+        diagonal_art: false,
+    };
+    atlas.revalidate(atlas_id);
+    Ok(atlas)
+}
+
+impl Atlas {
+    /// A one-tile atlas covering all of `image` in layer `layer_id`, for art made at runtime
+    /// like a generated sky strip.
+    pub fn from_image(image: &Image, layer_id: &str) -> Self {
+        let coords = Coords { x: 0, y: 0, w: image.width as i32, h: image.height as i32 };
+        let tiles = Tiles::from_tiles(0, 0, vec![Tile::new(coords, coords, 0, 0, None)]);
+        let mut atlas_info = AtlasInfo::new();
+        atlas_info.add_layer(layer_id, tiles);
+        Self {
+            atlas_info,
+            #[cfg(feature = "render")]
+            textures: vec![atlas_texture(image)],
+            texture_sizes: vec![(image.width as u32, image.height as u32)],
+            scale: 1,
+            data_path: None,
+            image_path: None,
+            findings: Vec::new(),
+            diagonal_art: layer_id.ends_with("@diag"),
+        }
+    }
+
+    /// The first tile whose atlas rect, or one of its frames or variants, reaches past its
+    /// page. Loading keeps these as `findings`; this is for an app that would rather refuse them.
+    pub fn check_bounds(&self, atlas_id: &str) -> Result<(), AtlasError> {
+        self.atlas_info.check_bounds(&self.texture_sizes)
+            .map_err(|source| AtlasError::Tiles { atlas_id: atlas_id.to_owned(), source })
+    }
+
+    // reruns `AtlasInfo::validate` at the atlas' scale against its texture
+    fn revalidate(&mut self, atlas_id: &str) {
+        self.findings = self.atlas_info.validate_scaled(&self.texture_sizes, self.scale);
+        for finding in &mut self.findings {
+            finding.atlas_id = Some(atlas_id.to_owned());
         }
+        self.diagonal_art = self.atlas_info.layers.keys().any(|layer_id| layer_id.ends_with("@diag"));
+    }
+}
+
+impl Collection for AtlasCollection {
+    fn load<P: AsRef<Path>>(&mut self, atlas_id: &str, image_data: &[u8], data_path: P) -> Result<(), AtlasError> {
+        let path = data_path.as_ref();
+        let json_data = read_file(atlas_id, path)?;
+        let atlas = parse_atlas(atlas_id, image_data, &json_data, Some(path))?;
+        self.insert(atlas_id.to_owned(), atlas);
+        Ok(())
+    }
+
+    fn load_paged<P: AsRef<Path>>(&mut self, atlas_id: &str, pages: &[&[u8]], data_path: P) -> Result<(), AtlasError> {
+        let path = data_path.as_ref();
+        let json_data = read_file(atlas_id, path)?;
+        let atlas = parse_paged_atlas(atlas_id, pages, &json_data, Some(path))?;
+        self.insert(atlas_id.to_owned(), atlas);
+        Ok(())
+    }
+
+    fn load_from_bytes(&mut self, atlas_id: &str, image_data: &[u8], json_data: &[u8]) -> Result<(), AtlasError> {
+        let atlas = parse_atlas(atlas_id, image_data, json_data, None)?;
+        self.insert(atlas_id.to_owned(), atlas);
+        Ok(())
+    }
+
+    #[cfg(feature = "render")]
+    async fn load_async(&mut self, atlas_id: &str, image_data: &[u8], data_path: &str) -> Result<(), AtlasError> {
+        let json_data = macroquad::file::load_file(data_path).await.map_err(|source| AtlasError::File {
+            atlas_id: atlas_id.to_owned(),
+            path: data_path.to_owned(),
+            source,
+        })?;
+        let atlas = parse_atlas(atlas_id, image_data, &json_data, Some(Path::new(data_path)))?;
+        self.insert(atlas_id.to_owned(), atlas);
+        Ok(())
+    }
+
+    fn load_files<P: AsRef<Path>>(&mut self, atlas_id: &str, image_path: P, data_path: P) -> Result<(), AtlasError> {
+        let image_data = read_file(atlas_id, image_path.as_ref())?;
+        self.load(atlas_id, &image_data, data_path)?;
+        if let Some(atlas) = self.get_mut(atlas_id) {
+            atlas.image_path = Some(image_path.as_ref().to_owned());
+        }
+        Ok(())
+    }
+
+    #[cfg(feature = "aseprite")]
+    fn load_aseprite<P: AsRef<Path>>(&mut self, atlas_id: &str, image_data: &[u8], data_path: P) -> Result<(), AtlasError> {
+        let path = data_path.as_ref();
+        let json_data = read_file(atlas_id, path)?;
+        let atlas_info = AtlasInfo::from_aseprite_json(json_data.as_slice())
+            .map_err(|source| AtlasError::Aseprite { atlas_id: atlas_id.to_owned(), path: path.to_owned(), source })?;
+        // no data path, `reload_from_files` would read the export as an atlas json
+        let atlas = atlas_from_pages(atlas_id, &[image_data], atlas_info, None)?;
+        self.insert(atlas_id.to_owned(), atlas);
+        Ok(())
+    }
+
+    fn reload<P: AsRef<Path>>(&mut self, atlas_id: &str, image_data: &[u8], data_path: P) -> Result<(), AtlasError> {
+        let Some(old) = self.get(atlas_id) else {
+            return Err(AtlasError::NotLoaded { atlas_id: atlas_id.to_owned() });
+        };
+        let path = data_path.as_ref();
+        let json_data = read_file(atlas_id, path)?;
+        let mut atlas = Atlas {
+            scale: old.scale,
+            image_path: old.image_path.clone(),
+            ..parse_atlas(atlas_id, image_data, &json_data, Some(path))?
+        };
+        atlas.revalidate(atlas_id);
+        self.insert(atlas_id.to_owned(), atlas);
+        Ok(())
+    }
+
+    fn reload_from_files(&mut self, atlas_id: &str) -> Result<(), AtlasError> {
+        let Some(atlas) = self.get(atlas_id) else {
+            return Err(AtlasError::NotLoaded { atlas_id: atlas_id.to_owned() });
+        };
+        let (Some(data_path), image_path) = (atlas.data_path.clone(), atlas.image_path.clone()) else {
+            return Err(AtlasError::NotLoaded { atlas_id: atlas_id.to_owned() });
+        };
+        match image_path {
+            Some(image_path) => {
+                let image_data = read_file(atlas_id, &image_path)?;
+                self.reload(atlas_id, &image_data, &data_path)
+            }
+            None => {
+                let json_data = read_file(atlas_id, &data_path)?;
+                let atlas_info = parse_atlas_info(atlas_id, &json_data, Some(&data_path))?;
+                if let Some(atlas) = self.get_mut(atlas_id) {
+                    atlas.atlas_info = atlas_info;
+                    atlas.revalidate(atlas_id);
+                }
+                Ok(())
+            }
+        }
+    }
+
+    fn reload_all(&mut self) -> Vec<AtlasError> {
+        let atlas_ids: Vec<String> = self.atlases_sorted()
+            .filter(|(_, atlas)| atlas.data_path.is_some())
+            .map(|(atlas_id, _)| atlas_id.clone())
+            .collect();
+        atlas_ids.iter().filter_map(|atlas_id| self.reload_from_files(atlas_id).err()).collect()
+    }
+
+    fn load_scaled<P: AsRef<Path>>(&mut self, atlas_id: &str, variants: Vec<(u32, &[u8], P)>, selected: u32) -> Result<(), AtlasError> {
+        let available: Vec<u32> = variants.iter().map(|(scale, _, _)| *scale).collect();
+        let Some((scale, image_data, data_path)) = variants.into_iter().find(|(scale, _, _)| *scale == selected) else {
+            return Err(AssetScaleError::MissingVariant { atlas_id: atlas_id.to_owned(), selected, available }.into());
+        };
+
+        self.load(atlas_id, image_data, data_path)?;
+        if let Some(atlas) = self.get_mut(atlas_id) {
+            atlas.scale = scale;
+            atlas.revalidate(atlas_id);
+        }
+        Ok(())
+    }
+
+    fn asset_scale(&self) -> Result<u32, AssetScaleError> {
+        let scales: Vec<(String, u32)> = self.atlases_sorted().map(|(atlas_id, atlas)| (atlas_id.clone(), atlas.scale)).collect();
+        match scales.first() {
+            None => Ok(1),
+            Some((_, first)) if scales.iter().all(|(_, scale)| scale == first) => Ok(*first),
+            Some(_) => Err(AssetScaleError::Mixed { scales }),
+        }
+    }
+
+    fn get_tile(&self, atlas_id: &str, layer_id: &str, x: i32, z: i32, orientation: Option<Orientation>) -> Option<&Tile> {
+        let layer = self.get(atlas_id)?.atlas_info.layers.get(layer_id)?;
+        layer.find(x, z, orientation)
+    }
+
+    fn atlases_sorted(&self) -> impl Iterator<Item = (&String, &Atlas)> {
+        sorted_by_key(self)
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Movement {
+    Forward,
+    Backward,
+    StrafeLeft,
+    StrafeRight,
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Turn {
+    Left,
+    Right,
+    Around,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum MoveResult {
+    Moved(IVec2),
+    /// the cell that stopped the move
+    Blocked(IVec2),
+}
+
+impl MoveResult {
+    pub fn is_moved(&self) -> bool {
+        matches!(self, MoveResult::Moved(_))
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, Default, PartialEq, Eq, Debug)]
+pub enum CornerOrder {
+    /// turn toward the side, then step forward in the new facing
+    #[default]
+    TurnThenStep,
+    /// step forward, then turn toward the side
+    StepThenTurn,
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Debug)]
+pub struct CornerConfig {
+    pub order: CornerOrder,
+    /// keep the turn of a corner move even when the step is blocked
+    pub turn_when_blocked: bool,
+}
+
+impl Default for CornerConfig {
+    fn default() -> Self {
+        Self {
+            order: CornerOrder::TurnThenStep,
+            turn_when_blocked: true,
+        }
+    }
+}
+
+fn default_animation_duration() -> f32 {
+    0.2
+}
+
+/// Serializes without any animation in progress.
+#[derive(Serialize, Deserialize, Clone, PartialEq, Debug)]
+pub struct Player {
+    pub x: i32,
+    pub y: i32,
+    pub direction: Direction,
+    #[serde(default)]
+    pub corner_config: CornerConfig,
+    /// seconds a `start_move` or `start_turn` takes to play out
+    #[serde(default = "default_animation_duration")]
+    pub animation_duration: f32,
+    #[serde(default)]
+    pub stats: Stats,
+    #[serde(skip)]
+    animation: Option<PlayerAnimation>,
+    #[serde(skip)]
+    animation_progress: f32,
+    #[serde(skip)]
+    queued_input: Option<PlayerInput>,
+}
+
+impl Player {
+    pub fn new(x: i32, y: i32, direction: Direction) -> Self {
+        Self {
+            x,
+            y,
+            direction,
+            corner_config: CornerConfig::default(),
+            animation_duration: default_animation_duration(),
+            stats: Stats::default(),
+            animation: None,
+            animation_progress: 0.0,
+            queued_input: None,
+        }
+    }
+
+    /// the view from the player's cell and facing
+    pub fn view_point(&self) -> ViewPoint {
+        ViewPoint::new(ivec2(self.x, self.y), self.direction)
+    }
+
+    /// whether the player can step from their cell into `pos`, see `MapSource::can_step`
+    pub fn can_move<M: MapSource + ?Sized>(&self, map: &M, pos: IVec2) -> bool {
+        map.can_step(ivec2(self.x, self.y), pos)
+    }
+
+    pub fn invert_direction(&self) -> Direction {
+        self.direction.opposite()
+    }
+
+    pub fn get_dest_pos(&self, direction: Direction) -> IVec2 {
+        coords::offset(ivec2(self.x, self.y), direction.to_vec(), 1)
+    }
+
+    /// the cell one step ahead in the current direction
+    pub fn facing_cell(&self) -> IVec2 {
+        self.get_dest_pos(self.direction)
+    }
+
+    /// the absolute direction a movement relative to the player's facing goes in
+    pub fn movement_direction(&self, movement: Movement) -> Direction {
+        match movement {
+            Movement::Forward => self.direction,
+            Movement::Backward => self.invert_direction(),
+            Movement::StrafeLeft => self.direction.quarter_left(),
+            Movement::StrafeRight => self.direction.quarter_right(),
+        }
+    }
+
+    pub fn move_forward<M: MapSource + ?Sized>(&mut self, map: &M) {
+
+        let dest_pos = self.get_dest_pos(self.direction);
+
+        if self.can_move(map, dest_pos) {
+            self.x = dest_pos.x;
+            self.y = dest_pos.y;
+        }
+    }
+
+    pub fn move_backward<M: MapSource + ?Sized>(&mut self, map: &M) {
+
+        let dest_pos = self.get_dest_pos(self.invert_direction());
+
+        if self.can_move(map, dest_pos) {
+            self.x = dest_pos.x;
+            self.y = dest_pos.y;
+        }
+    }
+
+    pub fn strafe_left<M: MapSource + ?Sized>(&mut self, map: &M) {
+
+        let direction = self.direction.quarter_left();
+
+        let dest_pos = self.get_dest_pos(direction);
+
+        if self.can_move(map, dest_pos) {
+            self.x = dest_pos.x;
+            self.y = dest_pos.y;
+        }
+    }
+
+    pub fn strafe_right<M: MapSource + ?Sized>(&mut self, map: &M) {
+
+        let direction = self.direction.quarter_right();
+
+        let dest_pos = self.get_dest_pos(direction);
+
+        if self.can_move(map, dest_pos) {
+            self.x = dest_pos.x;
+            self.y = dest_pos.y;
+        }
+    }
+
+    pub fn turn_left(&mut self) {
+        self.direction = self.direction.turn_left();
+    }
+
+    pub fn turn_right(&mut self) {
+        self.direction = self.direction.turn_right();
+    }
+
+    pub fn turn(&mut self, turn: Turn) {
+        match turn {
+            Turn::Left => self.turn_left(),
+            Turn::Right => self.turn_right(),
+            Turn::Around => self.quick_turn(),
+        }
+    }
+
+    /// about-face in one action
+    pub fn quick_turn(&mut self) {
+        self.direction = self.invert_direction();
+    }
+
+    /// steps one cell in an absolute direction if nothing is in the way
+    pub fn step<M: MapSource + ?Sized>(&mut self, map: &M, direction: Direction) -> MoveResult {
+        let dest_pos = self.get_dest_pos(direction);
+
+        // a diagonal step can't squeeze between two walls touching at the corner
+        let v = direction.to_vec();
+        let corner_blocked = direction.is_diagonal()
+            && !self.can_move(map, ivec2(self.x.saturating_add(v.x), self.y))
+            && !self.can_move(map, ivec2(self.x, self.y.saturating_add(v.y)));
+
+        if !corner_blocked && self.can_move(map, dest_pos) {
+            self.x = dest_pos.x;
+            self.y = dest_pos.y;
+            MoveResult::Moved(dest_pos)
+        } else {
+            MoveResult::Blocked(dest_pos)
+        }
+    }
+
+    pub fn corner_left<M: MapSource + ?Sized>(&mut self, map: &M) -> MoveResult {
+        self.corner(map, Turn::Left)
+    }
+
+    pub fn corner_right<M: MapSource + ?Sized>(&mut self, map: &M) -> MoveResult {
+        self.corner(map, Turn::Right)
+    }
+
+    // turn + step as one action, ordered and rolled back per `corner_config`
+    fn corner<M: MapSource + ?Sized>(&mut self, map: &M, turn: Turn) -> MoveResult {
+        let start_direction = self.direction;
+
+        match self.corner_config.order {
+            CornerOrder::TurnThenStep => {
+                self.turn(turn);
+                let result = self.step(map, self.direction);
+                if !result.is_moved() && !self.corner_config.turn_when_blocked {
+                    self.direction = start_direction;
+                }
+                result
+            }
+            CornerOrder::StepThenTurn => {
+                let result = self.step(map, self.direction);
+                if result.is_moved() || self.corner_config.turn_when_blocked {
+                    self.turn(turn);
+                }
+                result
+            }
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct AtlasMap {
+    pub width: usize,
+    pub height: usize,
+    pub wall: Grid,
+    pub floor: Grid,
+    pub ceiling: Grid,
+    pub object: Grid,
+    /// written by `bake_autotiles`, empty until then
+    #[serde(default)]
+    pub autotile: Grid,
+    /// brightness per cell written by `AtlasWorld::compute_lighting`, empty for an unlit map
+    #[serde(default, skip_serializing_if = "Grid::is_empty")]
+    pub light: Grid,
+    /// overlays drawn on the faces of the wall in the same cell, empty for a map without any
+    #[serde(default, skip_serializing_if = "Grid::is_empty")]
+    pub decal: Grid,
+    /// height of each cell's floor as `i8` values: 0 level, below 0 a pit, above 0 raised.
+    /// Empty for a flat map
+    #[serde(default, skip_serializing_if = "Grid::is_empty", with = "grid::signed")]
+    pub elevation: Grid,
+    /// any other layers, read from the map json under their own names
+    #[serde(flatten, serialize_with = "serialize_sorted")]
+    pub layers: AHashMap<String, Grid>,
+}
+
+impl AtlasMap {
+    /// a `width` x `height` map with every layer zeroed
+    pub fn empty(width: usize, height: usize) -> Self {
+        let layer = Grid::new(width, height);
+        Self {
+            width,
+            height,
+            wall: layer.clone(),
+            floor: layer.clone(),
+            ceiling: layer.clone(),
+            object: layer,
+            autotile: Grid::default(),
+            light: Grid::default(),
+            decal: Grid::default(),
+            elevation: Grid::default(),
+            layers: AHashMap::new(),
+        }
+    }
+
+    /// Every layer having `height` rows of `width` cells, which drawing indexes by. Layers that
+    /// are empty until baked, and absent decal and elevation layers, pass.
+    ///
+    /// # Errors
+    ///
+    /// The first layer, in `layer_names` order, with too few or many rows or columns.
+    pub fn check_shape(&self) -> Result<(), MapError> {
+        for name in self.layer_names() {
+            let Some(layer) = self.layer(name) else { continue };
+            if layer.is_empty() && matches!(name, "autotile" | "light" | "decal" | "elevation") {
+                continue;
+            }
+            if layer.width() != self.width && layer.height() > 0 {
+                return Err(MapError::RaggedRow { layer: name.to_owned(), row: 0, len: layer.width(), expected: self.width });
+            }
+            if layer.height() != self.height {
+                return Err(MapError::RowCount { layer: name.to_owned(), rows: layer.height(), expected: self.height });
+            }
+        }
+        Ok(())
+    }
+
+    /// Reads a json map or, with the `binary` feature, a binary one, telling them apart by
+    /// `binary::BINARY_MAGIC`.
+    ///
+    /// # Errors
+    ///
+    /// When reading fails, the contents are neither a valid binary nor a valid json map, or a
+    /// layer isn't the map's size.
+    pub fn from_reader_auto<R: std::io::Read>(mut reader: R) -> Result<AtlasMap, MapError> {
+        let mut bytes = Vec::new();
+        reader.read_to_end(&mut bytes).map_err(|source| MapError::Io { source })?;
+        #[cfg(feature = "binary")]
+        if bytes.starts_with(binary::BINARY_MAGIC) {
+            return Self::from_binary(&bytes);
+        }
+        let map: AtlasMap = serde_json::from_slice(&bytes)
+            .map_err(|source| json_shape_error(&bytes).unwrap_or(MapError::Json { line: source.line(), column: source.column(), source }))?;
+        map.check_shape()?;
+        Ok(map)
+    }
+
+    /// the well-known layers first, then any others sorted by name
+    pub fn layer_names(&self) -> impl Iterator<Item = &str> {
+        let mut names: Vec<&str> = self.layers.keys().map(String::as_str).collect();
+        names.sort_unstable();
+        ["wall", "floor", "ceiling", "object", "autotile", "light", "decal", "elevation"].into_iter().chain(names)
+    }
+
+    pub fn layer(&self, layer: &str) -> Option<&Grid> {
+        match layer {
+            "wall" => Some(&self.wall),
+            "floor" => Some(&self.floor),
+            "ceiling" => Some(&self.ceiling),
+            "object" => Some(&self.object),
+            "autotile" => Some(&self.autotile),
+            "light" => Some(&self.light),
+            "decal" => Some(&self.decal),
+            "elevation" => Some(&self.elevation),
+            _ => self.layers.get(layer),
+        }
+    }
+
+    pub fn layer_mut(&mut self, layer: &str) -> Option<&mut Grid> {
+        match layer {
+            "wall" => Some(&mut self.wall),
+            "floor" => Some(&mut self.floor),
+            "ceiling" => Some(&mut self.ceiling),
+            "object" => Some(&mut self.object),
+            "autotile" => Some(&mut self.autotile),
+            "light" => Some(&mut self.light),
+            "decal" => Some(&mut self.decal),
+            "elevation" => Some(&mut self.elevation),
+            _ => self.layers.get_mut(layer),
+        }
+    }
+
+    /// Adds a zeroed layer the size of the map. Returns false, and leaves the map alone, when a
+    /// layer by that name already exists.
+    pub fn add_layer(&mut self, layer: &str) -> bool {
+        if self.layer(layer).is_some() {
+            return false;
+        }
+        self.layers.insert(layer.to_owned(), Grid::new(self.width, self.height));
+        true
+    }
+
+    pub fn get_floor(&self, pos: IVec2) -> Option<u8> {
+        self.get("floor", pos)
+    }
+
+    pub fn get_ceiling(&self, pos: IVec2) -> Option<u8> {
+        self.get("ceiling", pos)
+    }
+
+    pub fn get_object(&self, pos: IVec2) -> Option<u8> {
+        self.get("object", pos)
+    }
+
+    // every write to the map goes through `MapSource::set`, these included
+    fn set_checked(&mut self, layer: &str, pos: IVec2, value: u8) -> Result<(), OutOfBounds> {
+        if self.set(layer, pos, value) {
+            Ok(())
+        } else {
+            Err(OutOfBounds { pos })
+        }
+    }
+
+    pub fn set_wall(&mut self, pos: IVec2, value: u8) -> Result<(), OutOfBounds> {
+        self.set_checked("wall", pos, value)
+    }
+
+    pub fn set_floor(&mut self, pos: IVec2, value: u8) -> Result<(), OutOfBounds> {
+        self.set_checked("floor", pos, value)
+    }
+
+    pub fn set_ceiling(&mut self, pos: IVec2, value: u8) -> Result<(), OutOfBounds> {
+        self.set_checked("ceiling", pos, value)
+    }
+
+    pub fn set_object(&mut self, pos: IVec2, value: u8) -> Result<(), OutOfBounds> {
+        self.set_checked("object", pos, value)
+    }
+
+    /// Sets every cell of `rect` on `layer` to `value`, leaving out the part outside the map.
+    /// Returns how many cells were written, 0 for an unknown layer.
+    pub fn fill_rect(&mut self, layer: &str, rect: IRect, value: u8) -> usize {
+        let x = rect.x.max(0)..rect.x.saturating_add(rect.w).min(self.width as i32);
+        let y = rect.y.max(0)..rect.y.saturating_add(rect.h).min(self.height as i32);
+        y.flat_map(|y| x.clone().map(move |x| ivec2(x, y)))
+            .filter(|pos| self.set(layer, *pos, value))
+            .count()
+    }
+}
+
+/// a cell write outside the map
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct OutOfBounds {
+    pub pos: IVec2,
+}
+
+impl std::fmt::Display for OutOfBounds {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "cell ({}, {}) is outside the map", self.pos.x, self.pos.y)
+    }
+}
+
+impl std::error::Error for OutOfBounds {}
+
+/// Cell access shared by every kind of map the world can walk around in. Layers are named
+/// like the `AtlasMap` fields: "wall", "floor", "ceiling" and "object", plus whatever other
+/// layers a map has.
+pub trait MapSource {
+    fn width(&self) -> usize;
+    fn height(&self) -> usize;
+
+    /// `None` outside the map, for an unknown layer, or where the cell isn't available
+    fn get(&self, layer: &str, pos: IVec2) -> Option<u8>;
+
+    /// returns false when the cell couldn't be written
+    fn set(&mut self, layer: &str, pos: IVec2, value: u8) -> bool;
+
+    fn in_bounds(&self, pos: IVec2) -> bool {
+        pos.x >= 0 && pos.y >= 0 && (pos.x as usize) < self.width() && (pos.y as usize) < self.height()
+    }
+
+    fn get_wall(&self, pos: IVec2) -> Option<u8> {
+        self.get("wall", pos)
+    }
+
+    /// Whether a door stands closed at `pos`, keeping out the player and sight. Any door counts
+    /// as closed, see `DoorView` for the world's door state.
+    fn is_door_closed(&self, pos: IVec2) -> bool {
+        self.get("doors", pos).unwrap_or(0) != 0
+    }
+
+    /// Open floor the player may stand on, cells that aren't available count as blocked.
+    fn is_passable(&self, pos: IVec2) -> bool {
+        self.get_wall(pos) == Some(0) && !self.is_door_closed(pos)
+    }
+
+    /// the floor height of `pos` from the "elevation" layer, 0 where there is none
+    fn elevation(&self, pos: IVec2) -> i8 {
+        self.get("elevation", pos).map_or(0, |value| value as i8)
+    }
+
+    /// Whether a step from `from` into `to` can be taken: `to` is passable and at the same
+    /// elevation. `MovementView` allows the climbs and falls its rules do.
+    fn can_step(&self, from: IVec2, to: IVec2) -> bool {
+        self.is_passable(to) && self.elevation(from) == self.elevation(to)
+    }
+
+    /// Called with the player's cell whenever it changes, streaming maps load around it.
+    /// Returns whether that changed what the map holds.
+    fn focus(&mut self, _pos: IVec2) -> bool {
+        false
+    }
+}
+
+impl MapSource for AtlasMap {
+    fn width(&self) -> usize {
+        self.width
+    }
+
+    fn height(&self) -> usize {
+        self.height
+    }
+
+    fn get(&self, layer: &str, pos: IVec2) -> Option<u8> {
+        if !self.in_bounds(pos) {
+            return None;
+        }
+        self.layer(layer)?.get(pos.x as usize, pos.y as usize)
+    }
+
+    fn set(&mut self, layer: &str, pos: IVec2, value: u8) -> bool {
+        if !self.in_bounds(pos) {
+            return false;
+        }
+        self.layer_mut(layer).is_some_and(|grid| grid.set(pos.x as usize, pos.y as usize, value))
+    }
+}
+
+// A ragged layer fails to parse without saying which layer, so a failed parse is looked over
+// again as plain json for a layer whose rows don't match the map's size.
+fn json_shape_error(bytes: &[u8]) -> Option<MapError> {
+    let serde_json::Value::Object(fields) = serde_json::from_slice(bytes).ok()? else {
+        return None;
+    };
+    let size = |name: &str| fields.get(name).and_then(serde_json::Value::as_u64).map(|size| size as usize);
+    let (width, height) = (size("width")?, size("height")?);
+    let mut names: Vec<&String> = fields.keys().collect();
+    names.sort_unstable();
+    for name in names {
+        let Some(rows) = fields[name].as_array().filter(|rows| rows.iter().all(serde_json::Value::is_array)) else {
+            continue;
+        };
+        let ragged = rows.iter().enumerate().find_map(|(row, cells)| {
+            let len = cells.as_array().map_or(0, Vec::len);
+            (len != width).then_some((row, len))
+        });
+        if let Some((row, len)) = ragged {
+            return Some(MapError::RaggedRow { layer: name.clone(), row, len, expected: width });
+        }
+        if rows.len() != height && !rows.is_empty() {
+            return Some(MapError::RowCount { layer: name.clone(), rows: rows.len(), expected: height });
+        }
+    }
+    None
+}
+
+/// Where in its cell an object sprite stands. `Center` uses the `object-{n}` layer as is.
+/// The edge anchors first look for an `object-{n}@near` / `object-{n}@far` layer authored
+/// for that position and otherwise reuse the plain layer's entry one row closer (near) or
+/// further away (far).
+#[derive(Serialize, Deserialize, Clone, Copy, Default, PartialEq, Eq, Debug)]
+#[serde(rename_all = "snake_case")]
+pub enum CellAnchor {
+    #[default]
+    Center,
+    NearEdge,
+    FarEdge,
+}
+
+#[derive(Serialize, Deserialize, Clone, Default)]
+pub struct ObjectDef {
+    #[serde(default)]
+    pub depth_anchor: CellAnchor,
+    /// localization key of the object's display name
+    #[serde(default)]
+    pub name: Option<String>,
+    /// pathfinding treats the cell as blocked
+    #[serde(default)]
+    pub blocks_paths: bool,
+    /// [x, y] offsets from the cell the object is stored in of the other map cells it covers,
+    /// for objects bigger than a cell. They block like the stored cell does. The object is
+    /// drawn once, in the stored cell's slot, from an `object-{n}@front` (`@back`, `@left`,
+    /// `@right`) layer for the side it's seen from when the atlas has one.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub footprint: Vec<[i32; 2]>,
+}
+
+impl ObjectDef {
+    /// every map cell the object covers when stored in `anchor`, `anchor` first. Offsets that
+    /// reach past the i32 limits are left out.
+    pub fn cells(&self, anchor: IVec2) -> impl Iterator<Item = IVec2> + '_ {
+        std::iter::once(anchor).chain(self.footprint.iter().filter_map(move |[x, y]| {
+            Some(ivec2(anchor.x.checked_add(*x)?, anchor.y.checked_add(*y)?))
+        }))
+    }
+}
+
+pub type ObjectDefs = AHashMap<u8, ObjectDef>;
+
+// The object in `pos` and the cell it's stored in: its own, or the one a footprint in
+// `footprints` (object value, [x, y] offsets) reaches it from.
+pub(crate) fn covering_object<'a, M: MapSource + ?Sized>(
+    map: &M,
+    pos: IVec2,
+    footprints: impl IntoIterator<Item = (u8, &'a [[i32; 2]])>,
+) -> Option<(u8, IVec2)> {
+    if let Some(value) = map.get("object", pos).filter(|value| *value != 0) {
+        return Some((value, pos));
+    }
+    footprints.into_iter().find_map(|(value, offsets)| {
+        offsets.iter()
+            .filter_map(|[x, y]| Some(ivec2(pos.x.checked_sub(*x)?, pos.y.checked_sub(*y)?)))
+            .find(|anchor| map.get("object", *anchor) == Some(value))
+            .map(|anchor| (value, anchor))
+    })
+}
+
+pub enum RegionFill {
+    /// solid colors looked up by wall value for wall cells and floor value otherwise
+    Colors {
+        wall: AHashMap<u8, Color>,
+        floor: AHashMap<u8, Color>,
+    },
+    /// the `floor-{n}` tile of the given atlas scaled into each cell, walls as a solid color
+    FloorTiles {
+        atlas_id: String,
+        wall_color: Color,
+    },
+}
+
+pub struct RegionStyle {
+    pub fill: RegionFill,
+    pub border: Option<(f32, Color)>,
+    pub player_color: Option<Color>,
+}
+
+impl Default for RegionStyle {
+    fn default() -> Self {
+        Self {
+            fill: RegionFill::Colors {
+                wall: AHashMap::new(),
+                floor: AHashMap::new(),
+            },
+            border: None,
+            player_color: Some(GREEN),
+        }
+    }
+}
+
+// how an untextured quad is drawn into its rect
+#[derive(Clone, Copy)]
+enum FillShape {
+    Rect,
+    Circle,
+}
+
+// the four edges of `cell` drawn as its border, half of `thickness` wide and inside the
+// cell like `draw_rectangle_lines` draws them
+fn border_rects(cell: Rect, thickness: f32) -> [Rect; 4] {
+    let t = (thickness / 2.0).min(cell.w / 2.0).min(cell.h / 2.0);
+    [
+        Rect::new(cell.x, cell.y, cell.w, t),
+        Rect::new(cell.x, cell.bottom() - t, cell.w, t),
+        Rect::new(cell.x, cell.y + t, t, cell.h - 2.0 * t),
+        Rect::new(cell.right() - t, cell.y + t, t, cell.h - 2.0 * t),
+    ]
+}
+
+/// The `len` cells of a row or column `extent` cells long shown around `center`, as in a
+/// minimap window. The window is shifted to stay inside `0..extent` and is only shorter than
+/// `len` when the whole line fits.
+pub fn window_range(center: i32, len: i32, extent: i32) -> Range<i32> {
+    let len = len.max(0);
+    let extent = extent.max(0);
+    if len >= extent {
+        return 0..extent;
+    }
+    let start = center.saturating_sub(len / 2).clamp(0, extent - len);
+    start..start + len
+}
+
+/// screen rect of cell (x, y) when `region` is painted into `dest`
+pub fn region_cell_rect(region: IRect, dest: Rect, x: i32, y: i32) -> Rect {
+    let cell_w = dest.w / region.w.max(1) as f32;
+    let cell_h = dest.h / region.h.max(1) as f32;
+    Rect::new(
+        dest.x + (x as f32 - region.x as f32) * cell_w,
+        dest.y + (y as f32 - region.y as f32) * cell_h,
+        cell_w,
+        cell_h,
+    )
+}
+
+/// How the side walls of the cells directly left and right of the player (|x| = 1, z = 0) are drawn.
+#[derive(Clone, Copy, Default, PartialEq, Eq, Debug)]
+pub enum NearSideWalls {
+    #[default]
+    Draw,
+    Skip,
+    /// draw them, but cut off whatever part of the tile lands outside the viewport
+    ClipToViewport,
+}
+
+/// Stand-in tiles for rows deeper than a layer was authored for. When a layer has no tile at
+/// (x, z) the deepest tile at the same x and a smaller |z| is reused, shrunk toward
+/// `vanishing_point` by `row_scale[|z|] / row_scale[|z_source|]`.
+#[derive(Clone, Debug)]
+pub struct DepthExtend {
+    pub vanishing_point: Vec2,
+    /// relative on-screen size of each row by |z|, rows past the end of the table are not synthesized
+    pub row_scale: Vec<f32>,
+}
+
+impl DepthExtend {
+    pub fn synthesize(&self, layer: &Tiles, x: i32, z: i32, orientation: Option<Orientation>) -> Option<Tile> {
+        let target_scale = *self.row_scale.get(z.unsigned_abs() as usize)?;
+
+        let source = layer.tiles().iter()
+            .filter(|tile| tile.x == x && tile.z.abs() < z.abs())
+            .filter(|tile| tile.orientation.is_none() || tile.orientation == orientation)
+            .max_by_key(|tile| tile.z.abs())?;
+        let source_scale = *self.row_scale.get(source.z.unsigned_abs() as usize)?;
+        let scale = target_scale / source_scale;
+
+        let rect = source.screen_coords;
+        let screen_coords = Coords {
+            x: (self.vanishing_point.x + (rect.x as f32 - self.vanishing_point.x) * scale).round() as i32,
+            y: (self.vanishing_point.y + (rect.y as f32 - self.vanishing_point.y) * scale).round() as i32,
+            w: (rect.w as f32 * scale).round() as i32,
+            h: (rect.h as f32 * scale).round() as i32,
+        };
+
+        let mut tile = Tile::new(source.atlas_coords, screen_coords, x, z, source.orientation);
+        tile.animation = source.animation.clone();
+        tile.page = source.page;
+        tile.variants = source.variants.clone();
+        Some(tile)
+    }
+}
+
+#[derive(Clone, Copy, Default, PartialEq, Eq, Debug)]
+pub enum SwayAxis {
+    #[default]
+    Horizontal,
+    Vertical,
+}
+
+/// A small sinusoidal wobble of a layer's tiles for things like grass or heat shimmer.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Sway {
+    pub axis: SwayAxis,
+    pub amplitude_px: f32,
+    /// seconds per full swing
+    pub period: f32,
+}
+
+impl Sway {
+    /// Offset of a tile standing in grid cell `cell` at world time `time`. Each cell gets its own
+    /// phase so neighbors don't move in lockstep.
+    pub fn offset(&self, time: f32, cell: IVec2) -> Vec2 {
+        if self.period.is_nan() || self.period <= 0.0 {
+            return Vec2::ZERO;
+        }
+        let phase = (cell.x as f32 * 0.618 + cell.y as f32 * 0.382).rem_euclid(1.0);
+        let amount = self.amplitude_px * (std::f32::consts::TAU * (time / self.period + phase)).sin();
+        match self.axis {
+            SwayAxis::Horizontal => vec2(amount, 0.0),
+            SwayAxis::Vertical => vec2(0.0, amount),
+        }
+    }
+}
+
+pub struct RenderConfig {
+    /// skip the wall faces of the cell the player stands in, which would otherwise cover the
+    /// whole viewport when a map encodes an archway as a wall value. Off by default, drawing
+    /// them as always.
+    pub skip_own_cell_walls: bool,
+    pub near_side_walls: NearSideWalls,
+    /// layer id -> synthesis of missing far rows, off for every layer unless added here
+    pub depth_extend: AHashMap<String, DepthExtend>,
+    /// pick wall variant layers from neighboring walls, off when `None`
+    pub autotile: Option<AutotileTable>,
+    /// layer id -> sway applied when its tiles are drawn
+    pub sway: AHashMap<String, Sway>,
+    /// let `sway` move `wall-*` layers too, which mostly looks broken
+    pub sway_walls: bool,
+    /// map layers drawn as flat tiles through their `RenderBindings`, each in the pass it's
+    /// listed with
+    pub flat_layers: Vec<(String, CellPass)>,
+    /// skip side wall faces covered by the neighboring wall, see `RenderDiagnostics::culled_faces`
+    pub cull_hidden_faces: bool,
+    /// submit `render`'s quads as meshes, one per run of quads from the same atlas; off draws
+    /// each quad on its own, for debugging
+    pub batch_quads: bool,
+    /// shift animated tiles by a phase picked from their cell, see `Animation::phase_offset`;
+    /// off keeps every instance of a tile in sync
+    pub animation_phase: bool,
+    /// the order cells are drawn in, see `render_order`
+    pub draw_order: DrawOrder,
+}
+
+impl Default for RenderConfig {
+    fn default() -> Self {
+        Self {
+            skip_own_cell_walls: false,
+            near_side_walls: NearSideWalls::Draw,
+            depth_extend: AHashMap::new(),
+            autotile: None,
+            sway: AHashMap::new(),
+            sway_walls: false,
+            flat_layers: vec![("floor".to_owned(), CellPass::Floor), ("ceiling".to_owned(), CellPass::Ceiling)],
+            cull_hidden_faces: true,
+            batch_quads: true,
+            animation_phase: false,
+            draw_order: DrawOrder::default(),
+        }
+    }
+}
+
+/// Tints tiles toward `color` the further away they are drawn.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct FogSettings {
+    pub color: Color,
+    /// depth in cells where the tint starts, the player's row is depth 0 and never tinted
+    pub start_depth: i32,
+    /// depth in cells where tiles are fully `color`
+    pub end_depth: i32,
+}
+
+impl FogSettings {
+    /// the draw color of a tile `depth` cells ahead of the player
+    pub fn tint(&self, depth: i32) -> Color {
+        if depth <= 0 {
+            return WHITE;
+        }
+        let t = if self.end_depth <= self.start_depth {
+            if depth >= self.start_depth { 1.0 } else { 0.0 }
+        } else {
+            ((depth - self.start_depth) as f32 / (self.end_depth - self.start_depth) as f32).clamp(0.0, 1.0)
+        };
+        Color::new(
+            1.0 + (self.color.r - 1.0) * t,
+            1.0 + (self.color.g - 1.0) * t,
+            1.0 + (self.color.b - 1.0) * t,
+            1.0 + (self.color.a - 1.0) * t,
+        )
+    }
+}
+
+/// An atlas layer id along with the `@diag` layer drawn in its place while the view is
+/// diagonal, kept as one string so neither is formatted while drawing.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct LayerId {
+    diag: String,
+    len: usize,
+}
+
+impl LayerId {
+    pub fn new(id: &str) -> Self {
+        Self { diag: format!("{id}@diag"), len: id.len() }
+    }
+
+    pub fn id(&self) -> &str {
+        &self.diag[..self.len]
+    }
+
+    pub fn diag(&self) -> &str {
+        &self.diag
+    }
+}
+
+// the variant layer ids the renderer looks for in place of one cell value's plain id
+#[derive(Clone, Debug, PartialEq, Eq)]
+struct VariantIds {
+    // `@{orientation}` for each of `Orientation::ALL`, by plain, `@near` and `@far`
+    oriented: [[LayerId; 3]; 4],
+    anchored: [LayerId; 2],
+    pit: LayerId,
+    raised: LayerId,
+}
+
+impl VariantIds {
+    fn new(plain: &str) -> Self {
+        let anchored = |id: &str| [LayerId::new(&format!("{id}@near")), LayerId::new(&format!("{id}@far"))];
+        Self {
+            oriented: Orientation::ALL.map(|orientation| {
+                let oriented = format!("{plain}@{orientation}");
+                let [near, far] = anchored(&oriented);
+                [LayerId::new(&oriented), near, far]
+            }),
+            anchored: anchored(plain),
+            pit: LayerId::new(&format!("{plain}-pit")),
+            raised: LayerId::new(&format!("{plain}-raised")),
+        }
+    }
+}
+
+/// The atlas and atlas layer a map layer is drawn from. The layer id of every cell value is
+/// worked out when the binding is made, and its variants the first time they're looked up,
+/// so drawing formats each id once at most.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct LayerBinding {
+    pub atlas_id: String,
+    template: String,
+    layer_ids: Vec<LayerId>,
+    variants: Vec<OnceCell<VariantIds>>,
+}
+
+impl LayerBinding {
+    pub fn new(atlas_id: &str, template: &str) -> Self {
+        let layer_ids = (0..=u8::MAX).map(|value| LayerId::new(&template.replace("{}", &value.to_string()))).collect();
+        let variants = vec![OnceCell::new(); 256];
+        Self { atlas_id: atlas_id.to_owned(), template: template.to_owned(), layer_ids, variants }
+    }
+
+    /// atlas layer id with `{}` standing in for the cell value, like "wall-{}"
+    pub fn template(&self) -> &str {
+        &self.template
+    }
+
+    pub fn layer_id(&self, value: u8) -> &str {
+        self.layer(value).id()
+    }
+
+    pub fn layer(&self, value: u8) -> &LayerId {
+        &self.layer_ids[value as usize]
+    }
+
+    /// `{id}-pit`, drawn for a floor below level when the atlas has it
+    pub fn pit_layer(&self, value: u8) -> &LayerId {
+        &self.variants(value).pit
+    }
+
+    /// `{id}-raised`, drawn for a floor above level when the atlas has it
+    pub fn raised_layer(&self, value: u8) -> &LayerId {
+        &self.variants(value).raised
+    }
+
+    /// `{id}@{orientation}` with `@near` or `@far` after it for the edge anchors, leaving out
+    /// whichever part is `None` or `Center`
+    pub fn object_layer(&self, value: u8, orientation: Option<Orientation>, anchor: CellAnchor) -> &LayerId {
+        let edge = match anchor {
+            CellAnchor::Center => 0,
+            CellAnchor::NearEdge => 1,
+            CellAnchor::FarEdge => 2,
+        };
+        match (orientation, edge) {
+            (None, 0) => self.layer(value),
+            (None, edge) => &self.variants(value).anchored[edge - 1],
+            (Some(orientation), edge) => &self.variants(value).oriented[orientation as usize][edge],
+        }
+    }
+
+    fn variants(&self, value: u8) -> &VariantIds {
+        self.variants[value as usize].get_or_init(|| VariantIds::new(self.layer_id(value)))
+    }
+}
+
+/// Map layer name -> what it's drawn from. Layers without a binding aren't drawn at all, so
+/// tilesets can be swapped at runtime by replacing the bindings.
+#[derive(Clone, Debug, PartialEq)]
+pub struct RenderBindings {
+    pub layers: AHashMap<String, LayerBinding>,
+}
+
+impl Default for RenderBindings {
+    fn default() -> Self {
+        let layers = [
+            ("wall", LayerBinding::new("dungeon", "wall-{}")),
+            ("floor", LayerBinding::new("dungeon", "floor-{}")),
+            ("ceiling", LayerBinding::new("dungeon", "ceiling-{}")),
+            ("object", LayerBinding::new("common_objects", "object-{}")),
+            ("doors", LayerBinding::new("dungeon", "door-{}")),
+            ("decal", LayerBinding::new("dungeon", "decal-{}")),
+        ];
+        Self { layers: layers.into_iter().map(|(layer, binding)| (layer.to_owned(), binding)).collect() }
+    }
+}
+
+impl RenderBindings {
+    pub fn get(&self, layer: &str) -> Option<&LayerBinding> {
+        self.layers.get(layer)
+    }
+
+    /// binds `layer`, replacing any earlier binding for it
+    pub fn bind(&mut self, layer: &str, atlas_id: &str, template: &str) {
+        self.layers.insert(layer.to_owned(), LayerBinding::new(atlas_id, template));
+    }
+
+    pub fn unbind(&mut self, layer: &str) -> Option<LayerBinding> {
+        self.layers.remove(layer)
+    }
+}
+
+/// Crops `dest` to the 1x viewport and shrinks `source` by the same proportions.
+/// Returns `None` when nothing of the quad is left on screen.
+pub fn clip_to_viewport(source: Rect, dest: Rect) -> Option<(Rect, Rect)> {
+    clip_to_rect(source, dest, Rect::new(0.0, 0.0, VIEWPORT_WIDTH as f32, VIEWPORT_HEIGHT as f32))
+}
+
+/// `clip_to_viewport` against an arbitrary rect
+pub fn clip_to_rect(source: Rect, dest: Rect, viewport: Rect) -> Option<(Rect, Rect)> {
+    let clipped = dest.intersect(viewport)?;
+    if clipped.w <= 0.0 || clipped.h <= 0.0 {
+        return None;
+    }
+
+    let scale_x = source.w / dest.w;
+    let scale_y = source.h / dest.h;
+    let clipped_source = Rect::new(
+        source.x + (clipped.x - dest.x) * scale_x,
+        source.y + (clipped.y - dest.y) * scale_y,
+        clipped.w * scale_x,
+        clipped.h * scale_y,
+    );
+
+    Some((clipped_source, clipped))
+}
+
+/// quads bigger than this in either direction are clamped
+pub const MAX_QUAD_SIZE: f32 = 16384.0;
+
+/// Checks a rect before it's drawn. `None` for non-finite or non-positive sizes, otherwise the
+/// rect with its values clamped to `MAX_QUAD_SIZE` and whether that changed anything.
+pub fn sanitize_rect(rect: Rect) -> Option<(Rect, bool)> {
+    let values = [rect.x, rect.y, rect.w, rect.h];
+    if values.iter().any(|v| !v.is_finite()) || rect.w <= 0.0 || rect.h <= 0.0 {
+        return None;
+    }
+
+    let clamped = Rect::new(
+        rect.x.clamp(-MAX_QUAD_SIZE, MAX_QUAD_SIZE),
+        rect.y.clamp(-MAX_QUAD_SIZE, MAX_QUAD_SIZE),
+        rect.w.min(MAX_QUAD_SIZE),
+        rect.h.min(MAX_QUAD_SIZE),
+    );
+    Some((clamped, clamped != rect))
+}
+
+/// What happened to the quads of the last `render`.
+#[derive(Clone, Copy, Default, PartialEq, Eq, Debug)]
+pub struct RenderDiagnostics {
+    /// quads drawn, or captured by `capture_draws`
+    pub drawn_quads: u32,
+    /// side wall faces skipped for being covered by the neighboring wall
+    pub culled_faces: u32,
+    /// cells skipped for being hidden behind walls
+    pub occluded_cells: u32,
+    /// quads with non-finite or non-positive sizes that were not drawn
+    pub skipped_quads: u32,
+    /// quads drawn after clamping absurd values
+    pub clamped_quads: u32,
+}
+
+pub(crate) fn atlas_rect(tile: &Tile) -> Rect {
+    coords_rect(tile.atlas_coords)
+}
+
+pub(crate) fn coords_rect(coords: Coords) -> Rect {
+    Rect::new(coords.x as f32, coords.y as f32, coords.w as f32, coords.h as f32)
+}
+
+pub(crate) fn screen_rect(tile: &Tile) -> Rect {
+    Rect::new(
+        tile.screen_coords.x as f32,
+        tile.screen_coords.y as f32,
+        tile.screen_coords.w as f32,
+        tile.screen_coords.h as f32,
+    )
+}
+
+// object sprite orientation for each facing of the viewer
+const OBJECT_ORIENTATIONS: [(Direction, Orientation); 4] = [
+    (Direction::North, Orientation::Front),
+    (Direction::East, Orientation::Right),
+    (Direction::South, Orientation::Back),
+    (Direction::West, Orientation::Left),
+];
+
+/// A single textured quad as `render` would submit it.
+#[derive(Clone, Debug, PartialEq)]
+pub struct DrawCommand {
+    /// empty for the untextured fills of `draw_map_region`, whose `source` is then empty too
+    pub atlas_id: String,
+    pub layer_id: String,
+    pub source: Rect,
+    pub dest: Rect,
+    pub color: Color,
+    /// the atlas texture `source` is cut from
+    pub page: usize,
+    /// the `DrawOrder::key` of the cell and pass this was drawn for
+    pub order: RenderOrderKey,
+}
+
+/// The map type is anything implementing `MapSource`, a plain `AtlasMap` unless told otherwise.
+pub struct AtlasWorld<M: MapSource = AtlasMap> {
+    pub player: Player,
+    pub map: M,
+    pub collection: AtlasCollection,
+    pub render_depth: i32,
+    pub render_width: i32,
+    pub object_defs: ObjectDefs,
+    pub render_config: RenderConfig,
+    pub render_bindings: RenderBindings,
+    /// skip cells hidden behind walls that block sight, see `occluded_cells`
+    pub occlusion_enabled: bool,
+    pub fog: Option<FogSettings>,
+    /// drawn behind the cells, seen wherever there is no ceiling
+    pub sky: Option<SkySettings>,
+    /// picks which of a tile's `variants` each cell shows, change it to reshuffle them
+    pub variant_seed: u64,
+    /// the least brightness a lit map is drawn with, see `compute_lighting`
+    pub ambient_light: u8,
+    /// brightness light loses per cell it spreads
+    pub light_falloff: u8,
+    /// size of the texture `render_to_target` renders into, and how it's presented
+    pub viewport: Viewport,
+    #[cfg(feature = "render")]
+    view_target: Option<RenderTarget>,
+    open_doors: AHashSet<IVec2>,
+    /// open doors close by themselves this many turns after opening, see `take_turn`
+    pub door_close_turns: Option<u32>,
+    // turn each open door was first seen open, for `door_close_turns`
+    door_timers: AHashMap<IVec2, u64>,
+    // see `secrets`
+    secret_walls: AHashSet<IVec2>,
+    revealed_secrets: AHashSet<IVec2>,
+    entities: Vec<Entity>,
+    next_entity_id: u32,
+    // what entity behaviors, spinners and random teleporter facings roll with, see `seed_entities`
+    entity_rng: generator::Rng,
+    /// which objects and entities keep the player out of their cell
+    pub movement_rules: MovementRules,
+    /// object values `attack` knocks out of their cell
+    pub destructible_objects: AHashSet<u8>,
+    pub wall_materials: WallMaterials,
+    pub wall_properties: WallProperties,
+    pub terrain: TerrainTable,
+    /// entering a cell uses up its terrain move cost in turns, see `advance_turn`
+    pub terrain_costs_turns: bool,
+    /// followed by `step_on` whenever the player lands on one
+    pub warps: Vec<Warp>,
+    /// teleporters on the current level, see `trigger_traps`
+    pub teleporters: Vec<Teleporter>,
+    pub spinners: Vec<Spinner>,
+    /// id of `map` among the world's levels, "main" unless renamed before adding others
+    pub current_map: String,
+    levels: AHashMap<String, levels::Level<M>>,
+    /// explored by `update` as the player walks around, off when `None`
+    pub automap: Option<AutoMap>,
+    /// seconds of world time, advanced by `update`
+    pub time: f32,
+    localizer: Option<Box<dyn Localizer>>,
+    encounter: Option<Encounter>,
+    defeated: AHashSet<EntityId>,
+    stats: Option<WorldStats>,
+    /// cross-check cached data against a fresh computation after every move, panicking on a
+    /// mismatch, to catch map edits that bypass `set_cell`
+    pub debug_check_caches: bool,
+    turn: u64,
+    // cost of the cell the player last entered, waiting to be charged by `advance_turn`
+    pending_move_cost: u32,
+    map_revision: u64,
+    passability_cache: RefCell<Option<(CollisionRules, PassabilityGrid)>>,
+    autotile_revision: Option<u64>,
+    // object value -> brightness, see `set_light_emitter`
+    light_emitters: AHashMap<u8, u8>,
+    // `map_revision` the light layer was computed at, `None` when emitters changed since
+    light_revision: Option<u64>,
+    events: VecDeque<WorldEvent>,
+    /// the most events `poll_events` is left to drain, older ones are dropped past it
+    pub max_queued_events: usize,
+    // events dropped for `max_queued_events`, and pushed ever, see `events`
+    dropped_events: u64,
+    events_pushed: u64,
+    capture: RefCell<Option<Vec<DrawCommand>>>,
+    current_order: Cell<RenderOrderKey>,
+    // set while `render_from` draws, the player's view otherwise
+    view: Cell<Option<ViewPoint>>,
+    // view-space cells `render_peek` leans by while it draws
+    peek_shift: Cell<f32>,
+    // alpha of the wall faces being drawn, see `WallProperties::alpha`
+    wall_alpha: Cell<f32>,
+    diagnostics: Cell<RenderDiagnostics>,
+    #[cfg(feature = "render")]
+    batch: RefCell<Option<batch::QuadBatch>>,
+    render_stats: Cell<RenderStats>,
+    // see `hooks`
+    cell_hook: RefCell<Option<hooks::CellHook>>,
+    post_render_hook: RefCell<Option<hooks::PostRenderHook>>,
+}
+
+impl<M: MapSource> AtlasWorld<M> {
+    pub fn new(player: Player, mut map: M, collection: AtlasCollection) -> Self {
+        map.focus(ivec2(player.x, player.y));
+        // a map that comes with baked autotiles is taken to be baked from what it holds now
+        let autotile_revision = map.get("autotile", IVec2::ZERO).map(|_| 0);
+        let viewport = viewport::authored_viewport(&collection);
+        Self {
+            player,
+            map,
+            collection,
+            render_depth: 9,
+            render_width: 22,
+            object_defs: ObjectDefs::new(),
+            render_config: RenderConfig::default(),
+            render_bindings: RenderBindings::default(),
+            occlusion_enabled: true,
+            fog: None,
+            sky: None,
+            variant_seed: 0,
+            ambient_light: 16,
+            light_falloff: 32,
+            viewport,
+            #[cfg(feature = "render")]
+            view_target: None,
+            open_doors: AHashSet::new(),
+            door_close_turns: None,
+            door_timers: AHashMap::new(),
+            secret_walls: AHashSet::new(),
+            revealed_secrets: AHashSet::new(),
+            entities: Vec::new(),
+            next_entity_id: 1,
+            entity_rng: generator::Rng::new(0),
+            movement_rules: MovementRules::default(),
+            destructible_objects: AHashSet::new(),
+            automap: None,
+            wall_materials: WallMaterials::new(),
+            wall_properties: WallProperties::new(),
+            terrain: TerrainTable::new(),
+            terrain_costs_turns: false,
+            warps: Vec::new(),
+            teleporters: Vec::new(),
+            spinners: Vec::new(),
+            current_map: "main".to_owned(),
+            levels: AHashMap::new(),
+            time: 0.0,
+            localizer: None,
+            encounter: None,
+            defeated: AHashSet::new(),
+            stats: None,
+            debug_check_caches: false,
+            turn: 0,
+            pending_move_cost: 1,
+            map_revision: 0,
+            passability_cache: RefCell::new(None),
+            autotile_revision,
+            light_emitters: AHashMap::new(),
+            light_revision: None,
+            events: VecDeque::new(),
+            max_queued_events: events::DEFAULT_MAX_QUEUED_EVENTS,
+            dropped_events: 0,
+            events_pushed: 0,
+            capture: RefCell::new(None),
+            current_order: Cell::new(render_order_key(IVec2::ZERO, CellPass::Floor)),
+            view: Cell::new(None),
+            peek_shift: Cell::new(0.0),
+            wall_alpha: Cell::new(1.0),
+            diagnostics: Cell::new(RenderDiagnostics::default()),
+            #[cfg(feature = "render")]
+            batch: RefCell::new(None),
+            render_stats: Cell::new(RenderStats::default()),
+            cell_hook: RefCell::new(None),
+            post_render_hook: RefCell::new(None),
+        }
+    }
+
+    /// Advances world time and the player's animation, starting a queued move or turn once
+    /// the one in flight is done, and reveals what the automap's `reveal_mode` asks for.
+    pub fn update(&mut self, dt: f32) {
+        self.time += dt;
+        self.update_animation(dt);
+
+        // the automap holds still while an encounter plays out
+        if self.encounter.is_some() {
+            return;
+        }
+        let pos = ivec2(self.player.x, self.player.y);
+        let seen = match self.automap.as_ref().map(|automap| automap.reveal_mode) {
+            Some(RevealMode::LineOfSight { radius }) => self.visible_cells(pos, radius),
+            _ => AHashSet::new(),
+        };
+        if let Some(automap) = &mut self.automap {
+            automap.visit(pos);
+            automap.reveal(&self.map, seen);
+        }
+    }
+
+    /// turns completed so far
+    pub fn turn(&self) -> u64 {
+        self.turn
+    }
+
+    /// Ends the player's turn and returns how many turns it took: the terrain move cost of the
+    /// cell entered since the last call when `terrain_costs_turns` is on, 1 otherwise. Anything
+    /// that runs per turn should run that many times.
+    pub fn advance_turn(&mut self) -> u32 {
+        let ticks = if self.terrain_costs_turns { self.pending_move_cost.max(1) } else { 1 };
+        self.pending_move_cost = 1;
+        self.turn += ticks as u64;
+        self.push_event(WorldEvent::TurnEnded { turn: self.turn, ticks });
+        ticks
+    }
+
+    /// Moves the player like the `Player` methods do, through `try_move`, but reports a blocked
+    /// move as a `WallBumped` event carrying the wall material's bump sound.
+    pub fn move_player(&mut self, movement: Movement) -> MoveResult {
+        let direction = self.player.movement_direction(movement);
+        self.try_move(direction).into()
+    }
+
+    /// turns the player, emitting a single `Turned` event even for an about-face
+    pub fn turn_player(&mut self, turn: Turn) {
+        let from = self.player.direction;
+        self.player.turn(turn);
+        self.push_event(WorldEvent::Turned { from, to: self.player.direction, about_face: turn == Turn::Around });
+    }
+
+    pub fn corner_left(&mut self) -> MoveResult {
+        self.corner_player(Turn::Left)
+    }
+
+    pub fn corner_right(&mut self) -> MoveResult {
+        self.corner_player(Turn::Right)
+    }
+
+    fn corner_player(&mut self, turn: Turn) -> MoveResult {
+        let from = self.player.direction;
+        let start = ivec2(self.player.x, self.player.y);
+        let view = MovementView { map: &self.map, open: &self.open_doors, entities: &self.entities, rules: &self.movement_rules, walls: &self.wall_properties, objects: &self.object_defs };
+        let result = self.player.corner(&view, turn);
+        if self.player.direction != from {
+            self.push_event(WorldEvent::Turned { from, to: self.player.direction, about_face: false });
+        }
+        self.report_move(start, result);
+        result
+    }
+
+    // `from` is the player's cell before the move
+    fn report_move(&mut self, from: IVec2, result: MoveResult) {
+        if self.debug_check_caches {
+            self.check_caches();
+        }
+
+        let pos = match result {
+            MoveResult::Moved(pos) => {
+                let floor = self.map.get("floor", pos);
+                self.pending_move_cost = floor.map_or(1, |value| self.terrain.move_cost(value));
+                if self.map.focus(pos) {
+                    self.touch_map();
+                }
+                self.push_event(WorldEvent::Moved { pos, floor: floor.unwrap_or(0) });
+                let (before, after) = (self.map.elevation(from), self.map.elevation(pos));
+                if self.movement_rules.fall_into_pits && after < 0 && after < before {
+                    self.push_event(WorldEvent::Fell { pos, depth: after.unsigned_abs() });
+                }
+                // triggers, warps and traps wait while an encounter plays out
+                if self.encounter.is_some() {
+                    return;
+                }
+                if let Some(id) = self.map.get("trigger", pos).filter(|id| *id != 0) {
+                    self.push_event(WorldEvent::TriggerEntered { id, pos });
+                }
+                self.step_on(pos);
+                return;
+            }
+            MoveResult::Blocked(pos) => pos,
+        };
+
+        if let Some(value) = self.map.get_wall(pos) {
+            if self.wall_blocks_movement(value) {
+                let sound = self.wall_materials.get(value).bump_sound.clone();
+                self.push_event(WorldEvent::WallBumped { pos, value, sound });
+                return;
+            }
+        }
+        let view = MovementView { map: &self.map, open: &self.open_doors, entities: &self.entities, rules: &self.movement_rules, walls: &self.wall_properties, objects: &self.object_defs };
+        match view.step_blocker(from, pos).unwrap_or(Blocker::Corner) {
+            Blocker::Entity(entity) => self.push_event(WorldEvent::EntityContact { entity, pos }),
+            blocker => self.push_event(WorldEvent::MoveBlocked { pos, blocker }),
+        }
+    }
+
+    /// runs `render` but records the quads it would draw instead of drawing them
+    pub fn capture_commands(&self) -> Vec<DrawCommand> {
+        self.capture_draws(|world| {
+            world.render();
+        })
+    }
+
+    // records the quads `draw` would draw instead of drawing them
+    pub(crate) fn capture_draws(&self, draw: impl FnOnce(&Self)) -> Vec<DrawCommand> {
+        *self.capture.borrow_mut() = Some(Vec::new());
+        draw(self);
+        self.capture.borrow_mut().take().unwrap_or_default()
+    }
+
+    /// Draws the view, returning how many quads and draw calls it took.
+    pub fn render(&self) -> RenderStats {
+        self.render_from(self.player.view_point())
+    }
+
+    /// Draws the view from `view` instead of the player, for cutscenes and scrying. The
+    /// player and the map are left as they are.
+    pub fn render_from(&self, view: ViewPoint) -> RenderStats {
+        self.view.set(Some(view));
+        let stats = self.render_view();
+        self.view.set(None);
+        stats
+    }
+
+    fn render_view(&self) -> RenderStats {
+        self.diagnostics.set(RenderDiagnostics::default());
+        self.render_stats.set(RenderStats::default());
+        #[cfg(feature = "render")]
+        if self.render_config.batch_quads {
+            *self.batch.borrow_mut() = Some(batch::QuadBatch::new());
+        }
+
+        let half_width = self.render_width / 2;
+        let mut cells: Vec<IVec2> = (-self.render_depth..1)
+            .flat_map(|z| (-half_width..=half_width).map(move |x| ivec2(x, z)))
+            .collect();
+        let draw_order = self.render_config.draw_order;
+        cells.sort_by_key(|cell| draw_order.key(*cell, CellPass::Floor));
+
+        self.draw_sky();
+        let occluded = if self.occlusion_enabled { self.occluded_cells() } else { AHashSet::new() };
+        for cell in cells {
+            if occluded.contains(&cell) {
+                continue;
+            }
+            self.draw_map_square(cell.x, cell.y);
+        }
+
+        let mut diagnostics = self.diagnostics.get();
+        diagnostics.occluded_cells = occluded.len() as u32;
+        self.diagnostics.set(diagnostics);
+
+        self.flush_batch();
+        #[cfg(feature = "render")]
+        self.batch.borrow_mut().take();
+        self.run_post_render_hook();
+        self.render_stats.get()
+    }
+
+
+    /// The facing the view is drawn with. A diagonal facing is only drawn diagonally when the
+    /// atlas has `@diag` layers for it, otherwise the nearest cardinal view stands in.
+    pub fn view_direction(&self) -> Direction {
+        let direction = self.view_point().direction;
+        if direction.is_diagonal() && !self.has_diagonal_art() {
+            direction.nearest_cardinal()
+        } else {
+            direction
+        }
+    }
+
+    /// the viewpoint being drawn: the one passed to `render_from`, otherwise the player's
+    pub fn view_point(&self) -> ViewPoint {
+        self.view.get().unwrap_or_else(|| self.player.view_point())
+    }
+
+    fn has_diagonal_art(&self) -> bool {
+        self.render_bindings.get("wall")
+            .and_then(|binding| self.collection.get(&binding.atlas_id))
+            .is_some_and(|atlas| atlas.diagonal_art)
+    }
+
+    fn view_cell(&self, x: i32, z: i32) -> IVec2 {
+        ViewPoint::new(self.view_point().pos, self.view_direction()).get_direction_vector_offsets(x, z)
+    }
+
+    pub fn draw_map_square(&self, x: i32, z: i32) {
+        let p = self.view_cell(x, z);
+
+        if !self.map.in_bounds(p) {
+            return;
+        }
+
+        let own_cell = x == 0 && z == 0;
+        let draw_walls = self.map.get_wall(p).unwrap_or(0) != 0 && !(own_cell && self.render_config.skip_own_cell_walls);
+
+        for pass in CellPass::ALL {
+            self.current_order.set(self.render_config.draw_order.key(ivec2(x, z), pass));
+            for (layer, _) in self.render_config.flat_layers.iter().filter(|(_, layer_pass)| *layer_pass == pass) {
+                let value = self.map.get(layer, p).unwrap_or(0);
+                if let (true, Some(binding)) = (value != 0, self.render_bindings.get(layer)) {
+                    let layer_id = if layer == "floor" { self.floor_layer_id(binding, value, p) } else { binding.layer(value) };
+                    self.draw_layer(&binding.atlas_id, layer_id, x, z, None);
+                }
+            }
+            match pass {
+                CellPass::SideWalls if draw_walls => self.draw_side_walls(x, z),
+                CellPass::FrontWall if draw_walls => self.draw_front_walls(x, z),
+                CellPass::FrontWall => self.draw_door(x, z),
+                CellPass::Object => {
+                    self.draw_objects(x, z);
+                    self.draw_entities(x, z);
+                }
+                _ => {}
+            }
+        }
+        self.run_cell_hook(p, x, z);
+    }
+
+    pub fn draw_side_walls(&self, x: i32, z: i32) {
+        let p = self.view_cell(x, z);
+        let Some(atlas_id) = self.render_bindings.get("wall").map(|binding| binding.atlas_id.as_str()) else {
+            return;
+        };
+
+        if let Some(wall_value) = self.map.get_wall(p) {
+            if wall_value != 0 && self.wall_properties.has_sides(wall_value) {
+                self.wall_alpha.set(self.wall_properties.alpha(wall_value));
+                let layer_id = self.wall_layer(p, wall_value);
+                let near_side = z == 0 && x.abs() == 1;
+                let sides = [Orientation::Left, Orientation::Right].into_iter().filter(|side| !self.face_hidden(x, z, *side));
+
+                let decal = self.decal_layer(p);
+                let faces = [Some((atlas_id, layer_id.as_ref())), decal].into_iter().flatten();
+                match self.render_config.near_side_walls {
+                    NearSideWalls::Skip if near_side => {}
+                    NearSideWalls::ClipToViewport if near_side => {
+                        for side in sides {
+                            for (atlas_id, layer_id) in faces.clone() {
+                                if let Some(tile) = self.collection.get_tile(atlas_id, layer_id.id(), x, z, Some(side)) {
+                                    if let Some((source, dest)) = clip_to_rect(self.animated_rect(tile, p), screen_rect(tile).offset(vec2(self.peek_offset(z), 0.0)), self.viewport_rect()) {
+                                        self.draw_quad(atlas_id, layer_id.id(), tile.page, source, dest, self.tile_color(x, z, Some(side)));
+                                    }
+                                }
+                            }
+                        }
+                    }
+                    _ => {
+                        for side in sides {
+                            for (atlas_id, layer_id) in faces.clone() {
+                                self.draw_layer(atlas_id, layer_id, x, z, Some(side));
+                            }
+                        }
+                    }
+                }
+                self.wall_alpha.set(1.0);
+            }
+        }
+    }
+
+    // A `Left` face is the side of a wall left of the player turned toward the middle of the
+    // view, so the wall cell one to the right covers it, and the other way around for `Right`.
+    fn face_hidden(&self, x: i32, z: i32, side: Orientation) -> bool {
+        if !self.render_config.cull_hidden_faces {
+            return false;
+        }
+        let neighbor = match side {
+            Orientation::Left => x + 1,
+            Orientation::Right => x - 1,
+            _ => return false,
+        };
+        let hidden = self.map.get_wall(self.view_cell(neighbor, z)).is_some_and(|value| self.wall_hides_view(value));
+        if hidden {
+            let mut diagnostics = self.diagnostics.get();
+            diagnostics.culled_faces += 1;
+            self.diagnostics.set(diagnostics);
+        }
+        hidden
+    }
+
+    pub fn draw_front_walls(&self, x: i32, z: i32) {
+        let p = self.view_cell(x, z);
+        let Some(binding) = self.render_bindings.get("wall") else {
+            return;
+        };
+
+        if let Some(wall_value) = self.map.get_wall(p) {
+            if wall_value != 0 {
+                self.wall_alpha.set(self.wall_properties.alpha(wall_value));
+                self.draw_layer(&binding.atlas_id, &self.wall_layer(p, wall_value), x, z, Some(Orientation::Front));
+                if let Some((atlas_id, layer_id)) = self.decal_layer(p) {
+                    self.draw_layer(atlas_id, layer_id, x, z, Some(Orientation::Front));
+                }
+                self.wall_alpha.set(1.0);
+            }
+        }
+    }
+
+    // `floor-{n}-pit` or `floor-{n}-raised` for a floor off level at `p`, when the atlas has it
+    fn floor_layer_id<'a>(&self, binding: &'a LayerBinding, value: u8, p: IVec2) -> &'a LayerId {
+        let layer_id = match self.map.elevation(p) {
+            0 => return binding.layer(value),
+            elevation if elevation < 0 => binding.pit_layer(value),
+            _ => binding.raised_layer(value),
+        };
+        match self.collection.get(&binding.atlas_id) {
+            Some(atlas) if atlas.atlas_info.layers.contains_key(layer_id.id()) => layer_id,
+            _ => binding.layer(value),
+        }
+    }
+
+    // the atlas and layer of the decal on the wall at `p`, drawn over each of its faces
+    fn decal_layer(&self, p: IVec2) -> Option<(&str, &LayerId)> {
+        let value = self.map.get("decal", p).filter(|value| *value != 0)?;
+        let binding = self.render_bindings.get("decal")?;
+        Some((&binding.atlas_id, binding.layer(value)))
+    }
+
+    pub fn draw_objects(&self, x: i32, z: i32) {
+
+        let p = self.view_cell(x, z);
+        let Some(binding) = self.render_bindings.get("object") else {
+            return;
+        };
+        let atlas_id = binding.atlas_id.as_str();
+
+        if let Some(map_value) = self.map.get("object", p) {
+            if map_value != 0 {
+                let facing = self.view_direction().nearest_cardinal();
+                let orientation = OBJECT_ORIENTATIONS.iter()
+                    .find(|(direction, _)| *direction == facing)
+                    .map(|(_, orientation)| *orientation);
+                let def = self.object_defs.get(&map_value);
+                let has_layer = |layer_id: &LayerId| self.collection.get(atlas_id)
+                    .is_some_and(|atlas| atlas.atlas_info.layers.contains_key(layer_id.id()));
+                // art for objects bigger than a cell depends on which side they're seen from
+                let side = orientation
+                    .filter(|_| def.is_some_and(|def| !def.footprint.is_empty()))
+                    .filter(|side| has_layer(binding.object_layer(map_value, Some(*side), CellAnchor::Center)));
+                let layer_id = binding.object_layer(map_value, side, CellAnchor::Center);
+                let anchor = def.map(|def| def.depth_anchor).unwrap_or_default();
+
+                let z_shift = match anchor {
+                    CellAnchor::Center => {
+                        self.draw_layer(atlas_id, layer_id, x, z, orientation);
+                        return;
+                    }
+                    // the near edge of the player's own row sits on the camera plane
+                    CellAnchor::NearEdge if z >= 0 => return,
+                    CellAnchor::NearEdge => 1,
+                    CellAnchor::FarEdge => -1,
+                };
+
+                let anchored_id = binding.object_layer(map_value, side, anchor);
+                if has_layer(anchored_id) {
+                    self.draw_layer(atlas_id, anchored_id, x, z, orientation);
+                } else {
+                    self.draw_layer_from_row(atlas_id, layer_id, x, z, z + z_shift, orientation);
+                }
+            }
+        }
+    }
+
+    /// The cells of `region` that are on the map, each with its screen rect when `region` is
+    /// painted into `dest`, row by row. This is what `draw_map_region` draws.
+    pub fn region_cells(&self, region: IRect, dest: Rect) -> impl Iterator<Item = (IVec2, Rect)> {
+        // only the part of the region that overlaps the map gets drawn
+        let start_x = region.x.max(0);
+        let start_y = region.y.max(0);
+        let end_x = region.x.saturating_add(region.w).min(self.map.width() as i32);
+        let end_y = region.y.saturating_add(region.h).min(self.map.height() as i32);
+
+        (start_y..end_y)
+            .flat_map(move |y| (start_x..end_x).map(move |x| ivec2(x, y)))
+            .map(move |pos| (pos, region_cell_rect(region, dest, pos.x, pos.y)))
+    }
+
+    pub fn draw_map_region(&self, region: IRect, dest: Rect, style: RegionStyle) {
+        let player = ivec2(self.player.x, self.player.y);
+        let mut player_cell = None;
+
+        for (pos, cell) in self.region_cells(region, dest) {
+            if pos == player {
+                player_cell = Some(cell);
+            }
+            let (Some(wall_value), Some(floor_value)) = (self.map.get_wall(pos), self.map.get("floor", pos)) else {
+                continue;
+            };
+
+            match &style.fill {
+                RegionFill::Colors { wall, floor } => {
+                    let color = if wall_value != 0 {
+                        wall.get(&wall_value)
+                    } else {
+                        floor.get(&floor_value)
+                    };
+                    if let Some(color) = color {
+                        self.draw_fill("fill", FillShape::Rect, cell, *color);
+                    }
+                }
+                RegionFill::FloorTiles { atlas_id, wall_color } => {
+                    if wall_value != 0 {
+                        self.draw_fill("fill", FillShape::Rect, cell, *wall_color);
+                    } else if let (true, Some(binding)) = (floor_value != 0, self.render_bindings.get("floor")) {
+                        let layer_id = binding.layer_id(floor_value);
+                        if let Some(tile) = self.thumbnail_tile(atlas_id, layer_id) {
+                            self.draw_tile_at(atlas_id, layer_id, tile, cell);
+                        }
+                    }
+                }
+            }
+
+            if let Some((thickness, color)) = style.border {
+                for edge in border_rects(cell, thickness) {
+                    self.draw_fill("border", FillShape::Rect, edge, color);
+                }
+            }
+        }
+
+        if let (Some(color), Some(cell)) = (style.player_color, player_cell) {
+            let radius = cell.w.min(cell.h) / 3.0;
+            let marker = Rect::new(cell.center().x - radius, cell.center().y - radius, radius * 2.0, radius * 2.0);
+            self.draw_fill("player", FillShape::Circle, marker, color);
+        }
+    }
+
+    // the tile straight ahead of the camera shows the most of the texture
+    fn thumbnail_tile(&self, atlas_id: &str, layer_id: &str) -> Option<&Tile> {
+        let layer = self.collection.get(atlas_id)?.atlas_info.layers.get(layer_id)?;
+        layer.tiles().iter()
+            .filter(|tile| tile.x == 0)
+            .max_by_key(|tile| tile.z)
+            .or_else(|| layer.tiles().first())
+    }
+
+    pub fn draw_tile(
+        &self,
+        atlas_id: &str,
+        layer_id: &str,
+        x: i32,
+        z: i32,
+        orientation: Option<Orientation>,
+    ) {
+        // an id that isn't from a binding only gets its `@diag` form made when there's art to find
+        if self.view_direction().is_diagonal() && self.collection.get(atlas_id).is_some_and(|atlas| atlas.diagonal_art) {
+            self.draw_layer(atlas_id, &LayerId::new(layer_id), x, z, orientation);
+        } else {
+            self.draw_tile_from(atlas_id, layer_id, None, ivec2(x, z), z, orientation);
+        }
+    }
+
+    /// `draw_tile` for an id from a `LayerBinding`, with its `@diag` variant already made
+    pub fn draw_layer(&self, atlas_id: &str, layer_id: &LayerId, x: i32, z: i32, orientation: Option<Orientation>) {
+        self.draw_layer_from_row(atlas_id, layer_id, x, z, z, orientation);
+    }
+
+    // `draw_layer` with the tile of view row `tile_z` standing in for the one of row `z`, which
+    // is still the row the quad is tinted and peeked for
+    fn draw_layer_from_row(&self, atlas_id: &str, layer_id: &LayerId, x: i32, z: i32, tile_z: i32, orientation: Option<Orientation>) {
+        let diag_id = Some(layer_id.diag()).filter(|_| self.view_direction().is_diagonal());
+        self.draw_tile_from(atlas_id, layer_id.id(), diag_id, ivec2(x, z), tile_z, orientation);
+    }
+
+    // draws the `diag_id` tile when there's one, otherwise the `layer_id` one, at view cell
+    // `view` with the screen rect of the tile in row `tile_z`
+    fn draw_tile_from(&self, atlas_id: &str, layer_id: &str, diag_id: Option<&str>, view: IVec2, tile_z: i32, orientation: Option<Orientation>) {
+        let (x, z) = (view.x, view.y);
+        let tile = diag_id.and_then(|diag_id| self.collection.get_tile(atlas_id, diag_id, x, tile_z, orientation))
+            .or_else(|| self.collection.get_tile(atlas_id, layer_id, x, tile_z, orientation));
+
+        // only tiles synthesized from `depth_extend` or placed by the projection are owned
+        let tile = tile.map(Cow::Borrowed).or_else(|| {
+            let info = &self.collection.get(atlas_id)?.atlas_info;
+            let layer = info.layers.get(layer_id)?;
+            let extended = self.render_config.depth_extend.get(layer_id).and_then(|extend| extend.synthesize(layer, x, tile_z, orientation));
+            extended.or_else(|| info.projection.unwrap_or_default().place(layer, x, tile_z, orientation)).map(Cow::Owned)
+        });
+
+        if let Some(tile) = tile {
+            let mut dest = screen_rect(&tile).offset(vec2(self.peek_offset(z), 0.0));
+            if let Some(sway) = self.render_config.sway.get(layer_id) {
+                if self.render_config.sway_walls || !layer_id.starts_with("wall-") {
+                    dest = dest.offset(sway.offset(self.time, self.view_cell(x, z)));
+                }
+            }
+            self.draw_quad(atlas_id, layer_id, tile.page, self.animated_rect(&tile, self.view_cell(x, z)), dest, self.tile_color(x, z, orientation));
+        }
+    }
+
+    /// the draw color of tiles in view row `z`, tinted by `fog`
+    pub fn depth_color(&self, z: i32) -> Color {
+        self.fog.map_or(WHITE, |fog| fog.tint(-z))
+    }
+
+    /// draws the atlas rect of `tile` into an arbitrary screen rect
+    pub fn draw_tile_at(&self, atlas_id: &str, layer_id: &str, tile: &Tile, dest: Rect) {
+        self.draw_quad(atlas_id, layer_id, tile.page, coords_rect(tile.atlas_coords_at(self.time)), dest, WHITE);
+    }
+
+    // the frame or variant of `tile` to draw for map cell `cell` at the current world time
+    fn animated_rect(&self, tile: &Tile, cell: IVec2) -> Rect {
+        let Some(animation) = &tile.animation else {
+            return coords_rect(tile.variant_coords(cell, self.variant_seed));
+        };
+        let phase = if self.render_config.animation_phase { animation.phase_offset(cell) } else { 0.0 };
+        coords_rect(tile.atlas_coords_at(self.time + phase))
+    }
+
+    /// The scale the loaded atlases were authored at.
+    ///
+    /// # Errors
+    ///
+    /// When atlases of different scales are mixed, which can't be drawn correctly.
+    pub fn asset_scale(&self) -> Result<u32, AssetScaleError> {
+        self.collection.asset_scale()
+    }
+
+    /// the area `render` draws into, the logical viewport times the asset scale
+    pub fn viewport_rect(&self) -> Rect {
+        let (width, height) = viewport_size(self.asset_scale().unwrap_or(1));
+        Rect::new(0.0, 0.0, width as f32, height as f32)
+    }
+
+    pub fn render_diagnostics(&self) -> RenderDiagnostics {
+        self.diagnostics.get()
+    }
+
+    // without `render` the checked rects only go to the capture
+    #[cfg_attr(not(feature = "render"), allow(unused_variables))]
+    fn draw_quad(&self, atlas_id: &str, layer_id: &str, page: usize, source: Rect, dest: Rect, color: Color) {
+        if !self.collection.contains_key(atlas_id) {
+            return;
+        }
+        let Some((source, dest)) = self.record_quad(atlas_id, layer_id, page, Some(source), dest, color) else {
+            return;
+        };
+
+        let mut stats = self.render_stats.get();
+        stats.quads += 1;
+        #[cfg(feature = "render")]
+        if self.submit_quad(atlas_id, page, source, dest, color) {
+            stats.draw_calls += 1;
+        }
+        self.render_stats.set(stats);
+    }
+
+    // an untextured `shape` of `color` filling `dest`, captured as a quad with an empty `atlas_id`
+    #[cfg_attr(not(feature = "render"), allow(unused_variables))]
+    fn draw_fill(&self, layer_id: &str, shape: FillShape, dest: Rect, color: Color) {
+        let Some((_, dest)) = self.record_quad("", layer_id, 0, None, dest, color) else {
+            return;
+        };
+
+        let mut stats = self.render_stats.get();
+        stats.quads += 1;
+        self.render_stats.set(stats);
+        // the batch is drawn first so the fill lands on top of what came before it
+        self.flush_batch();
+        #[cfg(feature = "render")]
+        match shape {
+            FillShape::Rect => draw_rectangle(dest.x, dest.y, dest.w, dest.h, color),
+            FillShape::Circle => draw_circle(dest.center().x, dest.center().y, dest.w.min(dest.h) / 2.0, color),
+        }
+    }
+
+    // Counts the quad in the diagnostics and records it while `capture_draws` runs. Returns the
+    // checked source and dest to draw for real, `None` when the quad was skipped or captured.
+    // Untextured quads have no `source` and are recorded with an empty one.
+    fn record_quad(&self, atlas_id: &str, layer_id: &str, page: usize, source: Option<Rect>, dest: Rect, color: Color) -> Option<(Rect, Rect)> {
+        let mut diagnostics = self.diagnostics.get();
+        let source = source.map_or(Some((Rect::default(), false)), sanitize_rect);
+        let (Some((source, source_clamped)), Some((dest, dest_clamped))) = (source, sanitize_rect(dest)) else {
+            diagnostics.skipped_quads += 1;
+            self.diagnostics.set(diagnostics);
+            return None;
+        };
+        if source_clamped || dest_clamped {
+            diagnostics.clamped_quads += 1;
+        }
+
+        diagnostics.drawn_quads += 1;
+        self.diagnostics.set(diagnostics);
+
+        if let Some(commands) = self.capture.borrow_mut().as_mut() {
+            commands.push(DrawCommand {
+                atlas_id: atlas_id.to_owned(),
+                layer_id: layer_id.to_owned(),
+                source,
+                dest,
+                color,
+                page,
+                order: self.current_order.get(),
+            });
+            return None;
+        }
+        Some((source, dest))
+    }
+
+    // draws what the batch gathered so far, so whatever is drawn next lands on top of it
+    pub(crate) fn flush_batch(&self) {
+        #[cfg(feature = "render")]
+        if self.batch.borrow_mut().as_mut().is_some_and(|batch| batch.flush()) {
+            let mut stats = self.render_stats.get();
+            stats.draw_calls += 1;
+            self.render_stats.set(stats);
+        }
+    }
+
+    // draws a quad, or adds it to the batch while `render` runs; returns whether that made a draw call
+    #[cfg(feature = "render")]
+    fn submit_quad(&self, atlas_id: &str, page: usize, source: Rect, dest: Rect, color: Color) -> bool {
+        let Some(texture) = self.collection.get(atlas_id).and_then(|atlas| atlas.textures.get(page)) else {
+            return false;
+        };
+        if let Some(batch) = self.batch.borrow_mut().as_mut() {
+            return batch.push(atlas_id, page, texture, source, dest, color);
+        }
+        draw_texture_ex(
+            texture,
+            dest.x,
+            dest.y,
+            color,
+            DrawTextureParams {
+                dest_size: Some(vec2(dest.w, dest.h)),
+                source: Some(source),
+                ..Default::default()
+            },
+        );
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::generator::{rooms_and_corridors, Rng};
+    use crate::test_support::adversarial;
+
+    fn random_world(rng: &mut Rng, seed: u64) -> AtlasWorld {
+        let generated = rooms_and_corridors(rng.range(3, 40), rng.range(3, 40), &GeneratorConfig::default(), seed);
+        let mut world = AtlasWorld::new(Player::new(generated.spawn.x, generated.spawn.y, Direction::North), generated.map, AtlasCollection::new());
+        world.player = Player::new(adversarial(rng), adversarial(rng), Direction::ALL[rng.below(Direction::ALL.len())]);
+        world
+    }
+
+    #[test]
+    fn window_stays_inside_the_line() {
+        let mut rng = Rng::new(217);
+        for _ in 0..10_000 {
+            let (center, len, extent) = (adversarial(&mut rng), adversarial(&mut rng), adversarial(&mut rng));
+            let window = window_range(center, len, extent);
+            assert!(window.start >= 0 && window.end <= extent.max(0), "{center} {len} {extent}: {window:?}");
+            assert_eq!(window.len(), len.clamp(0, extent.max(0)) as usize);
+            // a center inside the line is always shown
+            if (0..extent).contains(&center) && len > 0 {
+                assert!(window.contains(&center), "{center} {len} {extent}: {window:?}");
+            }
+        }
+    }
+
+    #[test]
+    fn movement_never_leaves_the_map() {
+        let mut rng = Rng::new(2172);
+        for seed in 0..400 {
+            let mut world = random_world(&mut rng, seed);
+            let from = ivec2(world.player.x, world.player.y);
+            for direction in Direction::ALL {
+                let to = world.player.get_dest_pos(direction);
+                if !world.map.in_bounds(to) {
+                    assert!(!world.player.can_move(&world.map, to), "{from} -> {to}");
+                }
+            }
+            let movement = [Movement::Forward, Movement::Backward, Movement::StrafeLeft, Movement::StrafeRight][rng.below(4)];
+            if let MoveResult::Moved(to) = world.move_player(movement) {
+                assert!(world.map.in_bounds(to), "{from} -> {to}");
+            }
+        }
+    }
+
+    #[test]
+    fn visibility_stays_inside_the_map() {
+        let mut rng = Rng::new(2173);
+        for seed in 0..200 {
+            let world = random_world(&mut rng, seed);
+            let origin = ivec2(world.player.x, world.player.y);
+            let radius = rng.below(12) as i32;
+            assert!(world.visible_cells(origin, radius).iter().all(|pos| world.map.in_bounds(*pos)));
+            world.line_of_sight(origin, ivec2(adversarial(&mut rng), adversarial(&mut rng)));
+            let region = IRect::new(adversarial(&mut rng), adversarial(&mut rng), adversarial(&mut rng), adversarial(&mut rng));
+            assert!(region_cell_rect(region, Rect::new(0.0, 0.0, 100.0, 100.0), origin.x, origin.y).w.is_finite());
+        }
+    }
+
+    // mangled copies of the demo map must come back as errors, never as panics or bad shapes
+    #[test]
+    fn corrupt_maps_are_errors() {
+        let bytes = std::fs::read(concat!(env!("CARGO_MANIFEST_DIR"), "/map.json")).unwrap();
+        let mut rng = Rng::new(2174);
+        for _ in 0..2_000 {
+            let mut mangled = bytes.clone();
+            match rng.below(3) {
+                0 => mangled.truncate(rng.below(bytes.len())),
+                1 => {
+                    for _ in 0..rng.range(1, 8) {
+                        let at = rng.below(mangled.len());
+                        mangled[at] = b"0123456789-,[]{}\" e"[rng.below(19)];
+                    }
+                }
+                _ => {
+                    let at = rng.below(mangled.len());
+                    mangled.drain(at..(at + rng.range(1, 40)).min(mangled.len()));
+                }
+            }
+            if let Ok(map) = AtlasMap::from_reader_auto(mangled.as_slice()) {
+                assert!(map.check_shape().is_ok());
+            }
+        }
+    }
+
+    #[test]
+    fn region_cells_are_clipped_to_the_map() {
+        let world = AtlasWorld::new(Player::new(0, 0, Direction::North), AtlasMap::empty(4, 3), AtlasCollection::new());
+        // a region hanging off the top left corner, painted at 10x10 per cell
+        let cells: Vec<(IVec2, Rect)> = world.region_cells(IRect::new(-1, -1, 4, 3), Rect::new(0.0, 0.0, 40.0, 30.0)).collect();
+        let positions: Vec<IVec2> = cells.iter().map(|(pos, _)| *pos).collect();
+        assert_eq!(positions, [ivec2(0, 0), ivec2(1, 0), ivec2(2, 0), ivec2(0, 1), ivec2(1, 1), ivec2(2, 1)]);
+        assert_eq!(cells[0].1, Rect::new(10.0, 10.0, 10.0, 10.0));
+        assert_eq!(cells[5].1, Rect::new(30.0, 20.0, 10.0, 10.0));
+
+        assert_eq!(world.region_cells(IRect::new(4, 0, 2, 2), Rect::new(0.0, 0.0, 20.0, 20.0)).count(), 0);
+        assert_eq!(world.region_cells(IRect::new(i32::MIN, i32::MIN, i32::MAX, i32::MAX), Rect::new(0.0, 0.0, 1.0, 1.0)).count(), 0);
+        assert_eq!(world.region_cells(IRect::new(0, 0, 0, 0), Rect::new(0.0, 0.0, 1.0, 1.0)).count(), 0);
+    }
+
+    #[test]
+    fn filled_rects_are_clipped_to_the_map() {
+        let mut map = AtlasMap::empty(4, 3);
+        assert_eq!(map.fill_rect("wall", IRect::new(-1, -1, 3, 3), 1), 4);
+        assert_eq!(map.wall.get(1, 1), Some(1));
+        assert_eq!(map.fill_rect("wall", IRect::new(2, 1, i32::MAX, i32::MAX), 2), 4);
+        assert_eq!(map.fill_rect("wall", IRect::new(i32::MAX - 1, i32::MAX - 1, i32::MAX, i32::MAX), 3), 0);
+        assert_eq!(map.fill_rect("wall", IRect::new(i32::MIN, i32::MIN, i32::MAX, i32::MAX), 3), 0);
+        assert_eq!(map.fill_rect("wall", IRect::new(i32::MIN, i32::MIN, -1, -1), 3), 0);
+        assert_eq!(map.fill_rect("nothing", IRect::new(0, 0, 4, 3), 3), 0);
+        assert_eq!(map.wall.get(3, 2), Some(2));
+    }
+
+    #[test]
+    fn footprints_past_the_i32_limits_are_left_out() {
+        let def = ObjectDef { footprint: vec![[1, 0], [i32::MAX, 0], [0, i32::MIN]], ..Default::default() };
+        assert_eq!(def.cells(ivec2(1, 1)).collect::<Vec<_>>(), [ivec2(1, 1), ivec2(2, 1), ivec2(1, i32::MIN + 1)]);
+        assert_eq!(def.cells(ivec2(i32::MAX, i32::MIN)).collect::<Vec<_>>(), [ivec2(i32::MAX, i32::MIN)]);
+
+        let mut map = AtlasMap::empty(3, 3);
+        map.object.set(1, 1, 4);
+        let footprints = [(4, def.footprint.as_slice())];
+        assert_eq!(covering_object(&map, ivec2(2, 1), footprints), Some((4, ivec2(1, 1))));
+        assert_eq!(covering_object(&map, ivec2(i32::MIN, i32::MAX), footprints), None);
+        assert_eq!(covering_object(&map, ivec2(i32::MAX, i32::MIN), footprints), None);
+    }
+
+    #[test]
+    fn bindings_make_every_variant_id_up_front() {
+        let binding = LayerBinding::new("common_objects", "object-{}");
+        assert_eq!((binding.layer(7).id(), binding.layer(7).diag()), ("object-7", "object-7@diag"));
+        assert_eq!(binding.layer_id(7), "object-7");
+        assert_eq!(binding.pit_layer(2).id(), "object-2-pit");
+        assert_eq!(binding.raised_layer(2).diag(), "object-2-raised@diag");
+        assert_eq!(binding.object_layer(3, Some(Orientation::Left), CellAnchor::Center).id(), "object-3@left");
+        assert_eq!(binding.object_layer(3, Some(Orientation::Back), CellAnchor::FarEdge).id(), "object-3@back@far");
+        assert_eq!(binding.object_layer(3, None, CellAnchor::NearEdge).diag(), "object-3@near@diag");
+    }
+
+    #[test]
+    fn floor_tiles_fill_their_cells() {
+        let mut map = AtlasMap::empty(3, 2);
+        map.floor.fill(1);
+        let mut world = crate::test_support::demo_world(Player::new(0, 0, Direction::North), map);
+        let style = || RegionStyle {
+            fill: RegionFill::FloorTiles { atlas_id: "dungeon".to_owned(), wall_color: GRAY },
+            border: None,
+            player_color: None,
+        };
+        let (region, dest) = (IRect::new(1, 0, 2, 2), Rect::new(100.0, 50.0, 16.0, 16.0));
+        let commands = world.capture_draws(|world| world.draw_map_region(region, dest, style()));
+        let cells: Vec<Rect> = world.region_cells(region, dest).map(|(_, cell)| cell).collect();
+        assert_eq!(commands.iter().map(|command| command.dest).collect::<Vec<_>>(), cells);
+        assert!(commands.iter().all(|command| command.atlas_id == "dungeon" && command.layer_id == "floor-1"));
+        assert_eq!(cells[0], Rect::new(100.0, 50.0, 8.0, 8.0));
+
+        // the layer comes from the floor binding
+        world.render_bindings.bind("floor", "dungeon", "ceiling-{}");
+        let commands = world.capture_draws(|world| world.draw_map_region(region, dest, style()));
+        assert!(commands.iter().all(|command| command.layer_id == "ceiling-1"));
+    }
+
+    #[test]
+    fn region_fills_borders_and_player_are_captured() {
+        let mut map = AtlasMap::empty(3, 2);
+        map.floor.fill(1);
+        map.wall.set(0, 0, 1);
+        let world = crate::test_support::demo_world(Player::new(1, 1, Direction::North), map);
+        let style = RegionStyle {
+            fill: RegionFill::Colors { wall: [(1, GRAY)].into_iter().collect(), floor: [(1, WHITE)].into_iter().collect() },
+            border: Some((2.0, BLACK)),
+            player_color: Some(GREEN),
+        };
+        let commands = world.capture_draws(|world| world.draw_map_region(IRect::new(0, 0, 3, 2), Rect::new(0.0, 0.0, 30.0, 20.0), style));
+        assert!(commands.iter().all(|command| command.atlas_id.is_empty() && command.source == Rect::default()));
+        let layer = |layer_id: &str| commands.iter().filter(|command| command.layer_id == layer_id).collect::<Vec<_>>();
+
+        let fills = layer("fill");
+        assert_eq!(fills.len(), 6);
+        assert_eq!((fills[0].dest, fills[0].color), (Rect::new(0.0, 0.0, 10.0, 10.0), GRAY));
+        assert!(fills[1..].iter().all(|fill| fill.color == WHITE));
+
+        let borders = layer("border");
+        assert_eq!(borders.len(), 24);
+        assert_eq!(borders[..4].iter().map(|border| border.dest).collect::<Vec<_>>(), [
+            Rect::new(0.0, 0.0, 10.0, 1.0),
+            Rect::new(0.0, 9.0, 10.0, 1.0),
+            Rect::new(0.0, 1.0, 1.0, 8.0),
+            Rect::new(9.0, 1.0, 1.0, 8.0),
+        ]);
+
+        let player = layer("player");
+        assert_eq!(player.len(), 1);
+        assert_eq!((player[0].dest.center(), player[0].color), (vec2(15.0, 15.0), GREEN));
+        assert!((player[0].dest.w - 20.0 / 3.0).abs() < 1e-4);
+        assert_eq!(commands.last(), Some(player[0]));
+    }
+
+    // the same layers inserted in opposite orders
+    fn layered_maps() -> [AtlasMap; 2] {
+        let names = ["zeta", "doors", "alpha", "trigger", "mid"];
+        [names.to_vec(), names.iter().rev().copied().collect()].map(|names| {
+            let mut map = AtlasMap::empty(2, 2);
+            for name in names {
+                map.layers.insert(name.to_owned(), Grid::filled(2, 2, name.len() as u8));
+            }
+            map
+        })
+    }
+
+    #[test]
+    fn maps_serialize_byte_for_byte() {
+        let [a, b] = layered_maps();
+        let (a, b) = (serde_json::to_string(&a).unwrap(), serde_json::to_string(&b).unwrap());
+        assert_eq!(a, b);
+        let at = |name: &str| a.find(&format!("\"{name}\"")).unwrap();
+        assert!(at("alpha") < at("doors") && at("doors") < at("mid") && at("mid") < at("trigger") && at("trigger") < at("zeta"));
+        assert_eq!(serde_json::from_str::<AtlasMap>(&a).unwrap(), layered_maps()[0]);
+    }
+
+    #[test]
+    fn atlases_serialize_byte_for_byte() {
+        let json = std::fs::read(concat!(env!("CARGO_MANIFEST_DIR"), "/mansion.json")).unwrap();
+        let mut infos: Vec<AtlasInfo> = (0..2).map(|_| serde_json::from_slice(&json).unwrap()).collect();
+        // a layer added to one and not the other, then removed, leaves the table laid out differently
+        infos[1].add_layer("zz-scratch", Tiles::new(0, 0));
+        infos[1].layers.remove("zz-scratch");
+        let written: Vec<String> = infos.iter().map(|info| serde_json::to_string(info).unwrap()).collect();
+        assert_eq!(written[0], written[1]);
+
+        let names: Vec<&String> = infos[0].layers_sorted().map(|(name, _)| name).collect();
+        assert_eq!(names, ["ceiling-1", "floor-1", "wall-1", "wall-2"]);
+
+        let collection = crate::test_support::demo_collection();
+        let ids: Vec<&String> = collection.atlases_sorted().map(|(atlas_id, _)| atlas_id).collect();
+        assert_eq!(ids, ["common_objects", "dungeon"]);
+    }
+
+    // a floor and a wall layer built in code, cut from the top left of mansion.png
+    fn built_atlas() -> AtlasInfo {
+        let coords = Coords { x: 0, y: 0, w: 32, h: 32 };
+        let mut floor = Tiles::new(0, Tiles::FLOOR_TYPE);
+        floor.push_tile(Tile::new(coords, coords, 0, 0, None)).unwrap();
+        floor.push_tile(Tile::new(Coords { x: 32, ..coords }, coords, 0, -1, None)).unwrap();
+        let mut wall = Tiles::new(0, 0);
+        wall.push_tile(Tile::new(coords, coords, 0, -1, Some(Orientation::Front))).unwrap();
+        wall.push_tile(Tile::new(coords, coords, -1, 0, Some(Orientation::Left))).unwrap();
+
+        let mut info = AtlasInfo::new();
+        assert!(info.add_layer("floor-1", floor).is_none());
+        assert!(info.add_layer("wall-1", wall).is_none());
+        info
+    }
+
+    #[test]
+    fn pushed_tiles_are_checked_and_found() {
+        let coords = Coords { x: 0, y: 0, w: 8, h: 8 };
+        let mut tiles = Tiles::new(0, 0);
+        tiles.push_tile(Tile::new(coords, coords, 1, -2, Some(Orientation::Right))).unwrap();
+        // another face of the same cell is fine, the same face again isn't
+        tiles.push_tile(Tile::new(coords, coords, 1, -2, Some(Orientation::Front))).unwrap();
+        let err = tiles.push_tile(Tile::new(coords, coords, 1, -2, Some(Orientation::Right))).unwrap_err();
+        assert_eq!((err.x, err.z, err.orientation), (1, -2, Some(Orientation::Right)));
+        assert_eq!(tiles.tiles().len(), 2);
+        assert!(tiles.find(1, -2, Some(Orientation::Front)).is_some());
+        assert!(tiles.find(1, -2, Some(Orientation::Left)).is_none());
+    }
+
+    #[test]
+    fn edited_tiles_are_found_where_they_now_are() {
+        let coords = Coords { x: 0, y: 0, w: 8, h: 8 };
+        let atlas_coords = |x| Coords { x, ..coords };
+        let mut tiles = Tiles::from_tiles(0, 0, vec![
+            Tile::new(atlas_coords(0), coords, 0, 0, None),
+            Tile::new(atlas_coords(1), coords, 1, 0, Some(Orientation::Front)),
+            Tile::new(atlas_coords(2), coords, 1, 0, None),
+        ]);
+        let found = |tiles: &Tiles, x, z, orientation| tiles.find(x, z, orientation).map(|tile| tile.atlas_coords.x);
+        // the first match in list order wins, oriented or not
+        assert_eq!(found(&tiles, 1, 0, Some(Orientation::Front)), Some(1));
+        assert_eq!(found(&tiles, 1, 0, Some(Orientation::Back)), Some(2));
+        assert_eq!(found(&tiles, 1, 0, None), Some(2));
+
+        // moved in place without changing the length
+        tiles.tiles_mut()[0].x = 5;
+        assert_eq!(found(&tiles, 0, 0, None), None);
+        assert_eq!(found(&tiles, 5, 0, None), Some(0));
+
+        tiles.tiles_mut().swap(1, 2);
+        assert_eq!(found(&tiles, 1, 0, Some(Orientation::Front)), Some(2));
+
+        let moved = {
+            let mut edit = tiles.tiles_mut();
+            let mut tile = edit.remove(0);
+            tile.z = -3;
+            edit.push(tile);
+            edit.len()
+        };
+        assert_eq!(moved, 3);
+        assert_eq!(found(&tiles, 5, 0, None), None);
+        assert_eq!(found(&tiles, 5, -3, None), Some(0));
+
+        // a layer deserialized on its own scans until it is indexed, and both agree
+        let mut reloaded: Tiles = serde_json::from_value(serde_json::to_value(&tiles).unwrap()).unwrap();
+        let queries = [(1, 0, Some(Orientation::Front)), (1, 0, Some(Orientation::Left)), (1, 0, None), (5, -3, None)];
+        let scanned: Vec<_> = queries.iter().map(|(x, z, orientation)| found(&reloaded, *x, *z, *orientation)).collect();
+        reloaded.build_index();
+        let indexed: Vec<_> = queries.iter().map(|(x, z, orientation)| found(&reloaded, *x, *z, *orientation)).collect();
+        assert_eq!(scanned, [Some(2), Some(2), Some(2), Some(0)]);
+        assert_eq!(indexed, scanned);
+    }
+
+    #[test]
+    fn built_atlases_round_trip_through_json() {
+        let json = serde_json::to_vec(&built_atlas()).unwrap();
+        let reloaded: AtlasInfo = serde_json::from_slice(&json).unwrap();
+        assert_eq!(reloaded.layers["floor-1"].name, "floor-1");
+        assert_eq!(reloaded.layers["floor-1"].r#type, Tiles::FLOOR_TYPE);
+        assert_eq!(reloaded.layers["wall-1"].find(-1, 0, Some(Orientation::Left)).map(|tile| tile.x), Some(-1));
+        assert_eq!(serde_json::to_vec(&reloaded).unwrap(), json);
+    }
+
+    // loading makes textures, which needs a window when rendering
+    #[cfg(not(feature = "render"))]
+    #[test]
+    fn built_atlases_load_like_authored_ones() {
+        let json = serde_json::to_vec(&built_atlas()).unwrap();
+        let image = std::fs::read(concat!(env!("CARGO_MANIFEST_DIR"), "/mansion.png")).unwrap();
+        let mut collection = AtlasCollection::new();
+        collection.load_from_bytes("built", &image, &json).unwrap();
+        assert!(collection["built"].findings.is_empty());
+        assert_eq!(collection.get_tile("built", "floor-1", 0, -1, None).map(|tile| tile.atlas_coords.x), Some(32));
+        assert!(collection.get_tile("built", "wall-1", 0, -1, Some(Orientation::Front)).is_some());
+    }
+
+    // the object quads drawn with object 1 anchored at `anchor` `z` rows ahead of a player
+    // looking north up a 3x5 room, as (layer id, dest)
+    fn object_draws(anchor: CellAnchor, z: i32, extra_layer: Option<&str>) -> Vec<(String, Rect)> {
+        object_commands(&object_world(anchor, z, extra_layer)).into_iter()
+            .map(|command| (command.layer_id, command.dest))
+            .collect()
+    }
+
+    fn object_commands(world: &AtlasWorld) -> Vec<DrawCommand> {
+        world.capture_commands().into_iter().filter(|command| command.atlas_id == "common_objects").collect()
+    }
+
+    fn object_world(anchor: CellAnchor, z: i32, extra_layer: Option<&str>) -> AtlasWorld {
+        let mut map = AtlasMap::empty(3, 5);
+        map.object.set(1, (4 + z) as usize, 1);
+        let mut world = crate::test_support::demo_world(Player::new(1, 4, Direction::North), map);
+        world.object_defs.insert(1, ObjectDef { depth_anchor: anchor, ..Default::default() });
+        if let Some(layer_id) = extra_layer {
+            let objects = &mut world.collection.get_mut("common_objects").unwrap().atlas_info;
+            let mut tiles = objects.layers["object-1"].clone();
+            tiles.tiles_mut().iter_mut().for_each(|tile| tile.screen_coords.y += 3);
+            objects.add_layer(layer_id, tiles);
+        }
+        world
+    }
+
+    #[test]
+    fn near_edge_objects_in_the_players_row_are_behind_the_camera() {
+        assert!(!object_draws(CellAnchor::Center, 0, None).is_empty());
+        assert!(object_draws(CellAnchor::NearEdge, 0, None).is_empty());
+    }
+
+    #[test]
+    fn anchored_objects_without_their_own_art_borrow_the_next_row() {
+        assert_eq!(object_draws(CellAnchor::NearEdge, -2, None), object_draws(CellAnchor::Center, -1, None));
+        assert_eq!(object_draws(CellAnchor::FarEdge, -1, None), object_draws(CellAnchor::Center, -2, None));
+        assert_ne!(object_draws(CellAnchor::Center, -1, None), object_draws(CellAnchor::Center, -2, None));
+    }
+
+    #[test]
+    fn borrowed_rows_only_lend_their_screen_rect() {
+        let fogged = |anchor, z| {
+            let mut world = object_world(anchor, z, None);
+            world.fog = Some(FogSettings { color: BLACK, start_depth: 0, end_depth: 4 });
+            world.peek_shift.set(0.25);
+            world
+        };
+        // the near edge of row -2 is drawn with row -1's tile but fogged and peeked as row -2
+        let world = fogged(CellAnchor::NearEdge, -2);
+        let peek = world.peek_offset(-2) - world.peek_offset(-1);
+        assert_ne!(peek, 0.0);
+        let (borrowed, own, lender) = (object_commands(&world), object_commands(&fogged(CellAnchor::Center, -2)), object_commands(&fogged(CellAnchor::Center, -1)));
+        assert!(!borrowed.is_empty() && borrowed.len() == lender.len());
+        for ((borrowed, own), lender) in borrowed.iter().zip(&own).zip(&lender) {
+            assert_eq!(borrowed.color, own.color);
+            assert_ne!(borrowed.color, lender.color);
+            assert_eq!(borrowed.source, lender.source);
+            assert_eq!(borrowed.dest, lender.dest.offset(vec2(peek, 0.0)));
+        }
+    }
+
+    #[test]
+    fn anchored_layers_are_used_when_the_atlas_has_them() {
+        let near = object_draws(CellAnchor::NearEdge, -2, Some("object-1@near"));
+        let center: Vec<(String, Rect)> = object_draws(CellAnchor::Center, -2, None);
+        assert_eq!(near.len(), center.len());
+        for ((layer_id, dest), (_, center)) in near.iter().zip(&center) {
+            assert_eq!(layer_id, "object-1@near");
+            assert_eq!(dest.y, center.y + 3.0);
+        }
+        let far = object_draws(CellAnchor::FarEdge, -2, Some("object-1@far"));
+        assert!(far.iter().all(|(layer_id, _)| layer_id == "object-1@far"));
+    }
+
+    #[test]
+    fn compacted_atlases_reload_to_the_same_tiles() {
+        let json = std::fs::read(concat!(env!("CARGO_MANIFEST_DIR"), "/mansion.json")).unwrap();
+        let original: AtlasInfo = serde_json::from_slice(&json).unwrap();
+        let mut compacted: AtlasInfo = serde_json::from_slice(&json).unwrap();
+        assert!(compacted.compact_with_templates() > 0);
+        assert!(!compacted.templates.is_empty());
+
+        let written = serde_json::to_vec(&compacted).unwrap();
+        assert!(written.len() < serde_json::to_vec(&original).unwrap().len());
+        let mut reloaded: AtlasInfo = serde_json::from_slice(&written).unwrap();
+        reloaded.templates.clear();
+        assert_eq!(serde_json::to_value(&reloaded).unwrap(), serde_json::to_value(&original).unwrap());
+    }
+
+    fn atlas_json(tiles: &str) -> String {
+        format!(
+            r#"{{"templates": [{{"x": 0, "z": -1, "orientation": "front", "screen_coords": {{"x": 10, "y": 20, "w": 30, "h": 40}}}}],
+                "layers": {{"wall-1": {{"mode": 0, "name": "wall-1", "type": 0, "tiles": [{tiles}]}}}}}}"#
+        )
+    }
+
+    #[test]
+    fn templates_fill_in_missing_screen_coords() {
+        let own = r#"{"atlas_coords": {"x": 0, "y": 0, "w": 8, "h": 8}, "screen_coords": {"x": 1, "y": 2, "w": 3, "h": 4}, "x": 0, "z": -1, "orientation": "front"}"#;
+        let templated = r#"{"atlas_coords": {"x": 8, "y": 0, "w": 8, "h": 8}, "x": 0, "z": -1, "orientation": "front"}"#;
+        let info: AtlasInfo = serde_json::from_str(&atlas_json(&format!("{templated}, {own}"))).unwrap();
+        let tiles = info.layers["wall-1"].tiles();
+        assert_eq!(tiles[0].screen_coords, Coords { x: 10, y: 20, w: 30, h: 40 });
+        // a tile's own rect wins over the template
+        assert_eq!(tiles[1].screen_coords, Coords { x: 1, y: 2, w: 3, h: 4 });
+    }
+
+    #[test]
+    fn tiles_without_screen_coords_or_a_template_are_errors() {
+        // the template is for the front face only
+        let untemplated = r#"{"atlas_coords": {"x": 0, "y": 0, "w": 8, "h": 8}, "x": 0, "z": -1, "orientation": "back"}"#;
+        let err = serde_json::from_str::<AtlasInfo>(&atlas_json(untemplated)).err().unwrap().to_string();
+        assert!(err.contains("layer 'wall-1': tile at x 0 z -1 orientation Some(Back) has no screen_coords"), "{err}");
+
+        let misnamed = r#"{"atlas_coords": {"x": 0, "y": 0, "w": 8, "h": 8}, "x": 0, "z": -1, "orientation": "up"}"#;
+        let err = serde_json::from_str::<AtlasInfo>(&atlas_json(misnamed)).err().unwrap().to_string();
+        assert!(err.contains("unknown orientation 'up'"), "{err}");
+    }
+
+    // fixtures/scenes/archway.json with the player standing in the archway, and wall-2 given
+    // faces for the player's own cell that cover the whole view
+    fn archway_world() -> AtlasWorld {
+        let file = std::fs::File::open(crate::test_support::manifest_path("fixtures/scenes/archway.json")).unwrap();
+        let mut world = crate::test_support::demo_world(Player::new(2, 2, Direction::North), AtlasMap::from_reader_auto(file).unwrap());
+        let screen = Coords { x: 0, y: 0, w: VIEWPORT_WIDTH, h: VIEWPORT_HEIGHT };
+        let wall = world.collection.get_mut("dungeon").unwrap().atlas_info.layers.get_mut("wall-2").unwrap();
+        for orientation in [Orientation::Front, Orientation::Left, Orientation::Right] {
+            wall.push_tile(Tile::new(Coords { x: 0, y: 0, w: 32, h: 32 }, screen, 0, 0, Some(orientation))).unwrap();
+        }
+        world
+    }
+
+    fn own_cell_walls(world: &AtlasWorld) -> Vec<DrawCommand> {
+        world.capture_commands().into_iter()
+            .filter(|command| world.render_config.draw_order.cell(command.order) == IVec2::ZERO && command.layer_id.starts_with("wall-"))
+            .collect()
+    }
+
+    #[test]
+    fn archway_walls_are_skipped_in_the_players_cell() {
+        let mut world = archway_world();
+        // drawn unless asked for
+        world.render_config.cull_hidden_faces = false;
+        let walls = own_cell_walls(&world);
+        assert_eq!(walls.len(), 3, "front, left and right faces");
+        assert!(walls.iter().all(|command| command.dest == Rect::new(0.0, 0.0, VIEWPORT_WIDTH as f32, VIEWPORT_HEIGHT as f32)));
+
+        world.render_config.skip_own_cell_walls = true;
+        assert!(own_cell_walls(&world).is_empty());
+        // the rest of the cell and the corridor ahead are still drawn
+        let commands = world.capture_commands();
+        assert!(commands.iter().any(|command| world.render_config.draw_order.cell(command.order) == IVec2::ZERO && command.layer_id == "floor-1"));
+        assert!(commands.iter().any(|command| command.layer_id == "wall-1"));
+    }
+
+    // the near side wall quads of fixtures/scenes/corridor.json as (source, dest), with the
+    // near side tiles pushed 16px out past the edges of the view
+    fn near_side_walls(mode: NearSideWalls) -> Vec<(Rect, Rect)> {
+        let file = std::fs::File::open(crate::test_support::manifest_path("fixtures/scenes/corridor.json")).unwrap();
+        let mut world = crate::test_support::demo_world(Player::new(2, 6, Direction::North), AtlasMap::from_reader_auto(file).unwrap());
+        world.render_config.near_side_walls = mode;
+        let wall = world.collection.get_mut("dungeon").unwrap().atlas_info.layers.get_mut("wall-1").unwrap();
+        for tile in wall.tiles_mut().iter_mut().filter(|tile| tile.z == 0) {
+            tile.screen_coords.x += tile.x * 16;
+        }
+        world.capture_commands().into_iter()
+            .filter(|command| command.layer_id == "wall-1" && {
+                let cell = world.render_config.draw_order.cell(command.order);
+                cell.y == 0 && cell.x.abs() == 1
+            })
+            .map(|command| (command.source, command.dest))
+            .collect()
+    }
+
+    #[test]
+    fn near_side_walls_follow_their_mode() {
+        assert_eq!(near_side_walls(NearSideWalls::Draw), [
+            (Rect::new(312.0, 496.0, 64.0, 256.0), Rect::new(-16.0, 0.0, 64.0, 256.0)),
+            (Rect::new(378.0, 496.0, 64.0, 256.0), Rect::new(272.0, 0.0, 64.0, 256.0)),
+        ]);
+        assert_eq!(near_side_walls(NearSideWalls::Skip), []);
+        // the part past the edge is cut from both rects
+        assert_eq!(near_side_walls(NearSideWalls::ClipToViewport), [
+            (Rect::new(328.0, 496.0, 48.0, 256.0), Rect::new(0.0, 0.0, 48.0, 256.0)),
+            (Rect::new(378.0, 496.0, 48.0, 256.0), Rect::new(272.0, 0.0, 48.0, 256.0)),
+        ]);
+    }
+
+    // a 5x5 room walled all round, the player in its north west corner facing north
+    fn corner_world(config: CornerConfig) -> AtlasWorld {
+        let mut map = AtlasMap::empty(5, 5);
+        map.wall.fill(1);
+        for y in 1..4 {
+            for x in 1..4 {
+                map.wall.set(x, y, 0);
+            }
+        }
+        let mut player = Player::new(1, 1, Direction::North);
+        player.corner_config = config;
+        AtlasWorld::new(player, map, AtlasCollection::new())
+    }
+
+    #[test]
+    fn quick_turns_are_one_about_face_event() {
+        let mut world = corner_world(CornerConfig::default());
+        world.take_turn(PlayerAction::Turn(Turn::Around));
+        assert_eq!(world.player.direction, Direction::South);
+        let turns: Vec<WorldEvent> = world.poll_events().filter(|event| matches!(event, WorldEvent::Turned { .. })).collect();
+        assert_eq!(turns, [WorldEvent::Turned { from: Direction::North, to: Direction::South, about_face: true }]);
+    }
+
+    #[cfg(feature = "render")]
+    #[test]
+    fn quick_turns_and_corners_are_bound() {
+        let input = InputMap::default();
+        for action in [PlayerAction::Turn(Turn::Around), PlayerAction::CornerLeft, PlayerAction::CornerRight] {
+            assert!(!input.keys(&HudAction::Player(action)).is_empty(), "{action:?} is unbound");
+        }
+    }
+
+    // with `diagonal` a corner turns by 45 degrees and steps diagonally
+    #[test]
+    fn corners_turn_and_step_as_one_move() {
+        let mut world = corner_world(CornerConfig::default());
+        world.player.x = 2;
+        world.player.y = 2;
+        let east = Direction::North.turn_right();
+        assert_eq!(world.corner_right(), MoveResult::Moved(ivec2(2, 2) + east.to_vec()));
+        assert_eq!(world.player.direction, east);
+        let events: Vec<WorldEvent> = world.poll_events().filter(|event| !matches!(event, WorldEvent::Moved { .. })).collect();
+        assert_eq!(events, [WorldEvent::Turned { from: Direction::North, to: east, about_face: false }]);
+
+        let mut world = corner_world(CornerConfig { order: CornerOrder::StepThenTurn, turn_when_blocked: true });
+        world.player.x = 2;
+        world.player.direction = Direction::South;
+        assert_eq!(world.corner_left(), MoveResult::Moved(ivec2(2, 2)));
+        assert_eq!(world.player.direction, Direction::South.turn_left());
+    }
+
+    #[test]
+    fn blocked_corners_keep_the_turn_only_when_configured() {
+        let west = Direction::North.turn_left();
+        let wall = ivec2(1, 1) + west.to_vec();
+        let bumped = WorldEvent::WallBumped { pos: wall, value: 1, sound: "bump".to_owned() };
+
+        let mut world = corner_world(CornerConfig::default());
+        assert_eq!(world.corner_left(), MoveResult::Blocked(wall));
+        assert_eq!((world.player.x, world.player.y, world.player.direction), (1, 1, west));
+        assert_eq!(world.poll_events().collect::<Vec<_>>(), [
+            WorldEvent::Turned { from: Direction::North, to: west, about_face: false },
+            bumped.clone(),
+        ]);
+
+        let mut world = corner_world(CornerConfig { order: CornerOrder::TurnThenStep, turn_when_blocked: false });
+        assert_eq!(world.corner_left(), MoveResult::Blocked(wall));
+        assert_eq!(world.player.direction, Direction::North);
+        assert_eq!(world.poll_events().collect::<Vec<_>>(), [bumped]);
+
+        // stepping first, the wall ahead stops the corner before it turns
+        let mut world = corner_world(CornerConfig { order: CornerOrder::StepThenTurn, turn_when_blocked: false });
+        assert_eq!(world.corner_left(), MoveResult::Blocked(ivec2(1, 0)));
+        assert_eq!(world.player.direction, Direction::North);
+        let mut world = corner_world(CornerConfig { order: CornerOrder::StepThenTurn, turn_when_blocked: true });
+        assert_eq!(world.corner_left(), MoveResult::Blocked(ivec2(1, 0)));
+        assert_eq!(world.player.direction, west);
+    }
+
+    #[cfg(feature = "diagonal")]
+    #[test]
+    fn diagonal_steps_cannot_cut_between_two_walls() {
+        let mut map = AtlasMap::empty(3, 3);
+        map.wall.set(2, 1, 1);
+        let mut player = Player::new(1, 1, Direction::North);
+        // one wall beside the corner still leaves room to pass
+        assert_eq!(player.step(&map, Direction::NorthEast), MoveResult::Moved(ivec2(2, 0)));
+
+        map.wall.set(1, 0, 1);
+        let mut player = Player::new(1, 1, Direction::North);
+        assert_eq!(player.step(&map, Direction::NorthEast), MoveResult::Blocked(ivec2(2, 0)));
+        assert_eq!(player.step(&map, Direction::SouthWest), MoveResult::Moved(ivec2(0, 2)));
+    }
+
+    #[cfg(feature = "diagonal")]
+    #[test]
+    fn diagonal_facings_are_drawn_from_the_nearest_cardinal_without_diag_art() {
+        let mut world = crate::test_support::demo_world(Player::new(2, 2, Direction::NorthEast), AtlasMap::empty(5, 5));
+        assert_eq!(world.view_direction(), Direction::North);
+
+        let dungeon = &mut world.collection.get_mut("dungeon").unwrap().atlas_info;
+        let floor = dungeon.layers["floor-1"].clone();
+        dungeon.add_layer("floor-1@diag", floor);
+        world.collection.get_mut("dungeon").unwrap().revalidate("dungeon");
+        assert_eq!(world.view_direction(), Direction::NorthEast);
+        world.map.floor.fill(1);
+        assert!(world.capture_commands().iter().any(|command| command.layer_id == "floor-1"));
+    }
+
+    // a long corridor of floor-1 with the tiles past row 4 cut from the atlas, and
+    // `depth_extend` on it when `extend` is set
+    fn extended_floor(extend: bool) -> AtlasWorld {
+        let mut map = AtlasMap::empty(1, 12);
+        map.floor.fill(1);
+        let mut world = crate::test_support::demo_world(Player::new(0, 11, Direction::North), map);
+        let floor = world.collection.get_mut("dungeon").unwrap().atlas_info.layers.get_mut("floor-1").unwrap();
+        floor.tiles_mut().retain(|tile| tile.z >= -4);
+        if extend {
+            world.render_config.depth_extend.insert("floor-1".to_owned(), DepthExtend {
+                vanishing_point: vec2(160.0, 80.0),
+                row_scale: vec![1.0, 0.8, 0.6, 0.45, 0.3, 0.2, 0.12],
+            });
+        }
+        world
+    }
+
+    fn floor_rows(world: &AtlasWorld) -> Vec<(i32, Rect, Rect)> {
+        world.capture_commands().into_iter()
+            .filter(|command| command.layer_id == "floor-1")
+            .map(|command| (world.render_config.draw_order.cell(command.order).y, command.source, command.dest))
+            .collect()
+    }
+
+    #[test]
+    fn depth_extend_shrinks_the_deepest_row_toward_the_vanishing_point() {
+        let plain = floor_rows(&extended_floor(false));
+        assert_eq!(plain.iter().map(|(z, _, _)| *z).min(), Some(-4));
+
+        let extended = floor_rows(&extended_floor(true));
+        let row_4 = plain.iter().find(|(z, _, _)| *z == -4).unwrap();
+        // rows 5 and 6 reuse row 4's art at 0.2 / 0.3 and 0.12 / 0.3 of its size, row 7 is past the table
+        assert_eq!(extended[..2], [
+            (-6, row_4.1, Rect::new(149.0, 90.0, 22.0, 3.0)),
+            (-5, row_4.1, Rect::new(142.0, 97.0, 36.0, 5.0)),
+        ]);
+        assert_eq!(extended[2..], plain[..]);
+    }
+
+    #[test]
+    fn degenerate_rects_are_dropped_and_absurd_ones_clamped() {
+        let rect = Rect::new(1.0, 2.0, 3.0, 4.0);
+        assert_eq!(sanitize_rect(rect), Some((rect, false)));
+        for broken in [Rect::new(0.0, 0.0, 0.0, 4.0), Rect::new(0.0, 0.0, 3.0, -1.0), Rect::new(f32::NAN, 0.0, 3.0, 4.0), Rect::new(0.0, 0.0, f32::INFINITY, 4.0)] {
+            assert_eq!(sanitize_rect(broken), None, "{broken:?}");
+        }
+        assert_eq!(sanitize_rect(Rect::new(-1e9, 0.0, 1e9, 4.0)), Some((Rect::new(-MAX_QUAD_SIZE, 0.0, MAX_QUAD_SIZE, 4.0), true)));
+    }
+
+    // corridor.json drawn with some of its tiles broken by `breaks`
+    fn corridor_draws(breaks: impl FnOnce(&mut AtlasInfo)) -> (Vec<DrawCommand>, RenderDiagnostics, PreflightReport) {
+        let file = std::fs::File::open(crate::test_support::manifest_path("fixtures/scenes/corridor.json")).unwrap();
+        let mut world = crate::test_support::demo_world(Player::new(2, 6, Direction::North), AtlasMap::from_reader_auto(file).unwrap());
+        breaks(&mut world.collection.get_mut("dungeon").unwrap().atlas_info);
+        let commands = world.capture_commands();
+        (commands, world.render_diagnostics(), world.preflight())
+    }
+
+    #[test]
+    fn broken_tiles_leave_the_rest_of_the_frame_alone() {
+        let (intact, diagnostics, _) = corridor_draws(|_| {});
+        assert_eq!((diagnostics.skipped_quads, diagnostics.clamped_quads), (0, 0));
+
+        let tile = |info: &mut AtlasInfo, layer: &str, x: i32, z: i32, orientation: Option<Orientation>| -> usize {
+            info.layers[layer].tiles().iter().position(|tile| (tile.x, tile.z, tile.orientation) == (x, z, orientation)).unwrap()
+        };
+        let (broken, diagnostics, preflight) = corridor_draws(|info| {
+            let at = tile(info, "wall-1", -1, 0, Some(Orientation::Left));
+            info.layers.get_mut("wall-1").unwrap().tiles_mut()[at].atlas_coords.w = 0;
+            let at = tile(info, "floor-1", 0, -1, None);
+            info.layers.get_mut("floor-1").unwrap().tiles_mut()[at].screen_coords.h = -5;
+            let at = tile(info, "wall-1", 0, -6, Some(Orientation::Front));
+            info.layers.get_mut("wall-1").unwrap().tiles_mut()[at].screen_coords.w = 100_000;
+        });
+        assert_eq!((diagnostics.skipped_quads, diagnostics.clamped_quads), (2, 1));
+        assert_eq!(broken.len(), intact.len() - 2);
+        assert_eq!(diagnostics.drawn_quads as usize, broken.len());
+
+        // everything else is drawn exactly as before, the oversized wall at the clamp
+        let mut intact = intact.into_iter();
+        for command in &broken {
+            let mut expected = intact.by_ref().find(|expected| expected.order == command.order && expected.layer_id == command.layer_id).unwrap();
+            if command.dest.w == MAX_QUAD_SIZE {
+                expected.dest.w = MAX_QUAD_SIZE;
+            }
+            assert_eq!(*command, expected);
+        }
+        assert!(broken.iter().any(|command| command.dest.w == MAX_QUAD_SIZE));
+
+        let degenerate: Vec<(&str, i32, i32)> = preflight.warnings.iter()
+            .filter_map(|warning| match warning {
+                PreflightWarning::DegenerateTile { layer_id, x, z, .. } => Some((layer_id.as_str(), *x, *z)),
+                _ => None,
+            })
+            .collect();
+        // in the order the atlas lists them
+        assert_eq!(degenerate, [("floor-1", 0, -1), ("wall-1", 0, -6), ("wall-1", -1, 0)]);
+    }
+
+    #[test]
+    fn sway_is_a_sinusoid_of_world_time() {
+        let sway = Sway { axis: SwayAxis::Horizontal, amplitude_px: 4.0, period: 2.0 };
+        let at = |time: f32, cell: IVec2| sway.offset(time, cell);
+        let close = |a: Vec2, b: Vec2| (a - b).length() < 1e-4;
+        // cell (0, 0) has no phase shift
+        assert!(close(at(0.0, IVec2::ZERO), Vec2::ZERO));
+        assert!(close(at(0.5, IVec2::ZERO), vec2(4.0, 0.0)));
+        assert!(close(at(1.0, IVec2::ZERO), Vec2::ZERO));
+        assert!(close(at(1.5, IVec2::ZERO), vec2(-4.0, 0.0)));
+        assert!(close(at(0.5, IVec2::ZERO), at(2.5, IVec2::ZERO)));
+
+        // neighbours are out of step, but each cell repeats itself exactly
+        let cell = ivec2(1, 0);
+        assert!(!close(at(0.5, cell), at(0.5, IVec2::ZERO)));
+        assert_eq!(at(0.7, cell), at(0.7, cell));
+        assert!(close(at(0.7, cell), at(2.7, cell)));
+        assert!(at(0.3, ivec2(3, -2)).length() <= 4.0 + 1e-4);
+
+        let vertical = Sway { axis: SwayAxis::Vertical, ..sway };
+        assert!(close(vertical.offset(0.5, IVec2::ZERO), vec2(0.0, 4.0)));
+        for period in [0.0, -1.0, f32::NAN] {
+            assert_eq!(Sway { period, ..sway }.offset(0.5, IVec2::ZERO), Vec2::ZERO);
+        }
+    }
+
+    // the dests of corridor.json's floor-1 and wall-1 quads, with both layers swaying unless
+    // `sway_walls` is `None`
+    fn swayed(sway_walls: Option<bool>) -> [Vec<Rect>; 2] {
+        let file = std::fs::File::open(crate::test_support::manifest_path("fixtures/scenes/corridor.json")).unwrap();
+        let mut world = crate::test_support::demo_world(Player::new(2, 6, Direction::North), AtlasMap::from_reader_auto(file).unwrap());
+        world.time = 0.3;
+        if let Some(sway_walls) = sway_walls {
+            let sway = Sway { axis: SwayAxis::Horizontal, amplitude_px: 3.0, period: 1.0 };
+            world.render_config.sway = ["floor-1", "wall-1"].into_iter().map(|layer_id| (layer_id.to_owned(), sway)).collect();
+            world.render_config.sway_walls = sway_walls;
+        }
+        let commands = world.capture_commands();
+        ["floor-1", "wall-1"].map(|layer_id| commands.iter().filter(|command| command.layer_id == layer_id).map(|command| command.dest).collect())
+    }
+
+    #[test]
+    fn walls_only_sway_when_asked_to() {
+        let [still_floor, still_walls] = swayed(None);
+        let [floor, walls] = swayed(Some(false));
+        assert_eq!(walls, still_walls);
+        assert_eq!(floor.len(), still_floor.len());
+        assert!(floor.iter().zip(&still_floor).all(|(swayed, still)| swayed.y == still.y && swayed.size() == still.size()));
+        assert!(floor.iter().zip(&still_floor).any(|(swayed, still)| swayed.x != still.x));
+
+        let [_, walls] = swayed(Some(true));
+        assert!(walls.iter().zip(&still_walls).any(|(swayed, still)| swayed.x != still.x));
     }
 }
@@ -0,0 +1,106 @@
+//! Light from emitters spread over the open cells around them, baked into the map's `light`
+//! layer and multiplied into tile colors when drawing. Maps without a `light` layer are drawn
+//! at full brightness.
+
+use std::collections::BinaryHeap;
+
+use crate::math::*;
+use crate::{
+    coords::{offset, GRID_EAST, GRID_NORTH, GRID_SOUTH, GRID_WEST},
+    AtlasMap, AtlasWorld, Grid, MapSource, Orientation,
+};
+
+impl AtlasWorld<AtlasMap> {
+    /// Fills the map's `light` layer from the object values in `light_emitters`: each emitter
+    /// lights its own cell and every open cell reachable from it, losing `light_falloff` per
+    /// step. Walls stop the light. Does nothing while the layer is current with the map and
+    /// emitters; returns whether it recomputed. With no emitters the layer is emptied.
+    pub fn compute_lighting(&mut self) -> bool {
+        if self.light_revision == Some(self.map_revision) {
+            return false;
+        }
+        self.light_revision = Some(self.map_revision);
+        if self.light_emitters.is_empty() {
+            self.map.light = Grid::default();
+            return true;
+        }
+
+        let (width, height) = (self.map.width, self.map.height);
+        let mut light = Grid::new(width, height);
+        let mut queue = BinaryHeap::new();
+        for (y, row) in self.map.object.iter_rows().enumerate() {
+            for (x, value) in row.iter().enumerate() {
+                if let Some(brightness) = self.light_emitters.get(value) {
+                    queue.push((*brightness, x as i32, y as i32));
+                }
+            }
+        }
+
+        // brightest first, so a cell is settled the first time it is lit
+        while let Some((brightness, x, y)) = queue.pop() {
+            if light.get(x as usize, y as usize).is_none_or(|lit| lit >= brightness) {
+                continue;
+            }
+            light.set(x as usize, y as usize, brightness);
+            let next = brightness.saturating_sub(self.light_falloff);
+            if next == 0 {
+                continue;
+            }
+            for step in [GRID_NORTH, GRID_EAST, GRID_SOUTH, GRID_WEST] {
+                let pos = offset(ivec2(x, y), step, 1);
+                if self.map.get_wall(pos) == Some(0) && light.get(pos.x as usize, pos.y as usize).is_some_and(|lit| lit < next) {
+                    queue.push((next, pos.x, pos.y));
+                }
+            }
+        }
+
+        self.map.light = light;
+        true
+    }
+}
+
+impl<M: MapSource> AtlasWorld<M> {
+    /// Makes cells holding object `value` give off `brightness`, 0 to stop them. Takes effect
+    /// at the next `compute_lighting`.
+    pub fn set_light_emitter(&mut self, value: u8, brightness: u8) {
+        if brightness == 0 {
+            self.light_emitters.remove(&value);
+        } else {
+            self.light_emitters.insert(value, brightness);
+        }
+        self.light_revision = None;
+    }
+
+    /// object value -> brightness of every emitter
+    pub fn light_emitters(&self) -> &ahash::AHashMap<u8, u8> {
+        &self.light_emitters
+    }
+
+    /// Brightness of a tile drawn in view cell (x, z) facing `orientation`, `None` when the map
+    /// has no light. A wall or closed door takes the light of the open cell in front of the face.
+    pub fn tile_brightness(&self, x: i32, z: i32, orientation: Option<Orientation>) -> Option<u8> {
+        let pos = self.view_cell(x, z);
+        let solid = self.map.get_wall(pos).is_some_and(|value| value != 0) || self.is_door_closed(pos);
+        let lit = match orientation {
+            Some(Orientation::Left | Orientation::Right) if solid => self.view_cell(x - x.signum(), z),
+            Some(Orientation::Back) if solid => self.view_cell(x, z - 1),
+            _ if solid => self.view_cell(x, z + 1),
+            _ => pos,
+        };
+        self.map.get("light", lit).map(|light| light.max(self.ambient_light))
+    }
+
+    /// `depth_color` with the light of view cell (x, z) multiplied in, and faded for a
+    /// transparent wall
+    pub fn tile_color(&self, x: i32, z: i32, orientation: Option<Orientation>) -> Color {
+        let mut color = self.depth_color(z);
+        color.a *= self.wall_alpha.get();
+        match self.tile_brightness(x, z, orientation) {
+            Some(brightness) => {
+                let scale = brightness as f32 / 255.0;
+                Color::new(color.r * scale, color.g * scale, color.b * scale, color.a)
+            }
+            None => color,
+        }
+    }
+}
@@ -0,0 +1,137 @@
+use std::borrow::Cow;
+
+use ahash::AHashMap;
+
+use crate::{AtlasWorld, MapSource};
+
+/// Turns the keys the crate hands out (object names, HUD labels, ...) into display text.
+pub trait Localizer {
+    /// unknown keys should come back unchanged
+    fn resolve<'a>(&'a self, key: &'a str) -> Cow<'a, str> {
+        Cow::Borrowed(key)
+    }
+}
+
+/// shows every key as is
+pub struct IdentityLocalizer;
+
+impl Localizer for IdentityLocalizer {}
+
+/// key -> text table, e.g. loaded from a json object per language
+impl Localizer for AHashMap<String, String> {
+    fn resolve<'a>(&'a self, key: &'a str) -> Cow<'a, str> {
+        match self.get(key) {
+            Some(text) => Cow::Borrowed(text),
+            None => Cow::Borrowed(key),
+        }
+    }
+}
+
+impl<M: MapSource> AtlasWorld<M> {
+    pub fn set_localizer<L: Localizer + 'static>(&mut self, localizer: L) {
+        self.localizer = Some(Box::new(localizer));
+    }
+
+    /// display text for `key`, the key itself without a localizer
+    pub fn resolve<'a>(&'a self, key: &'a str) -> Cow<'a, str> {
+        match &self.localizer {
+            Some(localizer) => localizer.resolve(key),
+            None => Cow::Borrowed(key),
+        }
+    }
+
+    /// the display name of an object value, if its def has one
+    pub fn object_name(&self, value: u8) -> Option<Cow<'_, str>> {
+        let key = self.object_defs.get(&value)?.name.as_deref()?;
+        Some(self.resolve(key))
+    }
+}
+
+/// Breaks `text` into lines no wider than `max_width` as reported by `measure`. Lines break at
+/// spaces where possible and otherwise between any two characters, so text without spaces
+/// (CJK) wraps too. Never splits inside a UTF-8 character.
+pub fn wrap_text(text: &str, max_width: f32, measure: impl Fn(&str) -> f32) -> Vec<String> {
+    let mut lines = Vec::new();
+
+    for paragraph in text.split('\n') {
+        let mut line = String::new();
+        for word in paragraph.split(' ') {
+            let candidate = if line.is_empty() { word.to_owned() } else { format!("{line} {word}") };
+            if measure(&candidate) <= max_width {
+                line = candidate;
+                continue;
+            }
+
+            if !line.is_empty() {
+                lines.push(std::mem::take(&mut line));
+            }
+            for c in word.chars() {
+                line.push(c);
+                if measure(&line) > max_width && line.chars().count() > 1 {
+                    line.pop();
+                    lines.push(std::mem::replace(&mut line, c.to_string()));
+                }
+            }
+        }
+        lines.push(line);
+    }
+
+    lines
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{AtlasCollection, AtlasMap, Direction, ObjectDef, Player};
+
+    // one unit per character, so widths count characters and not bytes
+    fn chars(text: &str) -> f32 {
+        text.chars().count() as f32
+    }
+
+    #[test]
+    fn keys_resolve_through_the_attached_localizer() {
+        let mut world = AtlasWorld::new(Player::new(0, 0, Direction::North), AtlasMap::empty(1, 1), AtlasCollection::new());
+        world.object_defs.insert(1, ObjectDef { name: Some("object.chest".to_owned()), ..Default::default() });
+        world.object_defs.insert(2, ObjectDef::default());
+        assert_eq!(world.resolve("object.chest"), "object.chest");
+        assert_eq!(world.object_name(1).as_deref(), Some("object.chest"));
+
+        world.set_localizer(AHashMap::from_iter([("object.chest".to_owned(), "Truhe aus Eiche".to_owned())]));
+        assert_eq!(world.object_name(1).as_deref(), Some("Truhe aus Eiche"));
+        assert_eq!(world.object_name(2), None);
+        assert_eq!(world.object_name(3), None);
+        assert_eq!(world.resolve("PAUSED"), "PAUSED");
+
+        world.set_localizer(IdentityLocalizer);
+        assert_eq!(world.object_name(1).as_deref(), Some("object.chest"));
+    }
+
+    #[test]
+    fn accented_text_wraps_at_spaces() {
+        let text = "Déjà vu: l'élève réveillé à Zürich";
+        let lines = wrap_text(text, 12.0, chars);
+        assert_eq!(lines, ["Déjà vu:", "l'élève", "réveillé à", "Zürich"]);
+        assert_eq!(lines.join(" "), text);
+    }
+
+    #[test]
+    fn cjk_text_wraps_between_characters() {
+        let text = "迷宮の奥には古い宝箱が眠っている";
+        let lines = wrap_text(text, 5.0, chars);
+        assert_eq!(lines, ["迷宮の奥に", "は古い宝箱", "が眠ってい", "る"]);
+        assert_eq!(lines.concat(), text);
+        // by bytes every character is three wide, which must not split one
+        let lines = wrap_text(text, 7.0, |line| line.len() as f32);
+        assert!(lines.iter().all(|line| line.chars().count() == 2 || line.chars().count() == 1));
+        assert_eq!(lines.concat(), text);
+    }
+
+    #[test]
+    fn paragraphs_and_overlong_characters_stay_put() {
+        assert_eq!(wrap_text("ä\nöü", 10.0, chars), ["ä", "öü"]);
+        // a single character wider than the line still gets a line of its own
+        assert_eq!(wrap_text("宝箱", 0.5, chars), ["宝", "箱"]);
+        assert_eq!(wrap_text("", 4.0, chars), [""]);
+    }
+}
@@ -1,6 +1,7 @@
 use std::io::BufReader;
 
-use atlas_world::*;
+use ahash::AHashMap;
+use atlas_world::{hud::HudContext, *};
 use macroquad::{prelude::*, ui::{root_ui, Skin, hash}};
 
 fn window_conf() -> Conf {
@@ -13,360 +14,548 @@ fn window_conf() -> Conf {
     }
 }
 
-#[macroquad::main(window_conf)]
-async fn main() {
-    let mut atlas = AtlasCollection::new();
-    atlas.load("dungeon", include_bytes!("../mansion.png"), "mansion.json");
-    atlas.load("common_objects", include_bytes!("../common_objects.png"), "common_objects.json");
-
-    let player = Player {
-        x: 1,
-        y: 1,
-        direction: 2,
-    };
-    
-    let mut show_map = false;
-
-    let f = std::fs::File::open("map.json").unwrap();
-    let file_buf = BufReader::new(f);
-    let map: AtlasMap = serde_json::from_reader(file_buf).unwrap();
-    let mut auto_map = vec![vec![false; map.width]; map.height];
-
-    let render_depth = 9;
-    let render_width = 22;
-
-    let mut fullscreen = false;
+/// The demo is a stack of states, only the top one receives input.
+/// Map view and pause are overlays, so everything underneath keeps drawing.
+enum GameState {
+    Exploring,
+    MapView,
+    Paused,
+    /// fades from black, then pops itself
+    Transitioning { elapsed: f32, duration: f32 },
+}
 
-    let screen = render_target(VIEWPORT_WIDTH as u32, VIEWPORT_HEIGHT as u32);
-    screen.texture.set_filter(FilterMode::Nearest);
+/// What the top state makes of an action.
+enum Outcome {
+    Ignore,
+    Push(GameState),
+    Pop,
+    /// the player acts in the world
+    Turn(PlayerAction),
+}
 
-    let zoom = vec2(1.0 / VIEWPORT_WIDTH as f32 * 2.0, 1.0 / VIEWPORT_HEIGHT as f32 * 2.0);
+impl GameState {
+    /// runs the state forward, returning whether it's done and comes off the stack
+    fn update(&mut self, dt: f32) -> bool {
+        match self {
+            GameState::Transitioning { elapsed, duration } => {
+                *elapsed += dt;
+                *elapsed >= *duration
+            }
+            _ => false,
+        }
+    }
 
-    let font = load_ttf_font("./assets/Minecraft.ttf").await.unwrap();
+    // pausing also backs out of the map view, and unpauses
+    fn handle(&self, action: Action) -> Outcome {
+        match (self, action) {
+            (GameState::Exploring, Action::Pause) => Outcome::Push(GameState::Paused),
+            (GameState::Exploring, Action::ToggleMap) => Outcome::Push(GameState::MapView),
+            (GameState::MapView, Action::Pause | Action::ToggleMap) | (GameState::Paused, Action::Pause) => Outcome::Pop,
+            (GameState::Exploring | GameState::MapView, Action::Player(action)) => Outcome::Turn(action),
+            _ => Outcome::Ignore,
+        }
+    }
 
-    let skin = {
-        let button_style = root_ui()
-            .style_builder()
-            .background(load_image("./assets/button.png").await.unwrap())
-            .background_margin(RectOffset { left: 6.0, right: 6.0, bottom: 6.0, top: 6.0 })
-            .color(WHITE)
-            .color_hovered(Color::new(0.75, 0.75, 0.75, 1.0))
-            .color_selected_hovered(WHITE)
-            .color_selected(WHITE)
-            .color_inactive(WHITE)
-            .color_clicked(WHITE)
-            .margin(RectOffset { left: 4.0, right: 4.0, bottom: 4.0, top: 4.0 })
-            .build();
-
-        let window_style = root_ui()
-            .style_builder()
-            .background(load_image("./assets/border.png").await.unwrap())
-            .background_clicked(load_image("./assets/border.png").await.unwrap())
-            .background_hovered(load_image("./assets/border.png").await.unwrap())
-            .background_margin(RectOffset { left: 14.0, right: 14.0, bottom: 14.0, top: 14.0 })
-            .margin(RectOffset { left: 4.0, right: 4.0, bottom: 4.0, top: 4.0 })
-            .color(WHITE)
-            .color_selected_hovered(WHITE)
-            .color_hovered(WHITE)
-            .color_selected(WHITE)
-            .color_inactive(WHITE)
-            .color_clicked(WHITE)
-            .build();
-
-        let scrollbar_style = root_ui()
-            .style_builder()
-            .color(Color::new(0.0, 0.0, 0.0, 0.0))
-            .color_hovered(Color::new(0.0, 0.0, 0.0, 0.0))
-            .color_clicked(Color::new(0.0, 0.0, 0.0, 0.0))
-            .color_selected(Color::new(0.0, 0.0, 0.0, 0.0))
-            .color_inactive(Color::new(0.0, 0.0, 0.0, 0.0))
-            .color_selected_hovered(Color::new(0.0, 0.0, 0.0, 0.0))
-            .build();
-
-        let window_titlebar_style = root_ui()
-            .style_builder()
-            .color(Color::new(0.0, 0.0, 0.0, 0.0))
-            .color_hovered(Color::new(0.0, 0.0, 0.0, 0.0))
-            .color_clicked(Color::new(0.0, 0.0, 0.0, 0.0))
-            .color_selected(Color::new(0.0, 0.0, 0.0, 0.0))
-            .color_inactive(Color::new(0.0, 0.0, 0.0, 0.0))
-            .color_selected_hovered(Color::new(0.0, 0.0, 0.0, 0.0))
-            .build();
-
-        let label_style = root_ui()
-            .style_builder()
-            .font_size(26)
-            .font(include_bytes!("../assets/Minecraft.ttf")).unwrap()
-            .build();
-            
-        Skin {
-            button_style,
-            window_style,
-            window_titlebar_style,
-            label_style,
-            scroll_width: 0.0,
-            title_height: 0.0,
-            scrollbar_handle_style: scrollbar_style,
-            ..root_ui().default_skin()
+    /// draws the state over the ones under it, `viewport` being where the view is presented
+    fn draw(&self, game: &Game, viewport: Rect, font: &Font) {
+        match self {
+            GameState::Exploring => {}
+            GameState::MapView => game.draw_map(viewport.size()),
+            GameState::Paused => {
+                draw_rectangle(viewport.x, viewport.y, viewport.w, viewport.h, Color::new(0.0, 0.0, 0.0, 0.6));
+                let text = game.world.resolve("PAUSED");
+                let text_size = measure_text(&text, Some(font), 48, 1.0);
+                draw_text_ex(&text, viewport.center().x - text_size.width / 2.0, viewport.center().y, TextParams {
+                    font: Some(font),
+                    font_size: 48,
+                    color: WHITE,
+                    ..Default::default()
+                });
+            }
+            GameState::Transitioning { elapsed, duration } => {
+                let alpha = 1.0 - (elapsed / duration).clamp(0.0, 1.0);
+                draw_rectangle(0.0, 0.0, screen_width(), screen_height(), Color::new(0.0, 0.0, 0.0, alpha));
+            }
         }
-    };
+    }
 
-    root_ui().push_skin(&skin);
+    fn accepts_input(&self) -> bool {
+        matches!(self, GameState::Exploring | GameState::MapView)
+    }
 
-    let viewport_camera = Camera2D {
-        render_target: Some(screen.clone()),
-        zoom,
-        offset: vec2(-1.0, -1.0),
-        ..Default::default()
-    };
+    /// the world stands still while paused, but keeps going under the map view and transitions
+    fn world_runs(&self) -> bool {
+        !matches!(self, GameState::Paused)
+    }
+}
+
+struct States {
+    stack: Vec<GameState>,
+}
 
-    let sword_image = image::open("./assets/sword_icon.png").unwrap();
-    let sword_texture = Texture2D::from_rgba8(sword_image.width() as u16, sword_image.height() as u16, &sword_image.to_rgba8());
+impl States {
+    /// exploring, faded in from black over `fade_in` seconds
+    fn new(fade_in: f32) -> Self {
+        Self { stack: vec![GameState::Exploring, GameState::Transitioning { elapsed: 0.0, duration: fade_in }] }
+    }
 
-    let shield_image = image::open("./assets/shield_icon.png").unwrap();
-    let shield_texture = Texture2D::from_rgba8(shield_image.width() as u16, shield_image.height() as u16, &shield_image.to_rgba8());
+    fn top(&self) -> &GameState {
+        self.stack.last().unwrap_or(&GameState::Exploring)
+    }
 
-    let parry_image = image::open("./assets/parry_icon.png").unwrap();
-    let parry_texture = Texture2D::from_rgba8(parry_image.width() as u16, parry_image.height() as u16, &parry_image.to_rgba8());
+    /// updates the top state, popping it once it's done
+    fn update(&mut self, dt: f32) {
+        if self.stack.last_mut().is_some_and(|state| state.update(dt)) {
+            self.stack.pop();
+        }
+        if self.stack.is_empty() {
+            self.stack.push(GameState::Exploring);
+        }
+    }
 
-    let charge_image = image::open("./assets/charge_icon.png").unwrap();
-    let charge_texture = Texture2D::from_rgba8(charge_image.width() as u16, charge_image.height() as u16, &charge_image.to_rgba8());
+    /// hands `action` to the top state, returning the turn it lets the player take
+    fn handle(&mut self, action: Action) -> Option<PlayerAction> {
+        match self.top().handle(action) {
+            Outcome::Ignore => {}
+            Outcome::Push(state) => self.stack.push(state),
+            Outcome::Pop => {
+                self.stack.pop();
+            }
+            Outcome::Turn(action) => return Some(action),
+        }
+        None
+    }
 
-    let forward_image = image::open("./assets/forward_arrow.png").unwrap();
-    let forward_texture = Texture2D::from_rgba8(forward_image.width() as u16, forward_image.height() as u16, &forward_image.to_rgba8());
-    let back_texture = Texture2D::from_rgba8(forward_image.width() as u16, forward_image.height() as u16, &forward_image.flipv().to_rgba8());
-    let right_texture = Texture2D::from_rgba8(forward_image.height() as u16, forward_image.width() as u16, &forward_image.rotate90().to_rgba8());
-    let left_texture = Texture2D::from_rgba8(forward_image.height() as u16, forward_image.width() as u16, &forward_image.rotate270().to_rgba8());
+    fn accepts_input(&self) -> bool {
+        self.top().accepts_input()
+    }
 
-    let turn_image = image::open("./assets/turn_arrow.png").unwrap();
-    let turn_left_texture = Texture2D::from_rgba8(turn_image.width() as u16, turn_image.height() as u16, &turn_image.to_rgba8());
-    let turn_right_texture = Texture2D::from_rgba8(turn_image.width() as u16, turn_image.height() as u16, &turn_image.fliph().to_rgba8());
+    fn world_runs(&self) -> bool {
+        self.top().world_runs()
+    }
+}
 
-    let map_image = image::open("./assets/map_icon.png").unwrap();
-    let map_texture = Texture2D::from_rgba8(map_image.width() as u16, map_image.height() as u16, &map_image.to_rgba8());
+#[derive(Clone, Copy)]
+enum Action {
+    Player(PlayerAction),
+    ToggleMap,
+    Pause,
+}
 
-    let background_image = image::open("./assets/background.png").unwrap();
-    let background_texture = Texture2D::from_rgba8(background_image.width() as u16, background_image.height() as u16, &background_image.to_rgba8());
+impl Action {
+    fn from_hud(action: &HudAction) -> Option<Self> {
+        match action {
+            HudAction::Player(action) => Some(Action::Player(*action)),
+            HudAction::Custom(name) if name == "toggle_map" => Some(Action::ToggleMap),
+            HudAction::Custom(name) if name == "pause" => Some(Action::Pause),
+            HudAction::Custom(_) => None,
+        }
+    }
+}
 
-    let gl = unsafe { get_internal_gl() };
-    let ctx = gl.quad_context;
-    ctx.texture_set_wrap(background_texture.raw_miniquad_id(), miniquad::TextureWrap::Repeat, miniquad::TextureWrap::Repeat);
-
-    let mut world = AtlasWorld {
-        player,
-        map,
-        collection: atlas,
-        render_depth,
-        render_width
-    };
+/// what the health bar fills up to, the player starts with all of it
+const PLAYER_MAX_HP: i32 = 20;
 
-    loop {
-        set_camera(&viewport_camera);
+struct Icons {
+    /// button icons by the names hud.json refers to them with
+    buttons: AHashMap<String, Texture2D>,
+    background: Texture2D,
+}
 
-        clear_background(BLACK);
+struct Game {
+    world: AtlasWorld,
+    states: States,
+    layout: HudLayout,
+    /// scale the atlases were authored at, see `AtlasWorld::asset_scale`
+    asset_scale: u32,
+    fullscreen: bool,
+    last_mouse: Vec2,
+    /// how far the view leans, negative to the left, eased toward the held lean keys
+    lean: f32,
+    input: InputMap,
+    /// holding a movement key walks at a steady pace
+    repeat: RepeatingInput,
+}
 
-        if !auto_map[world.player.y as usize][world.player.x as usize] {
-            auto_map[world.player.y as usize][world.player.x as usize] = true;
+impl Game {
+    fn update(&mut self, dt: f32) {
+        if self.states.world_runs() {
+            self.world.update(dt);
         }
 
-        if is_key_pressed(KeyCode::F) {
-            fullscreen = !fullscreen;
-            set_fullscreen(fullscreen);
+        // holding the right button peeks around by dragging the presented viewport
+        let mouse = Vec2::from(mouse_position());
+        if is_mouse_button_down(MouseButton::Right) {
+            let area = self.view_area();
+            self.world.viewport.peek_offset((mouse - self.last_mouse) * self.world.viewport.width as f32 / area.w);
         }
+        self.last_mouse = mouse;
+        self.world.viewport.update_ui(dt);
+
+        // holding the lean keys peeks around the corner on that side
+        let lean = |name: &str| self.input.is_down(&HudAction::Custom(name.to_owned())) && self.accepts_input();
+        let lean_target = match (lean("lean_left"), lean("lean_right")) {
+            (true, false) => -1.0,
+            (false, true) => 1.0,
+            _ => 0.0,
+        };
+        self.lean += (lean_target - self.lean) * (dt * 10.0).min(1.0);
 
-        if is_key_pressed(KeyCode::W) {
-            world.player.move_forward(&world.map);
-        }
-        if is_key_pressed(KeyCode::S) {
-            world.player.move_backward(&world.map);
-        }
+        self.states.update(dt);
 
-        if is_key_pressed(KeyCode::A) {
-            world.player.strafe_left(&world.map);
-        }
-        if is_key_pressed(KeyCode::D) {
-            world.player.strafe_right(&world.map);
+        for action in self.repeat.poll_with(&self.input, dt) {
+            match action {
+                HudAction::Custom(name) if name == "toggle_fullscreen" => {
+                    self.fullscreen = !self.fullscreen;
+                    set_fullscreen(self.fullscreen);
+                }
+                action => {
+                    if let Some(action) = Action::from_hud(&action) {
+                        self.apply(action);
+                    }
+                }
+            }
         }
 
-        if is_key_pressed(KeyCode::Q) {
-            world.player.turn_left();
-        }
-        if is_key_pressed(KeyCode::E) {
-            world.player.turn_right();
-        }
+        // nothing listens to world events in the demo yet
+        self.world.poll_events().for_each(drop);
+    }
+
+    fn accepts_input(&self) -> bool {
+        self.states.accepts_input()
+    }
 
-        if is_key_pressed(KeyCode::M) {
-            show_map = !show_map;
+    fn apply(&mut self, action: Action) {
+        if let Some(action) = self.states.handle(action) {
+            self.world.take_turn(action);
         }
+    }
 
-        world.render();
+    fn view_area(&self) -> Rect {
+        self.layout.resolve(vec2(screen_width(), screen_height()), self.asset_scale).viewport
+    }
 
-        set_default_camera();
+    fn draw(&mut self, icons: &Icons, font: &Font) {
+        let (side, lean) = if self.lean < 0.0 { (PeekSide::Left, -self.lean) } else { (PeekSide::Right, self.lean) };
+        let view = self.world.render_to_target_with(|world| world.render_peek(side, lean)).clone();
 
         clear_background(GRAY);
 
-        draw_texture_ex(&background_texture, 0.0, 0.0, WHITE, DrawTextureParams {
+        draw_texture_ex(&icons.background, 0.0, 0.0, WHITE, DrawTextureParams {
             source: Some(Rect::new(0.0, 0.0, screen_width(), screen_height())),
             ..Default::default()
         });
 
-        let dest_size = if (screen_width() * 0.7 - 20.0) / (VIEWPORT_WIDTH as f32 / VIEWPORT_HEIGHT as f32) > screen_height() - 20.0 {
-            vec2((screen_height() - 20.0) * (VIEWPORT_WIDTH as f32 / VIEWPORT_HEIGHT as f32), screen_height() - 20.0)
-        } else {
-            vec2(screen_width() * 0.7 - 20.0, (screen_width() * 0.7 - 20.0) / (VIEWPORT_WIDTH as f32 / VIEWPORT_HEIGHT as f32))
-        };
+        let viewport = self.view_area();
+        let dest_size = viewport.size();
 
-        draw_texture_ex(&screen.texture, 10.0, 10.0, WHITE, DrawTextureParams {
-            dest_size: Some(dest_size),
-            ..Default::default()
-        });
+        self.world.viewport.present(&view, viewport);
 
         // draws the border around the viewport
-        macroquad::ui::widgets::Window::new(hash!(), vec2(10.0, 10.0), dest_size).movable(false).close_button(false).ui(&mut root_ui(), |_| {});
+        macroquad::ui::widgets::Window::new(hash!(), viewport.point(), dest_size).movable(false).close_button(false).ui(&mut root_ui(), |_| {});
 
+        for action in self.draw_hud(icons, font) {
+            self.apply(action);
+        }
+
+        // every state draws, bottom up, so the overlays land on what's under them
+        for state in &self.states.stack {
+            state.draw(self, viewport, font);
+        }
+    }
+
+    fn draw_map(&self, dest_size: Vec2) {
         let map_size = dest_size * 0.9;
         let map_pos = vec2(10.0 + dest_size.x * 0.05, 10.0 + dest_size.y * 0.05);
 
-        if show_map {
-            // map border
-            macroquad::ui::widgets::Window::new(hash!(), map_pos, map_size).movable(false).close_button(false).ui(&mut root_ui(), |_| {});
+        // map border
+        macroquad::ui::widgets::Window::new(hash!(), map_pos, map_size).movable(false).close_button(false).ui(&mut root_ui(), |_| {});
 
-            draw_rectangle(map_pos.x, map_pos.y, map_size.x, map_size.y, BLACK);
-            let map_pos = map_pos + 18.0;
+        draw_rectangle(map_pos.x, map_pos.y, map_size.x, map_size.y, BLACK);
+        self.world.draw_automap(Rect::new(map_pos.x + 18.0, map_pos.y + 18.0, map_size.x - 36.0, map_size.y - 36.0), &MinimapOptions::default());
+    }
 
-            // let total_cells = vec2( (map_size.x - 36.0) / 15.0, (map_size.y - 36.0) / 15.0).floor();
-            let total_cells = vec2(50.0, 40.0);
-            let cell_size = ((map_size.x - 36.0) / total_cells.x).min((map_size.y - 36.0) / total_cells.y);
+    /// draws the button panel and status bar, returning the actions of any buttons pressed
+    fn draw_hud(&self, icons: &Icons, font: &Font) -> Vec<Action> {
+        let mut ctx = HudContext {
+            icons: &icons.buttons,
+            stats: AHashMap::from_iter([("health".to_owned(), (self.world.player.stats.hp as f32, PLAYER_MAX_HP as f32))]),
+            font: Some(font),
+            font_size: 26,
+            asset_scale: self.asset_scale,
+            actions: Vec::new(),
+        };
+        hud::draw_layout(&self.layout, &mut ctx);
+        ctx.actions.iter().filter_map(Action::from_hud).collect()
+    }
+}
 
-            let mut start_y = (world.player.y - (total_cells.y / 2.0) as i32).max(0);
-            let mut end_y = (start_y + total_cells.y as i32).min(world.map.height as i32);
-            if (start_y..end_y).len() < total_cells.y as usize {
-                let diff = total_cells.y as i32 - (start_y..end_y).len() as i32;
-                start_y -= diff;
-                end_y += diff;
-            }
+fn load_icon(path: &str) -> Texture2D {
+    texture_from_png_bytes(&read_icon(path)).unwrap_or_else(|err| panic!("{path}: {err}"))
+}
 
-            let mut start_x = (world.player.x - (total_cells.x / 2.0) as i32).max(0);
-            let mut end_x = (start_x + total_cells.x as i32).min(world.map.width as i32);
-            if (start_x..end_x).len() < total_cells.x as usize {
-                let diff = total_cells.x as i32 - (start_x..end_x).len() as i32;
-                start_x -= diff;
-                end_x += diff;
-            }
+fn read_icon(path: &str) -> Vec<u8> {
+    std::fs::read(path).unwrap_or_else(|err| panic!("{path}: {err}"))
+}
 
-            let mut draw_y = 0;
-            for y in start_y..end_y {
-                let mut draw_x = 0;
-                for x in start_x..end_x {
-                    if x >= 0 && y >= 0 && x < world.map.width as i32 && y < world.map.height as i32 {
-                        if auto_map[y as usize][x as usize] {
-                            draw_rectangle(map_pos.x as f32 + (cell_size * draw_x as f32), map_pos.y as f32 + (cell_size * draw_y as f32), cell_size, cell_size, GRAY);
-                            if x >= 0 {
-                                if x == 0 || world.map.wall[y as usize][x as usize - 1] != 0 {
-                                    draw_line(map_pos.x as f32 + (cell_size * draw_x as f32), map_pos.y as f32 + (cell_size * draw_y as f32), map_pos.x as f32 + (cell_size * draw_x as f32), map_pos.y as f32 + (cell_size * draw_y as f32) + cell_size, cell_size / 5.0, WHITE);
-                                }
-                            }
-                            if x <= world.map.width as i32 - 1 {
-                                if x == world.map.width as i32 - 1 || world.map.wall[y as usize][x as usize + 1] != 0 {
-                                    draw_line(map_pos.x as f32 + (cell_size * draw_x as f32) + cell_size, map_pos.y as f32 + (cell_size * draw_y as f32), map_pos.x as f32 + (cell_size * draw_x as f32) + cell_size, map_pos.y as f32 + (cell_size * draw_y as f32) + cell_size, cell_size / 5.0, WHITE);
-                                }
-                            }
-                            if y >= 0 {
-                                if y == 0 || world.map.wall[y as usize - 1][x as usize] != 0 {
-                                    draw_line(map_pos.x as f32 + (cell_size * draw_x as f32), map_pos.y as f32 + (cell_size * draw_y as f32), map_pos.x as f32 + (cell_size * draw_x as f32) + cell_size, map_pos.y as f32 + (cell_size * draw_y as f32), cell_size / 5.0, WHITE);
-                                }
-                            }
-                            if y <= world.map.height as i32 - 1{
-                                if y == world.map.height as i32 - 1 || world.map.wall[y as usize + 1][x as usize] != 0 {
-                                    draw_line(map_pos.x as f32 + (cell_size * draw_x as f32), map_pos.y as f32 + (cell_size * draw_y as f32) + cell_size, map_pos.x as f32 + (cell_size * draw_x as f32) + cell_size, map_pos.y as f32 + (cell_size * draw_y as f32) + cell_size, cell_size / 5.0, WHITE);
-                                }
-                            }
-                            if world.map.object[y as usize][x as usize] != 0 {
-                                draw_circle(map_pos.x as f32 + (cell_size * draw_x as f32) + cell_size / 2.0, map_pos.y as f32 + (cell_size * draw_y as f32) + cell_size / 2.0, cell_size / 4.0, WHITE);
-                            }
-                        }
-                        if world.player.x == x && world.player.y == y {
-                            draw_circle(map_pos.x as f32 + (cell_size * draw_x as f32) + cell_size / 2.0, map_pos.y as f32 + (cell_size * draw_y as f32) + cell_size / 2.0, cell_size / 3.0, GREEN);
-                        }
-                        draw_x += 1;
-                    }
-                }
-                if y >= 0 {draw_y += 1;}
-            }
+// a dusk gradient over hills whose height changes all the way round, so turning shows it
+fn sky_strip() -> Image {
+    let (width, height) = (512u16, 64u16);
+    let mut image = Image::gen_image_color(width, height, BLACK);
+    for x in 0..width as u32 {
+        let angle = x as f32 / width as f32 * std::f32::consts::TAU;
+        let hills = height as f32 * (0.75 - 0.12 * (angle * 3.0).sin().abs() - 0.06 * (angle * 7.0 + 1.0).cos());
+        for y in 0..height as u32 {
+            let t = y as f32 / height as f32;
+            let color = if y as f32 > hills { Color::new(0.12, 0.16, 0.14, 1.0) } else { Color::new(0.25 + 0.6 * t, 0.35 + 0.3 * t, 0.7 - 0.2 * t, 1.0) };
+            image.set_pixel(x, y, color);
         }
+    }
+    image
+}
+
+fn load_icons() -> Icons {
+    let forward_arrow = read_icon("./assets/forward_arrow.png");
+    let turn_arrow = read_icon("./assets/turn_arrow.png");
+    let icon = |texture: Result<Texture2D, macroquad::Error>| texture.unwrap_or_else(|err| panic!("{err}"));
+
+    let buttons = [
+        ("sword", load_icon("./assets/sword_icon.png")),
+        ("shield", load_icon("./assets/shield_icon.png")),
+        ("parry", load_icon("./assets/parry_icon.png")),
+        ("charge", load_icon("./assets/charge_icon.png")),
+        ("forward", icon(texture_from_png_bytes(&forward_arrow))),
+        ("back", icon(texture_flipped(&forward_arrow, Flip::Vertical))),
+        ("right", icon(texture_rotated(&forward_arrow, Rotation::Cw90))),
+        ("left", icon(texture_rotated(&forward_arrow, Rotation::Ccw90))),
+        ("turn_left", icon(texture_from_png_bytes(&turn_arrow))),
+        ("turn_right", icon(texture_flipped(&turn_arrow, Flip::Horizontal))),
+        ("map", load_icon("./assets/map_icon.png")),
+    ];
+    let icons = Icons {
+        buttons: buttons.into_iter().map(|(name, texture)| (name.to_owned(), texture)).collect(),
+        background: load_icon("./assets/background.png"),
+    };
+
+    let gl = unsafe { get_internal_gl() };
+    let ctx = gl.quad_context;
+    ctx.texture_set_wrap(icons.background.raw_miniquad_id(), miniquad::TextureWrap::Repeat, miniquad::TextureWrap::Repeat);
+
+    icons
+}
+
+async fn load_skin() -> Skin {
+    let button_style = root_ui()
+        .style_builder()
+        .background(load_image("./assets/button.png").await.unwrap())
+        .background_margin(RectOffset { left: 6.0, right: 6.0, bottom: 6.0, top: 6.0 })
+        .color(WHITE)
+        .color_hovered(Color::new(0.75, 0.75, 0.75, 1.0))
+        .color_selected_hovered(WHITE)
+        .color_selected(WHITE)
+        .color_inactive(WHITE)
+        .color_clicked(WHITE)
+        .margin(RectOffset { left: 4.0, right: 4.0, bottom: 4.0, top: 4.0 })
+        .build();
+
+    let window_style = root_ui()
+        .style_builder()
+        .background(load_image("./assets/border.png").await.unwrap())
+        .background_clicked(load_image("./assets/border.png").await.unwrap())
+        .background_hovered(load_image("./assets/border.png").await.unwrap())
+        .background_margin(RectOffset { left: 14.0, right: 14.0, bottom: 14.0, top: 14.0 })
+        .margin(RectOffset { left: 4.0, right: 4.0, bottom: 4.0, top: 4.0 })
+        .color(WHITE)
+        .color_selected_hovered(WHITE)
+        .color_hovered(WHITE)
+        .color_selected(WHITE)
+        .color_inactive(WHITE)
+        .color_clicked(WHITE)
+        .build();
+
+    let scrollbar_style = root_ui()
+        .style_builder()
+        .color(Color::new(0.0, 0.0, 0.0, 0.0))
+        .color_hovered(Color::new(0.0, 0.0, 0.0, 0.0))
+        .color_clicked(Color::new(0.0, 0.0, 0.0, 0.0))
+        .color_selected(Color::new(0.0, 0.0, 0.0, 0.0))
+        .color_inactive(Color::new(0.0, 0.0, 0.0, 0.0))
+        .color_selected_hovered(Color::new(0.0, 0.0, 0.0, 0.0))
+        .build();
+
+    let window_titlebar_style = root_ui()
+        .style_builder()
+        .color(Color::new(0.0, 0.0, 0.0, 0.0))
+        .color_hovered(Color::new(0.0, 0.0, 0.0, 0.0))
+        .color_clicked(Color::new(0.0, 0.0, 0.0, 0.0))
+        .color_selected(Color::new(0.0, 0.0, 0.0, 0.0))
+        .color_inactive(Color::new(0.0, 0.0, 0.0, 0.0))
+        .color_selected_hovered(Color::new(0.0, 0.0, 0.0, 0.0))
+        .build();
+
+    let label_style = root_ui()
+        .style_builder()
+        .font_size(26)
+        .font(include_bytes!("../assets/Minecraft.ttf")).unwrap()
+        .build();
+        
+    Skin {
+        button_style,
+        window_style,
+        window_titlebar_style,
+        label_style,
+        scroll_width: 0.0,
+        title_height: 0.0,
+        scrollbar_handle_style: scrollbar_style,
+        ..root_ui().default_skin()
+    }
+}
+
+#[macroquad::main(window_conf)]
+async fn main() {
+    let mut atlas = AtlasCollection::new();
+    atlas.load_async("dungeon", include_bytes!("../mansion.png"), "mansion.json").await.unwrap_or_else(|err| panic!("{err}"));
+    atlas.load_async("common_objects", include_bytes!("../common_objects.png"), "common_objects.json").await.unwrap_or_else(|err| panic!("{err}"));
 
-        let right_side = dest_size.x + 20.0;
+    let mut player = Player::new(1, 1, Direction::South);
+    player.stats = Stats::new(PLAYER_MAX_HP, 3, 1);
 
-        let mut button_size = ((screen_width() - right_side) - 10.0 - 36.0) / 3.0;
-        if screen_height() - 10.0 - 36.0 - button_size < button_size * 4.0 + 20.0 + 36.0 {
-            button_size = (screen_height() - 30.0 - 72.0) / 5.0;
+    let f = std::fs::File::open("map.json").unwrap();
+    let map = AtlasMap::from_reader_auto(BufReader::new(f)).unwrap_or_else(|err| panic!("{err}"));
+    for (atlas_id, loaded) in atlas.atlases_sorted() {
+        for finding in &loaded.findings {
+            eprintln!("atlas '{atlas_id}': {finding}");
         }
+    }
+    for finding in map.validate() {
+        eprintln!("map.json: {finding}");
+    }
+    let mut auto_map = AutoMap::new(map.width, map.height);
+    auto_map.reveal_mode = RevealMode::LineOfSight { radius: 9 };
 
-        let win_pos = vec2(right_side, (screen_height() - button_size * 4.0 - 10.0 - 36.0).max(20.0 + 36.0 + button_size));
-        let win_size = vec2(button_size * 3.0 + 36.0, button_size * 4.0 + 36.0);
+    // atlases of mixed scales draw wrong but the game still runs, at 1x like `viewport_rect`
+    let asset_scale = atlas.asset_scale().unwrap_or_else(|err| {
+        eprintln!("atlases: {err}");
+        1
+    });
 
-        let window_hash = hash!();
-        macroquad::ui::widgets::Window::new(window_hash, win_pos, win_size).movable(false).close_button(false).ui(&mut root_ui(), |ui| {
-            macroquad::ui::widgets::Button::new(charge_texture.clone()).position(vec2(button_size, 0.0)).size(vec2(button_size, button_size)).ui(ui);
-            macroquad::ui::widgets::Button::new(sword_texture.clone()).position(vec2(0.0, button_size)).size(vec2(button_size, button_size)).ui(ui);
-            macroquad::ui::widgets::Button::new(shield_texture.clone()).position(vec2(button_size, button_size)).size(vec2(button_size, button_size)).ui(ui);
-            macroquad::ui::widgets::Button::new(parry_texture.clone()).position(vec2(button_size * 2.0, button_size)).size(vec2(button_size, button_size)).ui(ui);
+    let font = load_ttf_font("./assets/Minecraft.ttf").await.unwrap();
 
-            if macroquad::ui::widgets::Button::new(forward_texture.clone()).position(vec2(button_size, button_size * 2.0)).size(vec2(button_size, button_size)).ui(ui) {
-                world.player.move_forward(&world.map);
-            }
-            if macroquad::ui::widgets::Button::new(turn_left_texture.clone()).position(vec2(0.0, button_size * 2.0)).size(vec2(button_size, button_size)).ui(ui) {
-                world.player.turn_left();
-            }
-            if macroquad::ui::widgets::Button::new(turn_right_texture.clone()).position(vec2(button_size * 2.0, button_size * 2.0)).size(vec2(button_size, button_size)).ui(ui) {
-                world.player.turn_right();
-            }
-            if macroquad::ui::widgets::Button::new(back_texture.clone()).position(vec2(button_size, button_size * 3.0)).size(vec2(button_size, button_size)).ui(ui) {
-                world.player.move_backward(&world.map);
-            }
-            if macroquad::ui::widgets::Button::new(left_texture.clone()).position(vec2(0.0, button_size * 3.0)).size(vec2(button_size, button_size)).ui(ui) {
-                world.player.strafe_left(&world.map);
-            }
-            if macroquad::ui::widgets::Button::new(right_texture.clone()).position(vec2(button_size * 2.0, button_size * 3.0)).size(vec2(button_size, button_size)).ui(ui) {
-                world.player.strafe_right(&world.map);
-            }
+    let skin = load_skin().await;
+    root_ui().push_skin(&skin);
 
-            if macroquad::ui::widgets::Button::new(map_texture.clone()).position(vec2(0.0, 0.0)).size(vec2(button_size, button_size)).ui(ui) {
-                show_map = !show_map;
-            }
-        });
+    let icons = load_icons();
 
-        let win_pos = vec2(right_side, 10.0);
-        let win_size = vec2(win_size.x, button_size + 36.0);
-        macroquad::ui::widgets::Window::new(hash!(), win_pos, win_size).movable(false).close_button(false).ui(&mut root_ui(), |ui| {
-            let mut canvas = ui.canvas();
-            let cursor = canvas.cursor();
-            canvas.rect(Rect::new(cursor.x, cursor.y, button_size * 3.0, button_size / 4.0), Color::default(), GRAY);
-            canvas.rect(Rect::new(cursor.x, cursor.y, button_size * 3.0 * (5.0 / 20.0), button_size / 4.0), Color::default(), RED);
-            let text_size = measure_text("15/20", Some(&font), 26, 1.0);
-            macroquad::ui::widgets::Label::new(&format!("15/20")).position(vec2(((win_size.x - 36.0) / 2.0) - (text_size.width / 2.0), button_size / 8.0 - (text_size.height / 2.0))).ui(ui);
-        });
-        root_ui().focus_window(window_hash);
+    let f = std::fs::File::open("hud.json").unwrap();
+    let layout: HudLayout = serde_json::from_reader(BufReader::new(f)).unwrap();
+
+    atlas.insert("sky".to_owned(), Atlas::from_image(&sky_strip(), "sky"));
+    let mut world = AtlasWorld::new(player, map, atlas);
+    world.automap = Some(auto_map);
+    world.sky = Some(SkySettings::new("sky", "sky"));
+    world.render_depth = 9;
+    world.render_width = 22;
+    eprint!("{}", world.preflight());
+
+    // bindings.json is optional, the default keys are used without it
+    let input = match std::fs::File::open("bindings.json") {
+        Ok(f) => serde_json::from_reader(BufReader::new(f)).unwrap_or_else(|err| panic!("bindings.json: {err}")),
+        Err(_) => InputMap::default(),
+    };
+
+    let mut game = Game {
+        world,
+        states: States::new(0.5),
+        layout,
+        asset_scale,
+        fullscreen: false,
+        last_mouse: Vec2::from(mouse_position()),
+        lean: 0.0,
+        input,
+        repeat: RepeatingInput::default(),
+    };
+
+    loop {
+        game.update(get_frame_time());
+        game.draw(&icons, &font);
 
         next_frame().await;
     }
 }
 
-// fn draw_floor(atlas: &AtlasCollection, player: &Player, map: &AtlasMap, x: i32, z: i32) {
-//     let p = get_player_direction_vector_offsets(player, x, z);
-// 
-//     if p.x >= 0 && p.y >= 0 && p.x < map.width as i32 && p.y < map.height as i32 {
-//         let map_value = map.floor[p.y as usize][p.x as usize];
-//         draw_tile(atlas, "dungeon", &format!("floor-{map_value}"), x, z, None);
-//     }
-// }
-
-// fn draw_ceiling(atlas: &AtlasCollection, player: &Player, map: &AtlasMap, x: i32, z: i32) {
-//     let p = get_player_direction_vector_offsets(player, x, z);
-// 
-//     if p.x >= 0 && p.y >= 0 && p.x < map.width as i32 && p.y < map.height as i32 {
-//         let map_value = map.ceiling[p.y as usize][p.x as usize];
-//         draw_tile(atlas, "dungeon", &format!("ceiling-{map_value}"), x, z, None);
-//     }
-// }
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn top(states: &States) -> &'static str {
+        match states.top() {
+            GameState::Exploring => "exploring",
+            GameState::MapView => "map",
+            GameState::Paused => "paused",
+            GameState::Transitioning { .. } => "transitioning",
+        }
+    }
+
+    #[test]
+    fn the_fade_in_ignores_input_until_it_ends() {
+        let mut states = States::new(0.5);
+        assert_eq!(top(&states), "transitioning");
+        assert!(!states.accepts_input());
+        states.handle(Action::Pause);
+        states.handle(Action::ToggleMap);
+        assert_eq!(top(&states), "transitioning");
+
+        states.update(0.3);
+        assert_eq!(top(&states), "transitioning");
+        states.update(0.3);
+        assert_eq!(top(&states), "exploring");
+        assert!(states.accepts_input() && states.world_runs());
+        states.update(10.0);
+        assert_eq!(states.stack.len(), 1);
+    }
+
+    #[test]
+    fn the_map_is_an_overlay_that_pausing_backs_out_of() {
+        let mut states = States::new(0.0);
+        states.update(0.0);
+        states.handle(Action::ToggleMap);
+        assert_eq!(top(&states), "map");
+        assert!(states.accepts_input() && states.world_runs());
+        states.handle(Action::ToggleMap);
+        assert_eq!(top(&states), "exploring");
+        assert_eq!(states.stack.len(), 1);
+
+        states.handle(Action::ToggleMap);
+        states.handle(Action::Pause);
+        assert_eq!(top(&states), "exploring");
+    }
+
+    #[test]
+    fn player_actions_only_reach_the_world_while_input_is_taken() {
+        let mut states = States::new(0.5);
+        assert!(states.handle(Action::Player(PlayerAction::Wait)).is_none());
+        states.update(0.5);
+        assert!(states.handle(Action::Player(PlayerAction::Wait)).is_some());
+        states.handle(Action::ToggleMap);
+        assert!(states.handle(Action::Player(PlayerAction::Wait)).is_some());
+        states.handle(Action::Pause);
+        states.handle(Action::Pause);
+        assert_eq!(top(&states), "paused");
+        assert!(states.handle(Action::Player(PlayerAction::Wait)).is_none());
+    }
 
+    #[test]
+    fn pausing_stops_the_world_and_input() {
+        let mut states = States::new(0.0);
+        states.update(0.0);
+        states.handle(Action::Pause);
+        assert_eq!(top(&states), "paused");
+        assert!(!states.accepts_input() && !states.world_runs());
+        // the map can't be opened over the pause
+        states.handle(Action::ToggleMap);
+        assert_eq!(top(&states), "paused");
+        states.handle(Action::Pause);
+        assert_eq!(top(&states), "exploring");
+        assert_eq!(states.stack.len(), 1);
+    }
+}
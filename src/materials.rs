@@ -0,0 +1,136 @@
+use ahash::AHashMap;
+use serde::{Deserialize, Serialize};
+
+use crate::math::*;
+use crate::{events::WorldEvent, AtlasWorld, MapSource};
+
+/// What a wall value is made of. Collision is unaffected: every nonzero wall value blocks movement.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(default)]
+pub struct WallMaterial {
+    pub name: String,
+    /// can be knocked down with `AtlasWorld::bash_wall`
+    pub bashable: bool,
+    /// does not block sight, for the occlusion pass and line of sight checks
+    pub see_through: bool,
+    /// sound cue carried by `WorldEvent::WallBumped`
+    pub bump_sound: String,
+    pub minimap_color: Option<[u8; 4]>,
+}
+
+impl Default for WallMaterial {
+    fn default() -> Self {
+        Self {
+            name: "stone".to_owned(),
+            bashable: false,
+            see_through: false,
+            bump_sound: "bump".to_owned(),
+            minimap_color: None,
+        }
+    }
+}
+
+/// Wall value -> material table. Values without an entry use `WallMaterial::default()`.
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+#[serde(transparent)]
+pub struct WallMaterials {
+    pub materials: AHashMap<u8, WallMaterial>,
+    #[serde(skip)]
+    default: WallMaterial,
+}
+
+impl WallMaterials {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn insert(&mut self, value: u8, material: WallMaterial) {
+        self.materials.insert(value, material);
+    }
+
+    pub fn get(&self, value: u8) -> &WallMaterial {
+        self.materials.get(&value).unwrap_or(&self.default)
+    }
+
+    /// whether a cell with this wall value hides what is behind it
+    pub fn blocks_sight(&self, value: u8) -> bool {
+        value != 0 && !self.get(value).see_through
+    }
+
+    pub fn minimap_color(&self, value: u8) -> Option<Color> {
+        self.get(value).minimap_color.map(|[r, g, b, a]| Color::from_rgba(r, g, b, a))
+    }
+}
+
+impl<M: MapSource> AtlasWorld<M> {
+    /// Knocks down a bashable wall, leaving the cell open. Returns the wall value that was removed.
+    pub fn bash_wall(&mut self, x: i32, y: i32) -> Option<u8> {
+        let value = self.map.get_wall(ivec2(x, y))?;
+        if value == 0 || !self.wall_materials.get(value).bashable {
+            return None;
+        }
+
+        if !self.set_cell("wall", ivec2(x, y), 0) {
+            return None;
+        }
+        self.push_event(WorldEvent::WallBashed { pos: ivec2(x, y), value });
+        Some(value)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{AtlasCollection, AtlasMap, Direction, Movement, MoveResult, Player, WorldSave};
+
+    // a corridor east from the player through iron bars (5) and then a wooden wall (3)
+    fn world() -> AtlasWorld {
+        let mut map = AtlasMap::empty(7, 3);
+        map.wall.fill(1);
+        for (x, value) in [0, 5, 0, 3, 0].into_iter().enumerate() {
+            map.wall.set(x + 1, 1, value);
+        }
+        let mut world = AtlasWorld::new(Player::new(1, 1, Direction::East), map, AtlasCollection::new());
+        world.wall_materials.insert(3, WallMaterial { name: "wood".to_owned(), bashable: true, bump_sound: "knock".to_owned(), ..Default::default() });
+        world.wall_materials.insert(5, WallMaterial { name: "bars".to_owned(), see_through: true, bump_sound: "clang".to_owned(), ..Default::default() });
+        world
+    }
+
+    #[test]
+    fn see_through_walls_pass_sight_but_not_the_player() {
+        let mut world = world();
+        assert!(world.line_of_sight(ivec2(1, 1), ivec2(3, 1)));
+        assert!(!world.line_of_sight(ivec2(1, 1), ivec2(5, 1)));
+        let visible = world.visible_cells(ivec2(1, 1), 6);
+        assert!(visible.contains(&ivec2(3, 1)) && visible.contains(&ivec2(4, 1)) && !visible.contains(&ivec2(5, 1)));
+
+        assert_eq!(world.move_player(Movement::Forward), MoveResult::Blocked(ivec2(2, 1)));
+        let events: Vec<WorldEvent> = world.poll_events().collect();
+        assert_eq!(events, [WorldEvent::WallBumped { pos: ivec2(2, 1), value: 5, sound: "clang".to_owned() }]);
+        // unknown values are plain stone
+        assert!(world.wall_materials.blocks_sight(1));
+        assert_eq!(world.wall_materials.get(1).bump_sound, "bump");
+    }
+
+    #[test]
+    fn bashed_walls_open_and_stay_open_through_a_save() {
+        let mut world = world();
+        assert_eq!(world.bash_wall(2, 1), None);
+        assert_eq!(world.bash_wall(0, 0), None);
+        assert_eq!(world.bash_wall(3, 1), None);
+        assert_eq!(world.bash_wall(-1, 1), None);
+        assert_eq!(world.poll_events().count(), 0);
+
+        assert_eq!(world.bash_wall(4, 1), Some(3));
+        assert_eq!(world.poll_events().collect::<Vec<_>>(), [WorldEvent::WallBashed { pos: ivec2(4, 1), value: 3 }]);
+        assert_eq!(world.map.get_wall(ivec2(4, 1)), Some(0));
+        assert!(world.line_of_sight(ivec2(1, 1), ivec2(5, 1)));
+        assert_eq!(world.bash_wall(4, 1), None);
+
+        let save: WorldSave = serde_json::from_str(&serde_json::to_string(&world.to_save("test", 0)).unwrap()).unwrap();
+        let mut reloaded = self::world();
+        reloaded.apply_save(&save).unwrap();
+        assert_eq!(reloaded.map.get_wall(ivec2(4, 1)), Some(0));
+        assert_eq!(reloaded.map.get_wall(ivec2(2, 1)), Some(5));
+    }
+}
@@ -0,0 +1,141 @@
+//! The vector, rect, color and image types the crate is written against. With the `render`
+//! feature they are macroquad's own, so values go straight into its draw calls; without it they
+//! are the same glam vectors and a `Rect`, `Color` and `Image` with macroquad's fields and
+//! methods, so worlds build and run without macroquad at all.
+
+#[cfg(feature = "render")]
+pub use macroquad::{color::{colors::*, Color}, math::*, texture::Image};
+
+#[cfg(not(feature = "render"))]
+pub use glam::*;
+#[cfg(not(feature = "render"))]
+pub use self::headless::*;
+
+#[cfg(not(feature = "render"))]
+mod headless {
+    use glam::{vec2, Vec2};
+
+    /// a rectangle by its top left corner, width and height, as macroquad's
+    #[derive(Clone, Copy, Debug, Default, PartialEq)]
+    pub struct Rect {
+        pub x: f32,
+        pub y: f32,
+        pub w: f32,
+        pub h: f32,
+    }
+
+    impl Rect {
+        pub const fn new(x: f32, y: f32, w: f32, h: f32) -> Rect {
+            Rect { x, y, w, h }
+        }
+
+        pub const fn point(&self) -> Vec2 {
+            vec2(self.x, self.y)
+        }
+
+        pub const fn size(&self) -> Vec2 {
+            vec2(self.w, self.h)
+        }
+
+        pub fn center(&self) -> Vec2 {
+            vec2(self.x + self.w * 0.5, self.y + self.h * 0.5)
+        }
+
+        pub const fn left(&self) -> f32 {
+            self.x
+        }
+
+        pub fn right(&self) -> f32 {
+            self.x + self.w
+        }
+
+        pub const fn top(&self) -> f32 {
+            self.y
+        }
+
+        pub fn bottom(&self) -> f32 {
+            self.y + self.h
+        }
+
+        /// whether `point` is inside, the right and bottom edges excluded
+        pub fn contains(&self, point: Vec2) -> bool {
+            point.x >= self.left() && point.x < self.right() && point.y < self.bottom() && point.y >= self.top()
+        }
+
+        pub fn overlaps(&self, other: &Rect) -> bool {
+            self.left() <= other.right() && self.right() >= other.left() && self.top() <= other.bottom() && self.bottom() >= other.top()
+        }
+
+        pub fn intersect(&self, other: Rect) -> Option<Rect> {
+            let (left, top) = (self.x.max(other.x), self.y.max(other.y));
+            let (right, bottom) = (self.right().min(other.right()), self.bottom().min(other.bottom()));
+            if right < left || bottom < top {
+                return None;
+            }
+            Some(Rect::new(left, top, right - left, bottom - top))
+        }
+
+        pub fn offset(self, offset: Vec2) -> Rect {
+            Rect::new(self.x + offset.x, self.y + offset.y, self.w, self.h)
+        }
+    }
+
+    /// an rgba color with 0 to 1 channels, as macroquad's
+    #[derive(Clone, Copy, Debug, Default, PartialEq)]
+    pub struct Color {
+        pub r: f32,
+        pub g: f32,
+        pub b: f32,
+        pub a: f32,
+    }
+
+    impl Color {
+        pub const fn new(r: f32, g: f32, b: f32, a: f32) -> Color {
+            Color { r, g, b, a }
+        }
+
+        pub const fn from_rgba(r: u8, g: u8, b: u8, a: u8) -> Color {
+            Color::new(r as f32 / 255.0, g as f32 / 255.0, b as f32 / 255.0, a as f32 / 255.0)
+        }
+    }
+
+    impl From<Color> for [u8; 4] {
+        fn from(color: Color) -> [u8; 4] {
+            [color.r, color.g, color.b, color.a].map(|channel| (channel * 255.0) as u8)
+        }
+    }
+
+    /// rgba pixels, 4 bytes each row after row, as macroquad's
+    #[derive(Clone, Debug, Default)]
+    pub struct Image {
+        pub bytes: Vec<u8>,
+        pub width: u16,
+        pub height: u16,
+    }
+
+    pub const LIGHTGRAY: Color = Color::new(0.78, 0.78, 0.78, 1.00);
+    pub const GRAY: Color = Color::new(0.51, 0.51, 0.51, 1.00);
+    pub const DARKGRAY: Color = Color::new(0.31, 0.31, 0.31, 1.00);
+    pub const YELLOW: Color = Color::new(0.99, 0.98, 0.00, 1.00);
+    pub const GOLD: Color = Color::new(1.00, 0.80, 0.00, 1.00);
+    pub const ORANGE: Color = Color::new(1.00, 0.63, 0.00, 1.00);
+    pub const PINK: Color = Color::new(1.00, 0.43, 0.76, 1.00);
+    pub const RED: Color = Color::new(0.90, 0.16, 0.22, 1.00);
+    pub const MAROON: Color = Color::new(0.75, 0.13, 0.22, 1.00);
+    pub const GREEN: Color = Color::new(0.00, 0.89, 0.19, 1.00);
+    pub const LIME: Color = Color::new(0.00, 0.62, 0.18, 1.00);
+    pub const DARKGREEN: Color = Color::new(0.00, 0.46, 0.17, 1.00);
+    pub const SKYBLUE: Color = Color::new(0.40, 0.75, 1.00, 1.00);
+    pub const BLUE: Color = Color::new(0.00, 0.47, 0.95, 1.00);
+    pub const DARKBLUE: Color = Color::new(0.00, 0.32, 0.67, 1.00);
+    pub const PURPLE: Color = Color::new(0.78, 0.48, 1.00, 1.00);
+    pub const VIOLET: Color = Color::new(0.53, 0.24, 0.75, 1.00);
+    pub const DARKPURPLE: Color = Color::new(0.44, 0.12, 0.49, 1.00);
+    pub const BEIGE: Color = Color::new(0.83, 0.69, 0.51, 1.00);
+    pub const BROWN: Color = Color::new(0.50, 0.42, 0.31, 1.00);
+    pub const DARKBROWN: Color = Color::new(0.30, 0.25, 0.18, 1.00);
+    pub const WHITE: Color = Color::new(1.00, 1.00, 1.00, 1.00);
+    pub const BLACK: Color = Color::new(0.00, 0.00, 0.00, 1.00);
+    pub const BLANK: Color = Color::new(0.00, 0.00, 0.00, 0.00);
+    pub const MAGENTA: Color = Color::new(1.00, 0.00, 1.00, 1.00);
+}
@@ -0,0 +1,190 @@
+//! What the player may walk into, and what stopped them when they couldn't. Walls and closed
+//! doors always block; objects and entities do according to the world's `MovementRules`.
+
+use ahash::AHashSet;
+
+use crate::math::*;
+use crate::{covering_object, encounter::EntityId, AtlasWorld, Direction, Entity, MapSource, MoveResult, ObjectDefs, WallProperties};
+
+#[derive(Clone, Debug)]
+pub struct MovementRules {
+    /// every nonzero object blocks
+    pub objects_block: bool,
+    /// object values that block even while `objects_block` is off
+    pub blocking_object_ids: AHashSet<u8>,
+    /// entities with `blocks_movement` keep the player out of their cell
+    pub entities_block: bool,
+    /// how much higher or lower than the current cell a cell can be and still be stepped into
+    pub max_step_height: u8,
+    /// pits can be stepped down into however deep they are, emitting `WorldEvent::Fell`
+    pub fall_into_pits: bool,
+    /// wall values drawn like any wall that can be walked through, for illusionary walls.
+    /// `AtlasWorld::wall_properties` wins for values it has an entry for.
+    pub passable_walls: AHashSet<u8>,
+}
+
+impl Default for MovementRules {
+    fn default() -> Self {
+        Self {
+            objects_block: false,
+            blocking_object_ids: AHashSet::new(),
+            entities_block: true,
+            max_step_height: 0,
+            fall_into_pits: false,
+            passable_walls: AHashSet::new(),
+        }
+    }
+}
+
+impl MovementRules {
+    pub fn blocks_object(&self, value: u8) -> bool {
+        value != 0 && (self.objects_block || self.blocking_object_ids.contains(&value))
+    }
+}
+
+/// What kept the player out of a cell.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Blocker {
+    /// outside the map, or a cell the map doesn't have available
+    Edge,
+    Wall(u8),
+    Door(u8),
+    Object(u8),
+    Entity(EntityId),
+    /// a diagonal step between two walls touching at the corner
+    Corner,
+    /// a cell `rise` higher, or lower when negative, than `max_step_height` allows
+    Ledge { rise: i8 },
+}
+
+/// How `AtlasWorld::try_move` went.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum MoveOutcome {
+    Moved(IVec2),
+    Blocked { pos: IVec2, blocker: Blocker },
+}
+
+impl MoveOutcome {
+    pub fn is_moved(&self) -> bool {
+        matches!(self, MoveOutcome::Moved(_))
+    }
+
+    pub fn blocker(&self) -> Option<Blocker> {
+        match self {
+            MoveOutcome::Moved(_) => None,
+            MoveOutcome::Blocked { blocker, .. } => Some(*blocker),
+        }
+    }
+}
+
+impl From<MoveOutcome> for MoveResult {
+    fn from(outcome: MoveOutcome) -> Self {
+        match outcome {
+            MoveOutcome::Moved(pos) => MoveResult::Moved(pos),
+            MoveOutcome::Blocked { pos, .. } => MoveResult::Blocked(pos),
+        }
+    }
+}
+
+/// A map as the player walks through it: the world's open doors passable, and cells closed
+/// off by `MovementRules`.
+pub struct MovementView<'a, M: MapSource + ?Sized> {
+    pub map: &'a M,
+    pub open: &'a AHashSet<IVec2>,
+    pub entities: &'a [Entity],
+    pub rules: &'a MovementRules,
+    /// wall values whose `blocks_movement` overrides `rules`
+    pub walls: &'a WallProperties,
+    /// footprints of objects bigger than a cell, which block all of it
+    pub objects: &'a ObjectDefs,
+}
+
+impl<M: MapSource + ?Sized> MovementView<'_, M> {
+    /// what keeps the player out of `pos`, `None` when it can be walked into
+    pub fn blocker(&self, pos: IVec2) -> Option<Blocker> {
+        let wall = match self.map.get_wall(pos) {
+            Some(wall) => wall,
+            None => return Some(Blocker::Edge),
+        };
+        let blocks = self.walls.get(wall).map_or_else(|| !self.rules.passable_walls.contains(&wall), |flags| flags.blocks_movement);
+        if wall != 0 && blocks {
+            return Some(Blocker::Wall(wall));
+        }
+        if self.is_door_closed(pos) {
+            return Some(Blocker::Door(self.map.get("doors", pos).unwrap_or(0)));
+        }
+        if self.rules.entities_block {
+            if let Some(entity) = self.entities.iter().find(|entity| entity.blocks_movement && entity.pos == pos) {
+                return Some(Blocker::Entity(entity.id));
+            }
+        }
+        let footprints = self.objects.iter()
+            .filter(|(value, _)| self.rules.blocks_object(**value))
+            .map(|(value, def)| (*value, def.footprint.as_slice()));
+        if let Some((object, _)) = covering_object(self.map, pos, footprints).filter(|(object, _)| self.rules.blocks_object(*object)) {
+            return Some(Blocker::Object(object));
+        }
+        None
+    }
+
+    /// `blocker` for a step from `from`, also kept out of cells too far above or below it
+    pub fn step_blocker(&self, from: IVec2, to: IVec2) -> Option<Blocker> {
+        self.blocker(to).or_else(|| {
+            let (here, there) = (self.map.elevation(from), self.map.elevation(to));
+            let falls = self.rules.fall_into_pits && there < 0 && there < here;
+            (!falls && here.abs_diff(there) > self.rules.max_step_height).then(|| Blocker::Ledge { rise: there.saturating_sub(here) })
+        })
+    }
+}
+
+impl<M: MapSource + ?Sized> MapSource for MovementView<'_, M> {
+    fn width(&self) -> usize {
+        self.map.width()
+    }
+
+    fn height(&self) -> usize {
+        self.map.height()
+    }
+
+    fn get(&self, layer: &str, pos: IVec2) -> Option<u8> {
+        self.map.get(layer, pos)
+    }
+
+    /// the view is read only
+    fn set(&mut self, _layer: &str, _pos: IVec2, _value: u8) -> bool {
+        false
+    }
+
+    fn is_door_closed(&self, pos: IVec2) -> bool {
+        self.map.is_door_closed(pos) && !self.open.contains(&pos)
+    }
+
+    fn is_passable(&self, pos: IVec2) -> bool {
+        self.blocker(pos).is_none()
+    }
+
+    fn can_step(&self, from: IVec2, to: IVec2) -> bool {
+        self.step_blocker(from, to).is_none()
+    }
+}
+
+impl<M: MapSource> AtlasWorld<M> {
+    /// the map with the world's doors, entities and `movement_rules` applied
+    pub fn movement_view(&self) -> MovementView<'_, M> {
+        MovementView { map: &self.map, open: &self.open_doors, entities: &self.entities, rules: &self.movement_rules, walls: &self.wall_properties, objects: &self.object_defs }
+    }
+
+    /// Steps the player one cell in `direction`, with the same events as `move_player`, and
+    /// says what was in the way when the step was blocked.
+    pub fn try_move(&mut self, direction: Direction) -> MoveOutcome {
+        let view = MovementView { map: &self.map, open: &self.open_doors, entities: &self.entities, rules: &self.movement_rules, walls: &self.wall_properties, objects: &self.object_defs };
+        let from = ivec2(self.player.x, self.player.y);
+        let result = self.player.step(&view, direction);
+        let outcome = match result {
+            MoveResult::Moved(pos) => MoveOutcome::Moved(pos),
+            MoveResult::Blocked(pos) => MoveOutcome::Blocked { pos, blocker: view.step_blocker(from, pos).unwrap_or(Blocker::Corner) },
+        };
+        self.report_move(from, result);
+        outcome
+    }
+}
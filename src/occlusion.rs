@@ -0,0 +1,64 @@
+//! Working out which cells of the view cone can't be seen past the walls in front of them.
+//!
+//! Cells are tested in view space, x to the right and z toward the player, with cell centers on
+//! whole numbers. Sight lines start anywhere along the camera plane, the near edge of the
+//! player's row, and end at the corners and center of the tested cell. A cell counts as
+//! occluded only when every one of those lines crosses a wall that blocks sight, so partly
+//! hidden cells are still drawn.
+
+use ahash::AHashSet;
+
+use crate::math::*;
+use crate::{AtlasWorld, MapSource};
+
+const EYES: [f32; 5] = [-0.5, -0.25, 0.0, 0.25, 0.5];
+const TARGETS: [(f32, f32); 5] = [(0.0, 0.0), (-0.45, -0.45), (0.45, -0.45), (-0.45, 0.45), (0.45, 0.45)];
+/// samples per cell of sight line length
+const SAMPLES_PER_CELL: f32 = 8.0;
+
+/// Cells in -depth..=0 x -half_width..=half_width that can't be seen from the player's row.
+/// `blocks_sight` is asked about view cells.
+pub fn occluded_cells(depth: i32, half_width: i32, blocks_sight: impl Fn(IVec2) -> bool) -> AHashSet<IVec2> {
+    let mut occluded = AHashSet::new();
+    for z in -depth..=0 {
+        for x in -half_width..=half_width {
+            let cell = ivec2(x, z);
+            if !visible(cell, &blocks_sight) {
+                occluded.insert(cell);
+            }
+        }
+    }
+    occluded
+}
+
+fn visible(cell: IVec2, blocks_sight: &impl Fn(IVec2) -> bool) -> bool {
+    EYES.iter().any(|eye_x| {
+        let eye = vec2(*eye_x, 0.5);
+        TARGETS.iter().any(|(dx, dz)| clear_line(eye, vec2(cell.x as f32 + dx, cell.y as f32 + dz), cell, blocks_sight))
+    })
+}
+
+// walks the line in small steps, only counting points well inside a blocking cell so lines
+// that graze a corner aren't blocked by it
+fn clear_line(from: Vec2, to: Vec2, target: IVec2, blocks_sight: &impl Fn(IVec2) -> bool) -> bool {
+    let steps = ((to - from).length() * SAMPLES_PER_CELL).ceil().max(1.0) as i32;
+    (1..steps).all(|i| {
+        let point = from.lerp(to, i as f32 / steps as f32);
+        let cell = ivec2(point.x.round() as i32, point.y.round() as i32);
+        if cell == target || cell == IVec2::ZERO {
+            return true;
+        }
+        let inset = (point - cell.as_vec2()).abs().max_element();
+        inset > 0.49 || !blocks_sight(cell)
+    })
+}
+
+impl<M: MapSource> AtlasWorld<M> {
+    /// the view cells `render` skips when `occlusion_enabled` is set
+    pub fn occluded_cells(&self) -> AHashSet<IVec2> {
+        occluded_cells(self.render_depth, self.render_width / 2, |cell| {
+            let pos = self.view_cell(cell.x, cell.y);
+            self.map.get_wall(pos).is_some_and(|value| self.wall_hides_view(value)) || self.is_door_closed(pos)
+        })
+    }
+}
@@ -0,0 +1,802 @@
+use std::{cmp::Reverse, collections::BinaryHeap};
+
+use ahash::{AHashMap, AHashSet};
+
+use crate::coords::offset;
+use crate::math::*;
+use crate::{covering_object, AtlasMap, AtlasWorld, Direction, MapSource, MoveResult, Player};
+
+/// What besides walls gets in the way of paths and sight.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct CollisionRules {
+    /// object values that can't be walked through
+    pub blocking_objects: AHashSet<u8>,
+    /// blocking object value -> [x, y] offsets of the other cells it covers, see
+    /// `ObjectDef::footprint`
+    pub footprints: AHashMap<u8, Vec<[i32; 2]>>,
+    /// wall values that can be seen through
+    pub see_through_walls: AHashSet<u8>,
+    /// floor value -> cost of entering the cell, 1 when missing
+    pub move_costs: AHashMap<u8, u32>,
+    /// wall values that can be walked through, see `MovementRules::passable_walls`
+    pub passable_walls: AHashSet<u8>,
+    /// see `MovementRules::max_step_height`
+    pub max_step_height: u8,
+    /// see `MovementRules::fall_into_pits`
+    pub fall_into_pits: bool,
+}
+
+impl CollisionRules {
+    pub fn is_passable<M: MapSource + ?Sized>(&self, map: &M, pos: IVec2) -> bool {
+        let footprints = self.footprints.iter()
+            .filter(|(value, _)| self.blocking_objects.contains(*value))
+            .map(|(value, offsets)| (*value, offsets.as_slice()));
+        let open = map.get_wall(pos).is_some_and(|wall| wall == 0 || self.passable_walls.contains(&wall));
+        open && !map.is_door_closed(pos) && !covering_object(map, pos, footprints).is_some_and(|(value, _)| self.blocking_objects.contains(&value))
+    }
+
+    /// `is_passable` for `to`, which also can't be too far above or below `from`
+    pub fn can_step<M: MapSource + ?Sized>(&self, map: &M, from: IVec2, to: IVec2) -> bool {
+        let (here, there) = (map.elevation(from), map.elevation(to));
+        let falls = self.fall_into_pits && there < 0 && there < here;
+        self.is_passable(map, to) && (falls || here.abs_diff(there) <= self.max_step_height)
+    }
+
+    pub fn move_cost<M: MapSource + ?Sized>(&self, map: &M, pos: IVec2) -> u32 {
+        map.get("floor", pos)
+            .and_then(|value| self.move_costs.get(&value))
+            .map_or(1, |cost| (*cost).max(1))
+    }
+
+    /// walls other than `see_through_walls`, closed doors and cells outside the map
+    pub fn is_opaque<M: MapSource + ?Sized>(&self, map: &M, pos: IVec2) -> bool {
+        match map.get_wall(pos) {
+            Some(value) => (value != 0 && !self.see_through_walls.contains(&value)) || map.is_door_closed(pos),
+            None => true,
+        }
+    }
+}
+
+/// A copy of which cells can be walked through and seen through, cheap to clone and send to
+/// another thread. It doesn't follow later changes to the map.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct PassabilityGrid {
+    pub width: usize,
+    pub height: usize,
+    /// `AtlasWorld::map_revision` this was built from, 0 for grids built straight from a map
+    pub revision: u64,
+    passable: Vec<u64>,
+    opaque: Vec<u64>,
+    costs: Vec<u32>,
+    // floor heights, empty for a flat map
+    elevation: Vec<i8>,
+    max_step_height: u8,
+    fall_into_pits: bool,
+}
+
+impl PassabilityGrid {
+    pub fn from_map<M: MapSource + ?Sized>(map: &M, rules: &CollisionRules) -> Self {
+        let (width, height) = (map.width(), map.height());
+        let words = (width * height).div_ceil(64);
+        let mut grid = Self {
+            width,
+            height,
+            revision: 0,
+            passable: vec![0; words],
+            opaque: vec![0; words],
+            costs: vec![1; width * height],
+            elevation: Vec::new(),
+            max_step_height: rules.max_step_height,
+            fall_into_pits: rules.fall_into_pits,
+        };
+
+        for y in 0..height {
+            for x in 0..width {
+                let pos = ivec2(x as i32, y as i32);
+                let index = y * width + x;
+                if rules.is_passable(map, pos) {
+                    grid.passable[index / 64] |= 1 << (index % 64);
+                }
+                if rules.is_opaque(map, pos) {
+                    grid.opaque[index / 64] |= 1 << (index % 64);
+                }
+                grid.costs[index] = rules.move_cost(map, pos);
+                let elevation = map.elevation(pos);
+                if elevation != 0 && grid.elevation.is_empty() {
+                    grid.elevation = vec![0; width * height];
+                }
+                if let Some(cell) = grid.elevation.get_mut(index) {
+                    *cell = elevation;
+                }
+            }
+        }
+
+        grid
+    }
+
+    fn index(&self, pos: IVec2) -> Option<usize> {
+        let in_bounds = pos.x >= 0 && pos.y >= 0 && (pos.x as usize) < self.width && (pos.y as usize) < self.height;
+        in_bounds.then(|| pos.y as usize * self.width + pos.x as usize)
+    }
+
+    pub fn is_passable(&self, pos: IVec2) -> bool {
+        self.index(pos).is_some_and(|i| self.passable[i / 64] & (1 << (i % 64)) != 0)
+    }
+
+    /// cells outside the grid are opaque
+    pub fn is_opaque(&self, pos: IVec2) -> bool {
+        self.index(pos).is_none_or(|i| self.opaque[i / 64] & (1 << (i % 64)) != 0)
+    }
+
+    /// cost of entering the cell, 1 outside the grid
+    pub fn move_cost(&self, pos: IVec2) -> u32 {
+        self.index(pos).map_or(1, |i| self.costs[i])
+    }
+
+    /// floor height of the cell, 0 outside the grid
+    pub fn elevation(&self, pos: IVec2) -> i8 {
+        self.index(pos).and_then(|i| self.elevation.get(i)).copied().unwrap_or(0)
+    }
+
+    /// `CollisionRules::can_step` as of when the grid was built
+    pub fn can_step(&self, from: IVec2, to: IVec2) -> bool {
+        let (here, there) = (self.elevation(from), self.elevation(to));
+        let falls = self.fall_into_pits && there < 0 && there < here;
+        self.is_passable(to) && (falls || here.abs_diff(there) <= self.max_step_height)
+    }
+
+    pub fn find_path(&self, from: IVec2, to: IVec2) -> Option<Vec<IVec2>> {
+        find_path_with(from, to, |pos| self.is_passable(pos), |from, to| self.can_step(from, to), |pos| self.move_cost(pos))
+    }
+
+    pub fn line_of_sight(&self, from: IVec2, to: IVec2) -> bool {
+        line_of_sight_with(from, to, |pos| self.is_opaque(pos))
+    }
+}
+
+impl AtlasMap {
+    pub fn passability_snapshot(&self, rules: &CollisionRules) -> PassabilityGrid {
+        PassabilityGrid::from_map(self, rules)
+    }
+
+    /// Shortest walk from `start` to `goal` through cells `Player::can_move` allows, both ends
+    /// included. `None` when the goal can't be reached.
+    pub fn find_path(&self, start: IVec2, goal: IVec2) -> Option<Vec<IVec2>> {
+        find_path_with(start, goal, |pos| self.is_passable(pos), |from, to| self.can_step(from, to), |_| 1)
+    }
+
+    /// `find_path` with passable walls, blocking objects, steps and move costs taken from `rules`
+    pub fn find_path_with_rules(&self, start: IVec2, goal: IVec2, rules: &CollisionRules) -> Option<Vec<IVec2>> {
+        find_path_with(start, goal, |pos| rules.is_passable(self, pos), |from, to| rules.can_step(self, from, to), |pos| rules.move_cost(self, pos))
+    }
+}
+
+impl AtlasMap {
+    /// whether nothing but open cells lie between `a` and `b`, the ends themselves may be walls
+    pub fn line_of_sight(&self, a: IVec2, b: IVec2) -> bool {
+        line_of_sight_with(a, b, |pos| self.get_wall(pos) != Some(0))
+    }
+
+    /// Cells within `radius` of `origin` that can be seen from it. Walls are seen, but not
+    /// what is behind them.
+    pub fn visible_cells(&self, origin: IVec2, radius: i32) -> AHashSet<IVec2> {
+        let radius = radius.min(sight_extent(self.width, self.height));
+        let mut visible = visible_cells_with(origin, radius, |pos| self.get_wall(pos) != Some(0));
+        visible.retain(|pos| self.in_bounds(*pos));
+        visible
+    }
+}
+
+impl Player {
+    /// Takes the step after the player's cell on `path`, facing the way it goes. `None` when
+    /// the player isn't on the path or is already at its end.
+    pub fn follow_path<M: MapSource + ?Sized>(&mut self, map: &M, path: &[IVec2]) -> Option<MoveResult> {
+        let here = ivec2(self.x, self.y);
+        let index = path.iter().position(|pos| *pos == here)?;
+        let next = *path.get(index + 1)?;
+        let direction = Direction::from_vec(next - here)?;
+        self.direction = direction;
+        Some(self.step(map, direction))
+    }
+}
+
+impl<M: MapSource> AtlasWorld<M> {
+    /// the collision rules implied by the object defs, wall materials, wall properties and movement rules
+    pub fn collision_rules(&self) -> CollisionRules {
+        CollisionRules {
+            blocking_objects: self.object_defs.iter().filter(|(_, def)| def.blocks_paths).map(|(value, _)| *value).collect(),
+            footprints: self.object_defs.iter()
+                .filter(|(_, def)| def.blocks_paths && !def.footprint.is_empty())
+                .map(|(value, def)| (*value, def.footprint.clone()))
+                .collect(),
+            see_through_walls: self.wall_materials.materials.keys()
+                .chain(self.wall_properties.walls.keys())
+                .filter(|value| !self.wall_blocks_sight(**value))
+                .copied()
+                .collect(),
+            move_costs: self.terrain.terrains.keys().map(|value| (*value, self.terrain.move_cost(*value))).collect(),
+            passable_walls: self.movement_rules.passable_walls.iter()
+                .chain(self.wall_properties.walls.keys())
+                .filter(|value| !self.wall_blocks_movement(**value))
+                .copied()
+                .collect(),
+            max_step_height: self.movement_rules.max_step_height,
+            fall_into_pits: self.movement_rules.fall_into_pits,
+        }
+    }
+
+    pub fn passability_snapshot(&self) -> PassabilityGrid {
+        self.passability_snapshot_with(&self.collision_rules())
+    }
+
+    pub(crate) fn passability_snapshot_with(&self, rules: &CollisionRules) -> PassabilityGrid {
+        let mut grid = PassabilityGrid::from_map(&self.door_view(), rules);
+        grid.revision = self.map_revision;
+        grid
+    }
+
+    /// Cheapest walk from `from` to `to` by terrain move cost, both ends included, on the live
+    /// map. Entering a cell that costs N counts as N steps, so a dry detour around it is taken
+    /// when it's fewer than N - 1 steps longer.
+    pub fn find_path(&self, from: IVec2, to: IVec2) -> Option<Vec<IVec2>> {
+        let rules = self.collision_rules();
+        let map = self.door_view();
+        find_path_with(from, to, |pos| rules.is_passable(&map, pos), |from, to| rules.can_step(&map, from, to), |pos| rules.move_cost(&self.map, pos))
+    }
+
+    /// `AtlasMap::line_of_sight` on the live map, with see-through walls and closed doors
+    pub fn line_of_sight(&self, from: IVec2, to: IVec2) -> bool {
+        let rules = self.collision_rules();
+        let map = self.door_view();
+        line_of_sight_with(from, to, |pos| rules.is_opaque(&map, pos))
+    }
+
+    /// `AtlasMap::visible_cells` on the live map, with see-through walls and closed doors
+    pub fn visible_cells(&self, origin: IVec2, radius: i32) -> AHashSet<IVec2> {
+        let rules = self.collision_rules();
+        let map = self.door_view();
+        let radius = radius.min(sight_extent(self.map.width(), self.map.height()));
+        let mut visible = visible_cells_with(origin, radius, |pos| rules.is_opaque(&map, pos));
+        visible.retain(|pos| self.map.in_bounds(*pos));
+        visible
+    }
+}
+
+// A* over the single steps `can_step` allows in every `Direction`, weighted by the cost of the cell entered (at least 1,
+// which keeps the step-count heuristic admissible). Diagonal steps can't cut between two blocked cells.
+fn find_path_with(
+    from: IVec2,
+    to: IVec2,
+    passable: impl Fn(IVec2) -> bool,
+    can_step: impl Fn(IVec2, IVec2) -> bool,
+    move_cost: impl Fn(IVec2) -> u32,
+) -> Option<Vec<IVec2>> {
+    if !passable(from) || !passable(to) {
+        return None;
+    }
+
+    let diagonal = Direction::ALL.iter().any(|direction| direction.is_diagonal());
+    let heuristic = |pos: IVec2| {
+        let dx = pos.x.abs_diff(to.x);
+        let dy = pos.y.abs_diff(to.y);
+        if diagonal { dx.max(dy) } else { dx.saturating_add(dy) }
+    };
+
+    let mut open = BinaryHeap::new();
+    let mut cost: AHashMap<IVec2, u32> = AHashMap::new();
+    let mut came_from: AHashMap<IVec2, IVec2> = AHashMap::new();
+
+    cost.insert(from, 0);
+    // ties go to the deepest node, which keeps open maps from being searched breadth first
+    open.push(Reverse((heuristic(from), Reverse(0u32), from.x, from.y)));
+
+    while let Some(Reverse((_, Reverse(g), x, y))) = open.pop() {
+        let pos = ivec2(x, y);
+        if pos == to {
+            let mut path = vec![to];
+            let mut current = to;
+            while let Some(previous) = came_from.get(&current) {
+                current = *previous;
+                path.push(current);
+            }
+            path.reverse();
+            return Some(path);
+        }
+        if cost.get(&pos).is_some_and(|best| g > *best) {
+            continue;
+        }
+
+        for direction in Direction::ALL {
+            let v = direction.to_vec();
+            let next = crate::coords::offset(pos, v, 1);
+            if !can_step(pos, next) {
+                continue;
+            }
+            if direction.is_diagonal()
+                && !passable(ivec2(pos.x.saturating_add(v.x), pos.y))
+                && !passable(ivec2(pos.x, pos.y.saturating_add(v.y)))
+            {
+                continue;
+            }
+
+            let next_cost = g.saturating_add(move_cost(next).max(1));
+            if cost.get(&next).is_none_or(|best| next_cost < *best) {
+                cost.insert(next, next_cost);
+                came_from.insert(next, pos);
+                open.push(Reverse((next_cost.saturating_add(heuristic(next)), Reverse(next_cost), next.x, next.y)));
+            }
+        }
+    }
+
+    None
+}
+
+// recursive shadowcasting, one octant at a time, see `cast_octant`
+// no cell of a `width` by `height` map is further than this from another, so a larger radius
+// sees nothing more
+fn sight_extent(width: usize, height: usize) -> i32 {
+    i32::try_from(width.saturating_add(height)).unwrap_or(i32::MAX)
+}
+
+fn visible_cells_with(origin: IVec2, radius: i32, opaque: impl Fn(IVec2) -> bool) -> AHashSet<IVec2> {
+    const OCTANTS: [[i32; 4]; 8] = [
+        [1, 0, 0, 1],
+        [0, 1, 1, 0],
+        [0, -1, 1, 0],
+        [-1, 0, 0, 1],
+        [-1, 0, 0, -1],
+        [0, -1, -1, 0],
+        [0, 1, -1, 0],
+        [1, 0, 0, -1],
+    ];
+
+    let mut visible = AHashSet::new();
+    visible.insert(origin);
+    for octant in OCTANTS {
+        cast_octant(origin, radius, 1, 1.0, 0.0, octant, &opaque, &mut visible);
+    }
+    visible
+}
+
+// Scans rows of one octant outward from `row`, between the slopes `start` and `end`. A run of
+// opaque cells splits the scan: what lies past it is scanned by a recursive call on the part
+// before the run, and this call carries on after it.
+#[allow(clippy::too_many_arguments)]
+fn cast_octant(
+    origin: IVec2,
+    radius: i32,
+    row: i32,
+    mut start: f32,
+    end: f32,
+    [xx, xy, yx, yy]: [i32; 4],
+    opaque: &impl Fn(IVec2) -> bool,
+    visible: &mut AHashSet<IVec2>,
+) {
+    if start < end {
+        return;
+    }
+    let mut next_start = start;
+    for distance in row..=radius {
+        let dy = -distance;
+        let mut blocked = false;
+        for dx in -distance..=0 {
+            let left_slope = (dx as f32 - 0.5) / (dy as f32 + 0.5);
+            let right_slope = (dx as f32 + 0.5) / (dy as f32 - 0.5);
+            if start < right_slope {
+                continue;
+            }
+            if end > left_slope {
+                break;
+            }
+
+            let pos = offset(offset(origin, ivec2(xx, yx), dx), ivec2(xy, yy), dy);
+            // widened, the squares of a radius past 46340 don't fit in an i32
+            let (x, y, r) = (dx as i64, dy as i64, radius as i64);
+            if x * x + y * y <= r * r {
+                visible.insert(pos);
+            }
+
+            if blocked {
+                if opaque(pos) {
+                    next_start = right_slope;
+                } else {
+                    blocked = false;
+                    start = next_start;
+                }
+            } else if opaque(pos) && distance < radius {
+                blocked = true;
+                cast_octant(origin, radius, distance + 1, start, left_slope, [xx, xy, yx, yy], opaque, visible);
+                next_start = right_slope;
+            }
+        }
+        if blocked {
+            break;
+        }
+    }
+}
+
+// walks the cells between the two ends (Bresenham), any opaque one in between blocks the view
+fn line_of_sight_with(from: IVec2, to: IVec2, opaque: impl Fn(IVec2) -> bool) -> bool {
+    let (dx, dy) = (to.x.abs_diff(from.x) as i64, -(to.y.abs_diff(from.y) as i64));
+    let step_x = if from.x < to.x { 1 } else { -1 };
+    let step_y = if from.y < to.y { 1 } else { -1 };
+
+    let (mut x, mut y) = (from.x as i64, from.y as i64);
+    let mut error = dx + dy;
+    loop {
+        if (x, y) == (to.x as i64, to.y as i64) {
+            return true;
+        }
+        if (x, y) != (from.x as i64, from.y as i64) && opaque(ivec2(x as i32, y as i32)) {
+            return false;
+        }
+        let doubled = 2 * error;
+        if doubled >= dy {
+            error += dy;
+            x += step_x;
+        }
+        if doubled <= dx {
+            error += dx;
+            y += step_y;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::generator::{rooms_and_corridors, GeneratorConfig, Rng};
+    use crate::{AtlasCollection, Grid, WallFlags};
+
+    // a 5x1 corridor with a door in the middle
+    fn world() -> AtlasWorld {
+        let mut map = AtlasMap::empty(5, 1);
+        let mut doors = Grid::new(5, 1);
+        doors.set(2, 0, 1);
+        map.layers.insert("doors".to_owned(), doors);
+        AtlasWorld::new(Player::new(0, 0, Direction::East), map, AtlasCollection::new())
+    }
+
+    // '#' is wall 1, anything else open
+    fn drawn(rows: &[&str]) -> AtlasMap {
+        let mut map = AtlasMap::empty(rows[0].len(), rows.len());
+        for (y, row) in rows.iter().enumerate() {
+            for (x, cell) in row.bytes().enumerate() {
+                map.wall.set(x, y, u8::from(cell == b'#'));
+            }
+        }
+        map
+    }
+
+    #[test]
+    fn a_pillar_hides_what_is_behind_it() {
+        let map = drawn(&[
+            ".......",
+            ".......",
+            ".......",
+            "...#...",
+            ".......",
+            ".......",
+            ".......",
+        ]);
+        let (origin, pillar) = (ivec2(1, 3), ivec2(3, 3));
+        assert!(map.line_of_sight(origin, pillar));
+        assert!(!map.line_of_sight(origin, ivec2(5, 3)));
+        assert!(!map.line_of_sight(origin, ivec2(6, 3)));
+        assert!(map.line_of_sight(origin, ivec2(6, 0)));
+
+        let visible = map.visible_cells(origin, 10);
+        assert!(visible.contains(&pillar));
+        assert!(!visible.contains(&ivec2(5, 3)) && !visible.contains(&ivec2(6, 3)));
+        assert!(visible.contains(&ivec2(6, 0)) && visible.contains(&ivec2(6, 6)));
+    }
+
+    #[test]
+    fn a_corridor_shows_its_walls_and_nothing_past_them() {
+        let map = drawn(&[
+            ".#.#.",
+            ".#.#.",
+            ".#.#.",
+            ".#.#.",
+            ".#.#.",
+        ]);
+        let origin = ivec2(2, 4);
+        assert!(map.line_of_sight(origin, ivec2(2, 0)));
+        assert!(!map.line_of_sight(origin, ivec2(0, 0)));
+
+        let visible = map.visible_cells(origin, 10);
+        for y in 0..5 {
+            assert!(visible.contains(&ivec2(2, y)), "floor at y {y}");
+        }
+        assert!(visible.contains(&ivec2(1, 3)) && visible.contains(&ivec2(3, 3)));
+        assert!((0..5).all(|y| !visible.contains(&ivec2(0, y)) && !visible.contains(&ivec2(4, y))));
+    }
+
+    #[test]
+    fn sight_passes_diagonal_gaps() {
+        let map = drawn(&[
+            ".#..",
+            "#...",
+            "....",
+            "....",
+        ]);
+        let origin = ivec2(0, 0);
+        // the walls touch only at their corners, so the diagonal runs between them
+        assert!((1..4).all(|i| map.line_of_sight(origin, ivec2(i, i))));
+        // but nothing straight past either of them shows
+        assert!(!map.line_of_sight(origin, ivec2(2, 0)) && !map.line_of_sight(origin, ivec2(0, 2)));
+
+        let visible = map.visible_cells(origin, 5);
+        assert!((1..4).all(|i| visible.contains(&ivec2(i, i))));
+        for pos in [ivec2(2, 0), ivec2(3, 0), ivec2(0, 2), ivec2(0, 3)] {
+            assert!(!visible.contains(&pos), "{pos}");
+        }
+    }
+
+    #[test]
+    fn vision_stays_on_the_map_and_in_the_radius() {
+        let map = drawn(&[
+            "...",
+            "...",
+            "...",
+        ]);
+        let visible = map.visible_cells(ivec2(0, 0), 1);
+        let mut visible: Vec<IVec2> = visible.into_iter().collect();
+        visible.sort_by_key(|pos| (pos.y, pos.x));
+        assert_eq!(visible, [ivec2(0, 0), ivec2(1, 0), ivec2(0, 1)]);
+        assert_eq!(map.visible_cells(ivec2(1, 1), 0).len(), 1);
+        assert_eq!(map.visible_cells(ivec2(1, 1), i32::MAX).len(), 9);
+        assert_eq!(map.visible_cells(ivec2(i32::MAX, 0), i32::MAX).len(), 0);
+    }
+
+    #[test]
+    fn closed_doors_block_sight() {
+        let mut world = world();
+        assert!(!world.line_of_sight(ivec2(0, 0), ivec2(4, 0)));
+        assert!(!world.visible_cells(ivec2(0, 0), 4).contains(&ivec2(3, 0)));
+        assert!(world.passability_snapshot().is_opaque(ivec2(2, 0)));
+
+        world.set_open_doors([ivec2(2, 0)]);
+        assert!(world.line_of_sight(ivec2(0, 0), ivec2(4, 0)));
+        assert!(world.visible_cells(ivec2(0, 0), 4).contains(&ivec2(3, 0)));
+        assert!(!world.passability_snapshot().is_opaque(ivec2(2, 0)));
+    }
+
+    // a 5x3 room split by a column of wall 7 at x = 2, with a gap at (2, 2) up a step
+    fn split_room() -> AtlasWorld {
+        let mut map = AtlasMap::empty(5, 3);
+        map.wall.set(2, 0, 7);
+        map.wall.set(2, 1, 7);
+        map.elevation = Grid::new(5, 3);
+        map.elevation.set(2, 2, 2);
+        AtlasWorld::new(Player::new(0, 1, Direction::East), map, AtlasCollection::new())
+    }
+
+    #[test]
+    fn paths_follow_the_movement_rules() {
+        let mut world = split_room();
+        let (from, to) = (ivec2(0, 1), ivec2(4, 1));
+        // the only gap is too high to step into
+        assert_eq!(world.find_path(from, to), None);
+        assert_eq!(world.passability_snapshot().find_path(from, to), None);
+
+        world.movement_rules.max_step_height = 2;
+        assert!(world.find_path(from, to).unwrap().contains(&ivec2(2, 2)));
+        assert!(world.passability_snapshot().find_path(from, to).unwrap().contains(&ivec2(2, 2)));
+
+        // walking through the wall is shorter once it's passable
+        world.movement_rules.passable_walls.insert(7);
+        assert_eq!(world.find_path(from, to).map(|path| path.len()), Some(5));
+        assert_eq!(world.passability_snapshot().find_path(from, to).map(|path| path.len()), Some(5));
+
+        // and wall properties win over the movement rules
+        world.wall_properties.insert(7, WallFlags::default());
+        assert!(world.find_path(from, to).unwrap().contains(&ivec2(2, 2)));
+        assert!(!world.passability_snapshot().is_passable(ivec2(2, 1)));
+    }
+
+    #[test]
+    fn pits_can_be_fallen_into_but_not_climbed_out_of() {
+        let mut world = split_room();
+        world.map.elevation.set(2, 2, (-3i8) as u8);
+        world.movement_rules.fall_into_pits = true;
+        let grid = world.passability_snapshot();
+        assert!(grid.can_step(ivec2(1, 2), ivec2(2, 2)));
+        assert!(!grid.can_step(ivec2(2, 2), ivec2(3, 2)));
+        assert_eq!(world.find_path(ivec2(0, 1), ivec2(4, 1)), None);
+    }
+
+    fn send_and_sync<T: Send + Sync + Clone + 'static>() {}
+
+    // generated maps with doors, some of them open, and a blocking object
+    fn random_world(rng: &mut Rng, seed: u64) -> AtlasWorld {
+        let config = GeneratorConfig { objects: vec![(1, 1), (2, 1)], object_chance: 0.1, ..GeneratorConfig::default() };
+        let generated = rooms_and_corridors(rng.range(8, 40), rng.range(8, 40), &config, seed);
+        let mut map = generated.map;
+        let mut doors = Grid::new(map.width, map.height);
+        let mut open = Vec::new();
+        for y in 0..map.height {
+            for x in 0..map.width {
+                if map.wall.get(x, y) == Some(0) && rng.chance(0.05) {
+                    doors.set(x, y, 1);
+                    if rng.chance(0.5) {
+                        open.push(ivec2(x as i32, y as i32));
+                    }
+                }
+            }
+        }
+        map.layers.insert("doors".to_owned(), doors);
+        let mut world = AtlasWorld::new(Player::new(generated.spawn.x, generated.spawn.y, Direction::North), map, AtlasCollection::new());
+        world.object_defs.insert(1, crate::ObjectDef { blocks_paths: true, ..Default::default() });
+        world.set_open_doors(open);
+        world
+    }
+
+    #[test]
+    fn snapshots_agree_with_the_live_map() {
+        send_and_sync::<PassabilityGrid>();
+        let mut rng = Rng::new(218);
+        let mut found = 0;
+        for seed in 0..60 {
+            let world = random_world(&mut rng, seed);
+            let grid = world.passability_snapshot();
+            let cell = |rng: &mut Rng| ivec2(rng.below(world.map.width) as i32, rng.below(world.map.height) as i32);
+            for _ in 0..20 {
+                let (from, to) = (cell(&mut rng), cell(&mut rng));
+                let path = grid.find_path(from, to);
+                assert_eq!(path, world.find_path(from, to), "seed {seed}: {from} -> {to}");
+                found += usize::from(path.is_some());
+                assert_eq!(grid.line_of_sight(from, to), world.line_of_sight(from, to), "seed {seed}: {from} -> {to}");
+            }
+        }
+        assert!(found > 50, "only {found} paths found");
+    }
+
+    #[test]
+    fn snapshots_find_paths_on_another_thread() {
+        let mut rng = Rng::new(2180);
+        let world = random_world(&mut rng, 7);
+        let grid = world.passability_snapshot();
+        let cells: Vec<IVec2> = (0..world.map.height as i32)
+            .flat_map(|y| (0..world.map.width as i32).map(move |x| ivec2(x, y)))
+            .filter(|pos| grid.is_passable(*pos))
+            .collect();
+        let from = ivec2(world.player.x, world.player.y);
+        let goals: Vec<IVec2> = (0..12).map(|_| cells[rng.below(cells.len())]).collect();
+
+        let worker = {
+            let (grid, goals) = (grid.clone(), goals.clone());
+            std::thread::spawn(move || goals.iter().map(|goal| grid.find_path(from, *goal)).collect::<Vec<_>>())
+        };
+        let live: Vec<Option<Vec<IVec2>>> = goals.iter().map(|goal| world.find_path(from, *goal)).collect();
+        assert_eq!(worker.join().unwrap(), live);
+    }
+
+    // steps from `from` to every cell `Player::step` can reach, breadth first
+    fn step_distances(map: &AtlasMap, from: IVec2) -> AHashMap<IVec2, usize> {
+        let mut distances = AHashMap::from_iter([(from, 0)]);
+        let mut queue = std::collections::VecDeque::from([from]);
+        while let Some(pos) = queue.pop_front() {
+            for direction in Direction::ALL {
+                let mut player = Player::new(pos.x, pos.y, direction);
+                if let MoveResult::Moved(next) = player.step(map, direction) {
+                    if !distances.contains_key(&next) {
+                        distances.insert(next, distances[&pos] + 1);
+                        queue.push_back(next);
+                    }
+                }
+            }
+        }
+        distances
+    }
+
+    #[test]
+    fn map_paths_are_shortest_walks_the_player_can_take() {
+        let mut rng = Rng::new(265);
+        let mut found = 0;
+        for seed in 0..40 {
+            let map = rooms_and_corridors(rng.range(8, 40), rng.range(8, 40), &GeneratorConfig::default(), seed).map;
+            // mostly open cells, with walls now and then
+            let cell = |rng: &mut Rng| loop {
+                let pos = ivec2(rng.below(map.width) as i32, rng.below(map.height) as i32);
+                if map.is_passable(pos) || rng.chance(0.1) {
+                    break pos;
+                }
+            };
+            for _ in 0..10 {
+                let (from, to) = (cell(&mut rng), cell(&mut rng));
+                let path = map.find_path(from, to);
+                let reachable = match map.is_passable(from) {
+                    true => step_distances(&map, from).get(&to).copied(),
+                    false => None,
+                };
+                assert_eq!(path.as_ref().map(|path| path.len() - 1), reachable, "seed {seed}: {from} -> {to}");
+                let Some(path) = path else {
+                    continue;
+                };
+                found += 1;
+                assert_eq!((path[0], path[path.len() - 1]), (from, to));
+                // following it walks the player there one step at a time
+                let mut player = Player::new(from.x, from.y, Direction::North);
+                for step in path.windows(2) {
+                    assert_eq!(player.follow_path(&map, &path), Some(MoveResult::Moved(step[1])));
+                    assert_eq!(Some(player.direction), Direction::from_vec(step[1] - step[0]));
+                }
+                assert_eq!(player.follow_path(&map, &path), None);
+            }
+        }
+        assert!(found > 100, "only {found} paths found");
+    }
+
+    #[test]
+    fn map_paths_handle_the_edge_cases() {
+        let map = drawn(&[
+            "..#..",
+            "..#..",
+            "..#..",
+        ]);
+        assert_eq!(map.find_path(ivec2(1, 1), ivec2(1, 1)), Some(vec![ivec2(1, 1)]));
+        // walled off, in a wall and off the map
+        assert_eq!(map.find_path(ivec2(0, 0), ivec2(4, 0)), None);
+        assert_eq!(map.find_path(ivec2(0, 0), ivec2(2, 1)), None);
+        assert_eq!(map.find_path(ivec2(2, 1), ivec2(2, 1)), None);
+        assert_eq!(map.find_path(ivec2(0, 0), ivec2(-1, 0)), None);
+        assert_eq!(map.find_path(ivec2(i32::MAX, 0), ivec2(0, 0)), None);
+    }
+
+    #[test]
+    fn blocking_objects_only_block_paths_when_asked_to() {
+        let mut map = drawn(&[
+            "#####",
+            "#...#",
+            "#####",
+        ]);
+        map.object.set(2, 1, 4);
+        let (from, to) = (ivec2(1, 1), ivec2(3, 1));
+        assert_eq!(map.find_path(from, to).map(|path| path.len()), Some(3));
+        let rules = CollisionRules { blocking_objects: AHashSet::from_iter([4]), ..Default::default() };
+        assert_eq!(map.find_path_with_rules(from, to, &rules), None);
+        let rules = CollisionRules { blocking_objects: AHashSet::from_iter([5]), ..Default::default() };
+        assert_eq!(map.find_path_with_rules(from, to, &rules).map(|path| path.len()), Some(3));
+    }
+
+    #[test]
+    fn following_a_path_stops_where_it_is_blocked_or_left() {
+        let mut map = drawn(&[
+            "....",
+            "....",
+        ]);
+        let path = map.find_path(ivec2(0, 0), ivec2(3, 0)).unwrap();
+        map.wall.set(2, 0, 1);
+        let mut player = Player::new(0, 0, Direction::South);
+        assert_eq!(player.follow_path(&map, &path), Some(MoveResult::Moved(ivec2(1, 0))));
+        assert_eq!(player.direction, Direction::East);
+        // the wall put up since stops it, still facing the way the path goes
+        assert_eq!(player.follow_path(&map, &path), Some(MoveResult::Blocked(ivec2(2, 0))));
+        assert_eq!((player.x, player.y, player.direction), (1, 0, Direction::East));
+        // off the path there's no next step
+        player.y = 1;
+        assert_eq!(player.follow_path(&map, &path), None);
+        // nor between cells that aren't neighbors
+        let mut player = Player::new(0, 0, Direction::South);
+        assert_eq!(player.follow_path(&map, &[ivec2(0, 0), ivec2(3, 1)]), None);
+        assert_eq!(player.direction, Direction::South);
+    }
+
+    #[test]
+    fn large_maps_are_searched_end_to_end() {
+        // a 200x200 serpentine: every other row a wall with a gap at alternating ends, so the
+        // path runs the whole width of every row
+        let mut map = AtlasMap::empty(200, 200);
+        for y in (1..199).step_by(2) {
+            for x in 0..200 {
+                map.wall.set(x, y, 1);
+            }
+            map.wall.set(if y % 4 == 1 { 199 } else { 0 }, y, 0);
+        }
+        let open = AtlasMap::empty(200, 200);
+        let (from, to) = (ivec2(0, 0), ivec2(199, 199));
+        assert!(map.find_path(from, to).unwrap().len() > 99 * 199);
+        assert_eq!(open.find_path(from, to).unwrap().len(), 200 + if Direction::ALL.len() == 8 { 0 } else { 199 });
+    }
+}
@@ -0,0 +1,53 @@
+//! Leaning to look around a corner without stepping. The view slides sideways toward the
+//! neighboring cell: the baked `screen_coords` can't be re-projected, so every row is shifted by
+//! one cell's width at its depth, which is close enough for the short distance of a lean.
+
+use crate::{AtlasWorld, MapSource, RenderStats, ViewPoint};
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum PeekSide {
+    Left,
+    Right,
+}
+
+impl PeekSide {
+    /// the view-space x the lean goes toward
+    pub fn sign(self) -> i32 {
+        match self {
+            PeekSide::Left => -1,
+            PeekSide::Right => 1,
+        }
+    }
+}
+
+impl<M: MapSource> AtlasWorld<M> {
+    /// Renders the view leaned `amount` (0..1) of the way into the cell beside the player on
+    /// `side`. When that cell can't be walked into the view isn't leaned at all. The first half
+    /// of a lean shifts the player's view, the second half shifts the neighbor's view back, so a
+    /// full lean shows exactly what the neighboring cell sees.
+    pub fn render_peek(&self, side: PeekSide, amount: f32) -> RenderStats {
+        let view = self.player.view_point();
+        let neighbor = ViewPoint::new(view.get_direction_vector_offsets(side.sign(), 0), view.direction);
+        let amount = amount.clamp(0.0, 1.0);
+        if amount == 0.0 || !self.door_view().is_passable(neighbor.pos) {
+            return self.render_from(view);
+        }
+
+        // view-space cells the camera has moved from the view it draws
+        let (from, shift) = if amount <= 0.5 { (view, amount) } else { (neighbor, amount - 1.0) };
+        self.peek_shift.set(shift * side.sign() as f32);
+        let stats = self.render_from(from);
+        self.peek_shift.set(0.0);
+        stats
+    }
+
+    /// Screen pixels to move tiles of view row `z` by for the current lean. A cell in row `z`
+    /// is drawn about `1 / (1 - z)` as wide as the viewport.
+    pub(crate) fn peek_offset(&self, z: i32) -> f32 {
+        let shift = self.peek_shift.get();
+        if shift == 0.0 {
+            return 0.0;
+        }
+        -shift * self.viewport_rect().w / (1 - z.min(0)) as f32
+    }
+}
@@ -0,0 +1,58 @@
+//! Which cell and surface is under a point of the rendered view, for click-to-move and
+//! click-to-interact. The view is captured like `capture_commands` does and the quads tested
+//! last drawn first, so the nearest surface wins. Quads are tested by their whole rect, so a
+//! click on a transparent part of a sprite still picks it.
+
+use crate::math::*;
+use crate::{render_order::SKY_ORDER, AtlasWorld, CellPass, MapSource};
+
+/// The kind of quad a pick landed on.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum PickSurface {
+    /// the floor or anything else drawn flat on it
+    Floor,
+    Ceiling,
+    /// a wall or door facing the viewer
+    FrontWall,
+    /// the side of a wall left of the view, turned toward its middle
+    LeftWall,
+    RightWall,
+    /// an object or entity
+    Object,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct PickResult {
+    /// view-space cell, x to the right and z toward the viewer
+    pub view: IVec2,
+    /// map cell
+    pub cell: IVec2,
+    pub surface: PickSurface,
+}
+
+impl<M: MapSource> AtlasWorld<M> {
+    /// What was drawn at `point` of the view, in the `viewport_rect` space the tiles are laid
+    /// out in. `None` where nothing but the sky was drawn.
+    pub fn pick(&self, point: Vec2) -> Option<PickResult> {
+        let commands = self.capture_commands();
+        let hit = commands.iter().rev().find(|command| command.order != SKY_ORDER && command.dest.contains(point))?;
+        let IVec2 { x, y: z } = self.render_config.draw_order.cell(hit.order);
+        let surface = match CellPass::ALL.get(hit.order.2 as usize)? {
+            CellPass::Floor => PickSurface::Floor,
+            CellPass::Ceiling => PickSurface::Ceiling,
+            CellPass::SideWalls if x < 0 || (x == 0 && hit.dest.center().x < self.viewport_rect().center().x) => PickSurface::LeftWall,
+            CellPass::SideWalls => PickSurface::RightWall,
+            CellPass::FrontWall => PickSurface::FrontWall,
+            CellPass::Object => PickSurface::Object,
+        };
+        Some(PickResult { view: ivec2(x, z), cell: self.view_cell(x, z), surface })
+    }
+
+    /// `pick` at a window position, for a view presented into `area` by `Viewport::present`
+    pub fn pick_window(&self, window: Vec2, area: Rect) -> Option<PickResult> {
+        let pixel = self.viewport.window_to_viewport(window, area)?;
+        let authored = self.viewport_rect();
+        let scale = vec2(authored.w / self.viewport.width.max(1) as f32, authored.h / self.viewport.height.max(1) as f32);
+        self.pick(pixel * scale)
+    }
+}
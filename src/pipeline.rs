@@ -0,0 +1,318 @@
+//! JSON in, JSON out entry points for content pipelines written in other languages. Every
+//! function takes and returns plain serde types so the shapes below are the schema; `run`
+//! dispatches on a command name like the `atlas_world_tool` binary does.
+
+use std::{collections::VecDeque, fmt};
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::math::*;
+use crate::{generator::Rng, AtlasInfo, AtlasMap, CollisionRules, MapSource, PassabilityGrid, PreflightReport, RenderConfig, ValidationReport, Warp};
+
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq)]
+pub struct MapReport {
+    /// layers whose row or column counts don't match `width` x `height`
+    pub errors: Vec<String>,
+    /// open cells that can't be walked to from the first open cell, as [x, y]
+    pub unreachable: Vec<[i32; 2]>,
+}
+
+/// Shape checks and a connectivity check over the open cells of a map.
+pub fn validate_map(map: &AtlasMap) -> MapReport {
+    let mut report = MapReport { errors: shape_errors(map), ..Default::default() };
+    if !report.errors.is_empty() {
+        return report;
+    }
+
+    let mut scan = ConnectivityScan::new(map);
+    while !scan.step(map) {}
+    report.unreachable = scan.unreachable();
+    report
+}
+
+pub(crate) fn shape_errors(map: &AtlasMap) -> Vec<String> {
+    let mut errors = Vec::new();
+    // the autotile and light layers are empty until baked, and decals and elevation are optional
+    let names = map.layer_names().filter(|name| match *name {
+        "autotile" | "light" => false,
+        "decal" => !map.decal.is_empty(),
+        "elevation" => !map.elevation.is_empty(),
+        _ => true,
+    });
+    for (name, layer) in names.filter_map(|name| Some((name, map.layer(name)?))) {
+        if layer.height() != map.height {
+            errors.push(format!("{name} layer has {} rows, expected {}", layer.height(), map.height));
+        }
+        if layer.width() != map.width {
+            errors.push(format!("{name} layer rows have {} cells, expected {}", layer.width(), map.width));
+        }
+    }
+    errors
+}
+
+fn open_cells(map: &AtlasMap) -> Vec<IVec2> {
+    (0..map.height as i32)
+        .flat_map(|y| (0..map.width as i32).map(move |x| ivec2(x, y)))
+        .filter(|pos| map.is_passable(*pos))
+        .collect()
+}
+
+/// Flood fill from the first open cell, one cell per `step`. Only for maps without shape errors.
+pub(crate) struct ConnectivityScan {
+    open: Vec<IVec2>,
+    reached: Vec<Vec<bool>>,
+    reached_count: usize,
+    queue: VecDeque<IVec2>,
+}
+
+impl ConnectivityScan {
+    pub(crate) fn new(map: &AtlasMap) -> Self {
+        let open = open_cells(map);
+        let start = open.first().copied();
+        Self::start(map, open, start)
+    }
+
+    /// a fill from `start` instead, which reaches nothing when it isn't open
+    pub(crate) fn from_cell(map: &AtlasMap, start: IVec2) -> Self {
+        let open = open_cells(map);
+        Self::start(map, open, Some(start).filter(|start| map.is_passable(*start)))
+    }
+
+    fn start(map: &AtlasMap, open: Vec<IVec2>, start: Option<IVec2>) -> Self {
+        let mut reached = vec![vec![false; map.width]; map.height];
+        let mut queue = VecDeque::new();
+        if let Some(start) = start {
+            reached[start.y as usize][start.x as usize] = true;
+            queue.push_back(start);
+        }
+        Self { reached_count: queue.len(), open, reached, queue }
+    }
+
+    /// visits one cell, true once the fill is done
+    pub(crate) fn step(&mut self, map: &AtlasMap) -> bool {
+        let Some(pos) = self.queue.pop_front() else {
+            return true;
+        };
+        for direction in crate::Direction::CARDINALS {
+            let next = pos + direction.to_vec();
+            if map.is_passable(next) && !self.reached[next.y as usize][next.x as usize] {
+                self.reached[next.y as usize][next.x as usize] = true;
+                self.reached_count += 1;
+                self.queue.push_back(next);
+            }
+        }
+        self.queue.is_empty()
+    }
+
+    /// reached cells over all open cells, a lower bound when parts are unreachable
+    pub(crate) fn progress(&self) -> f32 {
+        if self.open.is_empty() {
+            1.0
+        } else {
+            self.reached_count as f32 / self.open.len() as f32
+        }
+    }
+
+    pub(crate) fn unreachable(&self) -> Vec<[i32; 2]> {
+        self.open.iter()
+            .filter(|pos| !self.reached[pos.y as usize][pos.x as usize])
+            .map(|pos| [pos.x, pos.y])
+            .collect()
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+pub struct PreflightInput {
+    pub map: AtlasMap,
+    /// [x, y] of the player's spawn cell
+    pub spawn: [i32; 2],
+    /// atlas id -> atlas json as the crate loads it
+    pub atlases: std::collections::BTreeMap<String, AtlasInfo>,
+}
+
+/// `AtlasWorld::preflight` without textures, with the default render settings
+pub fn preflight(input: &PreflightInput) -> Vec<String> {
+    let atlases: Vec<(&str, &AtlasInfo)> = input.atlases.iter().map(|(id, info)| (id.as_str(), info)).collect();
+    let report = PreflightReport::check(
+        &input.map,
+        ivec2(input.spawn[0], input.spawn[1]),
+        &atlases,
+        &RenderConfig::default(),
+        9,
+        22,
+    );
+    report.warnings.iter().map(|warning| warning.to_string()).collect()
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+pub struct ValidateInput {
+    pub map: AtlasMap,
+    /// [x, y] of the player's spawn cell
+    pub spawn: [i32; 2],
+    #[serde(default)]
+    pub warps: Vec<Warp>,
+    /// atlas id -> atlas json, the atlas checks are skipped when empty
+    #[serde(default)]
+    pub atlases: std::collections::BTreeMap<String, AtlasInfo>,
+}
+
+/// every check there is, as `ValidationReport::for_world` with the default render settings
+pub fn validate_all(input: &ValidateInput) -> ValidationReport {
+    let spawn = ivec2(input.spawn[0], input.spawn[1]);
+    if input.atlases.is_empty() {
+        return ValidationReport::for_map(&input.map, spawn, &input.warps);
+    }
+    let atlases: Vec<(&str, &AtlasInfo)> = input.atlases.iter().map(|(id, info)| (id.as_str(), info)).collect();
+    ValidationReport::for_world(&input.map, spawn, &input.warps, &atlases, &RenderConfig::default(), 9, 22)
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct PathQuery {
+    pub map: AtlasMap,
+    pub from: [i32; 2],
+    pub to: [i32; 2],
+    /// object values that block the path
+    #[serde(default)]
+    pub blocking_objects: Vec<u8>,
+}
+
+/// the cells of the shortest path as [x, y], both ends included, `None` when there is none
+pub fn find_path(query: &PathQuery) -> Option<Vec<[i32; 2]>> {
+    let rules = CollisionRules {
+        blocking_objects: query.blocking_objects.iter().copied().collect(),
+        ..Default::default()
+    };
+    let grid = PassabilityGrid::from_map(&query.map, &rules);
+    let path = grid.find_path(ivec2(query.from[0], query.from[1]), ivec2(query.to[0], query.to[1]))?;
+    Some(path.iter().map(|pos| [pos.x, pos.y]).collect())
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct GenerateParams {
+    pub width: usize,
+    pub height: usize,
+    #[serde(default)]
+    pub seed: u64,
+    /// wall value used for solid cells
+    #[serde(default = "default_wall")]
+    pub wall: u8,
+}
+
+fn default_wall() -> u8 {
+    1
+}
+
+/// A maze with corridors on odd cells and floor and ceiling value 1 everywhere, the same
+/// params and seed always give the same map.
+pub fn generate_map(params: &GenerateParams) -> AtlasMap {
+    let mut carver = MazeCarver::new(params);
+    while !carver.step() {}
+    carver.map
+}
+
+/// Depth first maze carving, one corridor cell per `step`.
+pub(crate) struct MazeCarver {
+    pub(crate) map: AtlasMap,
+    stack: Vec<IVec2>,
+    rng: Rng,
+    carved: usize,
+    total: usize,
+}
+
+impl MazeCarver {
+    pub(crate) fn new(params: &GenerateParams) -> Self {
+        let (width, height) = (params.width.max(3), params.height.max(3));
+        let mut map = AtlasMap::empty(width, height);
+        map.wall.fill(params.wall);
+        map.floor.fill(1);
+        map.ceiling.fill(1);
+        map.wall.set(1, 1, 0);
+
+        Self {
+            map,
+            stack: vec![ivec2(1, 1)],
+            rng: Rng::new(params.seed),
+            carved: 1,
+            total: ((width - 1) / 2) * ((height - 1) / 2),
+        }
+    }
+
+    /// carves or backtracks once, true when the maze is done
+    pub(crate) fn step(&mut self) -> bool {
+        let Some(pos) = self.stack.last().copied() else {
+            return true;
+        };
+        let (width, height) = (self.map.width as i32, self.map.height as i32);
+
+        let mut directions = crate::Direction::CARDINALS;
+        for i in (1..directions.len()).rev() {
+            directions.swap(i, self.rng.below(i + 1));
+        }
+
+        let step = directions.iter().map(|direction| direction.to_vec()).find(|v| {
+            let target = pos + *v * 2;
+            target.x > 0 && target.y > 0 && target.x < width - 1 && target.y < height - 1
+                && self.map.wall.get(target.x as usize, target.y as usize) != Some(0)
+        });
+
+        match step {
+            Some(v) => {
+                let (between, target) = (pos + v, pos + v * 2);
+                self.map.wall.set(between.x as usize, between.y as usize, 0);
+                self.map.wall.set(target.x as usize, target.y as usize, 0);
+                self.stack.push(target);
+                self.carved += 1;
+            }
+            None => {
+                self.stack.pop();
+            }
+        }
+        self.stack.is_empty()
+    }
+
+    pub(crate) fn progress(&self) -> f32 {
+        if self.stack.is_empty() {
+            1.0
+        } else {
+            (self.carved as f32 / self.total.max(1) as f32).min(0.99)
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum PipelineError {
+    UnknownCommand(String),
+    BadInput(serde_json::Error),
+}
+
+impl fmt::Display for PipelineError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PipelineError::UnknownCommand(command) => write!(
+                f,
+                "unknown command '{command}', expected validate_map, validate_all, preflight, find_path or generate_map"
+            ),
+            PipelineError::BadInput(err) => write!(f, "bad input: {err}"),
+        }
+    }
+}
+
+impl std::error::Error for PipelineError {}
+
+/// Runs one of `validate_map`, `validate_all`, `preflight`, `find_path` or `generate_map` on its JSON input.
+pub fn run(command: &str, input: Value) -> Result<Value, PipelineError> {
+    let output = match command {
+        "validate_map" => serde_json::to_value(validate_map(&from_value(input)?)),
+        "validate_all" => serde_json::to_value(validate_all(&from_value(input)?)),
+        "preflight" => serde_json::to_value(preflight(&from_value(input)?)),
+        "find_path" => serde_json::to_value(find_path(&from_value(input)?)),
+        "generate_map" => serde_json::to_value(generate_map(&from_value(input)?)),
+        _ => return Err(PipelineError::UnknownCommand(command.to_owned())),
+    };
+    Ok(output.expect("pipeline outputs always serialize"))
+}
+
+fn from_value<T: serde::de::DeserializeOwned>(input: Value) -> Result<T, PipelineError> {
+    serde_json::from_value(input).map_err(PipelineError::BadInput)
+}
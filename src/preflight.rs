@@ -0,0 +1,244 @@
+use std::fmt;
+
+use crate::math::*;
+use crate::{atlas_rect, sanitize_rect, screen_rect, AtlasInfo, AtlasWorld, Collection, MapSource, Orientation, RenderConfig};
+
+/// Something about the loaded data that will probably render or play wrong.
+#[derive(Clone, Debug, PartialEq)]
+pub enum PreflightWarning {
+    /// the player starts outside the map altogether
+    SpawnOutOfBounds { x: i32, y: i32 },
+    /// the player starts inside a cell with a wall value
+    SpawnInWall { x: i32, y: i32, value: u8 },
+    /// a wall layer with no side faces at (|x| = 1, z = 0), which leaves the corridor the
+    /// player stands in looking like an open tube
+    MissingNearSideWalls { atlas_id: String, layer_id: String },
+    /// positions (x, z) of a layer that will be drawn from `depth_extend` stand-ins
+    SynthesizedTiles { atlas_id: String, layer_id: String, positions: Vec<(i32, i32)> },
+    /// a terrain with a move cost of 0, which is treated as 1
+    ZeroMoveCost { value: u8 },
+    /// a tile whose atlas or screen rect is empty, negative or absurdly large, see `sanitize_rect`
+    DegenerateTile { atlas_id: String, layer_id: String, x: i32, z: i32 },
+}
+
+impl fmt::Display for PreflightWarning {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PreflightWarning::SpawnOutOfBounds { x, y } => {
+                write!(f, "spawn cell ({x}, {y}) is outside the map")
+            }
+            PreflightWarning::SpawnInWall { x, y, value } => {
+                write!(f, "spawn cell ({x}, {y}) has wall value {value}")
+            }
+            PreflightWarning::MissingNearSideWalls { atlas_id, layer_id } => {
+                write!(f, "atlas '{atlas_id}' layer '{layer_id}' has no side wall tiles next to the player (|x| = 1, z = 0)")
+            }
+            PreflightWarning::SynthesizedTiles { atlas_id, layer_id, positions } => {
+                write!(f, "atlas '{atlas_id}' layer '{layer_id}' synthesizes {} positions:", positions.len())?;
+                for (x, z) in positions {
+                    write!(f, " ({x}, {z})")?;
+                }
+                Ok(())
+            }
+            PreflightWarning::ZeroMoveCost { value } => {
+                write!(f, "terrain for floor value {value} has a move cost of 0")
+            }
+            PreflightWarning::DegenerateTile { atlas_id, layer_id, x, z } => {
+                write!(f, "atlas '{atlas_id}' layer '{layer_id}' tile ({x}, {z}) has a degenerate rect")
+            }
+        }
+    }
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct PreflightReport {
+    pub warnings: Vec<PreflightWarning>,
+}
+
+impl PreflightReport {
+    pub fn is_clean(&self) -> bool {
+        self.warnings.is_empty()
+    }
+}
+
+impl fmt::Display for PreflightReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for warning in &self.warnings {
+            writeln!(f, "warning: {warning}")?;
+        }
+        Ok(())
+    }
+}
+
+impl PreflightReport {
+    /// The checks behind `AtlasWorld::preflight` run on bare data, for tools that can't create
+    /// textures. `atlases` pairs atlas ids with their info and should be sorted by id.
+    pub fn check<M: MapSource + ?Sized>(
+        map: &M,
+        spawn: IVec2,
+        atlases: &[(&str, &AtlasInfo)],
+        render_config: &RenderConfig,
+        render_depth: i32,
+        render_width: i32,
+    ) -> Self {
+        let mut report = PreflightReport::default();
+
+        let (x, y) = (spawn.x, spawn.y);
+        if !map.in_bounds(spawn) {
+            report.warnings.push(PreflightWarning::SpawnOutOfBounds { x, y });
+        } else if let Some(value) = map.get_wall(spawn) {
+            if value != 0 {
+                report.warnings.push(PreflightWarning::SpawnInWall { x, y, value });
+            }
+        }
+
+        if let Some((_, atlas_info)) = atlases.iter().find(|(atlas_id, _)| *atlas_id == "dungeon") {
+            for (layer_id, tiles) in atlas_info.layers_sorted() {
+                if !layer_id.starts_with("wall-") {
+                    continue;
+                }
+                let has_near_sides = tiles.tiles().iter().any(|tile| {
+                    tile.z == 0 && tile.x.abs() == 1
+                        && matches!(tile.orientation, Some(Orientation::Left) | Some(Orientation::Right))
+                });
+                if !has_near_sides {
+                    report.warnings.push(PreflightWarning::MissingNearSideWalls {
+                        atlas_id: "dungeon".to_owned(),
+                        layer_id: layer_id.clone(),
+                    });
+                }
+            }
+        }
+
+        for (atlas_id, atlas_info) in atlases {
+            for (layer_id, layer) in atlas_info.layers_sorted() {
+                for tile in layer.tiles() {
+                    let rects = [atlas_rect(tile), screen_rect(tile)];
+                    if rects.iter().any(|rect| !matches!(sanitize_rect(*rect), Some((_, false)))) {
+                        report.warnings.push(PreflightWarning::DegenerateTile {
+                            atlas_id: atlas_id.to_string(),
+                            layer_id: layer_id.clone(),
+                            x: tile.x,
+                            z: tile.z,
+                        });
+                    }
+                }
+            }
+        }
+
+        for (atlas_id, atlas_info) in atlases {
+            for (layer_id, layer) in atlas_info.layers_sorted() {
+                let Some(extend) = render_config.depth_extend.get(layer_id) else {
+                    continue;
+                };
+
+                let mut positions = Vec::new();
+                for z in -render_depth..=0 {
+                    for x in (-render_width / 2)..=(render_width / 2) {
+                        let authored = layer.tiles().iter().any(|tile| tile.x == x && tile.z == z);
+                        if !authored && extend.synthesize(layer, x, z, None).is_some() {
+                            positions.push((x, z));
+                        }
+                    }
+                }
+
+                if !positions.is_empty() {
+                    report.warnings.push(PreflightWarning::SynthesizedTiles {
+                        atlas_id: atlas_id.to_string(),
+                        layer_id: layer_id.clone(),
+                        positions,
+                    });
+                }
+            }
+        }
+
+        report
+    }
+}
+
+impl<M: MapSource> AtlasWorld<M> {
+    /// checks the world as it stands before the first frame is drawn
+    pub fn preflight(&self) -> PreflightReport {
+        let atlases: Vec<(&str, &AtlasInfo)> = self.collection.atlases_sorted()
+            .map(|(atlas_id, atlas)| (atlas_id.as_str(), &atlas.atlas_info))
+            .collect();
+        let mut report = PreflightReport::check(
+            &self.map,
+            ivec2(self.player.x, self.player.y),
+            &atlases,
+            &self.render_config,
+            self.render_depth,
+            self.render_width,
+        );
+
+        report.warnings.extend(self.terrain.zero_costs().into_iter().map(|value| PreflightWarning::ZeroMoveCost { value }));
+
+        report
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::{demo_world, manifest_path};
+    use crate::{AtlasMap, Direction, Player};
+
+    fn archway() -> AtlasMap {
+        AtlasMap::from_reader_auto(std::fs::File::open(manifest_path("fixtures/scenes/archway.json")).unwrap()).unwrap()
+    }
+
+    #[test]
+    fn spawning_in_a_wall_is_reported() {
+        let spawn_warnings = |player: Player| -> Vec<PreflightWarning> {
+            demo_world(player, archway()).preflight().warnings.into_iter()
+                .filter(|warning| matches!(warning, PreflightWarning::SpawnInWall { .. } | PreflightWarning::SpawnOutOfBounds { .. }))
+                .collect()
+        };
+        assert_eq!(spawn_warnings(Player::new(2, 2, Direction::North)), [PreflightWarning::SpawnInWall { x: 2, y: 2, value: 2 }]);
+        assert_eq!(spawn_warnings(Player::new(2, 3, Direction::North)), []);
+        assert_eq!(spawn_warnings(Player::new(5, 0, Direction::North)), [PreflightWarning::SpawnOutOfBounds { x: 5, y: 0 }]);
+    }
+
+    #[test]
+    fn missing_near_side_walls_are_reported() {
+        let near_side_warnings = |world: &crate::AtlasWorld| -> Vec<PreflightWarning> {
+            world.preflight().warnings.into_iter()
+                .filter(|warning| matches!(warning, PreflightWarning::MissingNearSideWalls { .. }))
+                .collect()
+        };
+        let mut world = demo_world(Player::new(2, 3, Direction::North), archway());
+        assert_eq!(near_side_warnings(&world), []);
+
+        let wall = world.collection.get_mut("dungeon").unwrap().atlas_info.layers.get_mut("wall-2").unwrap();
+        wall.tiles_mut().retain(|tile| tile.z != 0);
+        assert_eq!(near_side_warnings(&world), [PreflightWarning::MissingNearSideWalls {
+            atlas_id: "dungeon".to_owned(),
+            layer_id: "wall-2".to_owned(),
+        }]);
+    }
+
+    #[test]
+    fn synthesized_positions_are_listed() {
+        let mut world = demo_world(Player::new(2, 3, Direction::North), archway());
+        let floor = world.collection.get_mut("dungeon").unwrap().atlas_info.layers.get_mut("floor-1").unwrap();
+        floor.tiles_mut().retain(|tile| tile.z >= -4);
+        let synthesized = |world: &crate::AtlasWorld| -> Vec<PreflightWarning> {
+            world.preflight().warnings.into_iter()
+                .filter(|warning| matches!(warning, PreflightWarning::SynthesizedTiles { .. }))
+                .collect()
+        };
+        assert_eq!(synthesized(&world), []);
+
+        world.render_config.depth_extend.insert("floor-1".to_owned(), crate::DepthExtend {
+            vanishing_point: vec2(160.0, 80.0),
+            row_scale: vec![1.0, 0.8, 0.6, 0.45, 0.3, 0.2],
+        });
+        let [PreflightWarning::SynthesizedTiles { atlas_id, layer_id, positions }] = &synthesized(&world)[..] else {
+            panic!("one layer synthesizes");
+        };
+        assert_eq!((atlas_id.as_str(), layer_id.as_str()), ("dungeon", "floor-1"));
+        // only row 5 is in the scale table
+        assert!(!positions.is_empty() && positions.iter().all(|(_, z)| *z == -5));
+        assert!(positions.contains(&(0, -5)));
+    }
+}
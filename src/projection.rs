@@ -0,0 +1,151 @@
+//! Screen rects worked out from a pinhole camera instead of read from the atlas json. A layer
+//! with `mode` `Tiles::COMPUTED_MODE` only needs an `atlas_coords` per orientation: tiles it
+//! doesn't list are placed by the atlas' `Projection`, and tiles it lists without
+//! `screen_coords` get theirs from it on load.
+//!
+//! The camera stands `camera_offset` cells in front of the near face of its own row, so view
+//! row `z` spans distances `-z + camera_offset` to one cell further. A cell at distance `d` is
+//! `cell_width / d` pixels wide, centered on the vanishing point.
+
+use serde::{Deserialize, Serialize};
+
+use crate::{Coords, Orientation, Tile, Tiles};
+
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Debug)]
+#[serde(default)]
+pub struct Projection {
+    /// screen point straight ahead at eye level
+    pub vanishing_point: [f32; 2],
+    /// width in pixels of a cell one cell away
+    pub cell_width: f32,
+    /// cells between the camera and the near face of its own row
+    pub camera_offset: f32,
+    /// eye height above the floor, in cells
+    pub eye_height: f32,
+    /// floor to ceiling, in cells
+    pub wall_height: f32,
+    /// width and height of upright sprites like objects, in cells
+    pub sprite_size: [f32; 2],
+}
+
+/// Fitted to the hand-placed rects of the 320x256 atlases.
+impl Default for Projection {
+    fn default() -> Self {
+        Self {
+            vanishing_point: [160.0, 73.0],
+            cell_width: 227.0,
+            camera_offset: 0.18,
+            eye_height: 0.73,
+            wall_height: 2.0,
+            sprite_size: [0.5, 0.5],
+        }
+    }
+}
+
+impl Projection {
+    /// pixels per cell on the plane `distance` cells from the camera
+    pub fn depth_scale(&self, distance: f32) -> f32 {
+        self.cell_width / distance.max(0.01)
+    }
+
+    // (near, far) scale of view row `z`
+    fn row_scales(&self, z: i32) -> (f32, f32) {
+        let near = -z as f32 + self.camera_offset;
+        (self.depth_scale(near), self.depth_scale(near + 1.0))
+    }
+
+    // screen x of view-space x `x` (in cells, 0 the middle of the view) at `scale`
+    fn screen_x(&self, x: f32, scale: f32) -> f32 {
+        self.vanishing_point[0] + x * scale
+    }
+
+    // screen y of the ceiling and floor at `scale`
+    fn screen_y(&self, scale: f32) -> (f32, f32) {
+        let [_, y] = self.vanishing_point;
+        (y - (self.wall_height - self.eye_height) * scale, y + self.eye_height * scale)
+    }
+
+    /// The rect of a wall face of view cell (x, z): `Front` its near face, `Back` its far face,
+    /// `Left` the face seen on cells left of the view and `Right` the one seen on the right.
+    /// `None` is an upright sprite standing in the middle of the cell.
+    pub fn cell_rect(&self, x: i32, z: i32, orientation: Option<Orientation>) -> Coords {
+        let (near, far) = self.row_scales(z);
+        let x = x as f32;
+        let face = |scale: f32| {
+            let (top, bottom) = self.screen_y(scale);
+            (self.screen_x(x - 0.5, scale), top, self.screen_x(x + 0.5, scale), bottom)
+        };
+        let side = |edge: f32| {
+            let (top, bottom) = self.screen_y(near);
+            let (a, b) = (self.screen_x(edge, near), self.screen_x(edge, far));
+            (a.min(b), top, a.max(b), bottom)
+        };
+        let (left, top, right, bottom) = match orientation {
+            Some(Orientation::Front) => face(near),
+            Some(Orientation::Back) => face(far),
+            Some(Orientation::Left) => side(x + 0.5),
+            Some(Orientation::Right) => side(x - 0.5),
+            None => {
+                let center = self.depth_scale(-z as f32 + self.camera_offset + 0.5);
+                let [w, h] = self.sprite_size;
+                let (_, floor) = self.screen_y(center);
+                let middle = self.screen_x(x, center);
+                (middle - w * center / 2.0, floor - h * center, middle + w * center / 2.0, floor)
+            }
+        };
+        coords(left, top, right, bottom)
+    }
+
+    /// the bounds of the floor of view cell (x, z)
+    pub fn floor_rect(&self, x: i32, z: i32) -> Coords {
+        let (near, far) = self.row_scales(z);
+        let (_, near_y) = self.screen_y(near);
+        let (_, far_y) = self.screen_y(far);
+        self.span(x, near, far, far_y, near_y)
+    }
+
+    /// the bounds of the ceiling of view cell (x, z)
+    pub fn ceiling_rect(&self, x: i32, z: i32) -> Coords {
+        let (near, far) = self.row_scales(z);
+        let (near_y, _) = self.screen_y(near);
+        let (far_y, _) = self.screen_y(far);
+        self.span(x, near, far, near_y, far_y)
+    }
+
+    // the bounds of cell column `x` across both planes of a row, between `top` and `bottom`
+    fn span(&self, x: i32, near: f32, far: f32, top: f32, bottom: f32) -> Coords {
+        let x = x as f32;
+        let left = self.screen_x(x - 0.5, near).min(self.screen_x(x - 0.5, far));
+        let right = self.screen_x(x + 0.5, near).max(self.screen_x(x + 0.5, far));
+        coords(left, top, right, bottom)
+    }
+
+    /// The rect for a tile of a layer of `tile_type`: the floor or ceiling for those layer
+    /// types, `cell_rect` for anything else.
+    pub fn tile_rect(&self, tile_type: i32, x: i32, z: i32, orientation: Option<Orientation>) -> Coords {
+        match tile_type {
+            Tiles::FLOOR_TYPE => self.floor_rect(x, z),
+            Tiles::CEILING_TYPE => self.ceiling_rect(x, z),
+            _ => self.cell_rect(x, z, orientation),
+        }
+    }
+
+    /// A tile at (x, z) cut like the tile of `layer` with the same orientation, when the layer
+    /// is computed and has one.
+    pub fn place(&self, layer: &Tiles, x: i32, z: i32, orientation: Option<Orientation>) -> Option<Tile> {
+        if layer.mode != Tiles::COMPUTED_MODE {
+            return None;
+        }
+        let source = layer.tiles().iter().find(|tile| tile.orientation == orientation)?;
+        let mut tile = Tile::new(source.atlas_coords, self.tile_rect(layer.r#type, x, z, orientation), x, z, orientation);
+        tile.animation = source.animation.clone();
+        tile.page = source.page;
+        tile.variants = source.variants.clone();
+        Some(tile)
+    }
+}
+
+fn coords(left: f32, top: f32, right: f32, bottom: f32) -> Coords {
+    let (x, y) = (left.round() as i32, top.round() as i32);
+    Coords { x, y, w: right.round() as i32 - x, h: bottom.round() as i32 - y }
+}
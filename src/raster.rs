@@ -0,0 +1,206 @@
+//! Software fallback for getting the view out as pixels without reading back from the GPU,
+//! e.g. to show a preview inside a tool that isn't a macroquad window. Every captured quad is
+//! filled with a flat color picked by its layer, textures aren't sampled.
+
+use ahash::AHashMap;
+
+use crate::math::*;
+use crate::{AtlasWorld, DrawCommand, MapSource};
+
+/// Flat colors for the rasterizer. An exact layer id wins over the color for its kind
+/// (the part before the first `-`, e.g. "wall" for "wall-2").
+#[derive(Clone, Debug)]
+pub struct LayerPalette {
+    pub layers: AHashMap<String, Color>,
+    pub kinds: AHashMap<String, Color>,
+    pub fallback: Color,
+}
+
+impl Default for LayerPalette {
+    fn default() -> Self {
+        let kinds = [
+            ("floor", Color::from_rgba(90, 70, 50, 255)),
+            ("ceiling", Color::from_rgba(50, 50, 60, 255)),
+            ("wall", Color::from_rgba(140, 140, 150, 255)),
+            ("object", Color::from_rgba(200, 170, 60, 255)),
+        ];
+        Self {
+            layers: AHashMap::new(),
+            kinds: kinds.into_iter().map(|(kind, color)| (kind.to_owned(), color)).collect(),
+            fallback: MAGENTA,
+        }
+    }
+}
+
+impl LayerPalette {
+    pub fn color(&self, layer_id: &str) -> Color {
+        if let Some(color) = self.layers.get(layer_id) {
+            return *color;
+        }
+        let kind = layer_id.split(['-', '@']).next().unwrap_or(layer_id);
+        self.kinds.get(kind).copied().unwrap_or(self.fallback)
+    }
+}
+
+/// An RGBA8 pixel buffer. Rows run top to bottom with no padding, so the stride is always
+/// `width * 4` bytes and pixel (x, y) starts at byte `(y * width + x) * 4`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct SoftwareViewport {
+    pub width: usize,
+    pub height: usize,
+    pixels: Vec<u8>,
+}
+
+impl SoftwareViewport {
+    /// a fully transparent buffer
+    pub fn new(width: usize, height: usize) -> Self {
+        Self {
+            width,
+            height,
+            pixels: vec![0; width * height * 4],
+        }
+    }
+
+    /// rasterizes what `render` would draw into a buffer the size of `world.viewport_rect()`
+    pub fn render<M: MapSource>(world: &AtlasWorld<M>, palette: &LayerPalette) -> Self {
+        let area = world.viewport_rect();
+        let mut viewport = Self::new(area.w as usize, area.h as usize);
+        viewport.clear(BLACK);
+        viewport.rasterize(&world.capture_commands(), palette);
+        viewport
+    }
+
+    pub fn stride(&self) -> usize {
+        self.width * 4
+    }
+
+    /// a copy of the buffer in the layout described on the type
+    pub fn read_pixels(&self) -> Vec<u8> {
+        self.pixels.clone()
+    }
+
+    pub fn pixels(&self) -> &[u8] {
+        &self.pixels
+    }
+
+    pub fn pixel(&self, x: usize, y: usize) -> Option<[u8; 4]> {
+        if x >= self.width || y >= self.height {
+            return None;
+        }
+        let i = (y * self.width + x) * 4;
+        Some([self.pixels[i], self.pixels[i + 1], self.pixels[i + 2], self.pixels[i + 3]])
+    }
+
+    pub fn clear(&mut self, color: Color) {
+        let rgba: [u8; 4] = color.into();
+        for pixel in self.pixels.chunks_exact_mut(4) {
+            pixel.copy_from_slice(&rgba);
+        }
+    }
+
+    /// draws the commands in order, each tinted palette color blended over what's below
+    pub fn rasterize(&mut self, commands: &[DrawCommand], palette: &LayerPalette) {
+        for command in commands {
+            let base = palette.color(&command.layer_id);
+            let tint = command.color;
+            let color = Color::new(base.r * tint.r, base.g * tint.g, base.b * tint.b, base.a * tint.a);
+            self.fill_rect(command.dest, color);
+        }
+    }
+
+    /// fills every pixel whose center lies inside `rect`
+    pub fn fill_rect(&mut self, rect: Rect, color: Color) {
+        if !(rect.w > 0.0 && rect.h > 0.0) {
+            return;
+        }
+
+        let to_pixel = |v: f32, max: usize| ((v - 0.5).ceil().max(0.0) as usize).min(max);
+        let (x0, x1) = (to_pixel(rect.x, self.width), to_pixel(rect.x + rect.w, self.width));
+        let (y0, y1) = (to_pixel(rect.y, self.height), to_pixel(rect.y + rect.h, self.height));
+
+        for y in y0..y1 {
+            for x in x0..x1 {
+                let i = (y * self.width + x) * 4;
+                let below = Color::from_rgba(self.pixels[i], self.pixels[i + 1], self.pixels[i + 2], self.pixels[i + 3]);
+                let blended = Color::new(
+                    color.r * color.a + below.r * (1.0 - color.a),
+                    color.g * color.a + below.g * (1.0 - color.a),
+                    color.b * color.a + below.b * (1.0 - color.a),
+                    color.a + below.a * (1.0 - color.a),
+                );
+                let rgba: [u8; 4] = blended.into();
+                self.pixels[i..i + 4].copy_from_slice(&rgba);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::{demo_world, manifest_path};
+    use crate::{AtlasMap, Direction, Player, VIEWPORT_HEIGHT, VIEWPORT_WIDTH};
+
+    #[test]
+    fn the_corridor_rasterizes_to_the_viewport() {
+        let file = std::fs::File::open(manifest_path("fixtures/scenes/corridor.json")).unwrap();
+        let world = demo_world(Player::new(2, 6, Direction::North), AtlasMap::from_reader_auto(file).unwrap());
+        let viewport = SoftwareViewport::render(&world, &LayerPalette::default());
+
+        assert_eq!((viewport.width, viewport.height), (VIEWPORT_WIDTH as usize, VIEWPORT_HEIGHT as usize));
+        assert_eq!(viewport.stride(), VIEWPORT_WIDTH as usize * 4);
+        assert_eq!(viewport.read_pixels().len(), viewport.stride() * viewport.height);
+        assert_eq!(viewport.read_pixels(), viewport.pixels());
+
+        // the wall beside the player fills the left edge, the floor ahead the bottom middle
+        assert_eq!(viewport.pixel(10, 128), Some([140, 140, 150, 255]));
+        assert_eq!(viewport.pixel(160, 240), Some([90, 70, 50, 255]));
+        assert_eq!(viewport.pixel(160, 10), Some([50, 50, 60, 255]));
+        assert_eq!(viewport.pixel(VIEWPORT_WIDTH as usize, 0), None);
+    }
+
+    #[test]
+    fn rects_fill_the_pixels_whose_centers_they_cover() {
+        let mut viewport = SoftwareViewport::new(4, 3);
+        assert!(viewport.pixels().iter().all(|byte| *byte == 0));
+        // (0.6, 0.4) to (2.6, 1.6) covers the centers of columns 1 and 2, rows 0 and 1
+        let red = Color::new(1.0, 0.0, 0.0, 1.0);
+        viewport.fill_rect(Rect::new(0.6, 0.4, 2.0, 1.2), red);
+        let filled: Vec<(usize, usize)> = (0..3).flat_map(|y| (0..4).map(move |x| (x, y)))
+            .filter(|(x, y)| viewport.pixel(*x, *y) == Some([255, 0, 0, 255]))
+            .collect();
+        assert_eq!(filled, [(1, 0), (2, 0), (1, 1), (2, 1)]);
+        // a pixel's center on the far edge isn't covered
+        let mut edge = SoftwareViewport::new(4, 1);
+        edge.fill_rect(Rect::new(0.0, 0.0, 1.5, 1.0), red);
+        assert_eq!((edge.pixel(0, 0), edge.pixel(1, 0)), (Some([255, 0, 0, 255]), Some([0, 0, 0, 0])));
+
+        // off the edges is clipped, empty and inverted rects draw nothing
+        let mut clipped = SoftwareViewport::new(4, 3);
+        clipped.fill_rect(Rect::new(-10.0, -10.0, 100.0, 100.0), Color::new(0.0, 0.0, 1.0, 1.0));
+        assert!(clipped.pixels().chunks_exact(4).all(|pixel| pixel == [0, 0, 255, 255]));
+        let before = clipped.clone();
+        clipped.fill_rect(Rect::new(1.0, 1.0, 0.0, 2.0), red);
+        clipped.fill_rect(Rect::new(1.0, 1.0, -2.0, 2.0), red);
+        clipped.fill_rect(Rect::new(f32::NAN, 0.0, 2.0, 2.0), red);
+        assert_eq!(clipped, before);
+    }
+
+    #[test]
+    fn translucent_quads_blend_over_what_is_below() {
+        let mut viewport = SoftwareViewport::new(1, 1);
+        viewport.clear(BLACK);
+        viewport.fill_rect(Rect::new(0.0, 0.0, 1.0, 1.0), Color::new(1.0, 1.0, 1.0, 0.5));
+        assert_eq!(viewport.pixel(0, 0), Some([127, 127, 127, 255]));
+    }
+
+    #[test]
+    fn layers_are_colored_by_id_then_kind() {
+        let mut palette = LayerPalette::default();
+        palette.layers.insert("wall-2".to_owned(), GREEN);
+        assert_eq!(palette.color("wall-2"), GREEN);
+        assert_eq!(palette.color("wall-1"), palette.kinds["wall"]);
+        assert_eq!(palette.color("object-3@near"), palette.kinds["object"]);
+        assert_eq!(palette.color("decal-1"), MAGENTA);
+    }
+}
@@ -0,0 +1,189 @@
+//! The order `AtlasWorld::render` draws in, which anything drawing into the view alongside the
+//! map relies on.
+//!
+//! Cells are given relative to the player as (x, z): x grows to the right, z is 0 on the
+//! player's row and negative in front of it. Each frame, in the default `DrawOrder`:
+//!
+//! 1. rows go from the farthest (`-render_depth`) to the player's own (`z == 0`),
+//! 2. within a row the left half goes first, outermost column to the one next to the center,
+//!    then the right half, outermost column to the center column (`x == 0`),
+//! 3. within a cell the passes go in `CellPass` order: floor, ceiling, side walls (left face
+//!    then right face), front wall, object.
+//!
+//! So everything drawn for a cell covers everything drawn for the cells before it, and a
+//! quad for the center column of a row is the last thing drawn for that row. Objects with a
+//! near or far depth anchor are drawn with a shifted tile but still in their own cell's slot.
+//!
+//! Other `DrawOrder`s, set in `RenderConfig::draw_order`, change the order of the rows or of
+//! the columns within them; the passes always go in `CellPass` order.
+//!
+//! `render_order_key` puts the default order into a sortable value, `DrawOrder::key` any of
+//! them. The renderer visits cells and passes by sorting on it, and every captured
+//! `DrawCommand` carries the key it was drawn under, so sorting captured commands by `order`
+//! never changes their order.
+
+use crate::math::*;
+
+/// (row, column rank, pass), compares in the order things are drawn
+pub type RenderOrderKey = (i32, i32, u8);
+
+/// The key the sky backdrop is drawn under, before every cell.
+pub const SKY_ORDER: RenderOrderKey = (i32::MIN, i32::MIN, 0);
+
+/// The steps `render` takes for each cell, in the order it takes them.
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Debug)]
+pub enum CellPass {
+    Floor,
+    Ceiling,
+    SideWalls,
+    FrontWall,
+    Object,
+}
+
+impl CellPass {
+    pub const ALL: [CellPass; 5] = [
+        CellPass::Floor,
+        CellPass::Ceiling,
+        CellPass::SideWalls,
+        CellPass::FrontWall,
+        CellPass::Object,
+    ];
+}
+
+/// Which way the rows go.
+#[derive(Clone, Copy, Default, PartialEq, Eq, Hash, Debug)]
+pub enum Depth {
+    /// the painter's order, nearer rows covering farther ones
+    #[default]
+    FarToNear,
+    /// the player's row first, for drawing that doesn't rely on overlap
+    NearToFar,
+}
+
+/// The order `render` visits cells in: how the columns of a row go, and which way the rows go.
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub enum DrawOrder {
+    /// each half of the row from its outermost column in, the left half first, so the center
+    /// column is drawn last over both; symmetric wall art expects this
+    CenterOut(Depth),
+    /// the leftmost column first, for wide art that spills into the column on its right
+    LeftToRight(Depth),
+    /// the rightmost column first, for wide art that spills into the column on its left
+    RightToLeft(Depth),
+}
+
+impl Default for DrawOrder {
+    fn default() -> Self {
+        DrawOrder::CenterOut(Depth::FarToNear)
+    }
+}
+
+impl DrawOrder {
+    pub fn depth(self) -> Depth {
+        match self {
+            DrawOrder::CenterOut(depth) | DrawOrder::LeftToRight(depth) | DrawOrder::RightToLeft(depth) => depth,
+        }
+    }
+
+    /// The key a draw for `pass` of the cell at `cell_relative` (x, z) is made under in this
+    /// order. Keys of earlier draws compare lower.
+    pub fn key(self, cell_relative: IVec2, pass: CellPass) -> RenderOrderKey {
+        let x = cell_relative.x;
+        let column = match self {
+            // the left half counts up towards the center, the right half ranks above all of it
+            // and counts up towards the center from the other side
+            DrawOrder::CenterOut(_) => if x < 0 { x } else { i32::MAX - x },
+            DrawOrder::LeftToRight(_) => x,
+            DrawOrder::RightToLeft(_) => -x,
+        };
+        let row = match self.depth() {
+            Depth::FarToNear => cell_relative.y,
+            Depth::NearToFar => -cell_relative.y,
+        };
+        (row, column, pass as u8)
+    }
+
+    /// the (x, z) cell a key made by `key` is for
+    pub fn cell(self, key: RenderOrderKey) -> IVec2 {
+        let (row, column, _) = key;
+        let x = match self {
+            DrawOrder::CenterOut(_) => if column < 0 { column } else { i32::MAX - column },
+            DrawOrder::LeftToRight(_) => column,
+            DrawOrder::RightToLeft(_) => -column,
+        };
+        let z = match self.depth() {
+            Depth::FarToNear => row,
+            Depth::NearToFar => -row,
+        };
+        ivec2(x, z)
+    }
+}
+
+/// The key a draw for `pass` of the cell at `cell_relative` (x, z) is made under in the default
+/// `DrawOrder`. Keys of earlier draws compare lower.
+pub fn render_order_key(cell_relative: IVec2, pass: CellPass) -> RenderOrderKey {
+    DrawOrder::default().key(cell_relative, pass)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::generator::{rooms_and_corridors, GeneratorConfig, Rng};
+    use crate::test_support::demo_world;
+    use crate::{DrawCommand, Direction, Player};
+
+    const ORDERS: [DrawOrder; 6] = [
+        DrawOrder::CenterOut(Depth::FarToNear),
+        DrawOrder::CenterOut(Depth::NearToFar),
+        DrawOrder::LeftToRight(Depth::FarToNear),
+        DrawOrder::LeftToRight(Depth::NearToFar),
+        DrawOrder::RightToLeft(Depth::FarToNear),
+        DrawOrder::RightToLeft(Depth::NearToFar),
+    ];
+
+    #[test]
+    fn cell_inverts_key() {
+        for order in ORDERS {
+            for z in -6..=0 {
+                for x in -4..=4 {
+                    assert_eq!(order.cell(order.key(ivec2(x, z), CellPass::Object)), ivec2(x, z), "{order:?}");
+                }
+            }
+        }
+    }
+
+    // random scenes in every order: a stable sort by `order` must leave the capture as it is
+    #[test]
+    fn captured_commands_are_already_in_key_order() {
+        let mut rng = Rng::new(231);
+        for seed in 0..48 {
+            let config = GeneratorConfig {
+                wall: 1 + rng.below(2) as u8,
+                objects: vec![(1, 3), (2, 1)],
+                object_chance: 0.25,
+                ..GeneratorConfig::default()
+            };
+            let generated = rooms_and_corridors(rng.range(12, 32), rng.range(12, 32), &config, seed);
+            let open: Vec<IVec2> = (0..generated.map.wall.height())
+                .flat_map(|y| (0..generated.map.wall.width()).map(move |x| ivec2(x as i32, y as i32)))
+                .filter(|cell| generated.map.wall.get(cell.x as usize, cell.y as usize) == Some(0))
+                .collect();
+            let at = open[rng.below(open.len())];
+            let facing = [Direction::North, Direction::East, Direction::South, Direction::West][rng.below(4)];
+            let order = ORDERS[rng.below(ORDERS.len())];
+
+            let mut world = demo_world(Player::new(at.x, at.y, facing), generated.map);
+            world.render_config.draw_order = order;
+            world.render_depth = rng.range(2, 8) as i32;
+            let commands = world.capture_commands();
+            assert!(!commands.is_empty(), "seed {seed}");
+
+            let mut sorted: Vec<&DrawCommand> = commands.iter().collect();
+            sorted.sort_by_key(|command| command.order);
+            assert!(
+                sorted.iter().zip(&commands).all(|(sorted, captured)| std::ptr::eq(*sorted, captured)),
+                "seed {seed}, {order:?} facing {facing:?} at {at}: captured out of key order"
+            );
+        }
+    }
+}
@@ -0,0 +1,302 @@
+//! Recording the player's actions and playing them back. Everything the world does on a turn
+//! follows from the map, the entity seed and the actions taken, so replaying a log against the
+//! same map ends in the same state, which `state_hash` tells.
+
+use std::fmt;
+
+use serde::{Deserialize, Serialize};
+
+use crate::math::*;
+use crate::{saves::STATE_LAYERS, AtlasWorld, MapSource, PlayerAction, TurnResult};
+
+// every layer `map_hash` covers, `STATE_LAYERS` being the ones `state_hash` covers
+const HASHED_LAYERS: [&str; 7] = ["wall", "floor", "ceiling", "object", "doors", "trigger", "decal"];
+
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Debug)]
+pub struct ReplayHeader {
+    /// what `AtlasWorld::seed_entities` was given when recording started
+    pub seed: u64,
+    /// `AtlasWorld::map_hash` when recording started
+    pub map_hash: u64,
+    /// `AtlasWorld::state_hash` when recording started
+    pub start_hash: u64,
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Debug)]
+pub struct RecordedAction {
+    /// `AtlasWorld::turn` before the action
+    pub turn: u64,
+    pub action: PlayerAction,
+}
+
+#[derive(Serialize, Deserialize, Clone, PartialEq, Eq, Debug)]
+pub struct ReplayLog {
+    pub header: ReplayHeader,
+    pub actions: Vec<RecordedAction>,
+    /// `AtlasWorld::state_hash` when recording finished
+    pub final_hash: Option<u64>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ReplayError {
+    /// the world's map isn't the one the log was recorded on
+    MapMismatch { expected: u64, found: u64 },
+    /// the world didn't start out where the recording did
+    StartMismatch { expected: u64, found: u64 },
+    /// the world is on a different turn than the recording was at this action, so it desynced
+    TurnMismatch { expected: u64, found: u64 },
+}
+
+impl fmt::Display for ReplayError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ReplayError::MapMismatch { expected, found } => write!(f, "replay was recorded on map {expected:016x}, not {found:016x}"),
+            ReplayError::StartMismatch { expected, found } => write!(f, "replay starts from state {expected:016x}, not {found:016x}"),
+            ReplayError::TurnMismatch { expected, found } => write!(f, "replay expected turn {expected} but the world is on turn {found}"),
+        }
+    }
+}
+
+impl std::error::Error for ReplayError {}
+
+/// Takes turns for the game, logging each action.
+#[derive(Clone, Debug)]
+pub struct Recorder {
+    log: ReplayLog,
+}
+
+impl Recorder {
+    /// Seeds `world`'s entities with `seed` and starts a log from its current state.
+    pub fn start<M: MapSource>(world: &mut AtlasWorld<M>, seed: u64) -> Self {
+        world.seed_entities(seed);
+        let header = ReplayHeader { seed, map_hash: world.map_hash(), start_hash: world.state_hash() };
+        Self { log: ReplayLog { header, actions: Vec::new(), final_hash: None } }
+    }
+
+    /// `AtlasWorld::take_turn`, logged
+    pub fn take_turn<M: MapSource>(&mut self, world: &mut AtlasWorld<M>, action: PlayerAction) -> TurnResult {
+        self.log.actions.push(RecordedAction { turn: world.turn(), action });
+        world.take_turn(action)
+    }
+
+    pub fn log(&self) -> &ReplayLog {
+        &self.log
+    }
+
+    /// the log, with the world's final state hash
+    pub fn finish<M: MapSource>(mut self, world: &AtlasWorld<M>) -> ReplayLog {
+        self.log.final_hash = Some(world.state_hash());
+        self.log
+    }
+}
+
+/// Plays a `ReplayLog` back, one action per `step`.
+#[derive(Clone, Debug)]
+pub struct Replayer {
+    log: ReplayLog,
+    next: usize,
+}
+
+impl Replayer {
+    /// Checks `world` has the log's map and starting state and seeds its entities like the
+    /// recording did.
+    pub fn start<M: MapSource>(log: ReplayLog, world: &mut AtlasWorld<M>) -> Result<Self, ReplayError> {
+        let found = world.map_hash();
+        if found != log.header.map_hash {
+            return Err(ReplayError::MapMismatch { expected: log.header.map_hash, found });
+        }
+        let found = world.state_hash();
+        if found != log.header.start_hash {
+            return Err(ReplayError::StartMismatch { expected: log.header.start_hash, found });
+        }
+        world.seed_entities(log.header.seed);
+        Ok(Self { log, next: 0 })
+    }
+
+    /// Takes the next recorded action. `Ok(None)` once every action was played.
+    pub fn step<M: MapSource>(&mut self, world: &mut AtlasWorld<M>) -> Result<Option<TurnResult>, ReplayError> {
+        let Some(recorded) = self.log.actions.get(self.next) else {
+            return Ok(None);
+        };
+        if recorded.turn != world.turn() {
+            return Err(ReplayError::TurnMismatch { expected: recorded.turn, found: world.turn() });
+        }
+        self.next += 1;
+        Ok(Some(world.take_turn(recorded.action)))
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.next >= self.log.actions.len()
+    }
+
+    /// whether `world` ended where the recording did, `None` when the log has no final hash
+    pub fn matches_final<M: MapSource>(&self, world: &AtlasWorld<M>) -> Option<bool> {
+        self.log.final_hash.map(|hash| hash == world.state_hash())
+    }
+}
+
+impl<M: MapSource> AtlasWorld<M> {
+    /// A hash of every layer of the map, stable across runs and machines.
+    pub fn map_hash(&self) -> u64 {
+        let mut hash = StableHash::new();
+        hash.write_u64(self.map.width() as u64);
+        hash.write_u64(self.map.height() as u64);
+        for layer in HASHED_LAYERS {
+            self.hash_layer(&mut hash, layer);
+        }
+        hash.finish()
+    }
+
+    /// A hash of what play changes: the player's cell, facing and stats, the turn, the layers
+    /// play writes, open doors and the entities. Stable across runs and machines.
+    pub fn state_hash(&self) -> u64 {
+        let mut hash = StableHash::new();
+        hash.write_i32(self.player.x);
+        hash.write_i32(self.player.y);
+        hash.write_i32(self.player.direction.to_index());
+        let stats = self.player.stats;
+        [stats.hp, stats.attack, stats.defense].into_iter().for_each(|value| hash.write_i32(value));
+        hash.write_u64(self.turn);
+        for layer in STATE_LAYERS {
+            self.hash_layer(&mut hash, layer);
+        }
+        for pos in self.open_doors() {
+            hash.write_i32(pos.x);
+            hash.write_i32(pos.y);
+        }
+        for entity in &self.entities {
+            hash.write_u64(entity.id.0 as u64);
+            hash.write_i32(entity.pos.x);
+            hash.write_i32(entity.pos.y);
+            hash.write_i32(entity.stats.hp);
+        }
+        hash.finish()
+    }
+
+    // layers the map doesn't have are hashed as a marker, so adding one changes the hash
+    fn hash_layer(&self, hash: &mut StableHash, layer: &str) {
+        hash.write(layer.as_bytes());
+        if self.map.get(layer, IVec2::ZERO).is_none() {
+            hash.write(&[0]);
+            return;
+        }
+        for y in 0..self.map.height() as i32 {
+            for x in 0..self.map.width() as i32 {
+                hash.write(&[self.map.get(layer, ivec2(x, y)).unwrap_or(0)]);
+            }
+        }
+    }
+}
+
+// FNV-1a, since the std and ahash hashers are seeded per process
+struct StableHash(u64);
+
+impl StableHash {
+    fn new() -> Self {
+        Self(0xcbf2_9ce4_8422_2325)
+    }
+
+    fn write(&mut self, bytes: &[u8]) {
+        for byte in bytes {
+            self.0 = (self.0 ^ *byte as u64).wrapping_mul(0x0100_0000_01b3);
+        }
+    }
+
+    fn write_i32(&mut self, value: i32) {
+        self.write(&value.to_le_bytes());
+    }
+
+    fn write_u64(&mut self, value: u64) {
+        self.write(&value.to_le_bytes());
+    }
+
+    fn finish(&self) -> u64 {
+        self.0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{AtlasCollection, AtlasMap, Behavior, Direction, Entity, Movement, Player, Turn};
+
+    // a walled 7x7 room with a wanderer that moves every turn, so how the session plays out
+    // depends on the seed as well as the actions
+    fn world() -> AtlasWorld {
+        let mut map = AtlasMap::empty(7, 7);
+        for i in 0..7 {
+            for (x, y) in [(i, 0), (i, 6), (0, i), (6, i)] {
+                map.wall.set(x, y, 1);
+            }
+        }
+        let mut world = AtlasWorld::new(Player::new(1, 1, Direction::South), map, AtlasCollection::new());
+        let mut wanderer = Entity::new(ivec2(5, 5), Direction::North, "common_objects", "object-1");
+        wanderer.behavior = Behavior::Wander { chance: 1.0 };
+        world.add_entity(wanderer);
+        world
+    }
+
+    const SESSION: [PlayerAction; 7] = [
+        PlayerAction::Move(Movement::Forward),
+        PlayerAction::Move(Movement::Forward),
+        PlayerAction::Turn(Turn::Left),
+        PlayerAction::Move(Movement::Forward),
+        PlayerAction::Wait,
+        PlayerAction::Move(Movement::StrafeRight),
+        PlayerAction::Turn(Turn::Around),
+    ];
+
+    fn recorded(seed: u64) -> ReplayLog {
+        let mut world = world();
+        let mut recorder = Recorder::start(&mut world, seed);
+        for action in SESSION {
+            recorder.take_turn(&mut world, action);
+        }
+        recorder.finish(&world)
+    }
+
+    #[test]
+    fn replays_end_where_the_recording_did() {
+        let log = recorded(7);
+        assert_eq!(log.actions.len(), SESSION.len());
+        // the log survives being written out
+        let log: ReplayLog = serde_json::from_str(&serde_json::to_string(&log).unwrap()).unwrap();
+
+        let mut world = world();
+        let mut replayer = Replayer::start(log, &mut world).unwrap();
+        let mut steps = 0;
+        while replayer.step(&mut world).unwrap().is_some() {
+            steps += 1;
+        }
+        assert_eq!(steps, SESSION.len());
+        assert!(replayer.is_finished());
+        assert_eq!(replayer.matches_final(&world), Some(true));
+        assert_ne!(world.entities()[0].pos, ivec2(5, 5));
+        assert_eq!((world.player.x, world.player.y), (2, 4));
+    }
+
+    #[test]
+    fn logs_for_another_map_are_rejected() {
+        let mut world = world();
+        world.map.wall.set(3, 3, 1);
+        let error = Replayer::start(recorded(7), &mut world).unwrap_err();
+        assert!(matches!(error, ReplayError::MapMismatch { .. }), "{error}");
+    }
+
+    #[test]
+    fn logs_from_another_start_are_rejected() {
+        let mut world = world();
+        world.player.x = 2;
+        let error = Replayer::start(recorded(7), &mut world).unwrap_err();
+        assert!(matches!(error, ReplayError::StartMismatch { .. }), "{error}");
+    }
+
+    #[test]
+    fn worlds_that_take_turns_of_their_own_desync() {
+        let mut world = world();
+        let mut replayer = Replayer::start(recorded(7), &mut world).unwrap();
+        replayer.step(&mut world).unwrap();
+        world.take_turn(PlayerAction::Wait);
+        assert_eq!(replayer.step(&mut world), Err(ReplayError::TurnMismatch { expected: 1, found: 2 }));
+    }
+}
@@ -0,0 +1,728 @@
+//! Savegames and the slots they are kept in.
+
+use std::{collections::BTreeMap, fmt, fs, io, path::PathBuf};
+
+use ahash::{AHashMap, AHashSet};
+
+use serde::{Deserialize, Serialize};
+
+use crate::math::*;
+use crate::{encounter::EntityId, stats::WorldStats, AtlasWorld, AutoMap, Behavior, Direction, Entity, Grid, MapSource, Player, Stats};
+
+/// size of the RGBA8 thumbnails stored with each slot
+pub const THUMBNAIL_WIDTH: usize = 80;
+pub const THUMBNAIL_HEIGHT: usize = 64;
+
+/// the map layers play can change, kept whole in a `WorldState`: walls for bashed walls,
+/// objects for ones taken or dropped, doors
+pub const STATE_LAYERS: [&str; 3] = ["wall", "object", "doors"];
+
+/// A `WorldState` with what the load menu shows about it. The map and atlases are loaded as
+/// usual and the save is applied on top.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct WorldSave {
+    pub level_name: String,
+    pub play_turns: u64,
+    pub state: WorldState,
+}
+
+/// Everything play changes: the player, the changeable parts of every map and the world's
+/// bookkeeping, without any slot information. A plain serde struct, so any format will do.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct WorldState {
+    pub player: Player,
+    /// `AtlasWorld::current_map`; `None` in states saved before levels were, which are loaded
+    /// onto whichever map is current
+    #[serde(default)]
+    pub current_map: Option<String>,
+    /// the current map's state
+    #[serde(flatten)]
+    pub level: LevelState,
+    /// the state of every other map, by id
+    #[serde(default)]
+    pub levels: BTreeMap<String, LevelState>,
+    /// `MovementRules::passable_walls`
+    #[serde(default)]
+    pub passable_walls: Vec<u8>,
+    /// `AtlasWorld::time`
+    #[serde(default)]
+    pub time: f32,
+    #[serde(default)]
+    pub defeated: Vec<EntityId>,
+    #[serde(default)]
+    pub stats: Option<WorldStats>,
+    /// the id the next added entity gets
+    #[serde(default)]
+    pub next_entity_id: u32,
+}
+
+/// The changeable parts of one map and what goes with it.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct LevelState {
+    pub width: usize,
+    pub height: usize,
+    /// the `STATE_LAYERS` the map has, as rows of cells
+    pub layers: BTreeMap<String, Grid>,
+    /// [x, y] of every open door
+    #[serde(default)]
+    pub open_doors: Vec<[i32; 2]>,
+    /// ([x, y], turn) of every open door `door_close_turns` is counting for
+    #[serde(default)]
+    pub door_timers: Vec<([i32; 2], u64)>,
+    #[serde(default)]
+    pub automap: Option<AutoMap>,
+    /// [x, y] of every secret wall not revealed yet
+    #[serde(default)]
+    pub secret_walls: Vec<[i32; 2]>,
+    /// [x, y] of every revealed secret wall
+    #[serde(default)]
+    pub revealed_secrets: Vec<[i32; 2]>,
+    /// every entity, defeated ones included; `None` in states saved before entities were,
+    /// which keeps the current ones
+    #[serde(default)]
+    pub entities: Option<Vec<SavedEntity>>,
+}
+
+/// An `Entity` as a `WorldState` keeps it.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct SavedEntity {
+    pub id: EntityId,
+    /// [x, y]
+    pub pos: [i32; 2],
+    pub facing: Direction,
+    pub atlas_id: String,
+    pub layer_id: String,
+    pub blocks_movement: bool,
+    pub behavior: Behavior,
+    pub stats: Stats,
+}
+
+impl From<&Entity> for SavedEntity {
+    fn from(entity: &Entity) -> Self {
+        Self {
+            id: entity.id,
+            pos: [entity.pos.x, entity.pos.y],
+            facing: entity.facing,
+            atlas_id: entity.atlas_id.clone(),
+            layer_id: entity.layer_id.clone(),
+            blocks_movement: entity.blocks_movement,
+            behavior: entity.behavior,
+            stats: entity.stats,
+        }
+    }
+}
+
+impl From<SavedEntity> for Entity {
+    fn from(saved: SavedEntity) -> Self {
+        Self {
+            id: saved.id,
+            pos: ivec2(saved.pos[0], saved.pos[1]),
+            facing: saved.facing,
+            atlas_id: saved.atlas_id,
+            layer_id: saved.layer_id,
+            blocks_movement: saved.blocks_movement,
+            behavior: saved.behavior,
+            stats: saved.stats,
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum SaveError {
+    Io(io::Error),
+    Json(serde_json::Error),
+    /// the saved player position isn't inside the map it's applied to
+    PlayerOutOfBounds { x: i32, y: i32 },
+    /// a state, or one of its layers, is a different size than the map
+    SizeMismatch { expected: (usize, usize), found: (usize, usize) },
+    /// a state for a map the world doesn't have
+    UnknownLevel(String),
+    NoSuchSlot(u32),
+}
+
+impl fmt::Display for SaveError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SaveError::Io(err) => write!(f, "save io error: {err}"),
+            SaveError::Json(err) => write!(f, "save is not valid json: {err}"),
+            SaveError::PlayerOutOfBounds { x, y } => write!(f, "saved player position ({x}, {y}) is outside the map"),
+            SaveError::SizeMismatch { expected, found } => {
+                write!(f, "saved state is {}x{} but the map is {}x{}", found.0, found.1, expected.0, expected.1)
+            }
+            SaveError::UnknownLevel(map_id) => write!(f, "saved state is for map '{map_id}', which the world doesn't have"),
+            SaveError::NoSuchSlot(slot) => write!(f, "save slot {slot} is empty"),
+        }
+    }
+}
+
+impl std::error::Error for SaveError {}
+
+impl From<io::Error> for SaveError {
+    fn from(err: io::Error) -> Self {
+        SaveError::Io(err)
+    }
+}
+
+impl From<serde_json::Error> for SaveError {
+    fn from(err: serde_json::Error) -> Self {
+        SaveError::Json(err)
+    }
+}
+
+impl<M: MapSource> AtlasWorld<M> {
+    pub fn to_save(&self, level_name: &str, play_turns: u64) -> WorldSave {
+        WorldSave { level_name: level_name.to_owned(), play_turns, state: self.save_state() }
+    }
+
+    /// `load_state` with the save's state. Nothing is changed when it doesn't fit the map.
+    pub fn apply_save(&mut self, save: &WorldSave) -> Result<(), SaveError> {
+        self.load_state(save.state.clone())
+    }
+
+    pub fn save_state(&self) -> WorldState {
+        let level = level_state(&self.map, (&self.open_doors, &self.door_timers), (&self.secret_walls, &self.revealed_secrets), &self.entities, self.automap.as_ref());
+        WorldState {
+            player: Player {
+                animation: None,
+                animation_progress: 0.0,
+                queued_input: None,
+                ..self.player.clone()
+            },
+            current_map: Some(self.current_map.clone()),
+            level,
+            levels: self.levels.iter().map(|(map_id, level)| (map_id.clone(), level.state())).collect(),
+            passable_walls: {
+                let mut walls: Vec<u8> = self.movement_rules.passable_walls.iter().copied().collect();
+                walls.sort_unstable();
+                walls
+            },
+            time: self.time,
+            defeated: {
+                let mut defeated: Vec<EntityId> = self.defeated().iter().copied().collect();
+                defeated.sort_unstable();
+                defeated
+            },
+            stats: self.stats().cloned(),
+            next_entity_id: self.next_entity_id,
+        }
+    }
+
+    /// Restores a state onto this world, switching to the map it was saved on. Nothing is
+    /// changed when it doesn't fit the world's maps.
+    pub fn load_state(&mut self, state: WorldState) -> Result<(), SaveError> {
+        let current_map = state.current_map.clone().unwrap_or_else(|| self.current_map.clone());
+        let map = self.level(&current_map).ok_or_else(|| SaveError::UnknownLevel(current_map.clone()))?;
+        state.level.check_size(map)?;
+        let pos = ivec2(state.player.x, state.player.y);
+        if !map.in_bounds(pos) {
+            return Err(SaveError::PlayerOutOfBounds { x: pos.x, y: pos.y });
+        }
+        for (map_id, level) in &state.levels {
+            level.check_size(self.level(map_id).ok_or_else(|| SaveError::UnknownLevel(map_id.clone()))?)?;
+        }
+
+        if current_map != self.current_map {
+            self.switch_level(&current_map, pos, state.player.direction);
+        }
+        restore_layers(&mut self.map, &state.level);
+        self.player = state.player;
+        self.set_open_doors(state.level.open_doors.iter().map(|[x, y]| ivec2(*x, *y)));
+        self.door_timers = door_timers(&state.level, &self.open_doors);
+        if state.level.automap.is_some() {
+            self.automap = state.level.automap;
+        }
+        self.set_secrets(cells(&state.level.secret_walls), cells(&state.level.revealed_secrets));
+        if let Some(entities) = state.level.entities {
+            self.entities = entities.into_iter().map(Entity::from).collect();
+        }
+        for (map_id, level) in state.levels {
+            if let Some(parked) = self.levels.get_mut(&map_id) {
+                parked.restore(level);
+            }
+        }
+        self.movement_rules.passable_walls = state.passable_walls.into_iter().collect();
+        self.time = state.time;
+        self.defeated = state.defeated.into_iter().collect();
+        self.stats = state.stats;
+        let after_last = self.entities.iter()
+            .chain(self.levels.values().flat_map(|level| level.entities()))
+            .map(|entity| entity.id.0 + 1)
+            .max()
+            .unwrap_or(1);
+        self.next_entity_id = state.next_entity_id.max(after_last);
+        self.map.focus(pos);
+        self.touch_map();
+        Ok(())
+    }
+}
+
+impl LevelState {
+    // the state, its layers and its automap all being the size of `map`
+    fn check_size<M: MapSource + ?Sized>(&self, map: &M) -> Result<(), SaveError> {
+        let expected = (map.width(), map.height());
+        let sizes = std::iter::once((self.width, self.height))
+            .chain(self.layers.values().map(|grid| (grid.width(), grid.height())))
+            .chain(self.automap.iter().map(|automap| (automap.width, automap.height)));
+        for found in sizes {
+            if found != expected {
+                return Err(SaveError::SizeMismatch { expected, found });
+            }
+        }
+        Ok(())
+    }
+}
+
+// the state of a map with its (open doors, door timers), (hidden, revealed) secrets, entities
+// and automap, the current one or one put away
+pub(crate) fn level_state<M: MapSource + ?Sized>(
+    map: &M,
+    (open_doors, timers): (&AHashSet<IVec2>, &AHashMap<IVec2, u64>),
+    (secret_walls, revealed_secrets): (&AHashSet<IVec2>, &AHashSet<IVec2>),
+    entities: &[Entity],
+    automap: Option<&AutoMap>,
+) -> LevelState {
+    let (width, height) = (map.width(), map.height());
+    let layers = STATE_LAYERS.iter()
+        .filter(|layer| map.get(layer, IVec2::ZERO).is_some())
+        .map(|layer| {
+            let mut grid = Grid::new(width, height);
+            for y in 0..height {
+                for x in 0..width {
+                    grid.set(x, y, map.get(layer, ivec2(x as i32, y as i32)).unwrap_or(0));
+                }
+            }
+            (layer.to_string(), grid)
+        })
+        .collect();
+    let mut door_timers: Vec<([i32; 2], u64)> = timers.iter().map(|(pos, turn)| ([pos.x, pos.y], *turn)).collect();
+    door_timers.sort_unstable();
+    LevelState {
+        width,
+        height,
+        layers,
+        open_doors: {
+            let mut open = cell_list(open_doors);
+            open.sort_by_key(|[x, y]| (*y, *x));
+            open
+        },
+        door_timers,
+        automap: automap.cloned(),
+        secret_walls: cell_list(secret_walls),
+        revealed_secrets: cell_list(revealed_secrets),
+        entities: Some(entities.iter().map(SavedEntity::from).collect()),
+    }
+}
+
+// writes the state's layers into `map`
+pub(crate) fn restore_layers<M: MapSource + ?Sized>(map: &mut M, state: &LevelState) {
+    for (layer, grid) in &state.layers {
+        for (y, row) in grid.iter_rows().enumerate() {
+            for (x, value) in row.iter().enumerate() {
+                map.set(layer, ivec2(x as i32, y as i32), *value);
+            }
+        }
+    }
+}
+
+// the state's door timers for the doors in `open`
+pub(crate) fn door_timers(state: &LevelState, open: &AHashSet<IVec2>) -> AHashMap<IVec2, u64> {
+    state.door_timers.iter()
+        .map(|([x, y], turn)| (ivec2(*x, *y), *turn))
+        .filter(|(pos, _)| open.contains(pos))
+        .collect()
+}
+
+pub(crate) fn cells(list: &[[i32; 2]]) -> impl Iterator<Item = IVec2> + '_ {
+    list.iter().map(|[x, y]| ivec2(*x, *y))
+}
+
+// [x, y] of every cell, sorted so saves come out the same each time
+fn cell_list(cells: &AHashSet<IVec2>) -> Vec<[i32; 2]> {
+    let mut cells: Vec<[i32; 2]> = cells.iter().map(|pos| [pos.x, pos.y]).collect();
+    cells.sort_unstable();
+    cells
+}
+
+/// Where slot files live. Files on disk by default; on the web a consumer can implement this
+/// over localStorage or similar.
+pub trait SaveStorage {
+    fn read(&self, key: &str) -> io::Result<Option<Vec<u8>>>;
+    /// must either fully replace `key` or leave it as it was
+    fn write_atomic(&mut self, key: &str, bytes: &[u8]) -> io::Result<()>;
+    fn delete(&mut self, key: &str) -> io::Result<()>;
+    fn keys(&self) -> io::Result<Vec<String>>;
+
+    /// seconds since the unix epoch, for slot timestamps
+    fn now(&self) -> u64 {
+        std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or(0)
+    }
+}
+
+/// One file per key in a directory, written to a temp file first and renamed over the old one.
+pub struct FileStorage {
+    pub dir: PathBuf,
+}
+
+impl FileStorage {
+    fn path(&self, key: &str) -> PathBuf {
+        self.dir.join(key)
+    }
+
+    fn temp_path(&self, key: &str) -> PathBuf {
+        self.dir.join(format!("{key}.tmp"))
+    }
+}
+
+impl SaveStorage for FileStorage {
+    fn read(&self, key: &str) -> io::Result<Option<Vec<u8>>> {
+        match fs::read(self.path(key)) {
+            Ok(bytes) => Ok(Some(bytes)),
+            Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(None),
+            Err(err) => Err(err),
+        }
+    }
+
+    fn write_atomic(&mut self, key: &str, bytes: &[u8]) -> io::Result<()> {
+        fs::create_dir_all(&self.dir)?;
+        let temp = self.temp_path(key);
+        fs::write(&temp, bytes)?;
+        fs::rename(&temp, self.path(key))
+    }
+
+    fn delete(&mut self, key: &str) -> io::Result<()> {
+        match fs::remove_file(self.path(key)) {
+            Err(err) if err.kind() != io::ErrorKind::NotFound => Err(err),
+            _ => Ok(()),
+        }
+    }
+
+    fn keys(&self) -> io::Result<Vec<String>> {
+        let entries = match fs::read_dir(&self.dir) {
+            Ok(entries) => entries,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(err) => return Err(err),
+        };
+        let mut keys = Vec::new();
+        for entry in entries {
+            if let Some(name) = entry?.file_name().to_str() {
+                keys.push(name.to_owned());
+            }
+        }
+        Ok(keys)
+    }
+}
+
+/// What the load menu shows for a slot.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct SlotInfo {
+    pub slot: u32,
+    /// seconds since the unix epoch
+    pub timestamp: u64,
+    pub level_name: String,
+    pub play_turns: u64,
+    /// `THUMBNAIL_WIDTH` x `THUMBNAIL_HEIGHT` RGBA8 pixels
+    pub thumbnail: Option<Vec<u8>>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct SlotFile {
+    info: SlotInfo,
+    save: WorldSave,
+}
+
+/// Numbered save slots, each kept as one `slot-{n}.json` entry of the storage.
+pub struct SaveSlotStore<S: SaveStorage = FileStorage> {
+    storage: S,
+}
+
+impl SaveSlotStore<FileStorage> {
+    pub fn new<P: Into<PathBuf>>(dir: P) -> Self {
+        Self::with_storage(FileStorage { dir: dir.into() })
+    }
+}
+
+impl<S: SaveStorage> SaveSlotStore<S> {
+    pub fn with_storage(storage: S) -> Self {
+        Self { storage }
+    }
+
+    fn key(slot: u32) -> String {
+        format!("slot-{slot}.json")
+    }
+
+    /// every readable slot, lowest slot number first
+    pub fn list(&self) -> Result<Vec<SlotInfo>, SaveError> {
+        let mut slots: Vec<u32> = self.storage.keys()?.iter()
+            .filter_map(|key| key.strip_prefix("slot-")?.strip_suffix(".json")?.parse().ok())
+            .collect();
+        slots.sort_unstable();
+
+        let mut infos = Vec::new();
+        for slot in slots {
+            let Some(bytes) = self.storage.read(&Self::key(slot))? else {
+                continue;
+            };
+            // a slot that doesn't parse is left out of the listing rather than failing it
+            if let Ok(file) = serde_json::from_slice::<SlotFile>(&bytes) {
+                infos.push(file.info);
+            }
+        }
+        Ok(infos)
+    }
+
+    /// `thumbnail` is typically a capture of the viewport and gets downscaled to the thumbnail size
+    pub fn write(&mut self, slot: u32, save: &WorldSave, thumbnail: Option<&Image>) -> Result<(), SaveError> {
+        let info = SlotInfo {
+            slot,
+            timestamp: self.storage.now(),
+            level_name: save.level_name.clone(),
+            play_turns: save.play_turns,
+            thumbnail: thumbnail.map(downscale),
+        };
+        let bytes = serde_json::to_vec(&SlotFile { info, save: save.clone() })?;
+        self.storage.write_atomic(&Self::key(slot), &bytes)?;
+        Ok(())
+    }
+
+    pub fn read(&self, slot: u32) -> Result<WorldSave, SaveError> {
+        let bytes = self.storage.read(&Self::key(slot))?.ok_or(SaveError::NoSuchSlot(slot))?;
+        Ok(serde_json::from_slice::<SlotFile>(&bytes)?.save)
+    }
+
+    pub fn delete(&mut self, slot: u32) -> Result<(), SaveError> {
+        Ok(self.storage.delete(&Self::key(slot))?)
+    }
+}
+
+// nearest neighbor, good enough for a menu thumbnail
+fn downscale(image: &Image) -> Vec<u8> {
+    let (width, height) = (image.width as usize, image.height as usize);
+    let mut pixels = Vec::with_capacity(THUMBNAIL_WIDTH * THUMBNAIL_HEIGHT * 4);
+    for y in 0..THUMBNAIL_HEIGHT {
+        for x in 0..THUMBNAIL_WIDTH {
+            let source_x = x * width / THUMBNAIL_WIDTH;
+            let source_y = y * height / THUMBNAIL_HEIGHT;
+            let i = (source_y * width + source_x) * 4;
+            pixels.extend_from_slice(image.bytes.get(i..i + 4).unwrap_or(&[0; 4]));
+        }
+    }
+    pixels
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{AtlasCollection, AtlasMap};
+
+    // a 5x5 map with a door at (2, 1)
+    fn map_with_door() -> AtlasMap {
+        let mut map = AtlasMap::empty(5, 5);
+        let mut doors = Grid::new(5, 5);
+        doors.set(2, 1, 1);
+        map.layers.insert("doors".to_owned(), doors);
+        map
+    }
+
+    fn world() -> AtlasWorld {
+        let mut world = AtlasWorld::new(Player::new(1, 1, Direction::North), map_with_door(), AtlasCollection::new());
+        world.add_level("cellar", map_with_door());
+        world
+    }
+
+    #[test]
+    fn load_returns_to_the_saved_level_with_every_level_kept() {
+        let mut world = world();
+        world.set_open_doors([ivec2(2, 1)]);
+        world.map.wall.set(3, 3, 4);
+        world.switch_level("cellar", ivec2(0, 4), Direction::East);
+        let state = world.save_state();
+        assert_eq!(state.current_map.as_deref(), Some("cellar"));
+
+        let mut loaded = self::world();
+        loaded.load_state(serde_json::from_str(&serde_json::to_string(&state).unwrap()).unwrap()).unwrap();
+        assert_eq!(loaded.current_map, "cellar");
+        assert_eq!((loaded.player.x, loaded.player.y), (0, 4));
+        assert!(loaded.open_doors().is_empty());
+
+        loaded.switch_level("main", ivec2(1, 1), Direction::North);
+        assert!(loaded.is_door_open(ivec2(2, 1)));
+        assert_eq!(loaded.map.get("wall", ivec2(3, 3)), Some(4));
+        assert_eq!(loaded.save_state().levels["cellar"], state.level);
+    }
+
+    #[test]
+    fn a_state_for_an_unknown_level_changes_nothing() {
+        let mut state = world().save_state();
+        state.current_map = Some("attic".to_owned());
+        state.player.x = 3;
+        let mut world = world();
+        assert!(matches!(world.load_state(state), Err(SaveError::UnknownLevel(map_id)) if map_id == "attic"));
+        assert_eq!((world.current_map.as_str(), world.player.x), ("main", 1));
+    }
+
+    #[test]
+    fn states_without_levels_load_onto_the_current_map() {
+        let mut state = serde_json::to_value(world().save_state()).unwrap();
+        let fields = state.as_object_mut().unwrap();
+        fields.remove("current_map");
+        fields.remove("levels");
+        let mut world = world();
+        world.switch_level("cellar", ivec2(0, 0), Direction::South);
+        world.load_state(serde_json::from_value(state).unwrap()).unwrap();
+        assert_eq!(world.current_map, "cellar");
+        assert_eq!((world.player.x, world.player.y), (1, 1));
+    }
+
+    #[test]
+    fn absurd_player_positions_are_rejected() {
+        let mut world = self::world();
+        for (x, y) in [(i32::MIN, 1), (1, i32::MAX), (-1, 0), (5, 4), (4, 5)] {
+            let mut state = world.save_state();
+            (state.player.x, state.player.y) = (x, y);
+            assert!(matches!(world.load_state(state), Err(SaveError::PlayerOutOfBounds { .. })), "({x}, {y})");
+            assert_eq!((world.player.x, world.player.y), (1, 1));
+        }
+    }
+
+    #[test]
+    fn states_round_trip_the_player_doors_objects_and_automap() {
+        let mut world = self::world();
+        world.map.object.set(3, 2, 6);
+        world.map.object.set(4, 4, 7);
+        world.player.corner_config.order = crate::CornerOrder::StepThenTurn;
+        world.player.animation_duration = 0.5;
+        world.set_open_doors([ivec2(2, 1)]);
+        world.automap = Some(AutoMap::new(5, 5));
+        world.update(0.0);
+        assert_eq!(world.take_object(ivec2(3, 2)), Some(6));
+        // the save is taken mid step, and keeps only where the step ends
+        world.start_move(crate::Movement::StrafeRight);
+        assert!(world.player.is_animating());
+        let state = world.save_state();
+
+        let json = serde_json::to_string(&state).unwrap();
+        let mut loaded = self::world();
+        loaded.load_state(serde_json::from_str(&json).unwrap()).unwrap();
+        assert_eq!(loaded.player, Player { animation_duration: 0.5, corner_config: world.player.corner_config, ..Player::new(2, 1, Direction::North) });
+        assert!(!loaded.player.is_animating());
+        assert_eq!((loaded.map.object.get(3, 2), loaded.map.object.get(4, 4)), (Some(0), Some(7)));
+        assert!(loaded.is_door_open(ivec2(2, 1)));
+        assert!(loaded.automap.as_ref().unwrap().is_explored(ivec2(1, 1)));
+        assert!(!loaded.automap.as_ref().unwrap().is_explored(ivec2(2, 2)));
+        assert_eq!(loaded.save_state(), state);
+    }
+
+    #[test]
+    fn maps_and_players_serialize() {
+        let mut map = map_with_door();
+        map.wall.set(0, 3, 2);
+        map.object.set(1, 4, 9);
+        let json = serde_json::to_string(&map).unwrap();
+        assert_eq!(serde_json::from_str::<AtlasMap>(&json).unwrap(), map);
+
+        // players written before the corner and animation settings were get the defaults
+        let player: Player = serde_json::from_str(r#"{ "x": 3, "y": -2, "direction": "West" }"#).unwrap();
+        assert_eq!(player, Player::new(3, -2, Direction::West));
+    }
+
+    #[test]
+    fn states_for_a_different_sized_map_are_rejected() {
+        let mut small = self::world();
+        small.map.object.set(1, 1, 3);
+        let small = small.save_state();
+
+        let mut world = AtlasWorld::new(Player::new(0, 0, Direction::East), AtlasMap::empty(6, 5), AtlasCollection::new());
+        world.add_level("cellar", map_with_door());
+        assert!(matches!(world.load_state(small.clone()), Err(SaveError::SizeMismatch { expected: (6, 5), found: (5, 5) })));
+        assert_eq!((world.player.x, world.player.direction), (0, Direction::East));
+
+        // a layer or automap that doesn't match the size the state claims is caught too
+        let mut world = self::world();
+        let mut state = small.clone();
+        state.level.layers.insert("object".to_owned(), Grid::new(5, 4));
+        assert!(matches!(world.load_state(state), Err(SaveError::SizeMismatch { expected: (5, 5), found: (5, 4) })));
+        let mut state = small.clone();
+        state.level.automap = Some(AutoMap::new(4, 5));
+        assert!(matches!(world.load_state(state), Err(SaveError::SizeMismatch { expected: (5, 5), found: (4, 5) })));
+        // as is another level's
+        let mut state = small.clone();
+        state.levels.get_mut("cellar").unwrap().height = 3;
+        assert!(matches!(world.load_state(state), Err(SaveError::SizeMismatch { expected: (5, 5), found: (5, 3) })));
+        assert_eq!(world.map.object.get(1, 1), Some(0));
+
+        world.load_state(small).unwrap();
+        assert_eq!(world.map.object.get(1, 1), Some(3));
+    }
+
+    fn store(name: &str) -> SaveSlotStore {
+        let dir = std::env::temp_dir().join(format!("atlas_world_saves_{name}_{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        SaveSlotStore::new(dir)
+    }
+
+    #[test]
+    fn slots_are_listed_by_number_with_their_info() {
+        let mut store = store("listing");
+        let world = world();
+        for (slot, turns) in [(10, 3), (2, 7), (1, 11)] {
+            store.write(slot, &world.to_save(&format!("level {slot}"), turns), None).unwrap();
+        }
+        // half-written, unreadable and foreign files are left out
+        fs::write(store.storage.dir.join("slot-5.json"), "{ truncated").unwrap();
+        fs::write(store.storage.dir.join("slot-6.json.tmp"), "{ truncated").unwrap();
+        fs::write(store.storage.dir.join("notes.txt"), "hello").unwrap();
+
+        let slots = store.list().unwrap();
+        let listed: Vec<(u32, &str, u64)> = slots.iter().map(|info| (info.slot, info.level_name.as_str(), info.play_turns)).collect();
+        assert_eq!(listed, [(1, "level 1", 11), (2, "level 2", 7), (10, "level 10", 3)]);
+        assert!(slots.iter().all(|info| info.timestamp > 0 && info.thumbnail.is_none()));
+
+        store.delete(2).unwrap();
+        store.delete(3).unwrap();
+        assert_eq!(store.list().unwrap().iter().map(|info| info.slot).collect::<Vec<_>>(), [1, 10]);
+        assert!(matches!(store.read(2), Err(SaveError::NoSuchSlot(2))));
+        assert_eq!(store.read(10).unwrap(), world.to_save("level 10", 3));
+    }
+
+    #[test]
+    fn thumbnails_are_downscaled() {
+        let mut store = store("thumbnail");
+        let mut bytes = [10, 20, 30, 255].repeat(320 * 256);
+        bytes[(255 * 320 + 319) * 4..].copy_from_slice(&[200, 0, 0, 255]);
+        let image = Image { bytes, width: 320, height: 256 };
+        store.write(1, &world().to_save("main", 0), Some(&image)).unwrap();
+        let thumbnail = store.list().unwrap()[0].thumbnail.clone().unwrap();
+        assert_eq!(thumbnail.len(), THUMBNAIL_WIDTH * THUMBNAIL_HEIGHT * 4);
+        assert_eq!(thumbnail[..4], [10, 20, 30, 255]);
+    }
+
+    // a crash between writing the temp file and renaming it over the slot leaves the temp
+    // file behind, and a write that fails part way leaves the old slot as it was
+    #[test]
+    fn failed_writes_leave_the_old_slot_whole() {
+        let mut store = store("atomic");
+        let mut world = world();
+        let saved = world.to_save("before", 1);
+        store.write(1, &saved, None).unwrap();
+
+        fs::write(store.storage.temp_path("slot-1.json"), "{ half a sa").unwrap();
+        assert_eq!(store.read(1).unwrap(), saved);
+        assert_eq!(store.list().unwrap().len(), 1);
+
+        // the temp file can't be written while a directory sits in its place
+        fs::remove_file(store.storage.temp_path("slot-1.json")).unwrap();
+        fs::create_dir(store.storage.temp_path("slot-1.json")).unwrap();
+        world.player.x = 3;
+        assert!(matches!(store.write(1, &world.to_save("after", 2), None), Err(SaveError::Io(_))));
+        assert_eq!(store.read(1).unwrap(), saved);
+        assert_eq!(store.list().unwrap()[0].level_name, "before");
+
+        fs::remove_dir(store.storage.temp_path("slot-1.json")).unwrap();
+        store.write(1, &world.to_save("after", 2), None).unwrap();
+        assert_eq!(store.read(1).unwrap().state.player.x, 3);
+        assert!(!store.storage.temp_path("slot-1.json").exists());
+    }
+}
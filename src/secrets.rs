@@ -0,0 +1,71 @@
+//! Secret walls: cells whose wall looks like any other until `reveal_secret` opens it. Which
+//! cells are secret, and which were revealed, is world state kept with each level and saved.
+//! Illusionary walls that never open but can always be walked through are
+//! `MovementRules::passable_walls` instead.
+
+use ahash::AHashSet;
+#[cfg(not(feature = "render"))]
+use crate::math::*;
+#[cfg(feature = "render")]
+use macroquad::prelude::*;
+
+use crate::{events::WorldEvent, AtlasWorld, MapSource};
+#[cfg(feature = "render")]
+use crate::automap::MinimapLayout;
+
+impl<M: MapSource> AtlasWorld<M> {
+    /// Marks the wall at `pos` as secret. Returns false for a cell without a wall.
+    pub fn add_secret_wall(&mut self, pos: IVec2) -> bool {
+        if self.map.get_wall(pos).unwrap_or(0) == 0 {
+            return false;
+        }
+        self.secret_walls.insert(pos)
+    }
+
+    /// the secret walls not revealed yet
+    pub fn secret_walls(&self) -> &AHashSet<IVec2> {
+        &self.secret_walls
+    }
+
+    /// the cells whose secret wall was revealed
+    pub fn revealed_secrets(&self) -> &AHashSet<IVec2> {
+        &self.revealed_secrets
+    }
+
+    /// Clears the secret wall at `pos`, emitting `SecretRevealed`. Returns false, changing
+    /// nothing, when `pos` isn't an unrevealed secret.
+    pub fn reveal_secret(&mut self, pos: IVec2) -> bool {
+        if !self.secret_walls.contains(&pos) {
+            return false;
+        }
+        let value = self.map.get_wall(pos).unwrap_or(0);
+        if !self.set_cell("wall", pos, 0) {
+            return false;
+        }
+        self.secret_walls.remove(&pos);
+        self.revealed_secrets.insert(pos);
+        self.push_event(WorldEvent::SecretRevealed { pos, value });
+        true
+    }
+
+    /// Replaces the secret state, as a save restores it. Revealed cells have their wall cleared
+    /// again, for states saved without the wall layer.
+    pub fn set_secrets(&mut self, hidden: impl IntoIterator<Item = IVec2>, revealed: impl IntoIterator<Item = IVec2>) {
+        self.secret_walls = hidden.into_iter().collect();
+        self.revealed_secrets = revealed.into_iter().collect();
+        for pos in self.revealed_secrets.iter() {
+            self.map.set("wall", *pos, 0);
+        }
+        self.touch_map();
+    }
+
+    /// Outlines the revealed secrets inside the map drawn as `layout`, like `draw_exits`.
+    /// `draw_automap` uses it; custom map views can call it with their own color.
+    #[cfg(feature = "render")]
+    pub fn draw_secrets(&self, layout: &MinimapLayout, color: Color, discovered: impl Fn(IVec2) -> bool) {
+        for pos in self.revealed_secrets.iter().filter(|pos| discovered(**pos) && layout.contains(**pos)) {
+            let cell = layout.cell_rect(*pos);
+            draw_rectangle_lines(cell.x, cell.y, cell.w, cell.h, cell.w / 6.0, color);
+        }
+    }
+}
@@ -0,0 +1,68 @@
+//! A sky behind cells without a ceiling. It is drawn before any cell across the top of the view,
+//! so ceilings, walls and everything else still cover it, and shows the part of a panoramic
+//! strip the view is turned toward.
+
+use crate::math::*;
+use crate::{coords_rect, render_order::SKY_ORDER, AtlasWorld, MapSource, PlayerAnimation};
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct SkySettings {
+    /// atlas and atlas layer whose first tile is the panoramic strip, running clockwise from
+    /// north
+    pub atlas_id: String,
+    pub layer_id: String,
+    /// share of the view's height covered from the top, down to the horizon
+    pub height: f32,
+    /// how many times the strip goes by in one full turn; at 1 each cardinal facing shows its
+    /// own quarter of it
+    pub parallax: f32,
+}
+
+impl SkySettings {
+    pub fn new(atlas_id: &str, layer_id: &str) -> Self {
+        Self { atlas_id: atlas_id.to_owned(), layer_id: layer_id.to_owned(), height: 0.5, parallax: 1.0 }
+    }
+}
+
+impl<M: MapSource> AtlasWorld<M> {
+    // draws the window of the strip the view is facing, in two pieces where it wraps around
+    pub(crate) fn draw_sky(&self) {
+        let Some(sky) = &self.sky else { return };
+        let Some(tile) = self.collection.get(&sky.atlas_id)
+            .and_then(|atlas| atlas.atlas_info.layers.get(&sky.layer_id))
+            .and_then(|layer| layer.tiles().first())
+        else {
+            return;
+        };
+        self.current_order.set(SKY_ORDER);
+
+        let strip = coords_rect(tile.atlas_coords_at(self.time));
+        let view = self.viewport_rect();
+        let dest = Rect::new(view.x, view.y, view.w, view.h * sky.height.clamp(0.0, 1.0));
+        let window = strip.w / 4.0;
+        let start = (self.sky_heading() * sky.parallax).rem_euclid(1.0) * strip.w;
+
+        let first = window.min(strip.w - start);
+        let split = dest.w * first / window;
+        self.draw_quad(&sky.atlas_id, &sky.layer_id, tile.page, Rect::new(strip.x + start, strip.y, first, strip.h), Rect::new(dest.x, dest.y, split, dest.h), WHITE);
+        if first < window {
+            let rest = Rect::new(strip.x, strip.y, window - first, strip.h);
+            self.draw_quad(&sky.atlas_id, &sky.layer_id, tile.page, rest, Rect::new(dest.x + split, dest.y, dest.w - split, dest.h), WHITE);
+        }
+    }
+
+    // the view's heading as a share of a full turn, eased through a turn the player is animating
+    fn sky_heading(&self) -> f32 {
+        let view = self.view_point();
+        let heading = view.direction.heading();
+        let (Some(PlayerAnimation::Turn { from, to }), Some(progress)) = (self.player.animation(), self.player.animation_progress()) else {
+            return heading;
+        };
+        if view != self.player.view_point() || to != view.direction {
+            return heading;
+        }
+        // the short way round, which is how the turn is drawn
+        let delta = (to.heading() - from.heading() + 0.5).rem_euclid(1.0) - 0.5;
+        (from.heading() + delta * progress).rem_euclid(1.0)
+    }
+}
@@ -0,0 +1,352 @@
+use std::{fmt, path::Path};
+
+use serde::{Deserialize, Serialize};
+
+use crate::math::*;
+use crate::{AtlasWorld, DrawCommand, MapSource};
+
+/// set to `1` to rewrite snapshot files instead of comparing against them
+pub const UPDATE_SNAPSHOTS_ENV: &str = "ATLAS_UPDATE_SNAPSHOTS";
+
+const HEADER: &str = "# atlas_world render snapshot v2";
+
+// written in place of an empty id, like the `atlas_id` of an untextured fill
+const EMPTY_ID: &str = "-";
+
+/// A draw command with its floats rounded to hundredths so snapshots don't churn on noise.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct SnapshotCommand {
+    pub atlas_id: String,
+    pub layer_id: String,
+    pub source: [f32; 4],
+    pub dest: [f32; 4],
+    pub color: [u8; 4],
+}
+
+fn round(v: f32) -> f32 {
+    (v * 100.0).round() / 100.0
+}
+
+fn rect_values(rect: Rect) -> [f32; 4] {
+    [round(rect.x), round(rect.y), round(rect.w), round(rect.h)]
+}
+
+impl From<&DrawCommand> for SnapshotCommand {
+    fn from(command: &DrawCommand) -> Self {
+        Self {
+            atlas_id: command.atlas_id.clone(),
+            layer_id: command.layer_id.clone(),
+            source: rect_values(command.source),
+            dest: rect_values(command.dest),
+            color: command.color.into(),
+        }
+    }
+}
+
+impl fmt::Display for SnapshotCommand {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let [sx, sy, sw, sh] = self.source;
+        let [dx, dy, dw, dh] = self.dest;
+        let [r, g, b, a] = self.color;
+        let id = |id: &str| if id.is_empty() { EMPTY_ID.to_owned() } else { id.to_owned() };
+        write!(f, "{} {} {sx} {sy} {sw} {sh} {dx} {dy} {dw} {dh} {r:02x}{g:02x}{b:02x}{a:02x}", id(&self.atlas_id), id(&self.layer_id))
+    }
+}
+
+fn parse_id(part: &str) -> String {
+    if part == EMPTY_ID { String::new() } else { part.to_owned() }
+}
+
+#[derive(Debug)]
+pub struct SnapshotParseError {
+    pub line: usize,
+    pub message: String,
+}
+
+impl fmt::Display for SnapshotParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "snapshot line {}: {}", self.line, self.message)
+    }
+}
+
+impl std::error::Error for SnapshotParseError {}
+
+/// The normalized list of draw commands for one frame, in draw order.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Default)]
+pub struct RenderSnapshot {
+    pub commands: Vec<SnapshotCommand>,
+}
+
+impl RenderSnapshot {
+    pub fn capture<M: MapSource>(world: &AtlasWorld<M>) -> Self {
+        Self::from_commands(&world.capture_commands())
+    }
+
+    /// Normalizes `commands` into a snapshot: sorted by `DrawCommand::order`, so the snapshot
+    /// follows the documented render order whichever way the commands were gathered. The sort
+    /// is stable, commands drawn under the same key keep the order they were drawn in.
+    pub fn from_commands(commands: &[DrawCommand]) -> Self {
+        let mut sorted: Vec<&DrawCommand> = commands.iter().collect();
+        sorted.sort_by_key(|command| command.order);
+        Self {
+            commands: sorted.into_iter().map(SnapshotCommand::from).collect(),
+        }
+    }
+
+    /// one command per line, meant to be committed next to the tests that use it
+    pub fn to_text(&self) -> String {
+        let mut text = String::from(HEADER);
+        text.push('\n');
+        for command in &self.commands {
+            text.push_str(&command.to_string());
+            text.push('\n');
+        }
+        text
+    }
+
+    pub fn from_text(text: &str) -> Result<Self, SnapshotParseError> {
+        let mut commands = Vec::new();
+
+        for (i, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            let error = |message: &str| SnapshotParseError {
+                line: i + 1,
+                message: message.to_owned(),
+            };
+
+            let parts: Vec<&str> = line.split_whitespace().collect();
+            if parts.len() != 11 {
+                return Err(error("expected an atlas id, a layer id, 8 rect values and a color"));
+            }
+
+            let mut values = [0.0; 8];
+            for (value, part) in values.iter_mut().zip(&parts[2..10]) {
+                *value = part.parse().map_err(|_| error(&format!("'{part}' is not a number")))?;
+            }
+
+            let color = u32::from_str_radix(parts[10], 16).map_err(|_| error("color is not rrggbbaa hex"))?;
+
+            commands.push(SnapshotCommand {
+                atlas_id: parse_id(parts[0]),
+                layer_id: parse_id(parts[1]),
+                source: [values[0], values[1], values[2], values[3]],
+                dest: [values[4], values[5], values[6], values[7]],
+                color: color.to_be_bytes(),
+            });
+        }
+
+        Ok(Self { commands })
+    }
+
+    pub fn diff(&self, other: &RenderSnapshot) -> SnapshotDiff {
+        let old = &self.commands;
+        let new = &other.commands;
+
+        // longest common subsequence table, lcs[i][j] is for old[i..] and new[j..]
+        let mut lcs = vec![vec![0usize; new.len() + 1]; old.len() + 1];
+        for i in (0..old.len()).rev() {
+            for j in (0..new.len()).rev() {
+                lcs[i][j] = if old[i] == new[j] {
+                    lcs[i + 1][j + 1] + 1
+                } else {
+                    lcs[i + 1][j].max(lcs[i][j + 1])
+                };
+            }
+        }
+
+        let mut entries = Vec::new();
+        let (mut i, mut j) = (0, 0);
+        while i < old.len() || j < new.len() {
+            if i < old.len() && j < new.len() && old[i] == new[j] {
+                i += 1;
+                j += 1;
+            } else if i < old.len() && j < new.len() && lcs[i + 1][j + 1] == lcs[i][j] {
+                // neither side is part of the common run, so call it an in-place change
+                entries.push(DiffEntry::Changed {
+                    index: j,
+                    before: old[i].clone(),
+                    after: new[j].clone(),
+                });
+                i += 1;
+                j += 1;
+            } else if j == new.len() || (i < old.len() && lcs[i + 1][j] >= lcs[i][j + 1]) {
+                entries.push(DiffEntry::Removed {
+                    index: i,
+                    command: old[i].clone(),
+                });
+                i += 1;
+            } else {
+                entries.push(DiffEntry::Added {
+                    index: j,
+                    command: new[j].clone(),
+                });
+                j += 1;
+            }
+        }
+
+        SnapshotDiff { entries }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum DiffEntry {
+    Added { index: usize, command: SnapshotCommand },
+    Removed { index: usize, command: SnapshotCommand },
+    Changed { index: usize, before: SnapshotCommand, after: SnapshotCommand },
+}
+
+#[derive(Clone, Debug, PartialEq, Default)]
+pub struct SnapshotDiff {
+    pub entries: Vec<DiffEntry>,
+}
+
+impl SnapshotDiff {
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+}
+
+impl fmt::Display for SnapshotDiff {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for entry in &self.entries {
+            match entry {
+                DiffEntry::Added { index, command } => writeln!(f, "+ [{index}] {command}")?,
+                DiffEntry::Removed { index, command } => writeln!(f, "- [{index}] {command}")?,
+                DiffEntry::Changed { index, before, after } => {
+                    writeln!(f, "~ [{index}] {before}")?;
+                    writeln!(f, "       -> {after}")?;
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Compares `actual` with the snapshot stored at `path`, panicking with a diff on mismatch.
+/// `ATLAS_UPDATE_SNAPSHOTS=1` writes `actual` out instead; without it a missing file panics
+/// too, so a mistyped path can't pass.
+pub fn assert_snapshot<P: AsRef<Path>>(actual: &RenderSnapshot, path: P) {
+    let path = path.as_ref();
+    let update = std::env::var(UPDATE_SNAPSHOTS_ENV).is_ok_and(|v| v == "1");
+
+    if update {
+        std::fs::write(path, actual.to_text()).unwrap();
+        return;
+    }
+    if !path.exists() {
+        panic!("no golden at {}, rerun with {UPDATE_SNAPSHOTS_ENV}=1", path.display());
+    }
+
+    let text = std::fs::read_to_string(path).unwrap();
+    let expected = RenderSnapshot::from_text(&text).unwrap();
+    let diff = expected.diff(actual);
+    if !diff.is_empty() {
+        panic!(
+            "render snapshot {} does not match ({} differences, rerun with {UPDATE_SNAPSHOTS_ENV}=1 to accept):\n{diff}",
+            path.display(),
+            diff.entries.len()
+        );
+    }
+}
+
+#[macro_export]
+macro_rules! assert_snapshot {
+    ($world:expr, $path:expr) => {
+        $crate::snapshot::assert_snapshot(&$crate::RenderSnapshot::capture(&$world), $path)
+    };
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::render_order::{render_order_key, CellPass};
+    use crate::test_support::{demo_world, manifest_path};
+    use crate::{AtlasMap, Direction, Player};
+
+    // a map from fixtures/scenes drawn with the demo atlases, checked against fixtures/snapshots
+    fn check_scene(name: &str, player: Player) {
+        let file = std::fs::File::open(manifest_path(&format!("fixtures/scenes/{name}.json"))).unwrap();
+        let world = demo_world(player, AtlasMap::from_reader_auto(file).unwrap());
+        crate::assert_snapshot!(world, manifest_path(&format!("fixtures/snapshots/{name}.snap")));
+    }
+
+    #[test]
+    fn corridor() {
+        check_scene("corridor", Player::new(2, 6, Direction::North));
+    }
+
+    #[test]
+    fn corner() {
+        check_scene("corner", Player::new(1, 4, Direction::North));
+    }
+
+    #[test]
+    fn object_room() {
+        check_scene("object_room", Player::new(3, 5, Direction::North));
+    }
+
+    fn command(atlas_id: &str, cell: IVec2, pass: CellPass) -> DrawCommand {
+        DrawCommand {
+            atlas_id: atlas_id.to_owned(),
+            layer_id: "wall-1".to_owned(),
+            source: Rect::new(0.0, 0.0, 16.0, 16.0),
+            dest: Rect::new(1.0 / 3.0, 0.0, 16.0, 16.0),
+            color: WHITE,
+            page: 0,
+            order: render_order_key(cell, pass),
+        }
+    }
+
+    #[test]
+    fn commands_are_sorted_stably_by_order() {
+        let commands = [
+            command("near", ivec2(0, 0), CellPass::Floor),
+            command("far", ivec2(0, -2), CellPass::Object),
+            command("far_floor_a", ivec2(0, -2), CellPass::Floor),
+            command("far_floor_b", ivec2(0, -2), CellPass::Floor),
+        ];
+        let atlases: Vec<String> = RenderSnapshot::from_commands(&commands).commands.into_iter().map(|command| command.atlas_id).collect();
+        assert_eq!(atlases, ["far_floor_a", "far_floor_b", "far", "near"]);
+    }
+
+    #[test]
+    fn text_round_trips() {
+        let snapshot = RenderSnapshot::from_commands(&[command("dungeon", ivec2(-1, -1), CellPass::SideWalls)]);
+        assert_eq!(snapshot.commands[0].dest[0], 0.33);
+        assert_eq!(RenderSnapshot::from_text(&snapshot.to_text()).unwrap(), snapshot);
+    }
+
+    #[test]
+    fn empty_ids_round_trip() {
+        let mut fill = command("", ivec2(0, 0), CellPass::Floor);
+        fill.layer_id = "fill".to_owned();
+        let snapshot = RenderSnapshot::from_commands(&[fill, command("dungeon", ivec2(0, -1), CellPass::Floor)]);
+        let text = snapshot.to_text();
+        assert!(text.contains("\n- fill "));
+        assert_eq!(RenderSnapshot::from_text(&text).unwrap(), snapshot);
+        assert_eq!(snapshot.commands[0].layer_id, "wall-1");
+    }
+
+    #[test]
+    fn layer_changes_show_in_the_diff() {
+        let before = RenderSnapshot::from_commands(&[command("dungeon", ivec2(0, -1), CellPass::Floor)]);
+        let mut after = before.clone();
+        after.commands[0].layer_id = "wall-2".to_owned();
+        assert_eq!(before.diff(&after).entries.len(), 1);
+    }
+
+    #[test]
+    fn missing_goldens_fail() {
+        // update mode writes the file instead
+        if std::env::var(UPDATE_SNAPSHOTS_ENV).is_ok_and(|v| v == "1") {
+            return;
+        }
+        let path = manifest_path("fixtures/snapshots/missing.snap");
+        assert!(std::panic::catch_unwind(|| assert_snapshot(&RenderSnapshot::default(), &path)).is_err());
+        assert!(!path.exists());
+    }
+}
@@ -0,0 +1,152 @@
+use std::collections::BTreeMap;
+
+use serde::{Deserialize, Serialize};
+
+use crate::{events::WorldEvent, AtlasWorld, MapSource};
+
+/// Running counts of world events, overall and per zone. Built-in events count under
+/// `WorldEvent::name`, custom ones under the name they were pushed with.
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq)]
+pub struct WorldStats {
+    pub totals: BTreeMap<String, u64>,
+    pub zones: BTreeMap<String, BTreeMap<String, u64>>,
+    /// zone events are currently counted for, set by the game and by `AtlasWorld::switch_level`
+    /// to the id of the map switched to
+    pub zone: Option<String>,
+}
+
+impl WorldStats {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn record(&mut self, event: &WorldEvent) {
+        let name = event.name();
+        *self.totals.entry(name.to_owned()).or_default() += 1;
+        if let Some(zone) = &self.zone {
+            *self.zones.entry(zone.clone()).or_default().entry(name.to_owned()).or_default() += 1;
+        }
+    }
+
+    pub fn enter_zone<S: Into<String>>(&mut self, zone: S) {
+        let zone = zone.into();
+        self.zones.entry(zone.clone()).or_default();
+        self.zone = Some(zone);
+    }
+
+    pub fn count(&self, name: &str) -> u64 {
+        self.totals.get(name).copied().unwrap_or(0)
+    }
+
+    pub fn per_zone(&self, zone: &str) -> Option<&BTreeMap<String, u64>> {
+        self.zones.get(zone)
+    }
+
+    /// zones entered at least once
+    pub fn zones_visited(&self) -> usize {
+        self.zones.len()
+    }
+}
+
+impl<M: MapSource> AtlasWorld<M> {
+    /// Starts counting events as they're pushed. The game still polls every one of them, and
+    /// events dropped from a full queue are counted all the same.
+    pub fn attach_stats(&mut self, stats: WorldStats) {
+        self.stats = Some(stats);
+    }
+
+    pub fn detach_stats(&mut self) -> Option<WorldStats> {
+        self.stats.take()
+    }
+
+    pub fn stats(&self) -> Option<&WorldStats> {
+        self.stats.as_ref()
+    }
+
+    pub fn stats_mut(&mut self) -> Option<&mut WorldStats> {
+        self.stats.as_mut()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::math::*;
+    use crate::{AtlasCollection, AtlasMap, Direction, Grid, Movement, Player};
+
+    // a 5x1 corridor with a closed door at x = 2, and a 3x1 cellar
+    fn world() -> AtlasWorld {
+        let mut map = AtlasMap::empty(5, 1);
+        let mut doors = Grid::new(5, 1);
+        doors.set(2, 0, 1);
+        map.layers.insert("doors".to_owned(), doors);
+        let mut world = AtlasWorld::new(Player::new(0, 0, Direction::East), map, AtlasCollection::new());
+        world.add_level("cellar", AtlasMap::empty(3, 1));
+        world.attach_stats(WorldStats::new());
+        world.stats_mut().unwrap().enter_zone("main");
+        world
+    }
+
+    // walks up to the door, opens it and goes through, then takes the stairs down
+    fn play(world: &mut AtlasWorld) -> usize {
+        let mut polled = 0;
+        world.move_player(Movement::Forward);
+        world.move_player(Movement::Forward);
+        world.toggle_door_at(ivec2(2, 0));
+        world.move_player(Movement::Forward);
+        world.push_custom_event("chest_opened");
+        world.push_custom_event("chest_opened");
+        polled += world.poll_events().count();
+        world.switch_level("cellar", ivec2(0, 0), Direction::East);
+        world.move_player(Movement::Forward);
+        world.push_custom_event("chest_opened");
+        polled += world.poll_events().count();
+        polled
+    }
+
+    fn counts(counts: &[(&str, u64)]) -> BTreeMap<String, u64> {
+        counts.iter().map(|(name, count)| (name.to_string(), *count)).collect()
+    }
+
+    #[test]
+    fn a_session_is_counted_overall_and_per_zone() {
+        let mut world = world();
+        let polled = play(&mut world);
+        let stats = world.stats().unwrap();
+
+        assert_eq!(stats.totals, counts(&[("chest_opened", 3), ("door_toggled", 1), ("level_changed", 1), ("move_blocked", 1), ("moved", 3)]));
+        // the game still got every event
+        assert_eq!(polled as u64, stats.totals.values().sum::<u64>());
+        assert_eq!(stats.count("chest_opened"), 3);
+        assert_eq!(stats.count("wall_bashed"), 0);
+
+        assert_eq!(stats.per_zone("main"), Some(&counts(&[("chest_opened", 2), ("door_toggled", 1), ("move_blocked", 1), ("moved", 2)])));
+        assert_eq!(stats.per_zone("cellar"), Some(&counts(&[("chest_opened", 1), ("level_changed", 1), ("moved", 1)])));
+        assert_eq!(stats.per_zone("attic"), None);
+        assert_eq!((stats.zones_visited(), stats.zone.as_deref()), (2, Some("cellar")));
+    }
+
+    #[test]
+    fn events_dropped_from_a_full_queue_are_still_counted() {
+        let mut world = world();
+        world.max_queued_events = 2;
+        for _ in 0..10 {
+            world.push_custom_event("tick");
+        }
+        assert_eq!(world.dropped_events(), 8);
+        assert_eq!(world.stats().unwrap().count("tick"), 10);
+    }
+
+    #[test]
+    fn stats_persist_in_saves() {
+        let mut world = world();
+        play(&mut world);
+        let json = serde_json::to_string(&world.to_save("test", 0)).unwrap();
+
+        let mut reloaded = self::world();
+        reloaded.apply_save(&serde_json::from_str(&json).unwrap()).unwrap();
+        assert_eq!(reloaded.stats(), world.stats());
+        let stats: WorldStats = serde_json::from_str(&serde_json::to_string(world.stats().unwrap()).unwrap()).unwrap();
+        assert_eq!(Some(&stats), world.stats());
+    }
+}
@@ -0,0 +1,189 @@
+//! Traps that disorient the player: teleporters that move them without a word and spinners
+//! that turn them. Both go off when a move lands on their cell, unless a warp is there. Like warps they belong to the current level.
+
+use serde::{Deserialize, Serialize};
+
+use crate::math::*;
+use crate::{events::WorldEvent, AtlasWorld, Direction, MapSource};
+
+/// Teleporters followed at most for one move, so two that lead to each other can't loop.
+pub const MAX_TELEPORT_CHAIN: usize = 8;
+
+/// Which way the player faces after a teleport.
+#[derive(Serialize, Deserialize, Clone, Copy, Default, PartialEq, Eq, Debug)]
+#[serde(rename_all = "snake_case")]
+pub enum TeleportFacing {
+    #[default]
+    Keep,
+    Face(Direction),
+    Random,
+}
+
+/// What a spinner does to the player's facing.
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Debug)]
+#[serde(rename_all = "snake_case")]
+pub enum Spin {
+    Random,
+    /// turn steps clockwise, negative for counterclockwise
+    Rotate(i32),
+}
+
+/// Entering (`x`, `y`) sends the player to (`target_x`, `target_y`) on the same level.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct Teleporter {
+    pub x: i32,
+    pub y: i32,
+    pub target_x: i32,
+    pub target_y: i32,
+    #[serde(default)]
+    pub facing: TeleportFacing,
+}
+
+impl Teleporter {
+    pub fn pos(&self) -> IVec2 {
+        ivec2(self.x, self.y)
+    }
+
+    pub fn target_pos(&self) -> IVec2 {
+        ivec2(self.target_x, self.target_y)
+    }
+}
+
+/// Entering (`x`, `y`) turns the player.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct Spinner {
+    pub x: i32,
+    pub y: i32,
+    pub spin: Spin,
+}
+
+impl Spinner {
+    pub fn pos(&self) -> IVec2 {
+        ivec2(self.x, self.y)
+    }
+}
+
+impl<M: MapSource> AtlasWorld<M> {
+    /// Follows the teleporters starting at `pos`, up to `MAX_TELEPORT_CHAIN` of them, then
+    /// turns the player if they end up on a spinner. A teleport emits one `Teleported` from
+    /// `pos` to where the chain ends and marks that cell visited on the automap; a spin emits
+    /// `Spun`. Returns whether anything happened.
+    pub fn trigger_traps(&mut self, pos: IVec2) -> bool {
+        let mut to = pos;
+        let mut hops = 0;
+        while let Some(teleporter) = self.teleporters.iter().find(|teleporter| teleporter.pos() == to).cloned() {
+            if hops == MAX_TELEPORT_CHAIN {
+                break;
+            }
+            hops += 1;
+            if let Some(automap) = self.automap.as_mut().filter(|automap| automap.mark_teleporters) {
+                automap.visit(to);
+            }
+            to = teleporter.target_pos();
+            let direction = match teleporter.facing {
+                TeleportFacing::Keep => self.player.direction,
+                TeleportFacing::Face(direction) => direction,
+                TeleportFacing::Random => self.random_direction(),
+            };
+            self.place_player(to, direction);
+        }
+        if hops > 0 {
+            if let Some(automap) = &mut self.automap {
+                automap.visit(to);
+            }
+            self.push_event(WorldEvent::Teleported { from: pos, to });
+        }
+
+        let Some(spin) = self.spinners.iter().find(|spinner| spinner.pos() == to).map(|spinner| spinner.spin) else {
+            return hops > 0;
+        };
+        let from = self.player.direction;
+        self.player.direction = match spin {
+            Spin::Random => self.random_direction(),
+            Spin::Rotate(steps) => {
+                let steps = steps.rem_euclid(Direction::ALL.len() as i32);
+                (0..steps).fold(from, |direction, _| direction.turn_right())
+            }
+        };
+        self.push_event(WorldEvent::Spun { pos: to, from, to: self.player.direction });
+        true
+    }
+
+    fn random_direction(&mut self) -> Direction {
+        Direction::ALL[self.entity_rng.below(Direction::ALL.len())]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{AtlasCollection, AtlasMap, Player};
+
+    // a 12x3 floor with the player at (0, 1) facing east
+    fn world() -> AtlasWorld {
+        AtlasWorld::new(Player::new(0, 1, Direction::East), AtlasMap::empty(12, 3), AtlasCollection::new())
+    }
+
+    fn teleporter(x: i32, target_x: i32) -> Teleporter {
+        Teleporter { x, y: 1, target_x, target_y: 1, facing: TeleportFacing::Keep }
+    }
+
+    fn trap_events(world: &mut AtlasWorld) -> Vec<WorldEvent> {
+        world.poll_events().filter(|event| matches!(event, WorldEvent::Teleported { .. } | WorldEvent::Spun { .. })).collect()
+    }
+
+    #[test]
+    fn teleporter_loops_end_after_the_chain_limit() {
+        let mut world = world();
+        world.teleporters = vec![teleporter(1, 2), teleporter(2, 1)];
+        world.try_move(Direction::East);
+        // back and forth until the limit, then the move is over
+        let end = if MAX_TELEPORT_CHAIN.is_multiple_of(2) { ivec2(1, 1) } else { ivec2(2, 1) };
+        assert_eq!(ivec2(world.player.x, world.player.y), end);
+        assert_eq!(trap_events(&mut world), [WorldEvent::Teleported { from: ivec2(1, 1), to: end }]);
+
+        // a chain longer than the limit stops exactly that many teleporters in
+        let mut world = self::world();
+        world.teleporters = (1..11).map(|x| teleporter(x, x + 1)).collect();
+        world.try_move(Direction::East);
+        let end = ivec2(1 + MAX_TELEPORT_CHAIN as i32, 1);
+        assert_eq!(ivec2(world.player.x, world.player.y), end);
+        assert_eq!(trap_events(&mut world), [WorldEvent::Teleported { from: ivec2(1, 1), to: end }]);
+        assert_eq!(world.player.direction, Direction::East);
+    }
+
+    #[test]
+    fn spinners_turn_the_player() {
+        for (spin, facing) in [(Spin::Rotate(1), Direction::South), (Spin::Rotate(-1), Direction::North), (Spin::Rotate(6), Direction::West), (Spin::Rotate(0), Direction::East)] {
+            let mut world = world();
+            world.spinners = vec![Spinner { x: 1, y: 1, spin }];
+            world.try_move(Direction::East);
+            assert_eq!((world.player.x, world.player.direction), (1, facing), "{spin:?}");
+            assert_eq!(trap_events(&mut world), [WorldEvent::Spun { pos: ivec2(1, 1), from: Direction::East, to: facing }], "{spin:?}");
+        }
+
+        // random spins come from the world's seed
+        let spun = |seed| {
+            let mut world = world();
+            world.spinners = vec![Spinner { x: 1, y: 1, spin: Spin::Random }];
+            world.seed_entities(seed);
+            world.try_move(Direction::East);
+            assert_eq!(trap_events(&mut world), [WorldEvent::Spun { pos: ivec2(1, 1), from: Direction::East, to: world.player.direction }]);
+            world.player.direction
+        };
+        assert_eq!(spun(3), spun(3));
+    }
+
+    #[test]
+    fn teleports_can_land_on_a_spinner() {
+        let mut world = world();
+        world.teleporters = vec![Teleporter { facing: TeleportFacing::Face(Direction::North), ..teleporter(1, 5) }];
+        world.spinners = vec![Spinner { x: 5, y: 1, spin: Spin::Rotate(2) }];
+        world.try_move(Direction::East);
+        assert_eq!((world.player.x, world.player.direction), (5, Direction::South));
+        assert_eq!(trap_events(&mut world), [
+            WorldEvent::Teleported { from: ivec2(1, 1), to: ivec2(5, 1) },
+            WorldEvent::Spun { pos: ivec2(5, 1), from: Direction::North, to: Direction::South },
+        ]);
+    }
+}
@@ -0,0 +1,125 @@
+use ahash::AHashMap;
+use serde::{Deserialize, Serialize};
+
+/// What a floor value is like to walk on.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(default)]
+pub struct Terrain {
+    pub name: String,
+    /// turns it takes to enter the cell, 0 is invalid and treated as 1
+    pub move_cost: u32,
+}
+
+impl Default for Terrain {
+    fn default() -> Self {
+        Self {
+            name: "ground".to_owned(),
+            move_cost: 1,
+        }
+    }
+}
+
+/// Floor value -> terrain table. Values without an entry use `Terrain::default()`.
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+#[serde(transparent)]
+pub struct TerrainTable {
+    pub terrains: AHashMap<u8, Terrain>,
+    #[serde(skip)]
+    default: Terrain,
+}
+
+impl TerrainTable {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn insert(&mut self, value: u8, terrain: Terrain) {
+        self.terrains.insert(value, terrain);
+    }
+
+    pub fn get(&self, value: u8) -> &Terrain {
+        self.terrains.get(&value).unwrap_or(&self.default)
+    }
+
+    pub fn move_cost(&self, value: u8) -> u32 {
+        self.get(value).move_cost.max(1)
+    }
+
+    /// floor values whose terrain has the invalid move cost 0, sorted
+    pub fn zero_costs(&self) -> Vec<u8> {
+        let mut values: Vec<u8> = self.terrains.iter()
+            .filter(|(_, terrain)| terrain.move_cost == 0)
+            .map(|(value, _)| *value)
+            .collect();
+        values.sort_unstable();
+        values
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::math::*;
+    use crate::{AtlasCollection, AtlasMap, AtlasWorld, Direction, Player};
+
+    const SWAMP: u8 = 3;
+
+    // Row 2 runs straight from (0, 2) to (4, 2) through a swamp at (2, 2); the dry way round
+    // goes up the open ends of row 1 and along row 0.
+    fn marsh(swamp_cost: u32) -> AtlasWorld {
+        let mut map = AtlasMap::empty(5, 3);
+        for x in 1..4 {
+            map.wall.set(x, 1, 1);
+        }
+        map.floor.set(2, 2, SWAMP);
+        let mut world = AtlasWorld::new(Player::new(0, 2, Direction::East), map, AtlasCollection::new());
+        world.terrain.insert(SWAMP, Terrain { name: "swamp".to_owned(), move_cost: swamp_cost });
+        world
+    }
+
+    // how many steps longer the dry way is than the 4 through the swamp
+    fn detour() -> u32 {
+        let dry = marsh(u32::MAX).find_path(ivec2(0, 2), ivec2(4, 2)).unwrap();
+        dry.len() as u32 - 1 - 4
+    }
+
+    fn crosses_swamp(world: &AtlasWorld) -> bool {
+        world.find_path(ivec2(0, 2), ivec2(4, 2)).unwrap().contains(&ivec2(2, 2))
+    }
+
+    #[test]
+    fn a_dry_detour_is_taken_when_fewer_than_cost_minus_one_steps_longer() {
+        let detour = detour();
+        assert!(detour > 0);
+        assert!(!crosses_swamp(&marsh(detour + 2)));
+        assert!(crosses_swamp(&marsh(detour)));
+    }
+
+    #[test]
+    fn advance_turn_ticks_the_cost_of_the_cell_entered() {
+        let mut world = marsh(3);
+        world.player.x = 1;
+        world.terrain_costs_turns = true;
+        assert!(world.try_move(Direction::East).is_moved());
+        assert_eq!(world.advance_turn(), 3);
+        assert_eq!(world.turn(), 3);
+        // the cost is used up
+        assert_eq!(world.advance_turn(), 1);
+        assert_eq!(world.turn(), 4);
+
+        world.terrain_costs_turns = false;
+        assert!(world.try_move(Direction::West).is_moved());
+        assert!(world.try_move(Direction::East).is_moved());
+        assert_eq!(world.advance_turn(), 1);
+    }
+
+    #[test]
+    fn zero_costs_are_errors() {
+        let mut world = marsh(0);
+        world.terrain.insert(1, Terrain { name: "ice".to_owned(), move_cost: 0 });
+        assert_eq!(world.terrain.zero_costs(), vec![1, SWAMP]);
+        let report = world.validate_all();
+        assert_eq!(report.findings.iter().filter(|finding| finding.code == "E-TERRAIN-ZERO-COST").count(), 2);
+        assert!(report.has_errors());
+    }
+}
@@ -0,0 +1,53 @@
+//! Worlds for the tests, drawn with the demo's own atlases. The atlases are loaded without
+//! textures, so nothing needs a window and `capture_commands` records what would be drawn.
+
+use std::path::PathBuf;
+
+use crate::generator::Rng;
+use crate::{Atlas, AtlasCollection, AtlasInfo, AtlasMap, AtlasWorld, Player};
+
+pub(crate) fn manifest_path(path: &str) -> PathBuf {
+    PathBuf::from(env!("CARGO_MANIFEST_DIR")).join(path)
+}
+
+/// the atlas described by `{name}.json`, with its page size read from the header of `{name}.png`
+pub(crate) fn atlas(name: &str, atlas_id: &str) -> Atlas {
+    let json = std::fs::read(manifest_path(&format!("{name}.json"))).unwrap();
+    let png = std::fs::read(manifest_path(&format!("{name}.png"))).unwrap();
+    let size = |at: usize| u32::from_be_bytes(png[at..at + 4].try_into().unwrap());
+    let mut atlas = Atlas {
+        atlas_info: serde_json::from_slice::<AtlasInfo>(&json).unwrap(),
+        #[cfg(feature = "render")]
+        textures: Vec::new(),
+        texture_sizes: vec![(size(16), size(20))],
+        scale: 1,
+        data_path: None,
+        image_path: None,
+        findings: Vec::new(),
+        diagonal_art: false,
+    };
+    atlas.revalidate(atlas_id);
+    atlas
+}
+
+/// mansion as "dungeon" and common_objects, the atlases the default bindings draw with
+pub(crate) fn demo_collection() -> AtlasCollection {
+    [("dungeon", atlas("mansion", "dungeon")), ("common_objects", atlas("common_objects", "common_objects"))]
+        .into_iter()
+        .map(|(atlas_id, atlas)| (atlas_id.to_owned(), atlas))
+        .collect()
+}
+
+pub(crate) fn demo_world(player: Player, map: AtlasMap) -> AtlasWorld {
+    AtlasWorld::new(player, map, demo_collection())
+}
+
+/// Mostly small values, with the i32 limits and their neighbours mixed in as a corrupted save
+/// or a huge map would produce them.
+pub(crate) fn adversarial(rng: &mut Rng) -> i32 {
+    match rng.below(4) {
+        0 => [i32::MIN, i32::MIN + 1, -1, 0, 1, i32::MAX - 1, i32::MAX][rng.below(7)],
+        1 => rng.next_u64() as i32,
+        _ => rng.range(0, 64) as i32 - 32,
+    }
+}
@@ -0,0 +1,86 @@
+//! Decoding icons and turning them around without pulling in an image crate. The pixel work is
+//! done on macroquad's RGBA `Image`, so it runs without a window; only making textures needs one.
+//! Behind the `render` feature.
+
+use macroquad::prelude::*;
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Flip {
+    /// top to bottom
+    Vertical,
+    /// left to right
+    Horizontal,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Rotation {
+    Cw90,
+    Ccw90,
+}
+
+/// `image` mirrored along `flip`
+pub fn image_flipped(image: &Image, flip: Flip) -> Image {
+    let (width, height) = (image.width as usize, image.height as usize);
+    let mut bytes = vec![0; image.bytes.len()];
+    for y in 0..height {
+        for x in 0..width {
+            let (to_x, to_y) = match flip {
+                Flip::Vertical => (x, height - 1 - y),
+                Flip::Horizontal => (width - 1 - x, y),
+            };
+            copy_pixel(image, &mut bytes, (x, y), to_y * width + to_x);
+        }
+    }
+    Image { bytes, width: image.width, height: image.height }
+}
+
+/// `image` turned a quarter by `rotation`, so its width and height swap
+pub fn image_rotated(image: &Image, rotation: Rotation) -> Image {
+    let (width, height) = (image.width as usize, image.height as usize);
+    let mut bytes = vec![0; image.bytes.len()];
+    for y in 0..height {
+        for x in 0..width {
+            // the rotated image is `height` wide
+            let (to_x, to_y) = match rotation {
+                Rotation::Cw90 => (height - 1 - y, x),
+                Rotation::Ccw90 => (y, width - 1 - x),
+            };
+            copy_pixel(image, &mut bytes, (x, y), to_y * height + to_x);
+        }
+    }
+    Image { bytes, width: image.height, height: image.width }
+}
+
+fn copy_pixel(image: &Image, bytes: &mut [u8], (x, y): (usize, usize), to: usize) {
+    let from = (y * image.width as usize + x) * 4;
+    bytes[to * 4..to * 4 + 4].copy_from_slice(&image.bytes[from..from + 4]);
+}
+
+/// Decodes a png with macroquad's own loader.
+///
+/// # Errors
+///
+/// When `bytes` aren't a png macroquad can decode.
+pub fn texture_from_png_bytes(bytes: &[u8]) -> Result<Texture2D, macroquad::Error> {
+    Ok(Texture2D::from_image(&Image::from_file_with_format(bytes, Some(ImageFormat::Png))?))
+}
+
+/// `texture_from_png_bytes`, mirrored along `flip`
+///
+/// # Errors
+///
+/// When `bytes` aren't a png macroquad can decode.
+pub fn texture_flipped(bytes: &[u8], flip: Flip) -> Result<Texture2D, macroquad::Error> {
+    let image = Image::from_file_with_format(bytes, Some(ImageFormat::Png))?;
+    Ok(Texture2D::from_image(&image_flipped(&image, flip)))
+}
+
+/// `texture_from_png_bytes`, turned a quarter by `rotation`
+///
+/// # Errors
+///
+/// When `bytes` aren't a png macroquad can decode.
+pub fn texture_rotated(bytes: &[u8], rotation: Rotation) -> Result<Texture2D, macroquad::Error> {
+    let image = Image::from_file_with_format(bytes, Some(ImageFormat::Png))?;
+    Ok(Texture2D::from_image(&image_rotated(&image, rotation)))
+}
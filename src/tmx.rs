@@ -0,0 +1,144 @@
+//! Importing maps made in Tiled. Only finite, orthogonal maps are read, and only their tile
+//! layers: each one whose name matches a map layer, ignoring case, becomes that layer.
+
+use std::path::Path;
+
+use tiled::{FiniteTileLayer, LayerType, Loader, Orientation, TileLayer};
+
+use macroquad::prelude::ivec2;
+
+use crate::coords::{to_grid, YAxis};
+use crate::{AtlasMap, Grid, MapError};
+
+/// (Tiled layer name, map layer) pairs `from_tiled` imports
+pub const TILED_LAYERS: [(&str, &str); 4] = [("wall", "wall"), ("floor", "floor"), ("ceiling", "ceiling"), ("object", "object")];
+
+impl AtlasMap {
+    /// Reads a TMX file, importing the tile layers named in `TILED_LAYERS`.
+    pub fn from_tiled<P: AsRef<Path>>(path: P) -> Result<AtlasMap, MapError> {
+        Self::from_tiled_with(path, &TILED_LAYERS)
+    }
+
+    /// Reads a TMX file, importing each tile layer named in `layers` into the map layer it's
+    /// paired with, which is added when it's not one of the usual layers. A cell holds the
+    /// tile's id within its tileset plus one, its global id less the tileset's firstgid, so
+    /// empty cells are 0. Layers that aren't listed are skipped.
+    pub fn from_tiled_with<P: AsRef<Path>>(path: P, layers: &[(&str, &str)]) -> Result<AtlasMap, MapError> {
+        let path = path.as_ref();
+        let unsupported = |feature: &str| MapError::Unsupported { path: path.to_owned(), feature: feature.to_owned() };
+
+        let tiled = Loader::new().load_tmx_map(path)
+            .map_err(|source| MapError::Tiled { path: path.to_owned(), source })?;
+        if tiled.infinite() {
+            return Err(unsupported("an infinite map"));
+        }
+        match tiled.orientation {
+            Orientation::Orthogonal => {}
+            Orientation::Isometric => return Err(unsupported("isometric orientation")),
+            Orientation::Staggered => return Err(unsupported("staggered orientation")),
+            Orientation::Hexagonal => return Err(unsupported("hexagonal orientation")),
+            Orientation::Oblique => return Err(unsupported("oblique orientation")),
+        }
+
+        let mut map = AtlasMap::empty(tiled.width as usize, tiled.height as usize);
+        for layer in tiled.layers() {
+            let Some((_, target)) = layers.iter().find(|(name, _)| name.eq_ignore_ascii_case(&layer.name)) else {
+                continue;
+            };
+            let tiles = match layer.layer_type() {
+                LayerType::Tiles(TileLayer::Finite(tiles)) => tiles,
+                LayerType::Tiles(TileLayer::Infinite(_)) => return Err(unsupported(&format!("infinite layer '{}'", layer.name))),
+                _ => return Err(unsupported(&format!("layer '{}' that isn't a tile layer", layer.name))),
+            };
+            map.add_layer(target);
+            let cells = import_layer(&tiles, path, &layer.name)?;
+            if let Some(grid) = map.layer_mut(target) {
+                *grid = cells;
+            }
+        }
+        Ok(map)
+    }
+}
+
+fn import_layer(tiles: &FiniteTileLayer, path: &Path, name: &str) -> Result<Grid, MapError> {
+    let mut grid = Grid::new(tiles.width() as usize, tiles.height() as usize);
+    for y in 0..tiles.height() {
+        for x in 0..tiles.width() {
+            let Some(tile) = tiles.get_tile(x as i32, y as i32) else {
+                continue;
+            };
+            let value = u8::try_from(tile.id() + 1).map_err(|_| MapError::TileOutOfRange {
+                path: path.to_owned(),
+                layer: name.to_owned(),
+                x,
+                y,
+                id: tile.id() + 1,
+            })?;
+            // Tiled counts rows from the top like the grid does
+            let cell = to_grid(ivec2(x as i32, y as i32), tiles.height() as i32, YAxis::Down);
+            grid.set(cell.x as usize, cell.y as usize, value);
+        }
+    }
+    Ok(grid)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::manifest_path;
+
+    fn fixture(name: &str) -> std::path::PathBuf {
+        manifest_path(&format!("fixtures/tiled/{name}.tmx"))
+    }
+
+    fn unsupported(result: Result<AtlasMap, MapError>) -> String {
+        match result {
+            Err(MapError::Unsupported { feature, .. }) => feature,
+            Err(err) => panic!("expected an unsupported feature, got {err}"),
+            Ok(_) => panic!("expected an unsupported feature, got a map"),
+        }
+    }
+
+    #[test]
+    fn layers_are_matched_ignoring_case() {
+        let map = AtlasMap::from_tiled(fixture("small")).unwrap();
+        assert_eq!((map.width, map.height), (4, 3));
+        assert_eq!(map.wall.to_rows(), [[1, 1, 1, 1], [2, 0, 0, 2], [1, 1, 1, 1]]);
+        assert_eq!(map.floor.row(1), Some(&[0, 5, 6, 0][..]));
+        assert_eq!(map.ceiling.row(1), Some(&[0, 9, 9, 0][..]));
+        // unlisted layers and object groups are skipped
+        assert!(map.layer("trigger").is_none());
+        assert!(map.check_shape().is_ok());
+    }
+
+    #[test]
+    fn global_ids_are_taken_relative_to_their_tileset() {
+        // gids 17 and 18 are the first two tiles of the tileset starting at 17
+        let map = AtlasMap::from_tiled(fixture("small")).unwrap();
+        assert_eq!(map.object.row(1), Some(&[0, 1, 2, 0][..]));
+    }
+
+    #[test]
+    fn extra_layers_can_be_mapped() {
+        let map = AtlasMap::from_tiled_with(fixture("small"), &[("Trigger", "trigger"), ("wall", "wall")]).unwrap();
+        assert_eq!(map.layer("trigger").and_then(|trigger| trigger.get(2, 1)), Some(3));
+        assert_eq!(map.wall.get(0, 1), Some(2));
+        assert_eq!(map.floor.data(), [0; 12]);
+    }
+
+    #[test]
+    fn infinite_and_hexagonal_maps_are_refused() {
+        assert_eq!(unsupported(AtlasMap::from_tiled(fixture("infinite"))), "an infinite map");
+        assert_eq!(unsupported(AtlasMap::from_tiled(fixture("hexagonal"))), "hexagonal orientation");
+    }
+
+    #[test]
+    fn ids_past_a_cell_are_errors() {
+        match AtlasMap::from_tiled(fixture("large_id")) {
+            Err(MapError::TileOutOfRange { layer, x, y, id, .. }) => assert_eq!((layer.as_str(), x, y, id), ("wall", 1, 0, 300)),
+            Err(err) => panic!("expected a tile out of range, got {err}"),
+            Ok(_) => panic!("expected a tile out of range, got a map"),
+        }
+        assert!(matches!(AtlasMap::from_tiled(fixture("missing")), Err(MapError::Tiled { .. })));
+    }
+}
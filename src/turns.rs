@@ -0,0 +1,71 @@
+//! Turn-based play: the player acts, then the world gets its turn. The movement methods stay
+//! usable on their own for real-time games; `take_turn` goes through them.
+
+use crate::math::*;
+use crate::{encounter::EntityId, events::WorldEvent, AtlasWorld, AttackResult, Interaction, MapSource, MoveResult, PlayerAction};
+
+/// What came of one `take_turn`, for the game to react to.
+#[derive(Clone, Copy, Default, PartialEq, Eq, Debug)]
+pub struct TurnResult {
+    /// the cell the player stepped into
+    pub moved: Option<IVec2>,
+    /// the cell that stopped the player's move
+    pub bumped: Option<IVec2>,
+    /// the entity in the facing cell an `Attack` was aimed at
+    pub attacked: Option<EntityId>,
+    /// how an `Attack` went
+    pub attack: Option<AttackResult>,
+    /// the "trigger" layer value of the cell stepped into, when nonzero
+    pub triggered: Option<u8>,
+    pub interaction: Option<Interaction>,
+    /// turns the action took, see `advance_turn`
+    pub ticks: u32,
+}
+
+impl TurnResult {
+    fn record_move(&mut self, result: MoveResult) {
+        match result {
+            MoveResult::Moved(pos) => self.moved = Some(pos),
+            MoveResult::Blocked(pos) => self.bumped = Some(pos),
+        }
+    }
+}
+
+impl<M: MapSource> AtlasWorld<M> {
+    /// Applies `action` with the usual movement rules and events, then ends the turn: the turn
+    /// counter advances, entities act once per turn taken and open doors past
+    /// `door_close_turns` close.
+    pub fn take_turn(&mut self, action: PlayerAction) -> TurnResult {
+        let first_event = self.events_pushed;
+        let mut result = TurnResult::default();
+        match action {
+            PlayerAction::Move(movement) => result.record_move(self.move_player(movement)),
+            PlayerAction::Turn(turn) => self.turn_player(turn),
+            PlayerAction::CornerLeft => result.record_move(self.corner_left()),
+            PlayerAction::CornerRight => result.record_move(self.corner_right()),
+            PlayerAction::Interact => result.interaction = Some(self.interact()),
+            PlayerAction::Attack => {
+                result.attacked = self.attack_target();
+                result.attack = Some(self.attack());
+            }
+            PlayerAction::Wait => {}
+        }
+        result.triggered = self.events_since(first_event).find_map(|event| match event {
+            WorldEvent::TriggerEntered { id, .. } => Some(*id),
+            _ => None,
+        });
+
+        result.ticks = self.advance_turn();
+        for _ in 0..result.ticks {
+            self.update_entities();
+        }
+        self.close_expired_doors();
+        result
+    }
+
+    /// the first entity standing in the facing cell that hasn't been defeated
+    pub fn attack_target(&self) -> Option<EntityId> {
+        let pos = self.player.facing_cell();
+        self.entities_at(pos).map(|entity| entity.id).find(|id| !self.is_defeated(*id))
+    }
+}
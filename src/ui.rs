@@ -0,0 +1,96 @@
+//! Small widgets the HUD is built from, usable on their own too. Behind the `render` feature.
+
+use macroquad::{prelude::*, ui::{widgets, Ui}};
+
+/// A bar filled to `current / max`, labeled "current/max" in its middle.
+#[derive(Clone, Copy, Debug)]
+pub struct StatBar<'a> {
+    current: f32,
+    max: f32,
+    color: Color,
+    background: Color,
+    font: Option<&'a Font>,
+    font_size: u16,
+    label_color: Color,
+}
+
+impl<'a> StatBar<'a> {
+    /// `current` is clamped to `0..=max`; a `max` of 0 or less shows an empty bar
+    pub fn new(current: f32, max: f32) -> Self {
+        let max = max.max(0.0);
+        Self {
+            current: current.clamp(0.0, max),
+            max,
+            color: RED,
+            background: DARKGRAY,
+            font: None,
+            font_size: 26,
+            label_color: WHITE,
+        }
+    }
+
+    pub fn color(mut self, color: Color) -> Self {
+        self.color = color;
+        self
+    }
+
+    pub fn background(mut self, background: Color) -> Self {
+        self.background = background;
+        self
+    }
+
+    /// The font the label is drawn with standalone, and measured with to center it. Inside a
+    /// ui the skin's label font draws it, so this should match that.
+    pub fn label_font(mut self, font: &'a Font) -> Self {
+        self.font = Some(font);
+        self
+    }
+
+    pub fn font_size(mut self, font_size: u16) -> Self {
+        self.font_size = font_size;
+        self
+    }
+
+    /// the label color when drawn standalone, inside a ui the skin's is used
+    pub fn label_color(mut self, color: Color) -> Self {
+        self.label_color = color;
+        self
+    }
+
+    /// the filled share of the bar, in 0..=1
+    pub fn fraction(&self) -> f32 {
+        if self.max > 0.0 { self.current / self.max } else { 0.0 }
+    }
+
+    pub fn label(&self) -> String {
+        format!("{:.0}/{:.0}", self.current, self.max)
+    }
+
+    /// Draws into the window `ui` belongs to, `rect` being relative to its content.
+    pub fn draw(&self, ui: &mut Ui, rect: Rect) {
+        let mut canvas = ui.canvas();
+        let cursor = canvas.cursor();
+        let bar = rect.offset(cursor);
+        canvas.rect(bar, Color::default(), self.background);
+        canvas.rect(Rect::new(bar.x, bar.y, bar.w * self.fraction(), bar.h), Color::default(), self.color);
+
+        let label = self.label();
+        let text_size = measure_text(&label, self.font, self.font_size, 1.0);
+        let center = rect.center();
+        widgets::Label::new(label)
+            .position(vec2(center.x - text_size.width / 2.0, center.y - text_size.height / 2.0))
+            .ui(ui);
+    }
+
+    /// Draws straight to the screen at `rect`, outside of any ui.
+    pub fn draw_screen(&self, rect: Rect) {
+        draw_rectangle(rect.x, rect.y, rect.w, rect.h, self.background);
+        draw_rectangle(rect.x, rect.y, rect.w * self.fraction(), rect.h, self.color);
+
+        let label = self.label();
+        let text_size = measure_text(&label, self.font, self.font_size, 1.0);
+        let center = rect.center();
+        let params = TextParams { font: self.font, font_size: self.font_size, color: self.label_color, ..Default::default() };
+        draw_text_ex(&label, center.x - text_size.width / 2.0, center.y + text_size.offset_y / 2.0, params);
+    }
+}
@@ -0,0 +1,483 @@
+//! Every check the crate has, run in one go and reported with stable codes for CI.
+
+use std::{collections::BTreeSet, fmt};
+
+use serde::Serialize;
+
+use crate::math::*;
+use crate::{
+    pipeline::{shape_errors, validate_map, MapReport}, viewport_size, AtlasInfo, AtlasInfoError, AtlasMap, AtlasWorld, Collection, Coords,
+    MapSource, PreflightReport, PreflightWarning, RenderConfig, Warp,
+};
+
+#[derive(Serialize, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Debug)]
+#[serde(rename_all = "snake_case")]
+pub enum Severity {
+    Error,
+    Warning,
+}
+
+/// One problem found. `code` never changes meaning between versions, so CI can match on it.
+#[derive(Serialize, Clone, Debug, PartialEq)]
+pub struct Finding {
+    pub severity: Severity,
+    pub code: &'static str,
+    pub message: String,
+    /// map layer the finding is about
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub layer: Option<String>,
+    /// [x, y] map cell the finding is about
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub cell: Option<[i32; 2]>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub atlas_id: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub layer_id: Option<String>,
+}
+
+impl Finding {
+    fn new(severity: Severity, code: &'static str, message: String) -> Self {
+        Self { severity, code, message, layer: None, cell: None, atlas_id: None, layer_id: None }
+    }
+
+    fn error(code: &'static str, message: String) -> Self {
+        Self::new(Severity::Error, code, message)
+    }
+
+    fn warning(code: &'static str, message: String) -> Self {
+        Self::new(Severity::Warning, code, message)
+    }
+
+    fn layer(mut self, layer: &str) -> Self {
+        self.layer = Some(layer.to_owned());
+        self
+    }
+
+    fn cell(mut self, x: i32, y: i32) -> Self {
+        self.cell = Some([x, y]);
+        self
+    }
+
+    fn layer_id(mut self, layer_id: &str) -> Self {
+        self.layer_id = Some(layer_id.to_owned());
+        self
+    }
+
+    fn atlas_layer(mut self, atlas_id: &str, layer_id: &str) -> Self {
+        self.atlas_id = Some(atlas_id.to_owned());
+        self.layer_id = Some(layer_id.to_owned());
+        self
+    }
+}
+
+impl fmt::Display for Finding {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let severity = match self.severity {
+            Severity::Error => "error",
+            Severity::Warning => "warning",
+        };
+        write!(f, "{severity}[{}]: {}", self.code, self.message)
+    }
+}
+
+#[derive(Serialize, Clone, Debug, Default, PartialEq)]
+pub struct ValidationReport {
+    pub findings: Vec<Finding>,
+}
+
+impl ValidationReport {
+    pub fn has_errors(&self) -> bool {
+        self.findings.iter().any(|finding| finding.severity == Severity::Error)
+    }
+
+    pub fn is_clean(&self) -> bool {
+        self.findings.is_empty()
+    }
+
+    /// The checks that only need the map: layer shapes, connectivity, the spawn cell and warp
+    /// targets on this level.
+    pub fn for_map(map: &AtlasMap, spawn: IVec2, warps: &[Warp]) -> Self {
+        Self::for_map_with(map, spawn, warps, validate_map(map))
+    }
+
+    // `for_map` with the shape and connectivity checks already done
+    pub(crate) fn for_map_with(map: &AtlasMap, spawn: IVec2, warps: &[Warp], map_report: MapReport) -> Self {
+        let mut report = Self::default();
+
+        report.findings.extend(map_report.errors.into_iter().map(shape_finding));
+        // the rest indexes the layers, which isn't safe with mismatched rows
+        if report.has_errors() {
+            return report;
+        }
+        for [x, y] in map_report.unreachable {
+            report.findings.push(
+                Finding::warning("W-MAP-UNREACHABLE", format!("open cell ({x}, {y}) can't be walked to")).layer("wall").cell(x, y),
+            );
+        }
+
+        let (x, y) = (spawn.x, spawn.y);
+        if !map.in_bounds(spawn) {
+            report.findings.push(Finding::error("E-SPAWN-OOB", format!("spawn cell ({x}, {y}) is outside the map")).cell(x, y));
+        } else if let Some(value) = map.get_wall(spawn).filter(|value| *value != 0) {
+            report.findings.push(
+                Finding::error("E-SPAWN-IN-WALL", format!("spawn cell ({x}, {y}) has wall value {value}")).layer("wall").cell(x, y),
+            );
+        }
+
+        for warp in warps {
+            let (x, y) = (warp.x, warp.y);
+            if !map.in_bounds(warp.pos()) {
+                report.findings.push(Finding::error("E-WARP-SOURCE-OOB", format!("warp at ({x}, {y}) is outside the map")).cell(x, y));
+            }
+            // targets on other levels can only be checked against those levels
+            if warp.dest_level.is_some() {
+                continue;
+            }
+            let target = ivec2(warp.dest_x, warp.dest_y);
+            if !map.in_bounds(target) {
+                report.findings.push(Finding::error(
+                    "E-WARP-TARGET-OOB",
+                    format!("warp at ({x}, {y}) leads to ({}, {}) outside the map", target.x, target.y),
+                ).cell(x, y));
+            } else if !map.is_passable(target) {
+                report.findings.push(Finding::error(
+                    "E-WARP-TARGET-WALL",
+                    format!("warp at ({x}, {y}) leads into the wall at ({}, {})", target.x, target.y),
+                ).layer("wall").cell(x, y));
+            }
+        }
+
+        report
+    }
+
+    /// `for_map` plus the atlas checks of `PreflightReport::check` and a check that every value
+    /// used on the map has a layer to draw it with. `atlases` should be sorted by id.
+    pub fn for_world(
+        map: &AtlasMap,
+        spawn: IVec2,
+        warps: &[Warp],
+        atlases: &[(&str, &AtlasInfo)],
+        render_config: &RenderConfig,
+        render_depth: i32,
+        render_width: i32,
+    ) -> Self {
+        let mut report = Self::for_map(map, spawn, warps);
+        if report.findings.iter().any(|finding| finding.code == "E-MAP-ROWLEN") {
+            return report;
+        }
+
+        for (layer, atlas_id) in [("wall", "dungeon"), ("floor", "dungeon"), ("ceiling", "dungeon"), ("object", "common_objects")] {
+            let atlas = atlases.iter().find(|(id, _)| *id == atlas_id).map(|(_, info)| *info);
+            let mut reported = [false; 256];
+            for y in 0..map.height as i32 {
+                for x in 0..map.width as i32 {
+                    let value = map.get(layer, ivec2(x, y)).unwrap_or(0);
+                    if value == 0 || reported[value as usize] {
+                        continue;
+                    }
+                    let layer_id = format!("{layer}-{value}");
+                    if atlas.is_some_and(|info| info.layers.contains_key(&layer_id)) {
+                        continue;
+                    }
+                    reported[value as usize] = true;
+                    report.findings.push(Finding::warning(
+                        "W-ATLAS-MISSING-TILE",
+                        format!("{layer} value {value} at ({x}, {y}) has no '{layer_id}' layer in atlas '{atlas_id}'"),
+                    ).layer(layer).cell(x, y).atlas_layer(atlas_id, &layer_id));
+                }
+            }
+        }
+
+        let preflight = PreflightReport::check(map, spawn, atlases, render_config, render_depth, render_width);
+        report.findings.extend(preflight.warnings.iter().filter_map(preflight_finding));
+
+        report
+    }
+}
+
+fn shape_finding(error: String) -> Finding {
+    let layer = error.split(' ').next().unwrap_or_default().to_owned();
+    Finding::error("E-MAP-ROWLEN", error).layer(&layer)
+}
+
+impl AtlasMap {
+    /// every layer having `height` rows of `width` cells, the one thing drawing relies on
+    pub fn validate(&self) -> Vec<Finding> {
+        shape_errors(self).into_iter().map(shape_finding).collect()
+    }
+}
+
+fn rect_within(coords: &Coords, width: u32, height: u32) -> bool {
+    coords.x >= 0 && coords.y >= 0 && coords.x as i64 + coords.w as i64 <= width as i64 && coords.y as i64 + coords.h as i64 <= height as i64
+}
+
+impl AtlasInfo {
+    /// Checks every tile's atlas rect lies inside a texture of `texture_size` and its screen
+    /// rect inside the viewport, and that no layer has two tiles at one (x, z, orientation).
+    /// The findings name the layer but not the atlas.
+    pub fn validate(&self, texture_size: (u32, u32)) -> Vec<Finding> {
+        self.validate_scaled(&[texture_size], 1)
+    }
+
+    /// `validate` for an atlas of several pages, each tile checked against the size of its own
+    pub fn validate_pages(&self, texture_sizes: &[(u32, u32)]) -> Vec<Finding> {
+        self.validate_scaled(texture_sizes, 1)
+    }
+
+    /// The first tile, by layer name then position in the layer, with an atlas rect of its own,
+    /// a frame or a variant reaching past its page in `texture_sizes`. Tiles on a missing page
+    /// are left to `validate_pages`.
+    ///
+    /// # Errors
+    ///
+    /// `AtlasInfoError::RectOutOfBounds` for that tile.
+    pub fn check_bounds(&self, texture_sizes: &[(u32, u32)]) -> Result<(), AtlasInfoError> {
+        for (layer_id, layer) in self.layers_sorted() {
+            for (index, tile) in layer.tiles().iter().enumerate() {
+                let Some(&(width, height)) = texture_sizes.get(tile.page) else { continue };
+                let frames = tile.animation.iter().flat_map(|animation| animation.frames.iter());
+                for a in std::iter::once(&tile.atlas_coords).chain(frames).chain(&tile.variants) {
+                    let spans = [("width", a.x, a.w, width), ("height", a.y, a.h, height)];
+                    for (axis, start, len, limit) in spans {
+                        let (start, end) = (start as i64, start as i64 + len as i64);
+                        if start < 0 || end > limit as i64 {
+                            return Err(AtlasInfoError::RectOutOfBounds { layer: layer_id.clone(), index, axis, start, end, limit });
+                        }
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+
+    // `validate_pages` for an atlas authored at `asset_scale`, whose screen rects are that much larger
+    pub(crate) fn validate_scaled(&self, texture_sizes: &[(u32, u32)], asset_scale: u32) -> Vec<Finding> {
+        let (viewport_width, viewport_height) = viewport_size(asset_scale);
+        let mut findings = Vec::new();
+        for (layer_id, layer) in self.layers_sorted() {
+            let mut seen = BTreeSet::new();
+            for tile in layer.tiles() {
+                let (x, z) = (tile.x, tile.z);
+                let Some(&(texture_width, texture_height)) = texture_sizes.get(tile.page) else {
+                    findings.push(Finding::error(
+                        "E-ATLAS-PAGE-MISSING",
+                        format!("layer '{layer_id}' tile ({x}, {z}) is on page {} but the atlas has {} pages", tile.page, texture_sizes.len()),
+                    ).layer_id(layer_id));
+                    continue;
+                };
+                let a = &tile.atlas_coords;
+                if !rect_within(a, texture_width, texture_height) {
+                    findings.push(Finding::error(
+                        "E-ATLAS-RECT-OOB",
+                        format!("layer '{layer_id}' tile ({x}, {z}) atlas rect ({}, {}) {}x{} exceeds the {texture_width}x{texture_height} texture", a.x, a.y, a.w, a.h),
+                    ).layer_id(layer_id));
+                }
+                let frames = tile.animation.iter().flat_map(|animation| animation.frames.iter().enumerate());
+                for (frame, a) in frames.filter(|(_, a)| !rect_within(a, texture_width, texture_height)) {
+                    findings.push(Finding::error(
+                        "E-ATLAS-RECT-OOB",
+                        format!("layer '{layer_id}' tile ({x}, {z}) frame {frame} atlas rect ({}, {}) {}x{} exceeds the {texture_width}x{texture_height} texture", a.x, a.y, a.w, a.h),
+                    ).layer_id(layer_id));
+                }
+                for (variant, a) in tile.variants.iter().enumerate().filter(|(_, a)| !rect_within(a, texture_width, texture_height)) {
+                    findings.push(Finding::error(
+                        "E-ATLAS-RECT-OOB",
+                        format!("layer '{layer_id}' tile ({x}, {z}) variant {variant} atlas rect ({}, {}) {}x{} exceeds the {texture_width}x{texture_height} texture", a.x, a.y, a.w, a.h),
+                    ).layer_id(layer_id));
+                }
+                let s = &tile.screen_coords;
+                if !rect_within(s, viewport_width, viewport_height) {
+                    findings.push(Finding::warning(
+                        "W-ATLAS-SCREEN-OOB",
+                        format!("layer '{layer_id}' tile ({x}, {z}) screen rect ({}, {}) {}x{} exceeds the {viewport_width}x{viewport_height} viewport", s.x, s.y, s.w, s.h),
+                    ).layer_id(layer_id));
+                }
+                if !seen.insert((x, z, tile.orientation)) {
+                    findings.push(Finding::warning(
+                        "W-ATLAS-DUPLICATE-TILE",
+                        format!("layer '{layer_id}' has more than one tile at ({x}, {z}) {:?}, only the first is drawn", tile.orientation),
+                    ).layer_id(layer_id));
+                }
+            }
+        }
+        findings
+    }
+}
+
+// the spawn warnings are already covered by `for_map`
+fn preflight_finding(warning: &PreflightWarning) -> Option<Finding> {
+    let message = warning.to_string();
+    let finding = match warning {
+        PreflightWarning::SpawnOutOfBounds { .. } | PreflightWarning::SpawnInWall { .. } => return None,
+        PreflightWarning::MissingNearSideWalls { atlas_id, layer_id } => {
+            Finding::warning("W-ATLAS-NEAR-SIDES", message).atlas_layer(atlas_id, layer_id)
+        }
+        PreflightWarning::SynthesizedTiles { atlas_id, layer_id, .. } => {
+            Finding::warning("W-ATLAS-SYNTHESIZED", message).atlas_layer(atlas_id, layer_id)
+        }
+        PreflightWarning::ZeroMoveCost { .. } => Finding::error("E-TERRAIN-ZERO-COST", message).layer("floor"),
+        PreflightWarning::DegenerateTile { atlas_id, layer_id, .. } => {
+            Finding::error("E-ATLAS-DEGENERATE-TILE", message).atlas_layer(atlas_id, layer_id)
+        }
+    };
+    Some(finding)
+}
+
+impl fmt::Display for ValidationReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for finding in &self.findings {
+            writeln!(f, "{finding}")?;
+        }
+        Ok(())
+    }
+}
+
+impl AtlasWorld<AtlasMap> {
+    /// Runs every check against the world as loaded: `ValidationReport::for_world` plus the
+    /// world's own terrain, material and object settings.
+    pub fn validate_all(&self) -> ValidationReport {
+        let atlases: Vec<(&str, &AtlasInfo)> = self.collection.atlases_sorted()
+            .map(|(atlas_id, atlas)| (atlas_id.as_str(), &atlas.atlas_info))
+            .collect();
+        let mut report = ValidationReport::for_world(
+            &self.map,
+            ivec2(self.player.x, self.player.y),
+            &self.warps,
+            &atlases,
+            &self.render_config,
+            self.render_depth,
+            self.render_width,
+        );
+
+        for (atlas_id, atlas) in self.collection.atlases_sorted() {
+            report.findings.extend(atlas.findings.iter().cloned().map(|finding| Finding { atlas_id: Some(atlas_id.clone()), ..finding }));
+        }
+
+        report.findings.extend(self.terrain.zero_costs().into_iter().filter_map(|value| preflight_finding(&PreflightWarning::ZeroMoveCost { value })));
+
+        for y in 0..self.map.height as i32 {
+            for x in 0..self.map.width as i32 {
+                let value = self.map.get("object", ivec2(x, y)).unwrap_or(0);
+                let Some(def) = self.object_defs.get(&value).filter(|_| value != 0) else {
+                    continue;
+                };
+                // saturated rather than `def.cells`, which leaves out the offsets that overflow
+                let mut cells = def.footprint.iter().map(|[dx, dy]| ivec2(x.saturating_add(*dx), y.saturating_add(*dy)));
+                if let Some(outside) = cells.find(|cell| !self.map.in_bounds(*cell)) {
+                    report.findings.push(Finding::error(
+                        "E-OBJECT-FOOTPRINT-OOB",
+                        format!("object {value} at ({x}, {y}) covers ({}, {}) outside the map", outside.x, outside.y),
+                    ).layer("object").cell(x, y));
+                }
+            }
+        }
+
+        let mut materials: Vec<u8> = self.wall_materials.materials.keys().copied().collect();
+        materials.sort_unstable();
+        for value in materials {
+            if !self.map.wall.data().contains(&value) {
+                report.findings.push(
+                    Finding::warning("W-MATERIAL-UNUSED", format!("wall material for value {value} is never used on the map")).layer("wall"),
+                );
+            }
+        }
+
+        report
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+
+    use serde_json::Value;
+
+    use super::*;
+    use crate::pipeline::{validate_all, ValidateInput};
+    use crate::snapshot::UPDATE_SNAPSHOTS_ENV;
+    use crate::terrain::Terrain;
+    use crate::test_support::{demo_world, manifest_path};
+    use crate::{DepthExtend, Direction, ObjectDef, Player, WallMaterial};
+
+    // fixture -> the code of the one defect it has, checked against the demo atlases unless it
+    // brings its own
+    const MAP_FIXTURES: [(&str, &[&str]); 10] = [
+        ("clean", &[]),
+        ("row_length", &["E-MAP-ROWLEN"]),
+        ("unreachable", &["W-MAP-UNREACHABLE"]),
+        ("spawn_out_of_bounds", &["E-SPAWN-OOB"]),
+        ("spawn_in_wall", &["E-SPAWN-IN-WALL"]),
+        ("warp_source_out_of_bounds", &["E-WARP-SOURCE-OOB"]),
+        ("warp_target_out_of_bounds", &["E-WARP-TARGET-OOB"]),
+        ("warp_target_in_wall", &["E-WARP-TARGET-WALL"]),
+        ("missing_tile", &["W-ATLAS-MISSING-TILE"]),
+        ("atlas_preflight", &["W-ATLAS-NEAR-SIDES", "E-ATLAS-DEGENERATE-TILE"]),
+    ];
+
+    fn read<T: serde::de::DeserializeOwned>(path: &str) -> T {
+        serde_json::from_slice(&std::fs::read(manifest_path(path)).unwrap()).unwrap()
+    }
+
+    fn map_report(name: &str) -> ValidationReport {
+        let mut input: ValidateInput = read(&format!("fixtures/validation/{name}.json"));
+        if input.atlases.is_empty() {
+            input.atlases.insert("dungeon".to_owned(), read("mansion.json"));
+            input.atlases.insert("common_objects".to_owned(), read("common_objects.json"));
+        }
+        validate_all(&input)
+    }
+
+    // 64x64 pages, with a tile on a page that isn't there, one past its page, one past the
+    // viewport and two in one place
+    fn atlas_report() -> ValidationReport {
+        let atlas: AtlasInfo = read("fixtures/validation/atlas_defects.json");
+        ValidationReport { findings: atlas.validate_pages(&[(64, 64)]) }
+    }
+
+    // the clean map in a world drawn with the demo atlases, whose own settings have a defect each
+    fn world_report() -> ValidationReport {
+        let input: ValidateInput = read("fixtures/validation/clean.json");
+        let mut world = demo_world(Player::new(input.spawn[0], input.spawn[1], Direction::North), input.map);
+        world.map.object.set(4, 2, 2);
+        world.object_defs.insert(2, ObjectDef { footprint: vec![[0, 0], [2, 0]], ..Default::default() });
+        world.wall_materials.materials.insert(9, WallMaterial::default());
+        world.terrain.insert(1, Terrain { move_cost: 0, ..Default::default() });
+        // rows past the mansion's authored floor tiles are stood in for
+        world.render_depth += 2;
+        world.render_config.depth_extend.insert("floor-1".to_owned(), DepthExtend { vanishing_point: vec2(160.0, 90.0), row_scale: vec![1.0; 16] });
+        world.validate_all()
+    }
+
+    fn codes(report: &ValidationReport) -> BTreeSet<&'static str> {
+        report.findings.iter().map(|finding| finding.code).collect()
+    }
+
+    #[test]
+    fn each_fixture_has_its_one_defect() {
+        for (name, expected) in MAP_FIXTURES {
+            assert_eq!(codes(&map_report(name)), expected.iter().copied().collect(), "{name}");
+        }
+        let atlas_codes = ["E-ATLAS-PAGE-MISSING", "E-ATLAS-RECT-OOB", "W-ATLAS-SCREEN-OOB", "W-ATLAS-DUPLICATE-TILE"];
+        assert_eq!(codes(&atlas_report()), atlas_codes.into_iter().collect());
+        let world_codes = ["E-OBJECT-FOOTPRINT-OOB", "W-MATERIAL-UNUSED", "E-TERRAIN-ZERO-COST", "W-ATLAS-SYNTHESIZED"];
+        assert_eq!(codes(&world_report()), world_codes.into_iter().collect());
+    }
+
+    // every report as the pipeline serializes it, against fixtures/validation/report.json;
+    // rewritten like the render snapshots when `ATLAS_UPDATE_SNAPSHOTS=1`
+    #[test]
+    fn reports_match_the_golden_file() {
+        let mut reports: BTreeMap<&str, ValidationReport> = MAP_FIXTURES.iter().map(|(name, _)| (*name, map_report(name))).collect();
+        reports.insert("atlas_defects", atlas_report());
+        reports.insert("world_settings", world_report());
+        let actual = serde_json::to_value(&reports).unwrap();
+
+        let path = manifest_path("fixtures/validation/report.json");
+        if std::env::var(UPDATE_SNAPSHOTS_ENV).is_ok_and(|v| v == "1") || !path.exists() {
+            std::fs::write(&path, serde_json::to_string_pretty(&actual).unwrap() + "\n").unwrap();
+            return;
+        }
+        let expected: Value = read("fixtures/validation/report.json");
+        assert!(expected == actual, "validation reports changed, rerun with {UPDATE_SNAPSHOTS_ENV}=1 to accept:\n{}", serde_json::to_string_pretty(&actual).unwrap());
+    }
+}
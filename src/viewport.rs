@@ -0,0 +1,338 @@
+//! Rendering the view into a texture and presenting it on the window, including the mouse peek.
+
+#[cfg(not(feature = "render"))]
+use crate::math::*;
+#[cfg(feature = "render")]
+use macroquad::prelude::*;
+
+#[cfg(feature = "render")]
+use crate::{clip_to_rect, AtlasWorld, MapSource, RenderStats};
+use crate::{viewport_size, Collection, VIEWPORT_HEIGHT, VIEWPORT_WIDTH};
+
+/// What shows in the strip a peek uncovers at the edge of the presented viewport.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum PeekFill {
+    Color(Color),
+    /// the outermost row or column of the render stretched over the strip
+    StretchEdge,
+}
+
+/// The logical viewport and how it is presented on the window. A peek only moves the
+/// presentation; the render and the viewport coordinates are left alone.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Viewport {
+    pub width: u32,
+    pub height: u32,
+    /// furthest the peek can pan, in viewport pixels
+    pub max_peek: Vec2,
+    /// how fast a released peek springs back, higher is faster
+    pub peek_spring: f32,
+    pub peek_fill: PeekFill,
+    peek: Vec2,
+    peek_held: bool,
+}
+
+impl Default for Viewport {
+    fn default() -> Self {
+        Self::new(VIEWPORT_WIDTH as u32, VIEWPORT_HEIGHT as u32)
+    }
+}
+
+impl Viewport {
+    pub fn new(width: u32, height: u32) -> Self {
+        Self {
+            width,
+            height,
+            max_peek: vec2(12.0, 8.0),
+            peek_spring: 12.0,
+            peek_fill: PeekFill::Color(BLACK),
+            peek: Vec2::ZERO,
+            peek_held: false,
+        }
+    }
+
+    /// Pans the peek by `delta` viewport pixels. Call every frame the peek is held, with a zero
+    /// delta if need be, or it springs back on the next `update_ui`.
+    pub fn peek_offset(&mut self, delta: Vec2) {
+        self.peek = (self.peek + delta).clamp(-self.max_peek, self.max_peek);
+        self.peek_held = true;
+    }
+
+    pub fn peek(&self) -> Vec2 {
+        self.peek
+    }
+
+    /// springs the peek back towards the center unless it was held since the last call
+    pub fn update_ui(&mut self, dt: f32) {
+        if !self.peek_held {
+            self.peek *= (-self.peek_spring.max(0.0) * dt.max(0.0)).exp();
+            if self.peek.length_squared() < 0.01 * 0.01 {
+                self.peek = Vec2::ZERO;
+            }
+        }
+        self.peek_held = false;
+    }
+
+    fn window_scale(&self, area: Rect) -> Vec2 {
+        vec2(area.w / self.width.max(1) as f32, area.h / self.height.max(1) as f32)
+    }
+
+    /// where the viewport lands when presented into `area`, peek included
+    pub fn present_rect(&self, area: Rect) -> Rect {
+        area.offset(self.peek * self.window_scale(area))
+    }
+
+    /// The viewport pixel under a window position when presented into `area`, peek included.
+    /// `None` outside of `area`, where nothing of the render shows.
+    pub fn window_to_viewport(&self, window: Vec2, area: Rect) -> Option<Vec2> {
+        if !area.contains(window) {
+            return None;
+        }
+        let presented = self.present_rect(area);
+        let pixel = (window - presented.point()) / self.window_scale(area);
+        let inside = pixel.x >= 0.0 && pixel.y >= 0.0 && pixel.x < self.width as f32 && pixel.y < self.height as f32;
+        inside.then_some(pixel)
+    }
+
+    /// draws the rendered `texture` into `area`, shifted by the peek and cropped to `area`
+    #[cfg(feature = "render")]
+    pub fn present(&self, texture: &Texture2D, area: Rect) {
+        let full = Rect::new(0.0, 0.0, self.width as f32, self.height as f32);
+        let presented = self.present_rect(area);
+
+        match self.peek_fill {
+            PeekFill::Color(color) => draw_rectangle(area.x, area.y, area.w, area.h, color),
+            PeekFill::StretchEdge => {
+                let strips = [
+                    (presented.left() - area.left(), Rect::new(0.0, 0.0, 1.0, full.h), Rect::new(area.x, presented.y, presented.left() - area.left(), presented.h)),
+                    (area.right() - presented.right(), Rect::new(full.w - 1.0, 0.0, 1.0, full.h), Rect::new(presented.right(), presented.y, area.right() - presented.right(), presented.h)),
+                    (presented.top() - area.top(), Rect::new(0.0, 0.0, full.w, 1.0), Rect::new(presented.x, area.y, presented.w, presented.top() - area.top())),
+                    (area.bottom() - presented.bottom(), Rect::new(0.0, full.h - 1.0, full.w, 1.0), Rect::new(presented.x, presented.bottom(), presented.w, area.bottom() - presented.bottom())),
+                ];
+                for (gap, source, dest) in strips {
+                    if gap <= 0.0 {
+                        continue;
+                    }
+                    if let Some((source, dest)) = clip_to_rect(source, dest, area) {
+                        draw_texture_ex(texture, dest.x, dest.y, WHITE, DrawTextureParams {
+                            source: Some(source),
+                            dest_size: Some(dest.size()),
+                            ..Default::default()
+                        });
+                    }
+                }
+            }
+        }
+
+        if let Some((source, dest)) = clip_to_rect(full, presented, area) {
+            draw_texture_ex(texture, dest.x, dest.y, WHITE, DrawTextureParams {
+                source: Some(source),
+                dest_size: Some(dest.size()),
+                ..Default::default()
+            });
+        }
+    }
+}
+
+#[cfg(feature = "render")]
+impl<M: MapSource> AtlasWorld<M> {
+    /// Renders the view into a texture the size of `viewport` and returns it. The target is
+    /// made on first use and again whenever the viewport changes size. Tiles are laid out for
+    /// the size the atlases were authored for, `viewport_rect`, and stretched to the viewport.
+    pub fn render_to_target(&mut self) -> &Texture2D {
+        self.render_to_target_with(Self::render)
+    }
+
+    /// `render_to_target` drawing with `draw`, like `render_from` or `render_peek`, in place of `render`
+    pub fn render_to_target_with(&mut self, draw: impl FnOnce(&Self) -> RenderStats) -> &Texture2D {
+        let size = vec2(self.viewport.width.max(1) as f32, self.viewport.height.max(1) as f32);
+        if self.view_target.as_ref().is_some_and(|target| target.texture.size() != size) {
+            self.view_target = None;
+        }
+        let target = self.view_target.get_or_insert_with(|| {
+            let target = render_target(size.x as u32, size.y as u32);
+            target.texture.set_filter(FilterMode::Nearest);
+            target
+        }).clone();
+
+        let authored = self.viewport_rect();
+        set_camera(&Camera2D {
+            render_target: Some(target.clone()),
+            zoom: vec2(2.0 / authored.w, 2.0 / authored.h),
+            offset: vec2(-1.0, -1.0),
+            ..Default::default()
+        });
+        clear_background(BLACK);
+        draw(self);
+        set_default_camera();
+
+        &self.view_target.insert(target).texture
+    }
+}
+
+/// the viewport the atlases of `collection` were authored for
+pub(crate) fn authored_viewport(collection: &impl Collection) -> Viewport {
+    let (width, height) = viewport_size(collection.asset_scale().unwrap_or(1));
+    Viewport::new(width, height)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use super::*;
+    use crate::test_support::{demo_world, manifest_path};
+    use crate::{fit_viewport_integer, AssetScaleError, Atlas, AtlasCollection, AtlasInfo, AtlasMap, AtlasWorld, Direction, HudLayout, Player};
+
+    // the same two floor tiles authored for the 320x256 viewport and at twice that
+    fn fixture(scale: u32, ext: &str) -> PathBuf {
+        PathBuf::from(env!("CARGO_MANIFEST_DIR")).join(format!("fixtures/scale/dungeon@{scale}x.{ext}"))
+    }
+
+    // the fixture authored at `authored`, loaded as if it were authored at `scale`
+    fn atlas(authored: u32, scale: u32) -> Atlas {
+        let json = std::fs::read(fixture(authored, "json")).unwrap();
+        let png = std::fs::read(fixture(authored, "png")).unwrap();
+        let size = |at: usize| u32::from_be_bytes(png[at..at + 4].try_into().unwrap());
+        let mut atlas = Atlas {
+            atlas_info: serde_json::from_slice::<AtlasInfo>(&json).unwrap(),
+            #[cfg(feature = "render")]
+            textures: Vec::new(),
+            texture_sizes: vec![(size(16), size(20))],
+            scale,
+            data_path: None,
+            image_path: None,
+            findings: Vec::new(),
+            diagonal_art: false,
+        };
+        atlas.revalidate("dungeon");
+        atlas
+    }
+
+    fn collection(atlases: Vec<(&str, Atlas)>) -> AtlasCollection {
+        atlases.into_iter().map(|(atlas_id, atlas)| (atlas_id.to_owned(), atlas)).collect()
+    }
+
+    #[test]
+    fn each_variant_fits_the_viewport_at_its_own_scale() {
+        assert!(atlas(1, 1).findings.is_empty());
+        assert!(atlas(2, 2).findings.is_empty());
+        // 2x tiles taken for 1x ones run off the viewport
+        assert!(!atlas(2, 1).findings.is_empty());
+    }
+
+    #[test]
+    fn the_viewport_follows_the_asset_scale() {
+        for scale in [1, 2] {
+            let collection = collection(vec![("dungeon", atlas(scale, scale))]);
+            assert_eq!(collection.asset_scale(), Ok(scale));
+            let viewport = authored_viewport(&collection);
+            assert_eq!((viewport.width, viewport.height), (320 * scale, 256 * scale));
+            let world = AtlasWorld::new(Player::new(0, 0, Direction::North), AtlasMap::empty(1, 1), collection);
+            assert_eq!(world.viewport_rect(), Rect::new(0.0, 0.0, 320.0 * scale as f32, 256.0 * scale as f32));
+        }
+    }
+
+    #[test]
+    fn mixed_scales_are_an_error() {
+        let collection = collection(vec![("dungeon", atlas(1, 1)), ("objects", atlas(2, 2))]);
+        assert_eq!(
+            collection.asset_scale(),
+            Err(AssetScaleError::Mixed { scales: vec![("dungeon".to_owned(), 1), ("objects".to_owned(), 2)] })
+        );
+    }
+
+    #[test]
+    fn presentation_scales_2x_assets_once() {
+        let area = Rect::new(0.0, 0.0, 1300.0, 1100.0);
+        assert_eq!(fit_viewport_integer(area, 1), Rect::new(0.0, 0.0, 1280.0, 1024.0));
+        assert_eq!(fit_viewport_integer(area, 2), Rect::new(0.0, 0.0, 1280.0, 1024.0));
+
+        let layout = HudLayout { margin: 0.0, frame: 0.0, viewport_width: 1.0, panels: Vec::new() };
+        assert_eq!(layout.resolve(vec2(700.0, 600.0), 2).viewport, Rect::new(0.0, 0.0, 640.0, 512.0));
+        assert_eq!(layout.resolve(vec2(700.0, 600.0), 1).viewport, Rect::new(0.0, 0.0, 640.0, 512.0));
+        // too small for 2x, so the aspect ratio fit it falls back to
+        assert_eq!(layout.resolve(vec2(600.0, 500.0), 2).viewport, Rect::new(0.0, 0.0, 600.0, 480.0));
+    }
+
+    // loading creates textures, which needs a window with `render`
+    #[cfg(not(feature = "render"))]
+    #[test]
+    fn load_scaled_picks_the_selected_variant() {
+        let images = [std::fs::read(fixture(1, "png")).unwrap(), std::fs::read(fixture(2, "png")).unwrap()];
+        let variants = || vec![(1, images[0].as_slice(), fixture(1, "json")), (2, images[1].as_slice(), fixture(2, "json"))];
+        let mut collection = AtlasCollection::new();
+        collection.load_scaled("dungeon", variants(), 2).unwrap();
+        assert_eq!(collection.asset_scale(), Ok(2));
+        assert_eq!(collection["dungeon"].texture_sizes, vec![(128, 64)]);
+        assert!(collection["dungeon"].findings.is_empty());
+        assert!(collection.load_scaled("objects", variants(), 3).is_err());
+    }
+
+    #[test]
+    fn a_held_peek_stays_clamped_and_springs_back_once_let_go() {
+        let mut viewport = Viewport::new(320, 256);
+        viewport.peek_offset(vec2(5.0, -3.0));
+        viewport.peek_offset(vec2(20.0, -1.0));
+        assert_eq!(viewport.peek(), vec2(12.0, -4.0));
+        // held through the frame, so it doesn't move
+        viewport.update_ui(0.5);
+        assert_eq!(viewport.peek(), vec2(12.0, -4.0));
+
+        // let go, it decays by e^(-spring * dt) a frame whatever the frame rate
+        let dt = std::f32::consts::LN_2 / viewport.peek_spring;
+        viewport.update_ui(dt);
+        assert!(viewport.peek().distance(vec2(6.0, -2.0)) < 1e-4);
+        viewport.update_ui(dt / 2.0);
+        viewport.update_ui(dt / 2.0);
+        assert!(viewport.peek().distance(vec2(3.0, -1.0)) < 1e-4);
+        // time running backwards doesn't push it out again
+        viewport.update_ui(-1.0);
+        assert!(viewport.peek().distance(vec2(3.0, -1.0)) < 1e-4);
+        // and it settles at exactly zero rather than creeping forever
+        viewport.update_ui(2.0);
+        assert_eq!(viewport.peek(), Vec2::ZERO);
+
+        viewport.peek_spring = 0.0;
+        viewport.peek_offset(vec2(-4.0, 0.0));
+        viewport.update_ui(0.1);
+        viewport.update_ui(10.0);
+        assert_eq!(viewport.peek(), vec2(-4.0, 0.0));
+    }
+
+    #[test]
+    fn window_positions_follow_the_peek() {
+        let mut viewport = Viewport::new(320, 256);
+        let area = Rect::new(10.0, 20.0, 640.0, 512.0);
+        assert_eq!(viewport.window_to_viewport(vec2(10.0, 20.0), area), Some(vec2(0.0, 0.0)));
+        assert_eq!(viewport.window_to_viewport(vec2(330.0, 276.0), area), Some(vec2(160.0, 128.0)));
+        assert_eq!(viewport.window_to_viewport(vec2(9.0, 20.0), area), None);
+
+        // peeking 4 right and 2 up moves the render 8 and 4 window pixels
+        viewport.peek_offset(vec2(4.0, -2.0));
+        assert_eq!(viewport.present_rect(area), Rect::new(18.0, 16.0, 640.0, 512.0));
+        assert_eq!(viewport.window_to_viewport(vec2(330.0, 276.0), area), Some(vec2(156.0, 130.0)));
+        assert_eq!(viewport.window_to_viewport(vec2(18.0, 20.0), area), Some(vec2(0.0, 2.0)));
+        // the strips the peek uncovered show no render
+        assert_eq!(viewport.window_to_viewport(vec2(12.0, 100.0), area), None);
+        assert_eq!(viewport.window_to_viewport(vec2(100.0, 526.0), area), Some(vec2(41.0, 255.0)));
+        assert_eq!(viewport.window_to_viewport(vec2(100.0, 530.0), area), None);
+    }
+
+    #[test]
+    fn picking_in_the_window_sees_through_the_peek() {
+        let file = std::fs::File::open(manifest_path("fixtures/scenes/corridor.json")).unwrap();
+        let mut world = demo_world(Player::new(2, 6, Direction::North), AtlasMap::from_reader_auto(file).unwrap());
+        let area = Rect::new(0.0, 0.0, 640.0, 512.0);
+        let picks = |world: &AtlasWorld, points: &[Vec2]| points.iter().map(|point| world.pick_window(*point, area)).collect::<Vec<_>>();
+        // the left wall, the far end of the corridor and the floor just ahead
+        let points = [vec2(40.0, 256.0), vec2(320.0, 230.0), vec2(320.0, 480.0)];
+        let still = picks(&world, &points);
+        assert!(still.iter().all(Option::is_some));
+
+        world.viewport.peek_offset(vec2(-10.0, 6.0));
+        let shifted: Vec<Vec2> = points.iter().map(|point| *point + vec2(-20.0, 12.0)).collect();
+        assert_eq!(picks(&world, &shifted), still);
+        assert_ne!(picks(&world, &points), still);
+    }
+}
@@ -0,0 +1,82 @@
+//! How each wall value behaves beyond what it looks like: grates that can be seen through but
+//! not walked through, curtains that can be walked through, fences with only a front face.
+//! Values without an entry behave as they always have, going by `wall_materials` for sight and
+//! `movement_rules` for movement.
+
+use ahash::AHashMap;
+use serde::{Deserialize, Serialize};
+
+use crate::{AtlasWorld, MapSource};
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(default)]
+pub struct WallFlags {
+    /// drawn at `WallProperties::transparent_alpha`, and cells behind it are still drawn
+    pub transparent: bool,
+    pub blocks_movement: bool,
+    /// for line of sight and which cells occlusion culls
+    pub blocks_sight: bool,
+    /// whether the side faces are drawn, off for walls with only a front face
+    pub sides: bool,
+}
+
+impl Default for WallFlags {
+    fn default() -> Self {
+        Self { transparent: false, blocks_movement: true, blocks_sight: true, sides: true }
+    }
+}
+
+/// Wall value -> flags table.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(default)]
+pub struct WallProperties {
+    pub walls: AHashMap<u8, WallFlags>,
+    /// the alpha `transparent` walls are drawn with
+    pub transparent_alpha: f32,
+}
+
+impl Default for WallProperties {
+    fn default() -> Self {
+        Self { walls: AHashMap::new(), transparent_alpha: 0.5 }
+    }
+}
+
+impl WallProperties {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn insert(&mut self, value: u8, flags: WallFlags) {
+        self.walls.insert(value, flags);
+    }
+
+    pub fn get(&self, value: u8) -> Option<&WallFlags> {
+        self.walls.get(&value)
+    }
+
+    /// the alpha the faces of `value` are drawn with
+    pub fn alpha(&self, value: u8) -> f32 {
+        if self.get(value).is_some_and(|flags| flags.transparent) { self.transparent_alpha } else { 1.0 }
+    }
+
+    pub fn has_sides(&self, value: u8) -> bool {
+        self.get(value).is_none_or(|flags| flags.sides)
+    }
+}
+
+impl<M: MapSource> AtlasWorld<M> {
+    /// whether wall `value` blocks line of sight
+    pub fn wall_blocks_sight(&self, value: u8) -> bool {
+        value != 0 && self.wall_properties.get(value).map_or_else(|| self.wall_materials.blocks_sight(value), |flags| flags.blocks_sight)
+    }
+
+    /// whether wall `value` keeps the player and entities out of its cell
+    pub fn wall_blocks_movement(&self, value: u8) -> bool {
+        value != 0 && self.wall_properties.get(value).map_or_else(|| !self.movement_rules.passable_walls.contains(&value), |flags| flags.blocks_movement)
+    }
+
+    // whether drawing can skip what wall `value` stands in front of
+    pub(crate) fn wall_hides_view(&self, value: u8) -> bool {
+        self.wall_blocks_sight(value) && !self.wall_properties.get(value).is_some_and(|flags| flags.transparent)
+    }
+}
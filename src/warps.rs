@@ -0,0 +1,195 @@
+#[cfg(not(feature = "render"))]
+use crate::math::*;
+#[cfg(feature = "render")]
+use macroquad::prelude::*;
+use serde::{Deserialize, Serialize};
+
+#[cfg(feature = "render")]
+use crate::automap::MinimapLayout;
+use crate::{AtlasWorld, Direction, MapSource};
+
+/// How a warp is shown on the maps.
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub enum WarpKind {
+    StairsUp,
+    StairsDown,
+    Portal,
+}
+
+/// A cell that sends the player somewhere else, on this level or another one, when a move
+/// lands on it.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct Warp {
+    pub x: i32,
+    pub y: i32,
+    /// name of the level the warp leads to, `None` for a spot on the same level
+    #[serde(default)]
+    pub dest_level: Option<String>,
+    pub dest_x: i32,
+    pub dest_y: i32,
+    /// which way the player faces on arrival, `None` to keep facing the way they stepped in
+    #[serde(default)]
+    pub direction: Option<Direction>,
+    #[serde(default)]
+    pub kind: Option<WarpKind>,
+}
+
+impl Warp {
+    pub fn pos(&self) -> IVec2 {
+        ivec2(self.x, self.y)
+    }
+
+    /// warps to another level are exits, the rest are just shortcuts
+    pub fn is_exit(&self) -> bool {
+        self.dest_level.is_some()
+    }
+
+    /// the kind the glyph is drawn as, a warp without one is a portal
+    pub fn glyph_kind(&self) -> WarpKind {
+        self.kind.unwrap_or(WarpKind::Portal)
+    }
+}
+
+/// Draws the glyph for `kind` into a map cell: an arrow for stairs, a ring for portals.
+#[cfg(feature = "render")]
+pub fn draw_warp_glyph(cell: Rect, kind: WarpKind, color: Color) {
+    let center = cell.center();
+    let (half_w, half_h) = (cell.w * 0.3, cell.h * 0.3);
+    match kind {
+        WarpKind::StairsUp => draw_triangle(
+            vec2(center.x, center.y - half_h),
+            vec2(center.x + half_w, center.y + half_h),
+            vec2(center.x - half_w, center.y + half_h),
+            color,
+        ),
+        WarpKind::StairsDown => draw_triangle(
+            vec2(center.x - half_w, center.y - half_h),
+            vec2(center.x + half_w, center.y - half_h),
+            vec2(center.x, center.y + half_h),
+            color,
+        ),
+        WarpKind::Portal => draw_circle_lines(center.x, center.y, half_w.min(half_h), cell.w.min(cell.h) / 10.0, color),
+    }
+}
+
+impl<M: MapSource> AtlasWorld<M> {
+    /// Exits to other levels at cells `discovered` says the player has seen. Exits in undiscovered
+    /// cells stay hidden so the map doesn't give them away.
+    pub fn discovered_exits<'a>(&'a self, discovered: impl Fn(IVec2) -> bool + 'a) -> impl Iterator<Item = &'a Warp> + 'a {
+        self.warps.iter().filter(move |warp| warp.is_exit() && discovered(warp.pos()))
+    }
+
+    /// the level a discovered exit at `pos` leads to, e.g. for a tooltip over its glyph
+    pub fn exit_destination(&self, pos: IVec2, discovered: impl Fn(IVec2) -> bool) -> Option<&str> {
+        if !discovered(pos) {
+            return None;
+        }
+        self.warps.iter()
+            .find(|warp| warp.pos() == pos && warp.is_exit())
+            .and_then(|warp| warp.dest_level.as_deref())
+    }
+
+    /// Draws the glyphs of the discovered exits inside the map drawn as `layout`. For a map
+    /// drawn by `draw_map_region`, pass `MinimapLayout::new(region, dest)`.
+    #[cfg(feature = "render")]
+    pub fn draw_exits(&self, layout: &MinimapLayout, color: Color, discovered: impl Fn(IVec2) -> bool) {
+        for warp in self.discovered_exits(discovered).filter(|warp| layout.contains(warp.pos())) {
+            draw_warp_glyph(layout.cell_rect(warp.pos()), warp.glyph_kind(), color);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::automap::{AutoMap, MinimapLayout, RevealMode};
+    use crate::{AtlasCollection, AtlasMap, IRect, Player};
+
+    // a 7x1 corridor with stairs down at its west end, stairs up at its east end, a portal
+    // without a kind beside them and a shortcut on the same level in the middle
+    fn world() -> AtlasWorld {
+        let mut world = AtlasWorld::new(Player::new(3, 0, Direction::East), AtlasMap::empty(7, 1), AtlasCollection::new());
+        world.add_level("cellar", AtlasMap::empty(3, 3));
+        world.add_level("attic", AtlasMap::empty(3, 3));
+        world.automap = Some(AutoMap::new(7, 1));
+        let warp = |x, dest_level: Option<&str>, kind| Warp { x, y: 0, dest_level: dest_level.map(str::to_owned), dest_x: 1, dest_y: 1, direction: None, kind };
+        world.warps = vec![
+            warp(0, Some("cellar"), Some(WarpKind::StairsDown)),
+            warp(3, None, Some(WarpKind::StairsUp)),
+            warp(5, Some("attic"), None),
+            warp(6, Some("attic"), Some(WarpKind::StairsUp)),
+        ];
+        world
+    }
+
+    fn exits(world: &AtlasWorld) -> Vec<(i32, WarpKind)> {
+        let automap = world.automap.as_ref().unwrap();
+        world.discovered_exits(|pos| automap.is_explored(pos)).map(|warp| (warp.x, warp.glyph_kind())).collect()
+    }
+
+    fn destination(world: &AtlasWorld, x: i32) -> Option<&str> {
+        let automap = world.automap.as_ref().unwrap();
+        world.exit_destination(ivec2(x, 0), |pos| automap.is_explored(pos))
+    }
+
+    #[test]
+    fn exits_show_once_discovered() {
+        let mut world = world();
+        world.update(0.0);
+        // the shortcut underfoot is explored but isn't an exit
+        assert_eq!(exits(&world), []);
+        assert_eq!(destination(&world, 3), None);
+
+        world.player.x = 5;
+        world.update(0.0);
+        assert_eq!(exits(&world), [(5, WarpKind::Portal)]);
+        assert_eq!(destination(&world, 6), None);
+
+        world.automap.as_mut().unwrap().reveal_mode = RevealMode::LineOfSight { radius: 1 };
+        world.update(0.0);
+        assert_eq!(exits(&world), [(5, WarpKind::Portal), (6, WarpKind::StairsUp)]);
+        assert_eq!(destination(&world, 6), Some("attic"));
+        assert_eq!(destination(&world, 0), None);
+
+        // a fully shown map shows every exit without discovering them
+        world.automap.as_mut().unwrap().reveal_mode = RevealMode::Full;
+        assert_eq!(exits(&world).len(), 3);
+        assert_eq!(destination(&world, 0), Some("cellar"));
+        world.automap.as_mut().unwrap().reveal_mode = RevealMode::Visited;
+        assert_eq!(destination(&world, 0), None);
+    }
+
+    #[test]
+    fn the_exit_under_the_pointer_names_its_level() {
+        let mut world = world();
+        world.automap.as_mut().unwrap().reveal_all();
+        let layout = MinimapLayout::new(IRect::new(0, 0, 7, 1), Rect::new(100.0, 50.0, 70.0, 10.0));
+        let automap = world.automap.as_ref().unwrap();
+        let hovered = |x: f32| layout.cell_at(vec2(x, 55.0)).and_then(|cell| world.exit_destination(cell, |pos| automap.is_explored(pos)));
+        assert_eq!(hovered(105.0), Some("cellar"));
+        assert_eq!(hovered(135.0), None);
+        assert_eq!(hovered(159.9), Some("attic"));
+        assert_eq!(hovered(169.0), Some("attic"));
+        assert_eq!(hovered(99.0), None);
+        assert_eq!(hovered(171.0), None);
+    }
+
+    #[test]
+    fn exits_go_and_come_back_with_their_level() {
+        let mut world = world();
+        world.automap.as_mut().unwrap().visit(ivec2(0, 0));
+        assert!(world.switch_level("cellar", ivec2(1, 1), Direction::North));
+        assert_eq!(exits(&world), []);
+        assert!(world.switch_level("main", ivec2(3, 0), Direction::East));
+        assert_eq!(exits(&world), [(0, WarpKind::StairsDown)]);
+    }
+
+    #[test]
+    fn warps_without_a_kind_load_as_portals() {
+        let warp: Warp = serde_json::from_str(r#"{ "x": 1, "y": 2, "dest_level": "cellar", "dest_x": 0, "dest_y": 0 }"#).unwrap();
+        assert_eq!((warp.kind, warp.glyph_kind()), (None, WarpKind::Portal));
+        let warp: Warp = serde_json::from_str(r#"{ "x": 1, "y": 2, "dest_x": 0, "dest_y": 0, "kind": "StairsDown" }"#).unwrap();
+        assert_eq!(warp.kind, Some(WarpKind::StairsDown));
+        assert!(!warp.is_exit());
+    }
+}
@@ -0,0 +1,56 @@
+//! Reloading atlases as their files are saved, by polling modification times.
+
+use std::{path::Path, time::SystemTime};
+
+use ahash::AHashMap;
+
+use crate::{AtlasCollection, AtlasError, Collection};
+
+fn modified(path: Option<&Path>) -> Option<SystemTime> {
+    std::fs::metadata(path?).and_then(|metadata| metadata.modified()).ok()
+}
+
+pub struct AtlasWatcher {
+    /// seconds between checks
+    pub interval: f32,
+    elapsed: f32,
+    /// modification times of each atlas' json and image at the last check
+    seen: AHashMap<String, [Option<SystemTime>; 2]>,
+}
+
+impl AtlasWatcher {
+    pub fn new(interval: f32) -> Self {
+        Self { interval, elapsed: 0.0, seen: AHashMap::new() }
+    }
+
+    /// Counts `dt` toward the next check and runs it once `interval` has passed.
+    pub fn update(&mut self, atlases: &mut AtlasCollection, dt: f32) -> Vec<(String, Result<(), AtlasError>)> {
+        self.elapsed += dt;
+        if self.elapsed < self.interval {
+            return Vec::new();
+        }
+        self.elapsed = 0.0;
+        self.poll(atlases)
+    }
+
+    /// Reloads the atlases whose files changed since the last check, returning each one's id
+    /// and how its reload went. An atlas seen for the first time is only noted.
+    pub fn poll(&mut self, atlases: &mut AtlasCollection) -> Vec<(String, Result<(), AtlasError>)> {
+        let mut changed = Vec::new();
+        for (atlas_id, atlas) in atlases.atlases_sorted() {
+            if atlas.data_path.is_none() {
+                continue;
+            }
+            let times = [modified(atlas.data_path.as_deref()), modified(atlas.image_path.as_deref())];
+            if self.seen.insert(atlas_id.clone(), times).is_some_and(|seen| seen != times) {
+                changed.push(atlas_id.clone());
+            }
+        }
+        changed.into_iter()
+            .map(|atlas_id| {
+                let result = atlases.reload_from_files(&atlas_id);
+                (atlas_id, result)
+            })
+            .collect()
+    }
+}
@@ -0,0 +1,98 @@
+//! Drives the `atlas_world_tool` binary over stdin/stdout with the JSON under `fixtures/tool`.
+
+#![cfg(feature = "tool")]
+
+use std::io::Write;
+use std::process::{Command, Output, Stdio};
+
+use serde_json::{json, Value};
+
+fn fixture(name: &str) -> String {
+    std::fs::read_to_string(format!("{}/fixtures/tool/{name}.json", env!("CARGO_MANIFEST_DIR"))).unwrap()
+}
+
+fn run_raw(args: &[&str], input: &str) -> Output {
+    let mut child = Command::new(env!("CARGO_BIN_EXE_atlas_world_tool"))
+        .args(args)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+    child.stdin.take().unwrap().write_all(input.as_bytes()).unwrap();
+    child.wait_with_output().unwrap()
+}
+
+fn run(command: &str, input: &str) -> Value {
+    let output = run_raw(&[command], input);
+    assert!(output.status.success(), "{command}: {}", String::from_utf8_lossy(&output.stderr));
+    serde_json::from_slice(&output.stdout).unwrap()
+}
+
+#[test]
+fn validate_map_reports_the_unreachable_pocket() {
+    let report = run("validate_map", &fixture("pocket"));
+    assert_eq!(report, json!({ "errors": [], "unreachable": [[4, 1], [5, 1], [4, 2], [5, 2]] }));
+}
+
+#[test]
+fn validate_map_reports_ragged_layers() {
+    let mut map: Value = serde_json::from_str(&fixture("pocket")).unwrap();
+    map["floor"].as_array_mut().unwrap().pop();
+    let report = run("validate_map", &map.to_string());
+    assert_eq!(report["errors"], json!(["floor layer has 3 rows, expected 4"]));
+}
+
+#[test]
+fn find_path_goes_around_blocking_objects() {
+    let path: Vec<[i32; 2]> = serde_json::from_value(run("find_path", &fixture("find_path"))).unwrap();
+    assert_eq!(path.first(), Some(&[2, 3]));
+    assert_eq!(path.last(), Some(&[4, 3]));
+    assert!(!path.contains(&[3, 3]));
+    #[cfg(not(feature = "diagonal"))]
+    assert_eq!(path.len(), 11);
+
+    let mut query: Value = serde_json::from_str(&fixture("find_path")).unwrap();
+    query["blocking_objects"] = json!([]);
+    assert_eq!(run("find_path", &query.to_string()), json!([[2, 3], [3, 3], [4, 3]]));
+    query["to"] = json!([0, 0]);
+    assert_eq!(run("find_path", &query.to_string()), Value::Null);
+}
+
+#[test]
+fn generated_maps_are_repeatable_and_connected() {
+    let map = run("generate_map", &fixture("generate_map"));
+    assert_eq!(run("generate_map", &fixture("generate_map")), map);
+    assert_eq!((map["width"].as_u64(), map["height"].as_u64()), (Some(9), Some(7)));
+    assert_eq!(run("validate_map", &map.to_string()), json!({ "errors": [], "unreachable": [] }));
+}
+
+#[test]
+fn preflight_checks_the_demo_map_against_its_atlases() {
+    let read = |name: &str| -> Value {
+        serde_json::from_str(&std::fs::read_to_string(format!("{}/{name}.json", env!("CARGO_MANIFEST_DIR"))).unwrap()).unwrap()
+    };
+    let mut input = json!({
+        "map": read("map"),
+        "spawn": [1, 1],
+        "atlases": { "dungeon": read("mansion"), "common_objects": read("common_objects") },
+    });
+    assert_eq!(run("preflight", &input.to_string()), json!([]));
+    input["spawn"] = json!([0, 0]);
+    assert_eq!(run("preflight", &input.to_string()), json!(["spawn cell (0, 0) has wall value 1"]));
+}
+
+#[test]
+fn bad_invocations_fail_with_a_message() {
+    let usage = run_raw(&[], "");
+    assert_eq!(usage.status.code(), Some(2));
+    assert!(String::from_utf8_lossy(&usage.stderr).starts_with("usage:"));
+
+    let unknown = run_raw(&["explode"], "{}");
+    assert_eq!(unknown.status.code(), Some(1));
+    assert!(String::from_utf8_lossy(&unknown.stderr).contains("unknown command 'explode'"));
+
+    let bad = run_raw(&["find_path"], "{\"map\": 3}");
+    assert_eq!(bad.status.code(), Some(1));
+    assert!(String::from_utf8_lossy(&bad.stderr).starts_with("bad input:"));
+}